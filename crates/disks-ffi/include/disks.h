@@ -0,0 +1,65 @@
+/*
+ * SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+ * SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+ *
+ * SPDX-License-Identifier: MPL-2.0
+ */
+
+#ifndef DISKS_H
+#define DISKS_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes */
+#define DISKS_OK 0
+#define DISKS_ERROR -1
+#define DISKS_INVALID_ARGUMENT -2
+
+/* Strategies and devices to plan for */
+typedef struct DisksPlanner DisksPlanner;
+
+/* Version of the bindings, static */
+const char *disks_version(void);
+
+/* Most recent failure on this thread, or NULL. Owned by the library. */
+const char *disks_last_error(void);
+
+/* Stable code of the most recent failure on this thread, or NULL. Owned by the library.
+ * "plan-changed" reports a plan that no longer matches the confirmed report, "panic" a
+ * bug in the library, which returned DISKS_ERROR or NULL instead. */
+const char *disks_last_error_code(void);
+
+/* Release a string returned by this library */
+void disks_string_free(char *s);
+
+/* Block devices of this system as a JSON array, or NULL */
+char *disks_enumerate(void);
+
+/* Superblock of the device or image at path as a JSON object, or NULL */
+char *disks_probe(const char *path);
+
+/* Planner for every block device of this system, or NULL */
+DisksPlanner *disks_planner_new(void);
+
+/* Release a planner */
+void disks_planner_free(DisksPlanner *planner);
+
+/* Load the strategies in path, a KDL file or a directory of them */
+int disks_planner_load(DisksPlanner *planner, const char *path);
+
+/* Every plan as a JSON array of dry-run reports, or NULL */
+char *disks_planner_plan(const DisksPlanner *planner);
+
+/* Apply the plan at index of disks_planner_plan() if it still matches report, the JSON
+ * report of that plan the user confirmed. Destroys data. */
+int disks_planner_apply(const DisksPlanner *planner, size_t index, const char *report);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DISKS_H */
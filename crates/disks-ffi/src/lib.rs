@@ -0,0 +1,513 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! C bindings for installers not written in Rust
+//!
+//! The ABI is deliberately narrow: results are handed over as JSON documents in
+//! NUL-terminated strings, so new fields never break existing callers. Strings returned
+//! by this library must be released with [`disks_string_free()`].
+//!
+//! Functions either return a pointer, `NULL` on failure, or one of the `DISKS_*` status
+//! codes. The reason for the most recent failure on the calling thread is available from
+//! [`disks_last_error()`] and [`disks_last_error_code()`], the latter being one of the
+//! stable [`provisioning::ErrorCode`] strings, `invalid-argument` for misuse of the API,
+//! `plan-changed` when a plan no longer matches the report the user confirmed or `panic`
+//! for a bug in the library. Panics never unwind into the caller.
+//!
+//! The matching declarations are in `include/disks.h`.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    fs::File,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr,
+};
+
+use disks::BlockDevice;
+use provisioning::{DeviceInfo, Failure, Operation, Parser, Provisioner};
+use serde::Serialize;
+use superblock::Superblock;
+use tracing::{debug, error, warn};
+
+/// The call succeeded
+pub const DISKS_OK: c_int = 0;
+
+/// The call failed, see [`disks_last_error()`]
+pub const DISKS_ERROR: c_int = -1;
+
+/// A required argument was `NULL` or otherwise invalid
+pub const DISKS_INVALID_ARGUMENT: c_int = -2;
+
+/// Error code reported for misuse of the API
+const INVALID_ARGUMENT: &CStr = c"invalid-argument";
+
+/// Error code reported when a plan differs from the confirmed report
+const PLAN_CHANGED: &CStr = c"plan-changed";
+
+/// Error code reported for a panic inside the library
+const PANIC: &CStr = c"panic";
+
+/// Version of the bindings, bumped whenever the ABI changes
+const VERSION: &CStr = c"0.2.0";
+
+/// The most recent failure on this thread
+struct LastError {
+    message: CString,
+    code: CString,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+/// Record `failure` as the most recent error
+fn set_failure(failure: &Failure) {
+    warn!("{}", failure);
+    set_error(
+        failure.to_string(),
+        CString::new(failure.code().as_str()).unwrap_or_default(),
+    );
+}
+
+/// Record an invalid argument as the most recent error
+fn set_invalid_argument(message: &str) {
+    set_error(message.to_owned(), INVALID_ARGUMENT.to_owned());
+}
+
+fn set_error(message: String, code: CString) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(LastError { message, code }));
+}
+
+/// Run the body of an exported function, recording a panic as the most recent error
+///
+/// A panic escaping an `extern "C"` function aborts the process, so it returns `error`
+/// instead.
+fn guard<T>(error: T, f: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_owned());
+            error!("Panic in disks-ffi: {}", message);
+            set_error(format!("internal error: {message}"), PANIC.to_owned());
+            error
+        }
+    }
+}
+
+/// Borrow a string argument, recording an error if it is missing or not UTF-8
+///
+/// # Safety
+/// `s` must be `NULL` or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_invalid_argument(&format!("{name} must not be NULL"));
+        return None;
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_invalid_argument(&format!("{name} is not valid UTF-8"));
+            None
+        }
+    }
+}
+
+/// Hand `value` over to C as a JSON string
+fn to_json(value: &impl Serialize) -> *mut c_char {
+    let json = serde_json::to_string(value).expect("FFI types always serialize");
+    to_c_string(json)
+}
+
+/// Hand `s` over to C, recording an error if it contains a NUL byte
+fn to_c_string(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            // Reported like a panic, only a bug in the library can get here
+            error!("String handed to C contains a NUL byte at {}", e.nul_position());
+            set_error(
+                format!("internal error: NUL byte at {} in returned string", e.nul_position()),
+                PANIC.to_owned(),
+            );
+            ptr::null_mut()
+        }
+    }
+}
+
+/// A superblock as reported by [`disks_probe()`]
+#[derive(Debug, Serialize)]
+struct SuperblockInfo {
+    kind: String,
+    uuid: Option<String>,
+    label: Option<String>,
+}
+
+/// Returns the version of the bindings
+///
+/// The string is static and must not be freed.
+#[no_mangle]
+pub extern "C" fn disks_version() -> *const c_char {
+    guard(ptr::null(), || VERSION.as_ptr())
+}
+
+/// Returns the message of the most recent failure on this thread, or `NULL`
+///
+/// The string is owned by the library and valid until the next failing call on the
+/// same thread.
+#[no_mangle]
+pub extern "C" fn disks_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.message.as_ptr()))
+    })
+}
+
+/// Returns the stable code of the most recent failure on this thread, or `NULL`
+///
+/// The string is owned by the library and valid until the next failing call on the
+/// same thread.
+#[no_mangle]
+pub extern "C" fn disks_last_error_code() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.code.as_ptr()))
+    })
+}
+
+/// Release a string returned by this library
+///
+/// # Safety
+/// `s` must be `NULL` or a string returned by this library that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn disks_string_free(s: *mut c_char) {
+    guard((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
+
+/// List the block devices of this system as a JSON array
+///
+/// Returns `NULL` if the devices could not be enumerated.
+#[no_mangle]
+pub extern "C" fn disks_enumerate() -> *mut c_char {
+    guard(ptr::null_mut(), || match BlockDevice::discover() {
+        Ok(devices) => to_json(&devices.iter().map(DeviceInfo::from).collect::<Vec<_>>()),
+        Err(e) => {
+            set_failure(&Failure::new(Operation::Discover, e));
+            ptr::null_mut()
+        }
+    })
+}
+
+/// Probe the superblock of the device or image at `path` as a JSON object
+///
+/// Returns `NULL` if the path cannot be read or holds no known superblock.
+///
+/// # Safety
+/// `path` must be `NULL` or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn disks_probe(path: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let Some(path) = str_arg(path, "path") else {
+            return ptr::null_mut();
+        };
+        let failure = |source: superblock::Error| Failure::new(Operation::Probe, source).with_device(path);
+
+        let superblock = File::open(path)
+            .map_err(superblock::Error::from)
+            .and_then(|mut file| Superblock::from_reader(&mut file).map(|superblock| (superblock, file)));
+        match superblock {
+            Ok((superblock, mut file)) => to_json(&SuperblockInfo {
+                kind: superblock.kind().to_string(),
+                uuid: superblock.uuid_string().ok(),
+                label: superblock.read_label(&mut file).ok(),
+            }),
+            Err(e) => {
+                set_failure(&failure(e));
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Strategies and devices to plan for
+pub struct DisksPlanner {
+    provisioner: Provisioner,
+}
+
+/// Create a planner for every block device of this system
+///
+/// Returns `NULL` if the devices could not be enumerated. Release the planner with
+/// [`disks_planner_free()`].
+#[no_mangle]
+pub extern "C" fn disks_planner_new() -> *mut DisksPlanner {
+    guard(ptr::null_mut(), || {
+        let devices = match BlockDevice::discover() {
+            Ok(devices) => devices,
+            Err(e) => {
+                set_failure(&Failure::new(Operation::Discover, e));
+                return ptr::null_mut();
+            }
+        };
+
+        let mut provisioner = Provisioner::new();
+        for device in devices {
+            provisioner.push_device(device);
+        }
+        Box::into_raw(Box::new(DisksPlanner { provisioner }))
+    })
+}
+
+/// Release a planner
+///
+/// # Safety
+/// `planner` must be `NULL` or a planner returned by [`disks_planner_new()`] that was
+/// not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn disks_planner_free(planner: *mut DisksPlanner) {
+    guard((), || {
+        if !planner.is_null() {
+            drop(Box::from_raw(planner));
+        }
+    })
+}
+
+/// Load the strategies in `path`, a KDL file or a directory of them
+///
+/// # Safety
+/// `planner` must be a valid planner and `path` must be `NULL` or point to a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn disks_planner_load(planner: *mut DisksPlanner, path: *const c_char) -> c_int {
+    guard(DISKS_ERROR, || {
+        let Some(planner) = planner.as_mut() else {
+            set_invalid_argument("planner must not be NULL");
+            return DISKS_INVALID_ARGUMENT;
+        };
+        let Some(path) = str_arg(path, "path") else {
+            return DISKS_INVALID_ARGUMENT;
+        };
+
+        let parser = match Path::new(path).is_dir() {
+            true => Parser::load_dir(path).map_err(|e| Failure::new(Operation::LoadStrategies, e)),
+            false => Parser::new_for_path(path).map_err(|e| Failure::new(Operation::LoadStrategies, e)),
+        };
+        match parser {
+            Ok(parser) => {
+                debug!("Loaded {} strategies from {}", parser.strategies.len(), path);
+                for strategy in parser.strategies {
+                    planner.provisioner.add_strategy(strategy);
+                }
+                DISKS_OK
+            }
+            Err(failure) => {
+                set_failure(&failure);
+                DISKS_ERROR
+            }
+        }
+    })
+}
+
+/// Compute every plan as a JSON array of dry-run reports
+///
+/// Plans are identified by their index in the array, which stays stable as long as the
+/// planner is not modified.
+///
+/// # Safety
+/// `planner` must be `NULL` or a valid planner.
+#[no_mangle]
+pub unsafe extern "C" fn disks_planner_plan(planner: *const DisksPlanner) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let Some(planner) = planner.as_ref() else {
+            set_invalid_argument("planner must not be NULL");
+            return ptr::null_mut();
+        };
+        let reports = planner
+            .provisioner
+            .plan()
+            .iter()
+            .map(|plan| plan.report())
+            .collect::<Vec<_>>();
+        to_json(&reports)
+    })
+}
+
+/// Apply the plan at `index` of [`disks_planner_plan()`] to the disks
+///
+/// `report` is the JSON report of the plan the user confirmed. Plans are computed again,
+/// so the disks may have changed in the meantime; if the plan at `index` no longer
+/// matches `report` nothing is written and the error code is `plan-changed`.
+///
+/// This destroys data. On failure the first error is reported through
+/// [`disks_last_error()`].
+///
+/// # Safety
+/// `planner` must be `NULL` or a valid planner and `report` must be `NULL` or point to
+/// a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn disks_planner_apply(
+    planner: *const DisksPlanner,
+    index: usize,
+    report: *const c_char,
+) -> c_int {
+    guard(DISKS_ERROR, || {
+        let Some(planner) = planner.as_ref() else {
+            set_invalid_argument("planner must not be NULL");
+            return DISKS_INVALID_ARGUMENT;
+        };
+        let Some(report) = str_arg(report, "report") else {
+            return DISKS_INVALID_ARGUMENT;
+        };
+        let confirmed = match serde_json::from_str::<serde_json::Value>(report) {
+            Ok(confirmed) => confirmed,
+            Err(e) => {
+                set_invalid_argument(&format!("report is not valid JSON: {e}"));
+                return DISKS_INVALID_ARGUMENT;
+            }
+        };
+        let plans = planner.provisioner.plan();
+        let Some(plan) = plans.get(index) else {
+            set_invalid_argument(&format!("no plan at index {index}, {} available", plans.len()));
+            return DISKS_INVALID_ARGUMENT;
+        };
+        if serde_json::to_value(plan.report()).ok() != Some(confirmed) {
+            warn!("Refusing to apply plan {} that changed since it was confirmed", index);
+            set_error(
+                format!("plan {index} changed since it was confirmed"),
+                PLAN_CHANGED.to_owned(),
+            );
+            return DISKS_ERROR;
+        }
+
+        let report = plan.apply();
+        if report.is_success() {
+            return DISKS_OK;
+        }
+        match report.into_failures().first() {
+            Some(failure) => set_failure(failure),
+            None => set_error("plan was not applied".to_owned(), c"partition-table".to_owned()),
+        }
+        DISKS_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::mock::MockDisk;
+
+    use super::*;
+
+    fn last_error() -> (String, String) {
+        unsafe {
+            (
+                CStr::from_ptr(disks_last_error()).to_string_lossy().into_owned(),
+                CStr::from_ptr(disks_last_error_code()).to_string_lossy().into_owned(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_panic() {
+        assert_eq!(guard(DISKS_ERROR, || panic!("boom")), DISKS_ERROR);
+        assert_eq!(last_error(), ("internal error: boom".into(), "panic".into()));
+
+        let planner = guard(ptr::null_mut::<DisksPlanner>(), || panic!("{} devices", 3));
+        assert!(planner.is_null());
+        assert_eq!(last_error(), ("internal error: 3 devices".into(), "panic".into()));
+    }
+
+    #[test]
+    fn test_probe_errors() {
+        unsafe {
+            assert!(disks_probe(ptr::null()).is_null());
+            assert_eq!(
+                last_error(),
+                ("path must not be NULL".into(), "invalid-argument".into())
+            );
+
+            assert!(disks_probe(c"/nonexistent/disk.img".as_ptr()).is_null());
+            let (message, code) = last_error();
+            assert_eq!(code, "not-found");
+            assert!(message.starts_with("probing superblock /nonexistent/disk.img failed"));
+        }
+    }
+
+    #[test]
+    fn test_planner() {
+        let planner = Box::into_raw(Box::new(DisksPlanner {
+            provisioner: Provisioner::new(),
+        }));
+        unsafe {
+            assert_eq!(
+                disks_planner_load(planner, c"../provisioning/tests/use_whole_disk.kdl".as_ptr()),
+                DISKS_OK
+            );
+            assert_eq!(disks_planner_load(planner, c"nonexistent.kdl".as_ptr()), DISKS_ERROR);
+            assert_eq!(last_error().1, "parse");
+
+            // No devices, so nothing to plan or apply
+            let json = disks_planner_plan(planner);
+            assert_eq!(CStr::from_ptr(json).to_str().unwrap(), "[]");
+            disks_string_free(json);
+            assert_eq!(disks_planner_apply(planner, 0, c"{}".as_ptr()), DISKS_INVALID_ARGUMENT);
+
+            disks_planner_free(planner);
+        }
+    }
+
+    #[test]
+    fn test_apply_confirmed() {
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(150 * 1024 * 1024 * 1024)));
+        let planner = Box::into_raw(Box::new(DisksPlanner { provisioner }));
+        unsafe {
+            assert_eq!(
+                disks_planner_load(planner, c"../provisioning/tests/use_whole_disk.kdl".as_ptr()),
+                DISKS_OK
+            );
+            let json = disks_planner_plan(planner);
+            let reports =
+                serde_json::from_str::<Vec<serde_json::Value>>(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+            disks_string_free(json);
+            let confirmed = CString::new(reports[0].to_string()).unwrap();
+
+            assert_eq!(disks_planner_apply(planner, 0, ptr::null()), DISKS_INVALID_ARGUMENT);
+            assert_eq!(disks_planner_apply(planner, 0, c"{".as_ptr()), DISKS_INVALID_ARGUMENT);
+
+            // The report of another plan is never applied
+            let other = CString::new(reports[1].to_string()).unwrap();
+            assert_eq!(disks_planner_apply(planner, 0, other.as_ptr()), DISKS_ERROR);
+            assert_eq!(
+                last_error(),
+                ("plan 0 changed since it was confirmed".into(), "plan-changed".into())
+            );
+
+            // Mock disks have no device node, so the confirmed plan fails to validate
+            assert_eq!(disks_planner_apply(planner, 0, confirmed.as_ptr()), DISKS_ERROR);
+            assert_ne!(last_error().1, "plan-changed");
+
+            disks_planner_free(planner);
+        }
+    }
+
+    #[test]
+    fn test_nul_in_string() {
+        assert!(to_c_string("nul\0byte".to_owned()).is_null());
+        assert_eq!(
+            last_error(),
+            (
+                "internal error: NUL byte at 3 in returned string".into(),
+                "panic".into()
+            )
+        );
+    }
+}
@@ -0,0 +1,75 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Privileged D-Bus front-end for installers
+//!
+//! Runs as root on the system bus under `org.serpentos.Disks1` and lets unprivileged
+//! clients list devices, compute plans for the strategies installed on the system and
+//! apply one of them. Every request is authorized through polkit, see
+//! `data/org.serpentos.Disks1.policy`, and logged with the requesting bus name.
+//!
+//! Clients cannot supply strategies of their own: they are loaded from a directory
+//! given on the command line, `/usr/share/disks-rs/strategies` by default.
+
+use std::{env, path::PathBuf, process::ExitCode};
+
+use tracing::{error, info};
+
+mod polkit;
+mod service;
+
+/// Well-known name of the service
+const BUS_NAME: &str = "org.serpentos.Disks1";
+
+/// Path the service object is served at
+const OBJECT_PATH: &str = "/org/serpentos/Disks1";
+
+/// Directory strategies are loaded from unless overridden
+const DEFAULT_STRATEGIES: &str = "/usr/share/disks-rs/strategies";
+
+fn usage() -> ExitCode {
+    eprintln!("usage: disks-service [--session] [--strategies <dir>]");
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+
+    let mut session = false;
+    let mut strategies = PathBuf::from(DEFAULT_STRATEGIES);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--session" => session = true,
+            "--strategies" => match args.next() {
+                Some(dir) => strategies = dir.into(),
+                None => return usage(),
+            },
+            _ => return usage(),
+        }
+    }
+
+    let builder = match session {
+        true => zbus::blocking::connection::Builder::session(),
+        false => zbus::blocking::connection::Builder::system(),
+    };
+    let connection = builder
+        .and_then(|b| b.name(BUS_NAME))
+        .and_then(|b| b.serve_at(OBJECT_PATH, service::Service::new(&strategies)))
+        .and_then(|b| b.build());
+
+    match connection {
+        Ok(_connection) => {
+            info!("Serving {} with strategies from {:?}", BUS_NAME, strategies);
+            loop {
+                std::thread::park();
+            }
+        }
+        Err(e) => {
+            error!("Failed to register on the bus: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
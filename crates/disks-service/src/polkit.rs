@@ -0,0 +1,63 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Authorization of callers through polkit
+
+use std::collections::HashMap;
+
+use tracing::{info, warn};
+use zbus::{message::Header, proxy, zvariant::Value, Connection};
+
+use crate::service::Error;
+
+/// Read the devices and compute plans
+pub const ACTION_PLAN: &str = "org.serpentos.disks.plan";
+
+/// Write a plan to the disks, destroying data
+pub const ACTION_APPLY: &str = "org.serpentos.disks.apply";
+
+/// Allow polkit to ask the user to authenticate
+const ALLOW_USER_INTERACTION: u32 = 1;
+
+#[proxy(
+    interface = "org.freedesktop.PolicyKit1.Authority",
+    default_service = "org.freedesktop.PolicyKit1",
+    default_path = "/org/freedesktop/PolicyKit1/Authority"
+)]
+trait Authority {
+    fn check_authorization(
+        &self,
+        subject: &(&str, HashMap<&str, Value<'_>>),
+        action_id: &str,
+        details: &HashMap<&str, &str>,
+        flags: u32,
+        cancellation_id: &str,
+    ) -> zbus::Result<(bool, bool, HashMap<String, String>)>;
+}
+
+/// Check that the sender of the message with `header` may perform `action`
+pub async fn authorize(connection: &Connection, header: &Header<'_>, action: &str) -> Result<(), Error> {
+    let sender = header
+        .sender()
+        .ok_or_else(|| Error::NotAuthorized("message has no sender".to_owned()))?
+        .to_string();
+
+    let subject = (
+        "system-bus-name",
+        HashMap::from([("name", Value::from(sender.as_str()))]),
+    );
+    let authority = AuthorityProxy::new(connection).await?;
+    let (authorized, _, _) = authority
+        .check_authorization(&subject, action, &HashMap::new(), ALLOW_USER_INTERACTION, "")
+        .await?;
+
+    if authorized {
+        info!(sender, action, "Authorized");
+        Ok(())
+    } else {
+        warn!(sender, action, "Not authorized");
+        Err(Error::NotAuthorized(format!("{sender} may not perform {action}")))
+    }
+}
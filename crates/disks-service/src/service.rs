@@ -0,0 +1,231 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! The `org.serpentos.Disks1` interface
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use disks::BlockDevice;
+use provisioning::{DeviceInfo, Failure, Operation, Parser, Provisioner};
+use tracing::{info, warn};
+use zbus::{interface, message::Header, Connection, DBusError};
+
+use crate::polkit::{self, ACTION_APPLY, ACTION_PLAN};
+
+/// Errors returned to D-Bus callers
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "org.serpentos.Disks1.Error")]
+pub enum Error {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    /// Polkit denied the request
+    NotAuthorized(String),
+    /// An argument was out of range or malformed
+    InvalidArgs(String),
+    /// The plan no longer matches the report the caller confirmed
+    PlanChanged(String),
+    /// Another plan is being applied
+    Busy(String),
+    /// The operation failed, the message starts with the stable error code
+    Failed(String),
+}
+
+impl From<Failure> for Error {
+    fn from(failure: Failure) -> Self {
+        Self::Failed(format!("{}: {}", failure.code(), failure))
+    }
+}
+
+/// Serves discovery, planning and apply to unprivileged clients
+pub struct Service {
+    /// Directory the strategies are loaded from
+    strategies: PathBuf,
+    /// Set while a plan is being applied
+    applying: Arc<AtomicBool>,
+}
+
+impl Service {
+    pub fn new(strategies: impl Into<PathBuf>) -> Self {
+        Self {
+            strategies: strategies.into(),
+            applying: Arc::default(),
+        }
+    }
+}
+
+#[interface(name = "org.serpentos.Disks1")]
+impl Service {
+    /// The block devices of the system as a JSON array
+    async fn list_devices(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+    ) -> Result<String, Error> {
+        polkit::authorize(connection, &header, ACTION_PLAN).await?;
+        blocking::unblock(list_devices).await
+    }
+
+    /// Every plan for the system as a JSON array of dry-run reports
+    async fn plan(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+    ) -> Result<String, Error> {
+        polkit::authorize(connection, &header, ACTION_PLAN).await?;
+        let strategies = self.strategies.clone();
+        blocking::unblock(move || plan(&strategies)).await
+    }
+
+    /// Apply the plan at `index` of [`Self::plan()`]
+    ///
+    /// `report` is the report of that plan as shown to the user. The plan is computed
+    /// again and only applied if it still matches, so devices changing in between never
+    /// lead to unconfirmed writes.
+    async fn apply(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+        index: u32,
+        report: String,
+    ) -> Result<(), Error> {
+        polkit::authorize(connection, &header, ACTION_APPLY).await?;
+        let Some(applying) = Applying::start(&self.applying) else {
+            return Err(Error::Busy("a plan is already being applied".to_owned()));
+        };
+
+        let sender = header.sender().map(|s| s.to_string()).unwrap_or_default();
+        let strategies = self.strategies.clone();
+        blocking::unblock(move || {
+            let _applying = applying;
+            apply(&strategies, index as usize, &report, &sender)
+        })
+        .await
+    }
+}
+
+/// Holds the `applying` flag of the service, clearing it when dropped
+///
+/// The flag is released even if applying panics, so the service never stays busy.
+struct Applying(Arc<AtomicBool>);
+
+impl Applying {
+    /// Set `flag`, unless another plan is being applied
+    fn start(flag: &Arc<AtomicBool>) -> Option<Self> {
+        (!flag.swap(true, Ordering::AcqRel)).then(|| Self(flag.clone()))
+    }
+}
+
+impl Drop for Applying {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+fn list_devices() -> Result<String, Error> {
+    let devices = BlockDevice::discover().map_err(|e| Failure::new(Operation::Discover, e))?;
+    let devices = devices.iter().map(DeviceInfo::from).collect::<Vec<_>>();
+    Ok(serde_json::to_string(&devices).expect("device info always serializes"))
+}
+
+/// Create a provisioner for every device and strategy
+fn provisioner(strategies: &Path) -> Result<Provisioner, Error> {
+    let parser = Parser::load_dir(strategies).map_err(|e| Failure::new(Operation::LoadStrategies, e))?;
+    let devices = BlockDevice::discover().map_err(|e| Failure::new(Operation::Discover, e))?;
+
+    let mut provisioner = Provisioner::new();
+    for device in devices {
+        provisioner.push_device(device);
+    }
+    for strategy in parser.strategies {
+        provisioner.add_strategy(strategy);
+    }
+    Ok(provisioner)
+}
+
+fn plan(strategies: &Path) -> Result<String, Error> {
+    let provisioner = provisioner(strategies)?;
+    let reports = provisioner.plan().iter().map(|p| p.report()).collect::<Vec<_>>();
+    Ok(serde_json::to_string(&reports).expect("reports always serialize"))
+}
+
+fn apply(strategies: &Path, index: usize, confirmed: &str, sender: &str) -> Result<(), Error> {
+    let confirmed = serde_json::from_str::<serde_json::Value>(confirmed)
+        .map_err(|e| Error::InvalidArgs(format!("report is not valid JSON: {e}")))?;
+
+    let provisioner = provisioner(strategies)?;
+    let plans = provisioner.plan();
+    let plan = plans
+        .get(index)
+        .ok_or_else(|| Error::InvalidArgs(format!("no plan at index {index}, {} available", plans.len())))?;
+    let report = plan.report();
+    if serde_json::to_value(&report).ok() != Some(confirmed) {
+        warn!(
+            sender,
+            index, "Refusing to apply a plan that changed since it was confirmed"
+        );
+        return Err(Error::PlanChanged(format!(
+            "plan {index} changed since it was confirmed"
+        )));
+    }
+
+    let devices = report
+        .devices
+        .iter()
+        .map(|d| d.device.display().to_string())
+        .collect::<Vec<_>>();
+    info!(sender, strategy = report.strategy, ?devices, "Applying plan");
+    let result = plan.apply();
+    if result.is_success() {
+        info!(sender, strategy = report.strategy, "Plan applied");
+        return Ok(());
+    }
+
+    match result.into_failures().into_iter().next() {
+        Some(failure) => {
+            warn!(sender, "Applying plan failed: {}", failure);
+            Err(failure.into())
+        }
+        None => Err(Error::Failed("partition-table: plan was not applied".to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_requests() {
+        assert!(matches!(
+            apply(Path::new("/nonexistent"), 0, "{", "test"),
+            Err(Error::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            plan(Path::new("/nonexistent")),
+            Err(Error::Failed(message)) if message.starts_with("parse: loading strategies failed")
+        ));
+    }
+
+    #[test]
+    fn test_applying() {
+        let flag = Arc::default();
+        let applying = Applying::start(&flag).unwrap();
+        assert!(Applying::start(&flag).is_none());
+        drop(applying);
+
+        // A panic while applying releases the flag too
+        let result = std::panic::catch_unwind(|| {
+            let _applying = Applying::start(&flag).unwrap();
+            panic!("apply failed");
+        });
+        assert!(result.is_err());
+        assert!(Applying::start(&flag).is_some());
+    }
+}
@@ -0,0 +1,290 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Block device benchmarks.
+//!
+//! A short benchmark tells installers whether the target media is fit for use before
+//! provisioning, e.g. to warn about a dying SD card. [`run()`] issues reads or writes of
+//! a fixed block size with `O_DIRECT`, bypassing the page cache, from as many threads as
+//! the requested queue depth, and records the latency of every request.
+//!
+//! Write benchmarks overwrite the device contents and must only be run on disks that are
+//! about to be wiped anyway.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Seek, SeekFrom},
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use nix::fcntl::OFlag;
+
+/// Alignment of buffers and offsets required by `O_DIRECT`
+const ALIGNMENT: usize = 4096;
+
+/// Order in which blocks are accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Consecutive blocks from the start of the device
+    Sequential,
+    /// Blocks at random aligned offsets
+    Random,
+}
+
+/// Kind of requests issued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Leaves the device contents alone
+    Read,
+    /// Destroys the device contents
+    Write,
+}
+
+/// Parameters of a benchmark run.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub pattern: Pattern,
+    pub operation: Operation,
+    /// Size of each request in bytes, a multiple of 4096
+    pub block_size: usize,
+    /// Number of requests in flight at once
+    pub queue_depth: usize,
+    /// How long to keep issuing requests
+    pub duration: Duration,
+    /// Only access the first bytes of the device, the whole device if unset
+    pub span: Option<u64>,
+}
+
+impl Default for Options {
+    /// Random 4KiB reads one at a time for two seconds, the weak spot of cheap flash.
+    fn default() -> Self {
+        Self {
+            pattern: Pattern::Random,
+            operation: Operation::Read,
+            block_size: 4096,
+            queue_depth: 1,
+            duration: Duration::from_secs(2),
+            span: None,
+        }
+    }
+}
+
+/// Outcome of a benchmark run.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Completed requests
+    pub operations: u64,
+    /// Bytes transferred
+    pub bytes: u64,
+    /// Wall clock time of the run
+    pub elapsed: Duration,
+    /// Latency of the median request
+    pub median_latency: Duration,
+    /// Latency below which 99% of the requests completed
+    pub p99_latency: Duration,
+    /// Latency of the slowest request
+    pub max_latency: Duration,
+    /// Whether the page cache was bypassed, some filesystems refuse `O_DIRECT`
+    pub direct: bool,
+}
+
+impl Report {
+    /// Throughput in bytes per second
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Requests completed per second
+    pub fn iops(&self) -> f64 {
+        self.operations as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Benchmarks the device or file at `path`.
+///
+/// # Arguments
+///
+/// * `path` - Path to the block device (e.g. /dev/sda) or a file
+/// * `options` - What to measure
+///
+/// # Returns
+///
+/// The measurements, or an IO error if the device can't be opened or a request fails.
+pub fn run(path: &Path, options: &Options) -> io::Result<Report> {
+    let block_size = options.block_size as u64;
+    if options.block_size == 0 || !options.block_size.is_multiple_of(ALIGNMENT) || options.queue_depth == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "block size must be a multiple of 4096 and queue depth at least 1",
+        ));
+    }
+
+    let (mut file, direct) = open(path, options.operation)?;
+    let size = file.seek(SeekFrom::End(0))?;
+    let blocks = options.span.unwrap_or(size).min(size) / block_size;
+    if blocks == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "device is smaller than a single block",
+        ));
+    }
+    tracing::debug!(?options, blocks, direct, "Benchmarking {:?}", path);
+
+    let cursor = AtomicU64::new(0);
+    let started = Instant::now();
+    let deadline = started + options.duration;
+    let results = thread::scope(|scope| {
+        let workers = (0..options.queue_depth)
+            .map(|worker| {
+                let (file, cursor) = (&file, &cursor);
+                scope.spawn(move || {
+                    let mut random = Xorshift::new(worker as u64);
+                    let next = || match options.pattern {
+                        Pattern::Sequential => cursor.fetch_add(1, Ordering::Relaxed) % blocks,
+                        Pattern::Random => random.next() % blocks,
+                    };
+                    worker_loop(file, options, deadline, next)
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|w| {
+                w.join()
+                    .unwrap_or_else(|_| Err(io::Error::other("benchmark thread panicked")))
+            })
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    let elapsed = started.elapsed();
+
+    let mut latencies = results.into_iter().flatten().collect::<Vec<_>>();
+    latencies.sort();
+    let percentile = |p: usize| {
+        latencies
+            .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or_default()
+    };
+    let report = Report {
+        operations: latencies.len() as u64,
+        bytes: latencies.len() as u64 * block_size,
+        elapsed,
+        median_latency: percentile(50),
+        p99_latency: percentile(99),
+        max_latency: latencies.last().copied().unwrap_or_default(),
+        direct,
+    };
+    tracing::info!(
+        "{:?}: {:.0} IOPS, {:.1} MiB/s, p99 latency {:?}",
+        path,
+        report.iops(),
+        report.throughput() / 1_048_576.0,
+        report.p99_latency
+    );
+    Ok(report)
+}
+
+/// Opens `path` with `O_DIRECT`, falling back to buffered I/O where it is refused.
+fn open(path: &Path, operation: Operation) -> io::Result<(File, bool)> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(operation == Operation::Write);
+    match options.clone().custom_flags(OFlag::O_DIRECT.bits()).open(path) {
+        Ok(file) => Ok((file, true)),
+        Err(e) if e.raw_os_error() == Some(nix::libc::EINVAL) => {
+            tracing::debug!("O_DIRECT refused for {:?}, using buffered I/O", path);
+            Ok((options.open(path)?, false))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Issues requests for the blocks returned by `next` until `deadline`, returning their latencies.
+fn worker_loop(
+    file: &File,
+    options: &Options,
+    deadline: Instant,
+    mut next: impl FnMut() -> u64,
+) -> io::Result<Vec<Duration>> {
+    // O_DIRECT needs an aligned buffer, so over-allocate and pick an aligned window
+    let mut storage = vec![0xa5u8; options.block_size + ALIGNMENT];
+    let shift = storage.as_ptr().align_offset(ALIGNMENT);
+    let buffer = &mut storage[shift..shift + options.block_size];
+
+    let mut latencies = vec![];
+    while Instant::now() < deadline {
+        let offset = next() * options.block_size as u64;
+        let started = Instant::now();
+        match options.operation {
+            Operation::Read => file.read_exact_at(buffer, offset)?,
+            Operation::Write => file.write_all_at(buffer, offset)?,
+        }
+        latencies.push(started.elapsed());
+    }
+    if options.operation == Operation::Write {
+        file.sync_data()?;
+    }
+    Ok(latencies)
+}
+
+/// Cheap pseudo random numbers for picking offsets.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn new(worker: u64) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        // The state must never be zero
+        Self((seed ^ worker.wrapping_mul(0x9e37_79b9_7f4a_7c15)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_benchmark_file() {
+        let path = std::env::temp_dir().join(format!("disks-bench-{}", std::process::id()));
+        File::create(&path).unwrap().set_len(4 * 1024 * 1024).unwrap();
+
+        let write = Options {
+            pattern: Pattern::Sequential,
+            operation: Operation::Write,
+            block_size: 64 * 1024,
+            queue_depth: 2,
+            duration: Duration::from_millis(50),
+            span: None,
+        };
+        let report = run(&path, &write).unwrap();
+        assert!(report.operations > 0);
+        assert_eq!(report.bytes, report.operations * 64 * 1024);
+        assert!(report.median_latency <= report.p99_latency && report.p99_latency <= report.max_latency);
+
+        let read = Options {
+            duration: Duration::from_millis(50),
+            span: Some(1024 * 1024),
+            ..Default::default()
+        };
+        assert!(run(&path, &read).unwrap().iops() > 0.0);
+
+        let invalid = Options {
+            block_size: 1000,
+            ..read
+        };
+        assert_eq!(run(&path, &invalid).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -9,8 +9,16 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use superblock::{Kind, Superblock};
+
 use crate::SYSFS_DIR;
-use crate::{mmc, mock, nvme, partition::Partition, scsi, sysfs, virt};
+use crate::{
+    dm,
+    flags::{self, PartitionFlags},
+    mbr, md, mmc, mock, nvme,
+    partition::Partition,
+    probe_cache, scsi, sysfs, virt,
+};
 
 /// Represents the type of disk device.
 #[derive(Debug)]
@@ -23,6 +31,10 @@ pub enum Disk {
     Nvme(nvme::Disk),
     /// Virtual disk device
     Virtual(virt::Disk),
+    /// Software RAID array (e.g. md0)
+    Md(md::Disk),
+    /// Device mapper device (e.g. dm-0)
+    DeviceMapper(dm::Disk),
     /// Mock disk for testing
     Mock(mock::MockDisk),
 }
@@ -37,6 +49,8 @@ impl Deref for Disk {
             Disk::Nvme(disk) => disk,
             Disk::Scsi(disk) => disk,
             Disk::Virtual(disk) => disk,
+            Disk::Md(disk) => disk,
+            Disk::DeviceMapper(disk) => disk,
             Disk::Mock(disk) => disk,
         }
     }
@@ -56,8 +70,16 @@ pub struct BasicDisk {
     pub(crate) model: Option<String>,
     /// Optional disk vendor name
     pub(crate) vendor: Option<String>,
+    /// Optional World Wide Name, unique to the device
+    pub(crate) wwn: Option<String>,
+    /// Optional serial number
+    pub(crate) serial: Option<String>,
     /// Partitions
     pub(crate) partitions: Vec<Partition>,
+    /// Whether the device reports removable media
+    pub(crate) removable: bool,
+    /// Filesystem on the whole device, for disks without a partition table
+    pub(crate) filesystem: Option<Kind>,
 }
 
 impl fmt::Display for Disk {
@@ -126,6 +148,28 @@ impl BasicDisk {
     pub fn vendor(&self) -> Option<&str> {
         self.vendor.as_deref()
     }
+
+    /// Returns the World Wide Name of the disk.
+    pub fn wwn(&self) -> Option<&str> {
+        self.wwn.as_deref()
+    }
+
+    /// Returns the serial number of the disk.
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Returns true if the disk reports removable media.
+    pub fn is_removable(&self) -> bool {
+        self.removable
+    }
+
+    /// Returns the filesystem written directly to the disk, if it has no partition table.
+    ///
+    /// Such "superfloppy" layouts are common on USB sticks and SD cards.
+    pub fn filesystem(&self) -> Option<&Kind> {
+        self.filesystem.as_ref()
+    }
 }
 
 /// Trait for initializing different types of disk devices from sysfs.
@@ -147,7 +191,7 @@ impl DiskInit for BasicDisk {
     fn from_sysfs_path(sysroot: &Path, name: &str) -> Option<Self> {
         let node = sysroot.join(SYSFS_DIR).join(name);
 
-        log::debug!("Initializing disk at sysfs path: {:?}", node);
+        tracing::debug!("Initializing disk at sysfs path: {:?}", node);
 
         // Read the partitions of the disk if any
         let mut partitions: Vec<_> = fs::read_dir(&node)
@@ -160,17 +204,70 @@ impl DiskInit for BasicDisk {
             .collect();
         partitions.sort_by_key(|p| p.number);
 
-        let sectors = sysfs::read(&node, "size").unwrap_or(0);
-        log::debug!("Read {} sectors for disk {}", sectors, name);
-
         let device = PathBuf::from("/dev").join(name);
-        log::debug!("Device path: {:?}", device);
+        tracing::debug!("Device path: {:?}", device);
+
+        // Partition types and flags live only in the table: the active flag of msdos disks,
+        // the attribute bits of GPT disks behind their protective MBR
+        if !partitions.is_empty() {
+            match probe_cache::open(&device).and_then(|mut f| mbr::read(&mut f)) {
+                Ok(Some(table)) if !table.is_protective() => {
+                    for partition in &mut partitions {
+                        partition.mbr = table.entry(partition.number).copied();
+                        if partition.mbr.is_some_and(|e| e.bootable) {
+                            partition.flags.insert(PartitionFlags::BOOTABLE);
+                        }
+                    }
+                }
+                Ok(Some(_)) => match probe_cache::open(&device).and_then(|mut f| {
+                    let sector_size = sysfs::read(&node, "queue/logical_block_size").unwrap_or(512);
+                    flags::read_gpt_entries(&mut f, sector_size)
+                }) {
+                    Ok(Some(entries)) => {
+                        for partition in &mut partitions {
+                            if let Some(entry) = entries.iter().find(|e| e.number == partition.number) {
+                                partition.flags = PartitionFlags::from_gpt_attributes(entry.attributes);
+                                partition.gpt_name = Some(entry.name.clone()).filter(|n| !n.is_empty());
+                            }
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::debug!("Cannot read GPT entries of {:?}: {}", device, e),
+                },
+                Ok(None) => {}
+                Err(e) => tracing::debug!("Cannot read MBR of {:?}: {}", device, e),
+            }
+        }
+
+        // Without partitions the filesystem may sit directly on the disk
+        let filesystem = if partitions.is_empty() {
+            probe_cache::open(&device)
+                .ok()
+                .and_then(|mut f| Superblock::from_reader(&mut f).ok())
+                .map(|s| s.kind())
+        } else {
+            None
+        };
+        tracing::debug!("Whole disk filesystem: {:?}", filesystem);
+
+        let sectors = sysfs::read(&node, "size").unwrap_or(0);
+        tracing::debug!("Read {} sectors for disk {}", sectors, name);
 
         let model = sysfs::read(&node, "device/model");
-        log::debug!("Model: {:?}", model);
+        tracing::debug!("Model: {:?}", model);
 
         let vendor = sysfs::read(&node, "device/vendor");
-        log::debug!("Vendor: {:?}", vendor);
+        tracing::debug!("Vendor: {:?}", vendor);
+
+        // NVMe namespaces expose the WWN directly, SCSI disks on the device
+        let wwn = sysfs::read(&node, "wwid").or_else(|| sysfs::read(&node, "device/wwid"));
+        tracing::debug!("WWN: {:?}", wwn);
+
+        let serial = sysfs::read(&node, "device/serial");
+        tracing::debug!("Serial: {:?}", serial);
+
+        let removable = sysfs::read::<u8>(&node, "removable").is_some_and(|r| r == 1);
+        tracing::debug!("Removable: {}", removable);
 
         Some(Self {
             name: name.to_owned(),
@@ -178,7 +275,11 @@ impl DiskInit for BasicDisk {
             device,
             model,
             vendor,
+            wwn,
+            serial,
             partitions,
+            removable,
+            filesystem,
         })
     }
 }
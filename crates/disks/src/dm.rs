@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Device mapper device enumeration and handling.
+//!
+//! Mapped devices, such as opened LUKS containers and LVM volumes, are exposed through the
+//! block subsystem as `/dev/dm-*` block devices. Their mapping name and UUID are found in
+//! the `dm` directory of their sysfs node.
+
+use std::{ops::Deref, path::Path};
+
+use crate::{sysfs, BasicDisk, DiskInit, SYSFS_DIR};
+
+/// Represents a device mapper device.
+///
+/// This struct wraps a BasicDisk to provide device mapper specific functionality.
+#[derive(Debug)]
+pub struct Disk {
+    disk: BasicDisk,
+    /// Name of the mapping, as found in `/dev/mapper`
+    mapping: Option<String>,
+    /// UUID of the mapping, prefixed by its owner (e.g. `CRYPT-LUKS2-...`)
+    uuid: Option<String>,
+}
+
+impl Deref for Disk {
+    type Target = BasicDisk;
+
+    fn deref(&self) -> &Self::Target {
+        &self.disk
+    }
+}
+
+impl Disk {
+    /// Returns the name of the mapping, e.g. `cryptroot` for `/dev/mapper/cryptroot`.
+    pub fn mapping(&self) -> Option<&str> {
+        self.mapping.as_deref()
+    }
+
+    /// Returns the UUID of the mapping, if one was set when it was created.
+    pub fn uuid(&self) -> Option<&str> {
+        self.uuid.as_deref()
+    }
+}
+
+impl DiskInit for Disk {
+    /// Creates a new Disk instance from a sysfs path if the device name matches the device mapper naming pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `sysroot` - The root path of the sysfs filesystem
+    /// * `name` - The device name to check (e.g. "dm-0", "dm-12")
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Disk)` if the name matches the device mapper pattern ("dm-" followed by digits)
+    /// * `None` if the name doesn't match or the device can't be initialized
+    fn from_sysfs_path(sysroot: &Path, name: &str) -> Option<Self> {
+        let number = name.strip_prefix("dm-")?;
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let node = sysroot.join(SYSFS_DIR).join(name);
+        Some(Self {
+            disk: BasicDisk::from_sysfs_path(sysroot, name)?,
+            mapping: sysfs::read(&node, "dm/name"),
+            uuid: sysfs::read::<String>(&node, "dm/uuid").filter(|u| !u.is_empty()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_dm_sysfs() {
+        let sysroot = std::env::temp_dir().join(format!("disks-dm-{}", std::process::id()));
+        let node = sysroot.join(SYSFS_DIR).join("dm-3");
+        fs::create_dir_all(node.join("dm")).unwrap();
+        fs::write(node.join("size"), "2048\n").unwrap();
+        fs::write(node.join("dm/name"), "cryptdata\n").unwrap();
+        fs::write(node.join("dm/uuid"), "\n").unwrap();
+
+        let disk = Disk::from_sysfs_path(&sysroot, "dm-3").unwrap();
+        assert_eq!(disk.mapping(), Some("cryptdata"));
+        assert_eq!(disk.uuid(), None);
+        assert_eq!(disk.sectors(), 2048);
+        assert!(Disk::from_sysfs_path(&sysroot, "dm-").is_none());
+        assert!(Disk::from_sysfs_path(&sysroot, "md3").is_none());
+        fs::remove_dir_all(&sysroot).unwrap();
+    }
+}
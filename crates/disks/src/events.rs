@@ -0,0 +1,436 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Partition change events
+//!
+//! A [`BlockDevice`] is a snapshot of the disk as discovered. When another tool (`fdisk`,
+//! `parted`, a desktop disk utility) changes the partition table, the kernel announces the
+//! new partitions with uevents but our copy goes stale. [`BlockDevice::refresh()`] rereads a
+//! device and reports how its partitions changed, and a [`Watcher`] does so whenever the
+//! kernel announces a change, so long-running daemons stay consistent.
+//!
+//! Partitions are matched by number. A partition that kept its number but moved to another
+//! start sector is reported as removed and added again.
+
+use std::{
+    collections::HashMap,
+    fmt, io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use nix::libc;
+
+use crate::{partition::Partition, BlockDevice, Disk};
+
+/// Kernel multicast group of `NETLINK_KOBJECT_UEVENT`, udev listens on group 2
+const KERNEL_GROUP: u32 = 1;
+
+/// Largest uevent the kernel sends
+const UEVENT_BUFFER_SIZE: usize = 8192;
+
+/// A change to the partitions of a disk
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A partition appeared
+    PartitionAdded { disk: String, partition: Partition },
+    /// A partition disappeared, with its last known state
+    PartitionRemoved { disk: String, partition: Partition },
+    /// A partition kept its start but changed its size, given in sectors
+    PartitionResized {
+        disk: String,
+        partition: Partition,
+        old_size: u64,
+    },
+}
+
+impl Event {
+    /// Name of the disk holding the partition
+    pub fn disk(&self) -> &str {
+        match self {
+            Event::PartitionAdded { disk, .. }
+            | Event::PartitionRemoved { disk, .. }
+            | Event::PartitionResized { disk, .. } => disk,
+        }
+    }
+
+    /// The partition, as it is now or as it was when removed
+    pub fn partition(&self) -> &Partition {
+        match self {
+            Event::PartitionAdded { partition, .. }
+            | Event::PartitionRemoved { partition, .. }
+            | Event::PartitionResized { partition, .. } => partition,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::PartitionAdded { disk, partition } => write!(f, "{} added to {disk}", partition.name),
+            Event::PartitionRemoved { disk, partition } => write!(f, "{} removed from {disk}", partition.name),
+            Event::PartitionResized {
+                disk,
+                partition,
+                old_size,
+            } => write!(
+                f,
+                "{} on {disk} resized from {old_size} to {} sectors",
+                partition.name, partition.size
+            ),
+        }
+    }
+}
+
+/// Compares the partitions of `disk` before and after a change
+pub fn diff(disk: &str, old: Vec<Partition>, new: &[Partition]) -> Vec<Event> {
+    let mut events = vec![];
+    let mut remaining = new.iter().collect::<Vec<_>>();
+
+    for partition in old {
+        let current = remaining
+            .iter()
+            .position(|p| p.number == partition.number && p.start == partition.start)
+            .map(|i| remaining.remove(i));
+        match current {
+            Some(current) if current.size != partition.size => events.push(Event::PartitionResized {
+                disk: disk.to_owned(),
+                partition: current.clone(),
+                old_size: partition.size,
+            }),
+            Some(_) => {}
+            None => events.push(Event::PartitionRemoved {
+                disk: disk.to_owned(),
+                partition,
+            }),
+        }
+    }
+
+    events.extend(remaining.into_iter().map(|partition| Event::PartitionAdded {
+        disk: disk.to_owned(),
+        partition: partition.clone(),
+    }));
+    events
+}
+
+impl BlockDevice {
+    /// Rereads the device from sysfs, returning how its partitions changed
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if the device is gone and
+    /// [`io::ErrorKind::Unsupported`] for mock devices, leaving the device untouched.
+    pub fn refresh(&mut self) -> io::Result<Vec<Event>> {
+        self.refresh_in_sysroot("/")
+    }
+
+    /// Like [`BlockDevice::refresh()`], reading the sysfs below `sysroot`
+    pub fn refresh_in_sysroot(&mut self, sysroot: impl AsRef<Path>) -> io::Result<Vec<Event>> {
+        if let BlockDevice::Disk(disk) = self {
+            if let Disk::Mock(_) = **disk {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "mock devices cannot be refreshed",
+                ));
+            }
+        }
+
+        let fresh = BlockDevice::from_sysfs_path(sysroot, self.name())?;
+        let old = mem::replace(self, fresh);
+        Ok(diff(self.name(), old.partitions().to_vec(), self.partitions()))
+    }
+}
+
+/// A kernel uevent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uevent {
+    /// What happened, e.g. `add`, `remove` or `change`
+    pub action: String,
+    /// Path of the device below `/sys`
+    pub devpath: String,
+    /// All properties sent with the event
+    pub properties: HashMap<String, String>,
+}
+
+impl Uevent {
+    /// Parses a uevent as sent by the kernel: `action@devpath` followed by `KEY=value`
+    /// properties, all nul terminated
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let mut fields = message
+            .split(|b| *b == 0)
+            .filter(|f| !f.is_empty())
+            .map(String::from_utf8_lossy);
+        let header = fields.next()?;
+        let (action, devpath) = header.split_once('@')?;
+        let properties = fields
+            .filter_map(|f| f.split_once('=').map(|(k, v)| (k.to_owned(), v.to_owned())))
+            .collect();
+        Some(Self {
+            action: action.to_owned(),
+            devpath: devpath.to_owned(),
+            properties,
+        })
+    }
+
+    /// Value of the property `key`
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    /// Name of the disk the event is about, for events of the `block` subsystem
+    ///
+    /// For partitions this is the parent disk, e.g. `sda` for `sda1`.
+    pub fn disk_name(&self) -> Option<&str> {
+        if self.property("SUBSYSTEM")? != "block" {
+            return None;
+        }
+        let mut components = self.devpath.rsplit('/');
+        let name = components.next()?;
+        match self.property("DEVTYPE")? {
+            "disk" => Some(name),
+            "partition" => components.next(),
+            _ => None,
+        }
+    }
+}
+
+/// A socket receiving uevents from the kernel
+#[derive(Debug)]
+pub struct Monitor {
+    socket: OwnedFd,
+}
+
+impl Monitor {
+    /// Subscribes to kernel uevents
+    pub fn open() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = KERNEL_GROUP;
+        let res = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { socket })
+    }
+
+    /// Waits for the next uevent, up to `timeout` if given
+    ///
+    /// Returns `Ok(None)` once the timeout expires.
+    pub fn next(&self, timeout: Option<Duration>) -> io::Result<Option<Uevent>> {
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        let mut buffer = vec![0u8; UEVENT_BUFFER_SIZE];
+        loop {
+            let mut poll = libc::pollfd {
+                fd: self.socket.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let res = unsafe { libc::poll(&mut poll, 1, timeout) };
+            match res {
+                0 => return Ok(None),
+                r if r < 0 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(e);
+                }
+                _ => {}
+            }
+
+            let len = unsafe { libc::recv(self.socket.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len(), 0) };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if let Some(uevent) = Uevent::parse(&buffer[..len as usize]) {
+                return Ok(Some(uevent));
+            }
+        }
+    }
+}
+
+/// Keeps a set of devices current as the kernel reports changes
+#[derive(Debug)]
+pub struct Watcher {
+    devices: Vec<BlockDevice>,
+    monitor: Monitor,
+    sysroot: PathBuf,
+}
+
+impl Watcher {
+    /// Watches `devices` for changes, typically the result of [`BlockDevice::discover()`]
+    pub fn new(devices: Vec<BlockDevice>) -> io::Result<Self> {
+        Ok(Self {
+            devices,
+            monitor: Monitor::open()?,
+            sysroot: PathBuf::from("/"),
+        })
+    }
+
+    /// The devices as of the last event
+    pub fn devices(&self) -> &[BlockDevice] {
+        &self.devices
+    }
+
+    /// Gives back the watched devices
+    pub fn into_devices(self) -> Vec<BlockDevice> {
+        self.devices
+    }
+
+    /// Waits for the next uevent, up to `timeout` if given, and refreshes the affected disk
+    ///
+    /// Returns the partition changes it caused, which are none for most uevents. Disks that
+    /// appear or disappear are added to or removed from the watched devices, with all their
+    /// partitions reported as added or removed.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<Vec<Event>> {
+        match self.monitor.next(timeout)? {
+            Some(uevent) => Ok(self.handle(&uevent)),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Refreshes the disk `uevent` is about
+    pub fn handle(&mut self, uevent: &Uevent) -> Vec<Event> {
+        let Some(name) = uevent.disk_name() else {
+            return vec![];
+        };
+        tracing::debug!("Handling {} uevent for {}", uevent.action, name);
+
+        match self.devices.iter().position(|d| d.name() == name) {
+            Some(index) => match self.devices[index].refresh_in_sysroot(&self.sysroot) {
+                Ok(events) => events,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    let device = self.devices.remove(index);
+                    diff(name, device.partitions().to_vec(), &[])
+                }
+                Err(e) => {
+                    tracing::debug!("Cannot refresh {}: {}", name, e);
+                    vec![]
+                }
+            },
+            None if uevent.action == "add" => match BlockDevice::from_sysfs_path(&self.sysroot, name) {
+                Ok(device) => {
+                    let events = diff(name, vec![], device.partitions());
+                    self.devices.push(device);
+                    events
+                }
+                Err(e) => {
+                    tracing::debug!("Cannot discover {}: {}", name, e);
+                    vec![]
+                }
+            },
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        flags::PartitionFlags,
+        snapshot::{DeviceSnapshot, PartitionSnapshot, Snapshot, Topology, VERSION},
+    };
+
+    #[test]
+    fn test_uevent() {
+        let message = b"add@/devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0/block/sda/sda2\0\
+            ACTION=add\0DEVPATH=/devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0/block/sda/sda2\0\
+            SUBSYSTEM=block\0DEVNAME=sda2\0DEVTYPE=partition\0PARTN=2\0SEQNUM=4242\0";
+        let uevent = Uevent::parse(message).unwrap();
+        assert_eq!(uevent.action, "add");
+        assert_eq!(uevent.property("PARTN"), Some("2"));
+        assert_eq!(uevent.disk_name(), Some("sda"));
+
+        let uevent = Uevent::parse(b"change@/devices/virtual/block/loop0\0SUBSYSTEM=block\0DEVTYPE=disk\0").unwrap();
+        assert_eq!(uevent.disk_name(), Some("loop0"));
+        assert!(Uevent::parse(b"libudev\0").is_none());
+    }
+
+    #[test]
+    fn test_refresh() {
+        let sysroot = std::env::temp_dir().join(format!("disks-rs-events-{}", std::process::id()));
+        let partition = |name: &str, number, start, size| PartitionSnapshot {
+            name: name.to_owned(),
+            number,
+            start,
+            size,
+            mbr: None,
+            flags: PartitionFlags::NONE,
+            gpt_name: None,
+            filesystem: None,
+            usage: None,
+        };
+        let mut snapshot = Snapshot {
+            version: VERSION,
+            devices: vec![DeviceSnapshot {
+                name: "sdz".to_owned(),
+                sectors: 1 << 24,
+                model: None,
+                vendor: None,
+                wwn: None,
+                serial: None,
+                removable: false,
+                backing_file: None,
+                topology: Topology::default(),
+                filesystem: None,
+                partitions: vec![
+                    partition("sdz1", 1, 2048, 1 << 20),
+                    partition("sdz2", 2, 2048 + (1 << 20), 1 << 20),
+                    partition("sdz3", 3, 2048 + (2 << 20), 1 << 20),
+                ],
+            }],
+            mounts: vec![],
+        };
+        snapshot.write_sysfs(&sysroot).unwrap();
+        let mut device = BlockDevice::from_sysfs_path(&sysroot, "sdz").unwrap();
+        assert!(device.refresh_in_sysroot(&sysroot).unwrap().is_empty());
+
+        // Grow the second partition, drop the third and add a fourth
+        let partitions = &mut snapshot.devices[0].partitions;
+        partitions[1].size = 2 << 20;
+        partitions.pop();
+        partitions.push(partition("sdz4", 4, 2048 + (4 << 20), 1 << 20));
+        fs::remove_dir_all(&sysroot).unwrap();
+        snapshot.write_sysfs(&sysroot).unwrap();
+
+        let events = device.refresh_in_sysroot(&sysroot).unwrap();
+        fs::remove_dir_all(&sysroot).unwrap();
+        assert_eq!(
+            events.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "sdz2 on sdz resized from 1048576 to 2097152 sectors",
+                "sdz3 removed from sdz",
+                "sdz4 added to sdz",
+            ]
+        );
+        assert_eq!(device.partitions().len(), 3);
+        assert_eq!(
+            device.refresh_in_sysroot(&sysroot).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let mut mock = BlockDevice::mock_device(crate::mock::MockDisk::new(1 << 30));
+        assert_eq!(mock.refresh().unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,306 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Partition flags
+//!
+//! GPT keeps flags in the 64 attribute bits of every partition entry, MBR only knows the
+//! active (boot) flag. [`PartitionFlags`] models the flags both have in common with the
+//! attributes of the [Discoverable Partitions Specification], and converts to and from GPT
+//! attribute bits. Attribute bits without a flag are left alone by the conversions.
+//!
+//! Flags are read from existing tables during discovery, see
+//! [`Partition::flags`](crate::partition::Partition::flags).
+//!
+//! [Discoverable Partitions Specification]: https://uapi-group.org/specifications/specs/discoverable_partitions_specification/
+
+use std::{
+    fmt,
+    io::{self, Read, Seek, SeekFrom},
+    ops::{BitOr, BitOrAssign},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::partition::decode_gpt_name;
+
+/// Signature at the start of a GPT header
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// Refuse entry arrays larger than this when reading attributes
+const MAX_ENTRIES_SIZE: u64 = 16 * 1024 * 1024;
+
+/// A set of partition flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "Vec<String>", try_from = "Vec<String>")]
+pub struct PartitionFlags(u8);
+
+impl PartitionFlags {
+    /// No flags set
+    pub const NONE: Self = Self(0);
+    /// Firmware may boot from the partition: the MBR active flag or GPT bit 2
+    pub const BOOTABLE: Self = Self(1 << 0);
+    /// The platform needs the partition to function (GPT bit 0)
+    pub const REQUIRED: Self = Self(1 << 1);
+    /// Grow the filesystem to the partition size on first mount (DPS bit 59)
+    pub const GROWFS: Self = Self(1 << 2);
+    /// Mount the partition read-only (DPS bit 60)
+    pub const READ_ONLY: Self = Self(1 << 3);
+    /// Hide the partition from automatic discovery (bit 62)
+    pub const HIDDEN: Self = Self(1 << 4);
+    /// Do not mount the partition automatically (DPS bit 63)
+    pub const NO_AUTOMOUNT: Self = Self(1 << 5);
+
+    /// Every flag with its KDL name and GPT attribute bit
+    const ALL: [(Self, &'static str, u64); 6] = [
+        (Self::BOOTABLE, "bootable", 1 << 2),
+        (Self::REQUIRED, "required", 1 << 0),
+        (Self::GROWFS, "growfs", 1 << 59),
+        (Self::READ_ONLY, "read-only", 1 << 60),
+        (Self::HIDDEN, "hidden", 1 << 62),
+        (Self::NO_AUTOMOUNT, "no-automount", 1 << 63),
+    ];
+
+    /// GPT attribute bits covered by flags
+    pub const GPT_MASK: u64 = 1 << 0 | 1 << 2 | 1 << 59 | 1 << 60 | 1 << 62 | 1 << 63;
+
+    /// Returns true if no flag is set
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every flag of `other` is set
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Set the flags of `other`
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Clear the flags of `other`
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// Flags set by the GPT attribute bits `attributes`
+    pub fn from_gpt_attributes(attributes: u64) -> Self {
+        Self::ALL
+            .iter()
+            .filter(|(_, _, bit)| attributes & bit != 0)
+            .fold(Self::NONE, |flags, (flag, _, _)| flags | *flag)
+    }
+
+    /// GPT attribute bits of the flags
+    pub fn gpt_attributes(&self) -> u64 {
+        Self::ALL
+            .iter()
+            .filter(|(flag, _, _)| self.contains(*flag))
+            .fold(0, |attributes, (_, _, bit)| attributes | bit)
+    }
+
+    /// Replace the flag bits of `attributes` with these flags, keeping all other bits
+    pub fn apply_to_gpt_attributes(&self, attributes: u64) -> u64 {
+        attributes & !Self::GPT_MASK | self.gpt_attributes()
+    }
+
+    /// Names of the set flags, e.g. `no-automount`
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Self::ALL
+            .iter()
+            .filter(|(flag, _, _)| self.contains(*flag))
+            .map(|(_, name, _)| *name)
+    }
+}
+
+impl BitOr for PartitionFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PartitionFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Display for PartitionFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.names().collect::<Vec<_>>().join(", "))
+    }
+}
+
+/// A flag name was not recognised
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownFlag(pub String);
+
+impl fmt::Display for UnknownFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown partition flag: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownFlag {}
+
+impl FromStr for PartitionFlags {
+    type Err = UnknownFlag;
+
+    /// Parse a single flag name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|(_, name, _)| *name == s)
+            .map(|(flag, _, _)| *flag)
+            .ok_or_else(|| UnknownFlag(s.to_owned()))
+    }
+}
+
+impl From<PartitionFlags> for Vec<String> {
+    fn from(flags: PartitionFlags) -> Self {
+        flags.names().map(str::to_owned).collect()
+    }
+}
+
+impl TryFrom<Vec<String>> for PartitionFlags {
+    type Error = UnknownFlag;
+
+    fn try_from(names: Vec<String>) -> Result<Self, Self::Error> {
+        names
+            .iter()
+            .try_fold(Self::NONE, |flags, name| Ok(flags | name.parse()?))
+    }
+}
+
+/// A used entry of a GPT, as read by [`read_gpt_entries()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GptEntry {
+    /// Partition number, the slot of the entry counting from 1
+    pub number: u32,
+    /// Attribute bits
+    pub attributes: u64,
+    /// Partition name, empty if unset
+    pub name: String,
+}
+
+/// Read the attribute bits of every used entry of the primary GPT of a device
+///
+/// See [`read_gpt_entries()`].
+pub fn read_gpt_attributes<R: Read + Seek>(reader: &mut R, sector_size: u64) -> io::Result<Option<Vec<(u32, u64)>>> {
+    Ok(read_gpt_entries(reader, sector_size)?
+        .map(|entries| entries.into_iter().map(|e| (e.number, e.attributes)).collect()))
+}
+
+/// Read every used entry of the primary GPT of a device
+///
+/// `sector_size` is the logical block size of the device, which LBAs in the header count
+/// in. Returns `None` if the device has no GPT header at LBA 1. Checksums are not
+/// verified, this is only meant for discovery, which reports what the kernel already
+/// accepted.
+pub fn read_gpt_entries<R: Read + Seek>(reader: &mut R, sector_size: u64) -> io::Result<Option<Vec<GptEntry>>> {
+    let mut header = [0u8; 92];
+    reader.seek(SeekFrom::Start(sector_size))?;
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    if &header[..8] != GPT_SIGNATURE {
+        return Ok(None);
+    }
+
+    let entries_lba = u64::from_le_bytes(header[72..80].try_into().unwrap());
+    let count = u32::from_le_bytes(header[80..84].try_into().unwrap()) as u64;
+    let entry_size = u32::from_le_bytes(header[84..88].try_into().unwrap()) as u64;
+    if entry_size < 128 || count * entry_size > MAX_ENTRIES_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid GPT entry array"));
+    }
+
+    let offset = entries_lba
+        .checked_mul(sector_size)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "GPT entry array out of range"))?;
+    let mut entries = vec![0u8; (count * entry_size) as usize];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut entries)?;
+
+    Ok(Some(
+        entries
+            .chunks_exact(entry_size as usize)
+            .enumerate()
+            // Unused entries have a nil type GUID
+            .filter(|(_, raw)| raw[..16].iter().any(|b| *b != 0))
+            .map(|(slot, raw)| GptEntry {
+                number: slot as u32 + 1,
+                attributes: u64::from_le_bytes(raw[48..56].try_into().unwrap()),
+                name: decode_gpt_name(&raw[56..128]),
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_gpt_attributes() {
+        let flags = PartitionFlags::BOOTABLE | PartitionFlags::NO_AUTOMOUNT;
+        assert_eq!(flags.gpt_attributes(), 1 << 2 | 1 << 63);
+        assert_eq!(PartitionFlags::from_gpt_attributes(1 << 2 | 1 << 63 | 1 << 56), flags);
+        // Bits without a flag survive
+        assert_eq!(
+            PartitionFlags::READ_ONLY.apply_to_gpt_attributes(1 << 56 | 1 << 63),
+            1 << 56 | 1 << 60
+        );
+
+        assert_eq!(flags.to_string(), "bootable, no-automount");
+        assert_eq!("hidden".parse(), Ok(PartitionFlags::HIDDEN));
+        assert_eq!("noauto".parse::<PartitionFlags>(), Err(UnknownFlag("noauto".into())));
+        assert_eq!(serde_json::to_string(&flags).unwrap(), r#"["bootable","no-automount"]"#);
+        assert_eq!(
+            serde_json::from_str::<PartitionFlags>(r#"["read-only"]"#).unwrap(),
+            PartitionFlags::READ_ONLY
+        );
+    }
+
+    #[test]
+    fn test_read_gpt_attributes() {
+        for sector_size in [512, 4096] {
+            let mut image = vec![0u8; 34 * sector_size];
+            let read = |image: &[u8]| read_gpt_attributes(&mut Cursor::new(image), sector_size as u64).unwrap();
+            assert_eq!(read(&image), None);
+
+            let header = sector_size;
+            image[header..header + 8].copy_from_slice(GPT_SIGNATURE);
+            image[header + 72..header + 80].copy_from_slice(&2u64.to_le_bytes());
+            image[header + 80..header + 84].copy_from_slice(&128u32.to_le_bytes());
+            image[header + 84..header + 88].copy_from_slice(&128u32.to_le_bytes());
+            // Second slot in use, the first one empty
+            let entry = 2 * sector_size + 128;
+            image[entry] = 0xaf;
+            image[entry + 48..entry + 56].copy_from_slice(&(1u64 << 63).to_le_bytes());
+            image[entry + 56..entry + 128].copy_from_slice(&crate::partition::encode_gpt_name("données"));
+
+            assert_eq!(read(&image), Some(vec![(2, 1 << 63)]));
+            assert_eq!(
+                read_gpt_entries(&mut Cursor::new(&image), sector_size as u64).unwrap(),
+                Some(vec![GptEntry {
+                    number: 2,
+                    attributes: 1 << 63,
+                    name: "données".to_owned(),
+                }])
+            );
+
+            // An entry array beyond the addressable range is rejected, not wrapped around
+            image[header + 72..header + 80].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+            let err = read_gpt_entries(&mut Cursor::new(&image), sector_size as u64).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
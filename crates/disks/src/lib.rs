@@ -10,12 +10,23 @@ use std::{
 
 pub use disk::*;
 use partition::Partition;
+pub mod bench;
+pub mod dm;
+pub mod events;
+pub mod flags;
 pub mod loopback;
+pub mod mbr;
+pub mod md;
 pub mod mmc;
 pub mod mock;
+pub mod mount;
+pub mod naming;
 pub mod nvme;
 pub mod partition;
+pub mod probe_cache;
 pub mod scsi;
+pub mod snapshot;
+pub use snapshot::snapshot;
 mod sysfs;
 pub mod virt;
 
@@ -66,6 +77,11 @@ impl BlockDevice {
         BlockDevice::Disk(Box::new(Disk::Mock(disk)))
     }
 
+    /// Creates a block device for a software RAID array.
+    pub fn md_device(disk: md::Disk) -> Self {
+        BlockDevice::Disk(Box::new(Disk::Md(disk)))
+    }
+
     /// Creates a loopback block device from a file path.
     pub fn loopback_device(device: loopback::Device) -> Self {
         BlockDevice::Loopback(Box::new(device))
@@ -92,6 +108,10 @@ impl BlockDevice {
             return Ok(BlockDevice::Disk(Box::new(Disk::Mmc(disk))));
         } else if let Some(device) = virt::Disk::from_sysfs_path(sysfs_dir, name) {
             return Ok(BlockDevice::Disk(Box::new(Disk::Virtual(device))));
+        } else if let Some(device) = md::Disk::from_sysfs_path(sysfs_dir, name) {
+            return Ok(BlockDevice::Disk(Box::new(Disk::Md(device))));
+        } else if let Some(device) = dm::Disk::from_sysfs_path(sysfs_dir, name) {
+            return Ok(BlockDevice::Disk(Box::new(Disk::DeviceMapper(device))));
         } else if let Some(device) = loopback::Device::from_sysfs_path(sysfs_dir, name) {
             return Ok(BlockDevice::Loopback(Box::new(device)));
         }
@@ -107,6 +127,48 @@ impl BlockDevice {
         }
     }
 
+    /// Returns the model name of the block device, if known.
+    pub fn model(&self) -> Option<&str> {
+        match self {
+            BlockDevice::Disk(disk) => disk.model(),
+            BlockDevice::Loopback(device) => device.disk().and_then(|d| d.model()),
+        }
+    }
+
+    /// Returns the World Wide Name of the block device, if known.
+    pub fn wwn(&self) -> Option<&str> {
+        match self {
+            BlockDevice::Disk(disk) => disk.wwn(),
+            BlockDevice::Loopback(device) => device.disk().and_then(|d| d.wwn()),
+        }
+    }
+
+    /// Returns the serial number of the block device, if known.
+    pub fn serial(&self) -> Option<&str> {
+        match self {
+            BlockDevice::Disk(disk) => disk.serial(),
+            BlockDevice::Loopback(device) => device.disk().and_then(|d| d.serial()),
+        }
+    }
+
+    /// Returns true if the device reports removable media (e.g. USB sticks, SD cards).
+    pub fn is_removable(&self) -> bool {
+        match self {
+            BlockDevice::Disk(disk) => disk.is_removable(),
+            BlockDevice::Loopback(_) => false,
+        }
+    }
+
+    /// Returns the filesystem written directly to the device, without a partition table.
+    ///
+    /// Creating partitions on such a device destroys the filesystem.
+    pub fn filesystem(&self) -> Option<&superblock::Kind> {
+        match self {
+            BlockDevice::Disk(disk) => disk.filesystem(),
+            BlockDevice::Loopback(device) => device.disk().and_then(|d| d.filesystem()),
+        }
+    }
+
     /// Returns the path to the block device in /dev.
     pub fn device(&self) -> &Path {
         match self {
@@ -115,6 +177,13 @@ impl BlockDevice {
         }
     }
 
+    /// Measures the speed of the block device, see [`bench::run()`].
+    ///
+    /// Write benchmarks destroy the contents of the device.
+    pub fn benchmark(&self, options: &bench::Options) -> io::Result<bench::Report> {
+        bench::run(self.device(), options)
+    }
+
     /// Discovers block devices in a specified sysroot directory.
     ///
     /// # Arguments
@@ -128,6 +197,7 @@ impl BlockDevice {
         let sysroot = sysroot.as_ref();
         let sysfs_dir = PathBuf::from(sysroot).join(SYSFS_DIR);
         let mut devices = Vec::new();
+        let _cache = probe_cache::pass();
 
         // Iterate over all block devices in sysfs and collect their filenames
         let mut entries = fs::read_dir(&sysfs_dir)?
@@ -0,0 +1,246 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! MBR (msdos) partition table parsing
+//!
+//! Reads the four primary entries of the master boot record and follows the chain of
+//! extended boot records for logical partitions. Partitions are numbered like the
+//! kernel does: primary entries by their slot (1-4), logical partitions from 5 on.
+
+use std::{
+    collections::HashSet,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Size of a logical block in bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// Offset of the first partition entry within a boot record
+const ENTRIES_OFFSET: usize = 0x1be;
+
+/// Size of a single partition entry
+const ENTRY_SIZE: usize = 16;
+
+/// Offset of the disk signature within the MBR
+const DISK_SIGNATURE_OFFSET: usize = 0x1b8;
+
+/// Partition type of a GPT protective MBR entry
+pub const TYPE_PROTECTIVE: u8 = 0xee;
+
+/// Partition types of extended partitions (CHS, LBA and Linux)
+const EXTENDED_TYPES: [u8; 3] = [0x05, 0x0f, 0x85];
+
+/// Upper bound for the number of logical partitions, guards against EBR loops
+const MAX_LOGICAL: usize = 128;
+
+/// A partition entry of an MBR partition table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Partition number, as assigned by the kernel
+    pub number: u32,
+    /// Partition type byte
+    pub partition_type: u8,
+    /// Whether the active (boot) flag is set
+    pub bootable: bool,
+    /// First sector of the partition
+    pub start: u64,
+    /// Size of the partition in sectors
+    pub size: u64,
+}
+
+impl Entry {
+    /// Returns true if the entry is a logical partition within an extended partition
+    pub fn is_logical(&self) -> bool {
+        self.number > 4
+    }
+
+    /// Returns true if the entry is an extended partition holding logical partitions
+    pub fn is_extended(&self) -> bool {
+        EXTENDED_TYPES.contains(&self.partition_type)
+    }
+}
+
+/// An MBR partition table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    /// Disk signature (NT disk identifier)
+    pub signature: u32,
+    /// Primary, extended and logical partitions ordered by number
+    pub entries: Vec<Entry>,
+}
+
+impl Table {
+    /// Returns true if this is the protective MBR of a GPT disk
+    pub fn is_protective(&self) -> bool {
+        self.entries.iter().any(|e| e.partition_type == TYPE_PROTECTIVE)
+    }
+
+    /// Returns the entry for partition `number`, if any
+    pub fn entry(&self, number: u32) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.number == number)
+    }
+}
+
+/// Read the MBR partition table of a device
+///
+/// Returns `None` if the first sector carries no boot signature.
+pub fn read<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Table>> {
+    let Some(mbr) = read_record(reader, 0)? else {
+        return Ok(None);
+    };
+    let signature = u32::from_le_bytes(
+        mbr[DISK_SIGNATURE_OFFSET..DISK_SIGNATURE_OFFSET + 4]
+            .try_into()
+            .unwrap(),
+    );
+
+    let mut entries = Vec::new();
+    let mut extended = None;
+    for (slot, raw) in raw_entries(&mbr).enumerate() {
+        let Some(entry) = parse_entry(raw, slot as u32 + 1, 0) else {
+            continue;
+        };
+        if entry.is_extended() && extended.is_none() {
+            extended = Some(entry.start);
+        }
+        entries.push(entry);
+    }
+
+    if let Some(base) = extended {
+        entries.extend(read_logical(reader, base)?);
+    }
+    Ok(Some(Table { signature, entries }))
+}
+
+/// Follow the EBR chain of the extended partition starting at sector `base`
+fn read_logical<R: Read + Seek>(reader: &mut R, base: u64) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = base;
+
+    while entries.len() < MAX_LOGICAL && seen.insert(current) {
+        let Some(ebr) = read_record(reader, current)? else {
+            break;
+        };
+        let mut raw = raw_entries(&ebr);
+        // The first entry is relative to this EBR, the link to the next one to the extended partition
+        if let Some(entry) = raw
+            .next()
+            .and_then(|r| parse_entry(r, 5 + entries.len() as u32, current))
+        {
+            entries.push(entry);
+        }
+        match raw.next().and_then(|r| parse_entry(r, 0, base)) {
+            Some(next) if next.is_extended() => current = next.start,
+            _ => break,
+        }
+    }
+    Ok(entries)
+}
+
+/// Read the boot record at `sector`, `None` if it lacks the boot signature
+fn read_record<R: Read + Seek>(reader: &mut R, sector: u64) -> io::Result<Option<[u8; 512]>> {
+    let mut record = [0u8; 512];
+    reader.seek(SeekFrom::Start(sector * SECTOR_SIZE))?;
+    match reader.read_exact(&mut record) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    Ok((record[510..512] == [0x55, 0xaa]).then_some(record))
+}
+
+fn raw_entries(record: &[u8; 512]) -> impl Iterator<Item = &[u8]> {
+    record[ENTRIES_OFFSET..ENTRIES_OFFSET + 4 * ENTRY_SIZE].chunks_exact(ENTRY_SIZE)
+}
+
+/// Parse a raw entry whose start is relative to sector `offset`, `None` if unused
+fn parse_entry(raw: &[u8], number: u32, offset: u64) -> Option<Entry> {
+    let partition_type = raw[4];
+    let start = u32::from_le_bytes(raw[8..12].try_into().unwrap()) as u64;
+    let size = u32::from_le_bytes(raw[12..16].try_into().unwrap()) as u64;
+    if partition_type == 0 || size == 0 {
+        return None;
+    }
+    Some(Entry {
+        number,
+        partition_type,
+        bootable: raw[0] & 0x80 != 0,
+        start: offset + start,
+        size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn set_entry(image: &mut [u8], record: u64, slot: usize, bootable: bool, kind: u8, start: u32, size: u32) {
+        let offset = (record * SECTOR_SIZE) as usize + ENTRIES_OFFSET + slot * ENTRY_SIZE;
+        image[offset] = if bootable { 0x80 } else { 0 };
+        image[offset + 4] = kind;
+        image[offset + 8..offset + 12].copy_from_slice(&start.to_le_bytes());
+        image[offset + 12..offset + 16].copy_from_slice(&size.to_le_bytes());
+        let signature = (record * SECTOR_SIZE) as usize + 510;
+        image[signature..signature + 2].copy_from_slice(&[0x55, 0xaa]);
+    }
+
+    #[test]
+    fn test_primary_and_logical() {
+        let mut image = vec![0u8; 4096 * SECTOR_SIZE as usize];
+        image[DISK_SIGNATURE_OFFSET..DISK_SIGNATURE_OFFSET + 4].copy_from_slice(&0xdeadbeefu32.to_le_bytes());
+        set_entry(&mut image, 0, 0, true, 0x83, 2048, 1024);
+        set_entry(&mut image, 0, 1, false, 0x0f, 3072, 1024);
+        // First EBR, linking to a second one 512 sectors into the extended partition
+        set_entry(&mut image, 3072, 0, false, 0x82, 63, 256);
+        set_entry(&mut image, 3072, 1, false, 0x05, 512, 512);
+        set_entry(&mut image, 3584, 0, false, 0x07, 63, 256);
+
+        let table = read(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(table.signature, 0xdeadbeef);
+        assert!(!table.is_protective());
+
+        let numbers = table.entries.iter().map(|e| e.number).collect::<Vec<_>>();
+        assert_eq!(numbers, vec![1, 2, 5, 6]);
+        assert!(table.entries[0].bootable);
+        assert!(table.entries[1].is_extended());
+        assert_eq!(
+            table.entry(5),
+            Some(&Entry {
+                number: 5,
+                partition_type: 0x82,
+                bootable: false,
+                start: 3135,
+                size: 256,
+            })
+        );
+        assert!(table.entry(6).unwrap().is_logical());
+        assert_eq!(table.entry(6).unwrap().start, 3647);
+    }
+
+    #[test]
+    fn test_protective_and_blank() {
+        let mut image = vec![0u8; 4 * SECTOR_SIZE as usize];
+        assert!(read(&mut Cursor::new(image.clone())).unwrap().is_none());
+
+        set_entry(&mut image, 0, 0, false, TYPE_PROTECTIVE, 1, u32::MAX);
+        assert!(read(&mut Cursor::new(image)).unwrap().unwrap().is_protective());
+    }
+
+    #[test]
+    fn test_ebr_loop() {
+        let mut image = vec![0u8; 2048 * SECTOR_SIZE as usize];
+        set_entry(&mut image, 0, 0, false, 0x05, 1024, 1024);
+        set_entry(&mut image, 1024, 0, false, 0x83, 63, 128);
+        // Links back to itself
+        set_entry(&mut image, 1024, 1, false, 0x05, 0, 1024);
+
+        let table = read(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(table.entries.len(), 2);
+    }
+}
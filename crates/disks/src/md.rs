@@ -0,0 +1,72 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Software RAID (mdraid) device enumeration and handling.
+//!
+//! Assembled arrays are exposed through the block subsystem as `/dev/md*` block devices.
+//! Arrays that have yet to be created, e.g. by a provisioning plan, can be represented
+//! with [`Disk::planned()`] so they can be partitioned like any other disk.
+
+use std::{ops::Deref, path::Path};
+
+use crate::{BasicDisk, DiskInit};
+
+/// Represents a software RAID array.
+///
+/// This struct wraps a BasicDisk to provide mdraid-specific functionality.
+#[derive(Debug)]
+pub struct Disk(pub BasicDisk);
+
+impl Deref for Disk {
+    type Target = BasicDisk;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Disk {
+    /// Creates an array that does not exist yet
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The array name, used for the `/dev/md/<name>` device path
+    /// * `size_bytes` - The usable size of the array
+    pub fn planned(name: &str, size_bytes: u64) -> Self {
+        Self(BasicDisk {
+            name: name.to_owned(),
+            sectors: size_bytes / 512,
+            device: Path::new("/dev/md").join(name),
+            model: Some("Software RAID".to_owned()),
+            vendor: None,
+            wwn: None,
+            serial: None,
+            partitions: Vec::new(),
+            removable: false,
+            filesystem: None,
+        })
+    }
+}
+
+impl DiskInit for Disk {
+    /// Creates a new Disk instance from a sysfs path if the device name matches the mdraid naming pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `sysroot` - The root path of the sysfs filesystem
+    /// * `name` - The device name to check (e.g. "md0", "md127")
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Disk)` if the name matches the mdraid pattern (starts with "md" followed by digits)
+    /// * `None` if the name doesn't match or the device can't be initialized
+    fn from_sysfs_path(sysroot: &Path, name: &str) -> Option<Self> {
+        let matching = name.len() > 2 && name.starts_with("md") && name[2..].chars().all(|c| c.is_ascii_digit());
+        if matching {
+            Some(Self(BasicDisk::from_sysfs_path(sysroot, name)?))
+        } else {
+            None
+        }
+    }
+}
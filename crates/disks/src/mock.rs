@@ -9,7 +9,9 @@
 
 use std::{ops::Deref, path::PathBuf};
 
-use crate::{partition::Partition, BasicDisk};
+use superblock::Kind;
+
+use crate::{naming, partition::Partition, BasicDisk};
 
 /// Represents a mock disk device.
 ///
@@ -35,27 +37,84 @@ impl MockDisk {
             device: PathBuf::from("/dev/mock0"),
             model: Some("Mock Device".to_string()),
             vendor: Some("Mock Vendor".to_string()),
+            wwn: None,
+            serial: None,
             partitions: Vec::new(),
+            removable: false,
+            filesystem: None,
         };
         Self(disk)
     }
 
+    /// Rename the mock disk, e.g. to `nvme0n1`, which also moves its device node
+    ///
+    /// Partitions added afterwards are named after the new name.
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.0.name = name.into();
+        self.0.device = PathBuf::from("/dev").join(&self.0.name);
+    }
+
+    /// Point the mock disk at another device node, e.g. a disk image written by tests
+    pub fn set_device(&mut self, device: impl Into<PathBuf>) {
+        self.0.device = device.into();
+    }
+
+    /// Set the model of the mock disk
+    pub fn set_model(&mut self, model: Option<String>) {
+        self.0.model = model;
+    }
+
+    /// Set the vendor of the mock disk
+    pub fn set_vendor(&mut self, vendor: Option<String>) {
+        self.0.vendor = vendor;
+    }
+
+    /// Mark the mock disk as removable media
+    pub fn set_removable(&mut self, removable: bool) {
+        self.0.removable = removable;
+    }
+
+    /// Put a filesystem directly on the mock disk, as if it had no partition table
+    pub fn set_filesystem(&mut self, filesystem: Option<Kind>) {
+        self.0.filesystem = filesystem;
+    }
+
+    /// Set the World Wide Name of the mock disk
+    pub fn set_wwn(&mut self, wwn: impl Into<String>) {
+        self.0.wwn = Some(wwn.into());
+    }
+
+    /// Set the serial number of the mock disk
+    pub fn set_serial(&mut self, serial: impl Into<String>) {
+        self.0.serial = Some(serial.into());
+    }
+
     /// Add a partition to the mock disk at the specified byte offsets
     pub fn add_partition(&mut self, start_bytes: u64, end_bytes: u64) {
         let partition_number = self.0.partitions().len() + 1;
         let start = start_bytes / 512;
         let end = end_bytes / 512;
 
+        let disk = &self.0.name;
+        let name = naming::partition_name(disk, partition_number as u32);
         let partition = Partition {
             number: partition_number as u32,
             start,
             end,
             size: end - start,
-            name: format!("mock0p{}", partition_number),
-            node: PathBuf::from("/sys/class/block/mock0/mock0p1"),
-            device: PathBuf::from(format!("/dev/mock0p{}", partition_number)),
+            node: PathBuf::from("/sys/class/block").join(disk).join(&name),
+            device: PathBuf::from("/dev").join(&name),
+            name,
+            mbr: None,
+            flags: Default::default(),
+            gpt_name: None,
         };
 
         self.0.partitions_mut().push(partition);
     }
+
+    /// Add a fully described partition, e.g. one captured from a real disk
+    pub fn push_partition(&mut self, partition: Partition) {
+        self.0.partitions_mut().push(partition);
+    }
 }
@@ -0,0 +1,117 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Mounted filesystem enumeration.
+//!
+//! Mounts are read from `/proc/self/mountinfo`, allowing callers to tell which block
+//! devices are currently in use.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A mounted filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mount {
+    /// Source of the mount, usually a device path (e.g. /dev/sda2)
+    pub source: PathBuf,
+    /// Where the filesystem is mounted
+    pub mountpoint: PathBuf,
+    /// Filesystem type (e.g. ext4)
+    pub fstype: String,
+}
+
+/// Reads the mount table of the current process.
+///
+/// # Returns
+///
+/// All mounts visible to the current process, or an IO error if the mount table can't be read.
+pub fn mounts() -> io::Result<Vec<Mount>> {
+    Ok(parse_mountinfo(&fs::read_to_string("/proc/self/mountinfo")?))
+}
+
+/// Returns the mounts whose source is `device` or one of its `partitions`.
+pub fn mounts_for<'m, 'p>(
+    mounts: &'m [Mount],
+    device: &'p Path,
+    partitions: impl IntoIterator<Item = &'p Path>,
+) -> Vec<&'m Mount> {
+    let mut paths = vec![device];
+    paths.extend(partitions);
+    mounts.iter().filter(|m| paths.contains(&m.source.as_path())).collect()
+}
+
+/// Parses the contents of a mountinfo file, skipping malformed lines.
+pub fn parse_mountinfo(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            // Optional fields end with a lone "-", followed by the fstype and source
+            let (mount, fs) = line.split_once(" - ")?;
+            let mountpoint = mount.split(' ').nth(4)?;
+            let mut fs = fs.split(' ');
+            let fstype = fs.next()?;
+            let source = fs.next()?;
+
+            Some(Mount {
+                source: PathBuf::from(unescape(source)),
+                mountpoint: PathBuf::from(unescape(mountpoint)),
+                fstype: fstype.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Decodes the octal escapes (e.g. `\040` for space) used in mountinfo fields.
+fn unescape(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let raw = field.as_bytes();
+    let mut i = 0;
+    while i < raw.len() {
+        let escaped = raw.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match (raw[i], escaped) {
+            (b'\\', Some(byte)) => {
+                bytes.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let contents = "\
+22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw
+35 22 8:1 / /boot/efi rw,relatime shared:2 - vfat /dev/sda1 rw
+40 22 7:0 / /run/initramfs/live ro - iso9660 /dev/sr0 ro
+41 22 8:17 / /media/USB\\040Stick rw - vfat /dev/sdb1 rw
+broken line
+";
+        let mounts = parse_mountinfo(contents);
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[1].mountpoint, Path::new("/boot/efi"));
+        assert_eq!(mounts[2].fstype, "iso9660");
+        assert_eq!(mounts[3].mountpoint, Path::new("/media/USB Stick"));
+
+        let sda = mounts_for(&mounts, Path::new("/dev/sda"), [Path::new("/dev/sda1")]);
+        assert_eq!(sda.len(), 1);
+        assert_eq!(sda[0].mountpoint, Path::new("/boot/efi"));
+        assert!(mounts_for(&mounts, Path::new("/dev/sdc"), []).is_empty());
+    }
+}
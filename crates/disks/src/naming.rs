@@ -0,0 +1,179 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Partition device naming.
+//!
+//! The kernel names partitions after their disk, separating the partition number with a
+//! `p` when the disk name ends in a digit: `sda3`, `vdb1`, `nvme0n1p3`, `mmcblk0p1`,
+//! `loop0p2`. Device-mapper disks are known by their `/dev/mapper` name instead of the
+//! `dm-N` kernel name, their partitions are created by `kpartx` or `multipath` using the
+//! same rule.
+//!
+//! Once a partition table has been written, the node only shows up after the kernel and
+//! udev have caught up. [`wait_for_partition()`] polls for it.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often [`wait_for_device()`] checks for the device node
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Naming schemes of disks and their partitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamingScheme {
+    /// SCSI, SATA, USB and virtio disks: `sda` → `sda3`
+    Scsi,
+    /// NVMe namespaces: `nvme0n1` → `nvme0n1p3`
+    Nvme,
+    /// MMC and SD cards: `mmcblk0` → `mmcblk0p1`
+    Mmc,
+    /// Loopback devices: `loop0` → `loop0p1`
+    Loop,
+    /// Device-mapper targets: `/dev/mapper/mpatha` → `/dev/mapper/mpatha1`
+    DeviceMapper,
+    /// Anything else, following the kernel rule
+    Other,
+}
+
+impl NamingScheme {
+    /// Determine the scheme from a disk name such as `nvme0n1` or `dm-0`
+    pub fn for_disk(name: &str) -> Self {
+        if name.starts_with("nvme") {
+            Self::Nvme
+        } else if name.starts_with("mmcblk") {
+            Self::Mmc
+        } else if name.starts_with("loop") {
+            Self::Loop
+        } else if name.starts_with("dm-") {
+            Self::DeviceMapper
+        } else if ["sd", "vd", "hd", "xvd"].iter().any(|p| name.starts_with(p)) {
+            Self::Scsi
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Separator between the disk name and the partition number
+    pub fn separator(&self, disk: &str) -> &'static str {
+        match self {
+            Self::Nvme | Self::Mmc | Self::Loop => "p",
+            Self::Scsi | Self::DeviceMapper | Self::Other => {
+                if disk.ends_with(|c: char| c.is_ascii_digit()) {
+                    "p"
+                } else {
+                    ""
+                }
+            }
+        }
+    }
+}
+
+/// Compute the name of partition `number` of the disk named `disk`
+///
+/// # Examples
+///
+/// ```
+/// use disks::naming::partition_name;
+/// assert_eq!(partition_name("sda", 3), "sda3");
+/// assert_eq!(partition_name("nvme0n1", 3), "nvme0n1p3");
+/// ```
+pub fn partition_name(disk: &str, number: u32) -> String {
+    let separator = NamingScheme::for_disk(disk).separator(disk);
+    format!("{disk}{separator}{number}")
+}
+
+/// Compute the device node of partition `number` of the disk at `disk`
+///
+/// `/dev/dm-N` nodes are resolved to their `/dev/mapper` name through sysfs, as that's
+/// where the partition nodes of device-mapper disks are created.
+pub fn partition_path(disk: &Path, number: u32) -> PathBuf {
+    partition_path_in_sysroot("/", disk, number)
+}
+
+/// Compute the device node of a partition, resolving device-mapper names below `sysroot`
+pub fn partition_path_in_sysroot(sysroot: impl AsRef<Path>, disk: &Path, number: u32) -> PathBuf {
+    let name = disk
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if NamingScheme::for_disk(&name) == NamingScheme::DeviceMapper {
+        let dm_name = sysroot.as_ref().join(crate::SYSFS_DIR).join(&name).join("dm/name");
+        if let Ok(mapped) = fs::read_to_string(dm_name) {
+            return Path::new("/dev/mapper").join(partition_name(mapped.trim(), number));
+        }
+    }
+
+    let partition = partition_name(&name, number);
+    match disk.parent() {
+        Some(parent) => parent.join(partition),
+        None => PathBuf::from(partition),
+    }
+}
+
+/// Wait up to `timeout` for the device node at `path` to appear
+pub fn wait_for_device(path: &Path, timeout: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    while !path.exists() {
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} did not appear within {:?}", path.display(), timeout),
+            ));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Wait up to `timeout` for partition `number` of `disk` to appear, returning its node
+pub fn wait_for_partition(disk: &Path, number: u32, timeout: Duration) -> io::Result<PathBuf> {
+    let path = partition_path(disk, number);
+    wait_for_device(&path, timeout)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_names() {
+        assert_eq!(partition_name("sda", 3), "sda3");
+        assert_eq!(partition_name("vdb", 1), "vdb1");
+        assert_eq!(partition_name("nvme0n1", 3), "nvme0n1p3");
+        assert_eq!(partition_name("mmcblk0", 1), "mmcblk0p1");
+        assert_eq!(partition_name("loop7", 2), "loop7p2");
+        assert_eq!(partition_name("md127", 1), "md127p1");
+        assert_eq!(partition_name("mpatha", 1), "mpatha1");
+
+        assert_eq!(partition_path(Path::new("/dev/md/root"), 2), Path::new("/dev/md/root2"));
+
+        // Device-mapper disks resolve to their mapped name
+        let sysroot = std::env::temp_dir().join(format!("disks-naming-{}", std::process::id()));
+        let dm = sysroot.join(crate::SYSFS_DIR).join("dm-0/dm");
+        fs::create_dir_all(&dm).unwrap();
+        fs::write(dm.join("name"), "luks-root0\n").unwrap();
+        assert_eq!(
+            partition_path_in_sysroot(&sysroot, Path::new("/dev/dm-0"), 1),
+            Path::new("/dev/mapper/luks-root0p1")
+        );
+        assert_eq!(
+            partition_path_in_sysroot(&sysroot, Path::new("/dev/dm-1"), 1),
+            Path::new("/dev/dm-1p1")
+        );
+        fs::remove_dir_all(&sysroot).unwrap();
+
+        assert_eq!(
+            wait_for_device(Path::new("/nonexistent"), Duration::ZERO)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::TimedOut
+        );
+    }
+}
@@ -5,11 +5,44 @@
 use std::fmt;
 use std::path::{Path, PathBuf};
 
-use crate::{sysfs, DEVFS_DIR, SYSFS_DIR};
+use serde::{Deserialize, Serialize};
+use superblock::Superblock;
+
+use crate::{flags::PartitionFlags, mbr, probe_cache, sysfs, DEVFS_DIR, SYSFS_DIR};
+
+/// Most UTF-16 code units in the name of a GPT partition
+pub const GPT_NAME_LEN: usize = 36;
+
+/// Space used and left on a partition, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    /// Size of the partition
+    pub total: u64,
+    /// Space taken by data and filesystem metadata
+    pub used: u64,
+    /// Free space in the filesystem plus any space past its end
+    pub free: u64,
+}
+
+impl Usage {
+    /// Computes the usage of a partition of `total` bytes holding the filesystem of `superblock`.
+    ///
+    /// Returns `None` if the filesystem doesn't record its free space in the superblock.
+    pub fn from_superblock(superblock: &Superblock, total: u64) -> Option<Self> {
+        let capacity = superblock.capacity().ok()?;
+        // A filesystem that was never grown leaves the rest of the partition unused
+        let free = (superblock.free_space().ok()? + total.saturating_sub(capacity)).min(total);
+        Some(Self {
+            total,
+            used: total - free,
+            free,
+        })
+    }
+}
 
 /// Represents a partition on a disk device
 /// - Size in sectors
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Partition {
     /// Name of the partition
     pub name: String,
@@ -25,6 +58,12 @@ pub struct Partition {
     pub node: PathBuf,
     /// Path to the partition device in /dev
     pub device: PathBuf,
+    /// Entry in the MBR partition table, if the disk uses one
+    pub mbr: Option<mbr::Entry>,
+    /// Flags read from the partition table
+    pub flags: PartitionFlags,
+    /// Name from the GPT entry, if the disk uses GPT and the name is not empty
+    pub gpt_name: Option<String>,
 }
 
 impl fmt::Display for Partition {
@@ -61,6 +100,115 @@ impl Partition {
             end: start + size,
             node,
             device: sysroot.join(DEVFS_DIR).join(name),
+            mbr: None,
+            flags: PartitionFlags::NONE,
+            gpt_name: None,
         })
     }
+
+    /// Returns the name stored in the GPT entry of this partition.
+    ///
+    /// The kernel only exposes names reduced to ASCII, so [`Partition::gpt_name`] is read from
+    /// the partition table itself. `None` for partitions of MBR disks and partitions without a name.
+    pub fn gpt_name(&self) -> Option<&str> {
+        self.gpt_name.as_deref()
+    }
+
+    /// Reads the usage of the filesystem on this partition from its superblock.
+    ///
+    /// Returns `None` if the partition can't be read, holds no filesystem, or one that
+    /// doesn't record its free space in the superblock (e.g. FAT).
+    pub fn usage(&self) -> Option<Usage> {
+        let mut file = probe_cache::open(&self.device).ok()?;
+        let superblock = Superblock::from_reader(&mut file).ok()?;
+        Usage::from_superblock(&superblock, self.size * 512)
+    }
+}
+
+/// Shortens `name` to the longest prefix that fits a GPT entry without splitting a character.
+pub fn truncate_gpt_name(name: &str) -> &str {
+    let mut units = 0;
+    for (index, c) in name.char_indices() {
+        units += c.len_utf16();
+        if units > GPT_NAME_LEN {
+            return &name[..index];
+        }
+    }
+    name
+}
+
+/// Encodes `name` as the NUL padded UTF-16LE name field of a GPT entry.
+///
+/// Names that are too long are cut short by [`truncate_gpt_name()`].
+pub fn encode_gpt_name(name: &str) -> [u8; GPT_NAME_LEN * 2] {
+    let mut field = [0u8; GPT_NAME_LEN * 2];
+    for (bytes, unit) in field.chunks_exact_mut(2).zip(truncate_gpt_name(name).encode_utf16()) {
+        bytes.copy_from_slice(&unit.to_le_bytes());
+    }
+    field
+}
+
+/// Decodes the UTF-16LE name field of a GPT entry, which ends at the first NUL.
+///
+/// Invalid UTF-16 is replaced rather than rejected, as other tools may have written it.
+pub fn decode_gpt_name(field: &[u8]) -> String {
+    let units = field
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|u| *u != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64MiB ext4 filesystem of 1KiB blocks with 40MiB free
+    fn ext4_superblock() -> Superblock {
+        let mut bytes = vec![0u8; 4096];
+        let field = |bytes: &mut [u8], offset: usize, value: u32| {
+            bytes[1024 + offset..1024 + offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        field(&mut bytes, 0x04, 64 * 1024);
+        field(&mut bytes, 0x0c, 40 * 1024);
+        bytes[1024 + 0x38..1024 + 0x3a].copy_from_slice(&0xef53u16.to_le_bytes());
+        Superblock::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_usage_from_superblock() {
+        const MB: u64 = 1024 * 1024;
+        let superblock = ext4_superblock();
+        assert_eq!(
+            Usage::from_superblock(&superblock, 64 * MB),
+            Some(Usage {
+                total: 64 * MB,
+                used: 24 * MB,
+                free: 40 * MB,
+            })
+        );
+
+        // The partition was grown without resizing the filesystem
+        let usage = Usage::from_superblock(&superblock, 100 * MB).unwrap();
+        assert_eq!(usage.used, 24 * MB);
+        assert_eq!(usage.free, 76 * MB);
+    }
+
+    #[test]
+    fn test_gpt_name_encoding() {
+        for name in ["", "EFI System Partition", "ルート", "home 🏠"] {
+            assert_eq!(decode_gpt_name(&encode_gpt_name(name)), name);
+        }
+
+        let long = "x".repeat(40);
+        assert_eq!(truncate_gpt_name(&long), &long[..GPT_NAME_LEN]);
+        assert_eq!(decode_gpt_name(&encode_gpt_name(&long)), &long[..GPT_NAME_LEN]);
+
+        // A surrogate pair straddling the limit is dropped as a whole
+        let emoji = format!("{}🏠", "x".repeat(GPT_NAME_LEN - 1));
+        assert_eq!(truncate_gpt_name(&emoji), &emoji[..GPT_NAME_LEN - 1]);
+        let fits = format!("{}🏠", "x".repeat(GPT_NAME_LEN - 2));
+        assert_eq!(decode_gpt_name(&encode_gpt_name(&fits)), fits);
+    }
 }
@@ -0,0 +1,243 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Shared read-ahead of device heads during discovery
+//!
+//! Discovering a disk reads its MBR, then its GPT header and entries, then probes the
+//! superblock of the disk or of each of its partitions, each time opening the device anew.
+//! On slow media (USB sticks, SD cards, optical drives) every one of those small reads
+//! costs a round trip. A [`ProbeCache`] reads the first bytes of each device once and
+//! serves later reads of that range from memory. Reads beyond it go to the device.
+//!
+//! [`BlockDevice::discover()`](crate::BlockDevice::discover) and [`crate::snapshot()`] use
+//! a fresh cache for each pass. Callers running several passes, e.g. while planning, can
+//! keep one cache active on the current thread with [`ProbeCache::activate()`]. Cached
+//! heads go stale once a device is written, see [`ProbeCache::invalidate()`].
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Bytes cached per device by default
+///
+/// Covers the superblocks of all supported filesystems and a GPT with 4K sectors.
+pub const DEFAULT_SIZE: usize = {
+    let size = superblock::PROBE_SIZE as usize;
+    if size > 64 * 1024 {
+        size
+    } else {
+        64 * 1024
+    }
+};
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Arc<ProbeCache>>> = const { RefCell::new(None) };
+}
+
+/// Memoized heads of devices
+#[derive(Debug)]
+pub struct ProbeCache {
+    size: usize,
+    heads: Mutex<HashMap<PathBuf, Arc<[u8]>>>,
+}
+
+impl Default for ProbeCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIZE)
+    }
+}
+
+impl ProbeCache {
+    /// Creates a cache holding the first `size` bytes of each device
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            heads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Opens `path`, reading its head into the cache unless it is there already
+    pub fn reader(&self, path: &Path) -> io::Result<CachedReader> {
+        let cached = self.heads.lock().unwrap().get(path).cloned();
+        let (head, file) = match cached {
+            Some(head) => (head, None),
+            None => {
+                let mut file = File::open(path)?;
+                let mut head = Vec::with_capacity(self.size);
+                (&mut file).take(self.size as u64).read_to_end(&mut head)?;
+                let head = Arc::<[u8]>::from(head);
+                self.heads.lock().unwrap().insert(path.to_owned(), head.clone());
+                (head, Some(file))
+            }
+        };
+        Ok(CachedReader {
+            path: path.to_owned(),
+            head,
+            file,
+            position: 0,
+        })
+    }
+
+    /// Forgets the head of `path`, e.g. after writing to it
+    pub fn invalidate(&self, path: &Path) {
+        self.heads.lock().unwrap().remove(path);
+    }
+
+    /// Forgets the heads of all devices
+    pub fn clear(&self) {
+        self.heads.lock().unwrap().clear();
+    }
+
+    /// Number of devices with a cached head
+    pub fn len(&self) -> usize {
+        self.heads.lock().unwrap().len()
+    }
+
+    /// Returns true if no device has been read yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Uses this cache for all discovery on the current thread until the guard is dropped
+    pub fn activate(self: &Arc<Self>) -> ActiveCache {
+        let previous = ACTIVE.with(|active| active.replace(Some(self.clone())));
+        ActiveCache { previous }
+    }
+}
+
+/// Keeps a [`ProbeCache`] active on the current thread, restoring the previous one on drop
+#[derive(Debug)]
+#[must_use = "the cache is deactivated when the guard is dropped"]
+pub struct ActiveCache {
+    previous: Option<Arc<ProbeCache>>,
+}
+
+impl Drop for ActiveCache {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE.with(|active| *active.borrow_mut() = previous);
+    }
+}
+
+/// The cache active on the current thread, if any
+pub fn active() -> Option<Arc<ProbeCache>> {
+    ACTIVE.with(|active| active.borrow().clone())
+}
+
+/// Activates a fresh cache for a discovery pass, unless one is active already
+pub fn pass() -> Option<ActiveCache> {
+    match active() {
+        Some(_) => None,
+        None => Some(Arc::new(ProbeCache::default()).activate()),
+    }
+}
+
+/// Opens `path` through the active cache, or directly without one
+pub fn open(path: &Path) -> io::Result<CachedReader> {
+    match active() {
+        Some(cache) => cache.reader(path),
+        None => Ok(CachedReader {
+            path: path.to_owned(),
+            head: Arc::from([]),
+            file: Some(File::open(path)?),
+            position: 0,
+        }),
+    }
+}
+
+/// A device served from its cached head, opening the device only for reads beyond it
+#[derive(Debug)]
+pub struct CachedReader {
+    path: PathBuf,
+    head: Arc<[u8]>,
+    file: Option<File>,
+    position: u64,
+}
+
+impl CachedReader {
+    fn file(&mut self) -> io::Result<&mut File> {
+        match &mut self.file {
+            Some(file) => Ok(file),
+            file => Ok(file.insert(File::open(&self.path)?)),
+        }
+    }
+}
+
+impl Read for CachedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(cached) = usize::try_from(self.position).ok().and_then(|p| self.head.get(p..)) {
+            if !cached.is_empty() {
+                let len = cached.len().min(buf.len());
+                buf[..len].copy_from_slice(&cached[..len]);
+                self.position += len as u64;
+                return Ok(len);
+            }
+        }
+
+        let position = self.position;
+        let file = self.file()?;
+        file.seek(SeekFrom::Start(position))?;
+        let len = file.read(buf)?;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for CachedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => Some(self.file()?.seek(SeekFrom::End(offset))?),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_probe_cache() {
+        let path = std::env::temp_dir().join(format!("disks-rs-probe-cache-{}", std::process::id()));
+        let image = (0..8192u32).map(|i| i as u8).collect::<Vec<_>>();
+        fs::write(&path, &image).unwrap();
+
+        let cache = Arc::new(ProbeCache::new(4096));
+        let mut bytes = vec![];
+        cache.reader(&path).unwrap().read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, image);
+        assert_eq!(cache.len(), 1);
+
+        // The head is served from memory, the rest from the device
+        fs::write(&path, vec![0xff; 8192]).unwrap();
+        let guard = cache.activate();
+        let mut reader = open(&path).unwrap();
+        let mut buf = [0u8; 8];
+        reader.seek(SeekFrom::Start(4092)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [252, 253, 254, 255, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 8192);
+        assert!(pass().is_none());
+        drop(guard);
+        assert!(active().is_none());
+
+        cache.invalidate(&path);
+        assert!(cache.is_empty());
+        let mut reader = cache.reader(&path).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0xff; 8]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,485 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Portable snapshots of the discovered device state.
+//!
+//! [`snapshot()`] captures every disk with its partitions, filesystems, queue topology and
+//! the mount table into a [`Snapshot`] that serializes to JSON. Attached to a bug report
+//! it describes the machine well enough to reproduce planning issues elsewhere:
+//!
+//! - [`Snapshot::mock_devices()`] recreates the disks as [`MockDisk`]s.
+//! - [`Snapshot::write_sysfs()`] writes a fake sysfs tree, so discovery itself can be run
+//!   against it with [`BlockDevice::discover_in_sysroot()`].
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use superblock::Superblock;
+
+use crate::{
+    flags::PartitionFlags,
+    mbr,
+    mock::MockDisk,
+    mount::{self, Mount},
+    partition::{Partition, Usage},
+    probe_cache, sysfs, BasicDisk, BlockDevice, DEVFS_DIR, SYSFS_DIR,
+};
+
+/// Version of the snapshot format
+pub const VERSION: u32 = 1;
+
+/// A filesystem or container found on a device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilesystemSnapshot {
+    /// Filesystem type, e.g. `ext4` or `luks2`
+    pub kind: String,
+    /// UUID as listed in `/dev/disk/by-uuid`, if the filesystem has one
+    pub uuid: Option<String>,
+    /// Label, if set
+    pub label: Option<String>,
+}
+
+/// Block sizes and rotational flag of a device queue
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Topology {
+    /// Logical block size in bytes
+    pub logical_block_size: u64,
+    /// Physical block size in bytes
+    pub physical_block_size: u64,
+    /// Whether the device is a spinning disk
+    pub rotational: bool,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Self {
+            logical_block_size: 512,
+            physical_block_size: 512,
+            rotational: false,
+        }
+    }
+}
+
+/// A partition of a [`DeviceSnapshot`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionSnapshot {
+    /// Kernel name of the partition
+    pub name: String,
+    /// Partition number on the disk
+    pub number: u32,
+    /// First sector
+    pub start: u64,
+    /// Size in sectors
+    pub size: u64,
+    /// Entry in the MBR partition table, if the disk uses one
+    pub mbr: Option<mbr::Entry>,
+    /// Flags read from the partition table
+    #[serde(default, skip_serializing_if = "PartitionFlags::is_empty")]
+    pub flags: PartitionFlags,
+    /// Name from the GPT entry, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpt_name: Option<String>,
+    /// Filesystem on the partition, if recognised
+    pub filesystem: Option<FilesystemSnapshot>,
+    /// Space used by the filesystem, if it records its free space
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// A disk of a [`Snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceSnapshot {
+    /// Kernel name of the device
+    pub name: String,
+    /// Size in 512 byte sectors
+    pub sectors: u64,
+    /// Model reported by the device, if any
+    pub model: Option<String>,
+    /// Vendor reported by the device, if any
+    pub vendor: Option<String>,
+    /// World Wide Name of the device, if any
+    pub wwn: Option<String>,
+    /// Serial number reported by the device, if any
+    pub serial: Option<String>,
+    /// Whether the media can be removed
+    pub removable: bool,
+    /// Backing file of loop devices
+    pub backing_file: Option<PathBuf>,
+    /// Queue topology
+    #[serde(default)]
+    pub topology: Topology,
+    /// Filesystem directly on the device, if recognised
+    pub filesystem: Option<FilesystemSnapshot>,
+    /// Partitions ordered by number
+    pub partitions: Vec<PartitionSnapshot>,
+}
+
+/// The complete discovered device state of a machine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Version of the format, see [`VERSION`]
+    pub version: u32,
+    /// Disks ordered by name
+    pub devices: Vec<DeviceSnapshot>,
+    /// Mounted filesystems
+    pub mounts: Vec<Mount>,
+}
+
+/// Captures the devices and mounts of the running system.
+pub fn snapshot() -> io::Result<Snapshot> {
+    let mut snapshot = snapshot_in_sysroot("/")?;
+    snapshot.mounts = mount::mounts()?;
+    Ok(snapshot)
+}
+
+/// Captures the devices found in the sysfs below `sysroot`.
+///
+/// Filesystems are probed through the device nodes in `dev` below `sysroot`, mounts are left empty.
+pub fn snapshot_in_sysroot(sysroot: impl AsRef<str>) -> io::Result<Snapshot> {
+    let sysroot = sysroot.as_ref();
+    let _cache = probe_cache::pass();
+    let devices = BlockDevice::discover_in_sysroot(sysroot)?
+        .iter()
+        .filter_map(|device| {
+            let (disk, backing_file) = match device {
+                BlockDevice::Disk(disk) => (&***disk, None),
+                BlockDevice::Loopback(device) => (device.disk()?, device.file_path().map(Path::to_owned)),
+            };
+            Some(capture_device(sysroot, disk, backing_file))
+        })
+        .collect();
+
+    Ok(Snapshot {
+        version: VERSION,
+        devices,
+        mounts: vec![],
+    })
+}
+
+fn capture_device(sysroot: &str, disk: &BasicDisk, backing_file: Option<PathBuf>) -> DeviceSnapshot {
+    let node = Path::new(sysroot).join(SYSFS_DIR).join(disk.name());
+    let topology = Topology {
+        logical_block_size: sysfs::read(&node, "queue/logical_block_size").unwrap_or(512),
+        physical_block_size: sysfs::read(&node, "queue/physical_block_size").unwrap_or(512),
+        rotational: sysfs::read::<u8>(&node, "queue/rotational").is_some_and(|r| r == 1),
+    };
+
+    DeviceSnapshot {
+        name: disk.name().to_owned(),
+        sectors: disk.sectors(),
+        model: disk.model().map(str::to_owned),
+        vendor: disk.vendor().map(str::to_owned),
+        wwn: disk.wwn().map(str::to_owned),
+        serial: disk.serial().map(str::to_owned),
+        removable: disk.is_removable(),
+        backing_file,
+        topology,
+        filesystem: probe(&Path::new(sysroot).join(DEVFS_DIR).join(disk.name())).map(|(_, f)| f),
+        partitions: disk
+            .partitions()
+            .iter()
+            .map(|p| {
+                let (superblock, filesystem) = probe(&p.device).unzip();
+                PartitionSnapshot {
+                    name: p.name.clone(),
+                    number: p.number,
+                    start: p.start,
+                    size: p.size,
+                    mbr: p.mbr,
+                    flags: p.flags,
+                    gpt_name: p.gpt_name.clone(),
+                    filesystem,
+                    usage: superblock
+                        .as_ref()
+                        .and_then(|s| Usage::from_superblock(s, p.size * 512)),
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Probe the superblock of a device node and describe its filesystem
+fn probe(device: &Path) -> Option<(Superblock, FilesystemSnapshot)> {
+    let mut file = probe_cache::open(device).ok()?;
+    let superblock = Superblock::from_reader(&mut file).ok()?;
+    let filesystem = FilesystemSnapshot {
+        kind: superblock.kind().to_string(),
+        uuid: superblock.uuid_string().ok(),
+        label: superblock.read_label(&mut file).ok().filter(|l| !l.is_empty()),
+    };
+    Some((superblock, filesystem))
+}
+
+impl Snapshot {
+    /// Parses a snapshot from JSON.
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let snapshot: Self = serde_json::from_str(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if snapshot.version > VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported snapshot version {}", snapshot.version),
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    /// Renders the snapshot as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots always serialize")
+    }
+
+    /// Recreates every disk as a mock device.
+    pub fn mock_devices(&self) -> Vec<BlockDevice> {
+        self.devices
+            .iter()
+            .map(|device| {
+                let mut disk = MockDisk::new(device.sectors * 512);
+                disk.set_name(&device.name);
+                disk.set_model(device.model.clone());
+                disk.set_vendor(device.vendor.clone());
+                if let Some(wwn) = &device.wwn {
+                    disk.set_wwn(wwn);
+                }
+                if let Some(serial) = &device.serial {
+                    disk.set_serial(serial);
+                }
+                disk.set_removable(device.removable);
+                if device.partitions.is_empty() {
+                    disk.set_filesystem(device.filesystem.as_ref().and_then(|f| f.kind.parse().ok()));
+                }
+                for partition in &device.partitions {
+                    disk.push_partition(Partition {
+                        name: partition.name.clone(),
+                        number: partition.number,
+                        start: partition.start,
+                        end: partition.start + partition.size,
+                        size: partition.size,
+                        node: Path::new("/").join(SYSFS_DIR).join(&device.name).join(&partition.name),
+                        device: Path::new("/").join(DEVFS_DIR).join(&partition.name),
+                        mbr: partition.mbr,
+                        flags: partition.flags,
+                        gpt_name: partition.gpt_name.clone(),
+                    });
+                }
+                BlockDevice::mock_device(disk)
+            })
+            .collect()
+    }
+
+    /// Writes a fake sysfs tree for the disks below `sysroot`.
+    ///
+    /// Devices are discovered from it with their original type, as that is derived from
+    /// the name. Only what discovery reads is written.
+    pub fn write_sysfs(&self, sysroot: &Path) -> io::Result<()> {
+        let class = sysroot.join(SYSFS_DIR);
+        for device in &self.devices {
+            let node = class.join(&device.name);
+            let mut attributes = vec![
+                ("size", Some(device.sectors.to_string())),
+                ("removable", Some(u8::from(device.removable).to_string())),
+                (
+                    "queue/logical_block_size",
+                    Some(device.topology.logical_block_size.to_string()),
+                ),
+                (
+                    "queue/physical_block_size",
+                    Some(device.topology.physical_block_size.to_string()),
+                ),
+                (
+                    "queue/rotational",
+                    Some(u8::from(device.topology.rotational).to_string()),
+                ),
+                ("device/model", device.model.clone()),
+                ("device/vendor", device.vendor.clone()),
+                ("device/serial", device.serial.clone()),
+                ("wwid", device.wwn.clone()),
+            ];
+            attributes.push((
+                "loop/backing_file",
+                device.backing_file.as_ref().map(|f| f.display().to_string()),
+            ));
+            for (key, value) in attributes {
+                if let Some(value) = value {
+                    write_attribute(&node, key, &value)?;
+                }
+            }
+
+            // Partitions are listed below their disk and in the class directory, as in sysfs
+            for partition in &device.partitions {
+                let partition_node = node.join(&partition.name);
+                write_attribute(&partition_node, "partition", &partition.number.to_string())?;
+                write_attribute(&partition_node, "start", &partition.start.to_string())?;
+                write_attribute(&partition_node, "size", &partition.size.to_string())?;
+                let link = class.join(&partition.name);
+                if !link.exists() {
+                    std::os::unix::fs::symlink(&partition_node, link)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_attribute(node: &Path, key: &str, value: &str) -> io::Result<()> {
+    let path = node.join(key);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, format!("{value}\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Snapshot {
+        let partition = |name: &str, number, start, size| PartitionSnapshot {
+            name: name.to_owned(),
+            number,
+            start,
+            size,
+            mbr: None,
+            flags: PartitionFlags::NONE,
+            gpt_name: None,
+            filesystem: None,
+            usage: None,
+        };
+        Snapshot {
+            version: VERSION,
+            devices: vec![
+                DeviceSnapshot {
+                    name: "nvme0n1".to_owned(),
+                    sectors: 1_000_215_216,
+                    model: Some("Example SSD".to_owned()),
+                    vendor: None,
+                    wwn: Some("eui.0025385b91b12345".to_owned()),
+                    serial: Some("S4EWNX0R123456".to_owned()),
+                    removable: false,
+                    backing_file: None,
+                    topology: Topology::default(),
+                    filesystem: None,
+                    partitions: vec![
+                        PartitionSnapshot {
+                            filesystem: Some(FilesystemSnapshot {
+                                kind: "fat".to_owned(),
+                                uuid: Some("1234-ABCD".to_owned()),
+                                label: None,
+                            }),
+                            ..partition("nvme0n1p1", 1, 2048, 2_097_152)
+                        },
+                        PartitionSnapshot {
+                            filesystem: Some(FilesystemSnapshot {
+                                kind: "ext4".to_owned(),
+                                uuid: Some("731af94c-9990-4eed-944d-5d230dbe8a0d".to_owned()),
+                                label: None,
+                            }),
+                            gpt_name: Some("root".to_owned()),
+                            usage: Some(Usage {
+                                total: 998_115_983 * 512,
+                                used: 120 * 1024 * 1024 * 1024,
+                                free: 998_115_983 * 512 - 120 * 1024 * 1024 * 1024,
+                            }),
+                            ..partition("nvme0n1p2", 2, 2_099_200, 998_115_983)
+                        },
+                    ],
+                },
+                DeviceSnapshot {
+                    name: "sda".to_owned(),
+                    sectors: 31_266_816,
+                    model: Some("Flash Drive".to_owned()),
+                    vendor: Some("Example".to_owned()),
+                    wwn: None,
+                    serial: None,
+                    removable: true,
+                    backing_file: None,
+                    topology: Topology {
+                        rotational: true,
+                        ..Topology::default()
+                    },
+                    filesystem: None,
+                    partitions: vec![PartitionSnapshot {
+                        mbr: Some(mbr::Entry {
+                            number: 1,
+                            partition_type: 0x0c,
+                            bootable: true,
+                            start: 2048,
+                            size: 31_264_768,
+                        }),
+                        flags: PartitionFlags::BOOTABLE,
+                        ..partition("sda1", 1, 2048, 31_264_768)
+                    }],
+                },
+            ],
+            mounts: vec![Mount {
+                source: PathBuf::from("/dev/sda1"),
+                mountpoint: PathBuf::from("/run/media/usb"),
+                fstype: "vfat".to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let snapshot = example();
+        assert_eq!(Snapshot::from_json(&snapshot.to_json()).unwrap(), snapshot);
+
+        let future = snapshot.to_json().replace("\"version\": 1", "\"version\": 99");
+        assert_eq!(
+            Snapshot::from_json(&future).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_mock_devices() {
+        let devices = example().mock_devices();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].device(), Path::new("/dev/nvme0n1"));
+        assert_eq!(devices[0].size(), 1_000_215_216 * 512);
+        assert_eq!(devices[0].partitions()[1].device, Path::new("/dev/nvme0n1p2"));
+        assert_eq!(devices[0].partitions()[1].gpt_name(), Some("root"));
+        assert!(devices[1].is_removable());
+        assert_eq!(devices[1].partitions()[0].mbr.unwrap().partition_type, 0x0c);
+        assert_eq!(devices[1].partitions()[0].flags, PartitionFlags::BOOTABLE);
+        assert!(devices[1].filesystem().is_none());
+
+        // A stick formatted without a partition table
+        let mut snapshot = example();
+        snapshot.devices[1].partitions.clear();
+        snapshot.devices[1].filesystem = Some(FilesystemSnapshot {
+            kind: "fat".to_owned(),
+            uuid: None,
+            label: Some("STICK".to_owned()),
+        });
+        let devices = snapshot.mock_devices();
+        assert_eq!(devices[1].filesystem(), Some(&superblock::Kind::FAT));
+    }
+
+    #[test]
+    fn test_sysfs_round_trip() {
+        let sysroot = std::env::temp_dir().join(format!("disks-rs-snapshot-{}", std::process::id()));
+        let snapshot = example();
+        snapshot.write_sysfs(&sysroot).unwrap();
+
+        let captured = snapshot_in_sysroot(sysroot.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&sysroot).unwrap();
+
+        // Neither filesystems nor partition tables can be read back without device nodes
+        let mut expected = snapshot;
+        expected.mounts.clear();
+        for device in &mut expected.devices {
+            for partition in &mut device.partitions {
+                partition.filesystem = None;
+                partition.usage = None;
+                partition.mbr = None;
+                partition.flags = PartitionFlags::NONE;
+                partition.gpt_name = None;
+            }
+        }
+        assert_eq!(captured, expected);
+    }
+}
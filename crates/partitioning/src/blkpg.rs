@@ -3,17 +3,18 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use disks::{BasicDisk, DiskInit};
-use log::{debug, error, info};
 use std::{
+    fmt,
     fs::File,
     io,
     os::fd::{AsFd, AsRawFd},
     path::{Path, PathBuf},
 };
 use thiserror::Error;
+use tracing::{debug, error, info, instrument};
 
 pub use gpt;
-use linux_raw_sys::ioctl::BLKPG;
+use linux_raw_sys::ioctl::{BLKPG, BLKRRPART};
 use nix::libc;
 
 /// Errors that can occur during partition operations
@@ -48,25 +49,13 @@ struct BlkpgIoctl {
 
 const BLKPG_ADD_PARTITION: i32 = 1;
 const BLKPG_DEL_PARTITION: i32 = 2;
+const BLKPG_RESIZE_PARTITION: i32 = 3;
 
-/// Adds a new partition to the specified block device
-///
-/// # Arguments
-/// * `fd` - File descriptor for the block device
-/// * `partition_number` - Number to assign to the new partition
-/// * `start` - Starting offset in bytes
-/// * `length` - Length of partition in bytes
-///
-/// # Returns
-/// `io::Result<()>` indicating success or failure
-pub(crate) fn add_partition<F>(fd: F, partition_number: i32, start: i64, length: i64) -> io::Result<()>
+/// Issue a BLKPG operation for a single partition
+fn blkpg<F>(fd: F, op: i32, partition_number: i32, start: i64, length: i64) -> io::Result<()>
 where
     F: AsRawFd,
 {
-    debug!(
-        "Initiating partition addition - Number: {}, Start: {}, Length: {}",
-        partition_number, start, length
-    );
     let mut part = BlkpgPartition {
         start,
         length,
@@ -76,7 +65,7 @@ where
     };
 
     let mut ioctl = BlkpgIoctl {
-        op: BLKPG_ADD_PARTITION,
+        op,
         flags: 0,
         datalen: std::mem::size_of::<BlkpgPartition>() as i32,
         data: &mut part,
@@ -84,11 +73,31 @@ where
 
     let res = unsafe { libc::ioctl(fd.as_raw_fd(), BLKPG as _, &mut ioctl) };
     if res < 0 {
-        let err = io::Error::last_os_error();
-        error!("Partition creation failed: {}", err);
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Adds a new partition to the specified block device
+///
+/// # Arguments
+/// * `fd` - File descriptor for the block device
+/// * `partition_number` - Number to assign to the new partition
+/// * `start` - Starting offset in bytes
+/// * `length` - Length of partition in bytes
+///
+/// # Returns
+/// `io::Result<()>` indicating success or failure
+pub(crate) fn add_partition<F>(fd: F, partition_number: i32, start: i64, length: i64) -> io::Result<()>
+where
+    F: AsRawFd,
+{
+    debug!(partition = partition_number, start, length, "Adding partition");
+    if let Err(err) = blkpg(fd, BLKPG_ADD_PARTITION, partition_number, start, length) {
+        error!(partition = partition_number, "Partition creation failed: {}", err);
         return Err(err);
     }
-    info!("Successfully created partition {}", partition_number);
+    info!(partition = partition_number, "Created partition");
     Ok(())
 }
 
@@ -104,51 +113,136 @@ pub(crate) fn delete_partition<F>(fd: F, partition_number: i32) -> io::Result<()
 where
     F: AsRawFd,
 {
-    info!("Initiating deletion of partition {}", partition_number);
-    let mut part = BlkpgPartition {
-        start: 0,
-        length: 0,
-        pno: partition_number,
-        devname: [0; 64],
-        volname: [0; 64],
-    };
-
-    let mut ioctl = BlkpgIoctl {
-        op: BLKPG_DEL_PARTITION,
-        flags: 0,
-        datalen: std::mem::size_of::<BlkpgPartition>() as i32,
-        data: &mut part,
-    };
+    debug!(partition = partition_number, "Deleting partition");
+    if let Err(err) = blkpg(fd, BLKPG_DEL_PARTITION, partition_number, 0, 0) {
+        error!(partition = partition_number, "Failed to delete partition: {}", err);
+        return Err(err);
+    }
+    info!(partition = partition_number, "Removed partition");
+    Ok(())
+}
 
-    let res = unsafe { libc::ioctl(fd.as_raw_fd(), BLKPG as _, &mut ioctl) };
+/// Asks the kernel to read the partition table of a whole disk again
+///
+/// Fails with `EBUSY` while any partition of the disk is in use, and with `EINVAL` for
+/// devices the kernel does not scan for partitions, e.g. loop devices without partscan.
+/// [`sync_gpt_partitions()`] works in both cases, but only for GPT disks.
+pub fn reread_partition_table<F>(fd: F) -> io::Result<()>
+where
+    F: AsRawFd,
+{
+    let res = unsafe { libc::ioctl(fd.as_raw_fd(), BLKRRPART as _) };
     if res < 0 {
-        let err = io::Error::last_os_error();
-        error!("Failed to delete partition {}: {}", partition_number, err);
-        return Err(err);
+        return Err(io::Error::last_os_error());
     }
-    info!("Successfully removed partition {}", partition_number);
+    info!("Partition table reread");
     Ok(())
 }
 
-/// Updates kernel partition representations to match the GPT table
+/// Changes the length of a partition known to the kernel, without removing it first
+///
+/// The kernel only moves the end of a partition, `start` must match its current offset.
+/// Unlike deleting and re-adding, this works while the partition is in use, e.g. when
+/// growing a mounted filesystem.
 ///
 /// # Arguments
-/// * `path` - Path to the block device
+/// * `fd` - File descriptor for the block device
+/// * `partition_number` - Number of the partition to resize
+/// * `start` - Current starting offset in bytes
+/// * `length` - New length of the partition in bytes
+pub fn resize_partition<F>(fd: F, partition_number: i32, start: i64, length: i64) -> io::Result<()>
+where
+    F: AsRawFd,
+{
+    debug!(partition = partition_number, start, length, "Resizing partition");
+    if let Err(err) = blkpg(fd, BLKPG_RESIZE_PARTITION, partition_number, start, length) {
+        error!(partition = partition_number, "Failed to resize partition: {}", err);
+        return Err(err);
+    }
+    info!(partition = partition_number, length, "Resized partition");
+    Ok(())
+}
+
+/// Moves a partition known to the kernel to a different number, keeping its start and length
 ///
-/// # Returns
-/// `Result<(), Error>` indicating success or partition operation failure
-pub fn sync_gpt_partitions<P: AsRef<Path>>(path: P) -> Result<(), Error> {
-    info!("Initiating GPT partition synchronization for {:?}", path.as_ref());
-    let file = File::open(&path)?;
+/// The kernel refuses to delete partitions that are in use, in which case nothing changes.
+/// If the new number cannot be added, the partition is restored under its old number.
+///
+/// # Arguments
+/// * `fd` - File descriptor for the block device
+/// * `from` - Current number of the partition
+/// * `to` - Number the partition should have afterwards
+/// * `start` - Starting offset in bytes
+/// * `length` - Length of the partition in bytes
+pub fn renumber_partition<F>(fd: F, from: i32, to: i32, start: i64, length: i64) -> io::Result<()>
+where
+    F: AsRawFd,
+{
+    if from == to {
+        return Ok(());
+    }
+
+    let fd = fd.as_raw_fd();
+    debug!(from, to, "Renumbering partition");
+    delete_partition(fd, from)?;
+    if let Err(err) = add_partition(fd, to, start, length) {
+        if let Err(restore) = add_partition(fd, from, start, length) {
+            error!(partition = from, "Failed to restore partition: {}", restore);
+        }
+        return Err(err);
+    }
+    info!(from, to, "Renumbered partition");
+    Ok(())
+}
+
+/// A single BLKPG call, with offsets and lengths in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Remove a partition from the kernel
+    Delete { partition: i32 },
+    /// Change the length of a partition, keeping its start
+    Resize { partition: i32, start: i64, length: i64 },
+    /// Make a partition known to the kernel
+    Add { partition: i32, start: i64, length: i64 },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Delete { partition } => write!(f, "delete partition {partition}"),
+            Self::Resize {
+                partition,
+                start,
+                length,
+            } => write!(f, "resize partition {partition} at {start} to {length} bytes"),
+            Self::Add {
+                partition,
+                start,
+                length,
+            } => write!(f, "add partition {partition} at {start} with {length} bytes"),
+        }
+    }
+}
 
-    // Read GPT table
-    debug!("Reading GPT partition table");
+/// Computes the BLKPG calls [`sync_gpt_partitions()`] would make, without making them
+///
+/// Partitions the kernel already knows with the same start and length are left alone.
+/// Deletions come first, then resizes (shrinking before growing) and finally additions,
+/// so that no call overlaps a partition that is still in the way.
+pub fn plan_gpt_sync<P: AsRef<Path>>(path: P) -> Result<Vec<Operation>, Error> {
     let gpt = gpt::GptConfig::new().writable(false).open(&path)?;
-    let partitions = gpt.partitions();
     let block_size = 512;
-    info!("Located {} partitions (block size: {})", partitions.len(), block_size);
-
-    debug!("Beginning partition cleanup process");
+    let table = gpt
+        .partitions()
+        .iter()
+        .map(|(i, p)| {
+            (
+                *i as i32,
+                p.first_lba as i64 * block_size,
+                (p.last_lba - p.first_lba + 1) as i64 * block_size,
+            )
+        })
+        .collect::<Vec<_>>();
 
     // Find the disk for enumeration purposes
     let base_name = path
@@ -159,22 +253,159 @@ pub fn sync_gpt_partitions<P: AsRef<Path>>(path: P) -> Result<(), Error> {
         .to_string();
     let disk = BasicDisk::from_sysfs_path(&PathBuf::from("/"), &base_name)
         .ok_or(Error::Io(io::Error::from(io::ErrorKind::InvalidInput)))?;
+    let current = disk
+        .partitions()
+        .iter()
+        .map(|p| (p.number as i32, p.start as i64 * 512, p.size as i64 * 512))
+        .collect::<Vec<_>>();
+
+    Ok(operations(&current, &table))
+}
 
-    for partition in disk.partitions() {
-        let _ = delete_partition(file.as_raw_fd(), partition.number as i32);
+/// The operations turning the `current` partitions into those of `table`
+///
+/// Both hold the number, start and length of each partition.
+fn operations(current: &[(i32, i64, i64)], table: &[(i32, i64, i64)]) -> Vec<Operation> {
+    let mut deletes = vec![];
+    let mut resizes = vec![];
+    let mut adds = vec![];
+
+    for &(partition, start, length) in current {
+        match table.iter().find(|(n, _, _)| *n == partition) {
+            Some(&(_, new_start, new_length)) if new_start == start => {
+                if new_length != length {
+                    resizes.push((new_length > length, partition, start, new_length));
+                }
+            }
+            _ => deletes.push(Operation::Delete { partition }),
+        }
+    }
+    for &(partition, start, length) in table {
+        let known = current.iter().find(|(n, _, _)| *n == partition);
+        if known.is_none_or(|(_, current_start, _)| *current_start != start) {
+            adds.push(Operation::Add {
+                partition,
+                start,
+                length,
+            });
+        }
     }
 
-    // Add partitions from GPT
-    debug!("Beginning partition creation from GPT table");
-    for (i, partition) in partitions.iter() {
-        add_partition(
-            file.as_fd(),
-            *i as i32,
-            partition.first_lba as i64 * block_size,
-            (partition.last_lba - partition.first_lba + 1) as i64 * block_size,
-        )?;
+    // Shrinking first frees the space partitions grow into
+    resizes.sort_by_key(|(grows, partition, _, _)| (*grows, *partition));
+    deletes
+        .into_iter()
+        .chain(
+            resizes
+                .into_iter()
+                .map(|(_, partition, start, length)| Operation::Resize {
+                    partition,
+                    start,
+                    length,
+                }),
+        )
+        .chain(adds)
+        .collect()
+}
+
+/// Updates kernel partition representations to match the GPT table
+///
+/// Only the partitions that differ are touched, see [`plan_gpt_sync()`] for the calls made.
+///
+/// # Arguments
+/// * `path` - Path to the block device
+///
+/// # Returns
+/// `Result<(), Error>` indicating success or partition operation failure
+#[instrument(name = "sync", skip_all, fields(device = %path.as_ref().display()))]
+pub fn sync_gpt_partitions<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    info!("Initiating GPT partition synchronization");
+    let file = File::open(&path)?;
+
+    debug!("Comparing GPT partition table with the kernel");
+    let operations = plan_gpt_sync(&path)?;
+    info!(operations = operations.len(), "Planned partition updates");
+
+    for operation in operations {
+        match operation {
+            // Deleting a partition in use fails, adding over it will report the problem
+            Operation::Delete { partition } => {
+                let _ = delete_partition(file.as_raw_fd(), partition);
+            }
+            Operation::Resize {
+                partition,
+                start,
+                length,
+            } => resize_partition(file.as_fd(), partition, start, length)?,
+            Operation::Add {
+                partition,
+                start,
+                length,
+            } => add_partition(file.as_fd(), partition, start, length)?,
+        }
     }
 
     info!("GPT partition synchronization completed successfully");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: i64 = 1024 * 1024;
+
+    #[test]
+    fn test_operations() {
+        let current = [
+            (1, MB, 100 * MB),
+            (2, 101 * MB, 200 * MB),
+            (3, 301 * MB, 50 * MB),
+            (4, 400 * MB, MB),
+        ];
+        let table = [
+            (1, MB, 100 * MB),
+            (2, 101 * MB, 150 * MB),
+            (3, 301 * MB, 80 * MB),
+            (5, 500 * MB, MB),
+            (4, 401 * MB, MB),
+        ];
+
+        assert_eq!(
+            operations(&current, &table),
+            vec![
+                Operation::Delete { partition: 4 },
+                Operation::Resize {
+                    partition: 2,
+                    start: 101 * MB,
+                    length: 150 * MB
+                },
+                Operation::Resize {
+                    partition: 3,
+                    start: 301 * MB,
+                    length: 80 * MB
+                },
+                Operation::Add {
+                    partition: 5,
+                    start: 500 * MB,
+                    length: MB
+                },
+                Operation::Add {
+                    partition: 4,
+                    start: 401 * MB,
+                    length: MB
+                },
+            ]
+        );
+        assert_eq!(
+            Operation::Resize {
+                partition: 2,
+                start: 0,
+                length: MB
+            }
+            .to_string(),
+            "resize partition 2 at 0 to 1048576 bytes"
+        );
+        assert!(operations(&table, &table).is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Btrfs subvolume provisioning
+//!
+//! Once a btrfs filesystem has been created, installers typically need a set of
+//! subvolumes (`@`, `@home`, `@snapshots`) with one of them selected as the default
+//! subvolume so that it is mounted when no `subvol=` option is given.
+//!
+//! The filesystem is mounted inside a private mount namespace, so the temporary
+//! mount is never visible to the rest of the system.
+
+use std::{
+    io,
+    path::{Component, Path},
+};
+
+use thiserror::Error;
+
+#[cfg(feature = "linux")]
+use std::{ffi::CString, fs, os::fd::AsRawFd, path::PathBuf};
+
+#[cfg(feature = "linux")]
+use linux_raw_sys::ioctl::{BTRFS_IOC_DEFAULT_SUBVOL, BTRFS_IOC_INO_LOOKUP, BTRFS_IOC_SUBVOL_CREATE};
+#[cfg(feature = "linux")]
+use nix::libc;
+#[cfg(feature = "linux")]
+use tracing::{debug, error, info};
+
+#[cfg(feature = "linux")]
+use crate::namespace::with_private_mount;
+
+/// Errors that can occur while creating subvolumes
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// Mount or namespace operation failed
+    #[cfg(feature = "linux")]
+    #[error("system call failed: {0}")]
+    Nix(#[from] nix::Error),
+    /// The subvolume path is empty, absolute or escapes the filesystem root
+    #[error("invalid subvolume path: {0}")]
+    InvalidPath(String),
+    /// The same subvolume path was requested more than once
+    #[error("duplicate subvolume: {0}")]
+    Duplicate(String),
+    /// More than one subvolume was marked as the default
+    #[error("multiple default subvolumes requested")]
+    MultipleDefaults,
+}
+
+/// A single subvolume to create
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subvolume {
+    /// Path of the subvolume relative to the top-level subvolume (e.g. `@home`)
+    pub path: String,
+    /// Whether this subvolume becomes the default subvolume
+    pub default: bool,
+    /// Where the subvolume is intended to be mounted, if anywhere
+    pub mountpoint: Option<String>,
+}
+
+impl Subvolume {
+    /// Create a new (non-default) subvolume request
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            default: false,
+            mountpoint: None,
+        }
+    }
+
+    /// Mark this subvolume as the default subvolume
+    pub fn as_default(self) -> Self {
+        Self { default: true, ..self }
+    }
+
+    /// Set the intended mount point of this subvolume
+    pub fn with_mountpoint(self, mountpoint: impl Into<String>) -> Self {
+        Self {
+            mountpoint: Some(mountpoint.into()),
+            ..self
+        }
+    }
+}
+
+/// An ordered set of subvolumes to create after formatting
+///
+/// Subvolumes are created in the order they were added, so nested subvolumes
+/// must be added after their parents.
+#[derive(Debug, Clone, Default)]
+pub struct SubvolumeLayout {
+    subvolumes: Vec<Subvolume>,
+}
+
+/// Argument block for `BTRFS_IOC_SUBVOL_CREATE`
+#[cfg(feature = "linux")]
+#[repr(C)]
+struct VolArgs {
+    fd: i64,
+    name: [u8; 4088],
+}
+
+/// Argument block for `BTRFS_IOC_INO_LOOKUP`
+#[cfg(feature = "linux")]
+#[repr(C)]
+struct InoLookupArgs {
+    treeid: u64,
+    objectid: u64,
+    name: [u8; 4080],
+}
+
+/// Object ID of the root directory within any btrfs subvolume
+#[cfg(feature = "linux")]
+const FIRST_FREE_OBJECTID: u64 = 256;
+
+impl SubvolumeLayout {
+    /// Create an empty layout
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The commonly used `@`, `@home`, `@snapshots` layout with `@` as the default
+    pub fn standard() -> Self {
+        let mut layout = Self::new();
+        layout.add(Subvolume::new("@").as_default().with_mountpoint("/"));
+        layout.add(Subvolume::new("@home").with_mountpoint("/home"));
+        layout.add(Subvolume::new("@snapshots").with_mountpoint("/.snapshots"));
+        layout
+    }
+
+    /// Add a subvolume to the layout
+    pub fn add(&mut self, subvolume: Subvolume) {
+        self.subvolumes.push(subvolume);
+    }
+
+    /// Returns the subvolumes in creation order
+    pub fn subvolumes(&self) -> &[Subvolume] {
+        &self.subvolumes
+    }
+
+    /// Returns the subvolume selected as default, if any
+    pub fn default_subvolume(&self) -> Option<&Subvolume> {
+        self.subvolumes.iter().find(|s| s.default)
+    }
+
+    /// Check that the layout can be created
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut seen = Vec::new();
+
+        for subvolume in &self.subvolumes {
+            let path = Path::new(&subvolume.path);
+            let valid = !subvolume.path.is_empty() && path.components().all(|c| matches!(c, Component::Normal(_)));
+            if !valid {
+                return Err(Error::InvalidPath(subvolume.path.clone()));
+            }
+            if seen.contains(&path) {
+                return Err(Error::Duplicate(subvolume.path.clone()));
+            }
+            seen.push(path);
+        }
+
+        if self.subvolumes.iter().filter(|s| s.default).count() > 1 {
+            return Err(Error::MultipleDefaults);
+        }
+
+        Ok(())
+    }
+
+    /// Get a human readable description of this layout
+    pub fn describe(&self) -> String {
+        if self.subvolumes.is_empty() {
+            return "No subvolumes".to_string();
+        }
+
+        let mut description = "Subvolumes:\n".to_string();
+        for subvolume in &self.subvolumes {
+            let marker = if subvolume.default { " (default)" } else { "" };
+            match &subvolume.mountpoint {
+                Some(mountpoint) => {
+                    description.push_str(&format!("  {} -> {}{}\n", subvolume.path, mountpoint, marker))
+                }
+                None => description.push_str(&format!("  {}{}\n", subvolume.path, marker)),
+            }
+        }
+        description
+    }
+
+    /// Create the subvolumes on the given btrfs device
+    ///
+    /// The device is mounted in a private mount namespace for the duration of the call.
+    #[cfg(feature = "linux")]
+    pub fn apply<P: AsRef<Path>>(&self, device: P) -> Result<(), Error> {
+        self.validate()?;
+
+        let device = device.as_ref();
+        info!("Creating {} btrfs subvolumes on {:?}", self.subvolumes.len(), device);
+
+        with_private_mount(device, "btrfs", Some("subvolid=5"), |root| self.create_all(root))
+    }
+
+    /// Create all subvolumes below the mounted top-level subvolume
+    #[cfg(feature = "linux")]
+    fn create_all(&self, root: &Path) -> Result<(), Error> {
+        for subvolume in &self.subvolumes {
+            let target = root.join(&subvolume.path);
+            create_subvolume(&target)?;
+
+            if subvolume.default {
+                set_default_subvolume(root, &target)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Create a single subvolume at the given absolute path
+#[cfg(feature = "linux")]
+fn create_subvolume(target: &Path) -> Result<(), Error> {
+    let parent = target.parent().map(PathBuf::from).unwrap_or_default();
+    let name = target
+        .file_name()
+        .ok_or_else(|| Error::InvalidPath(target.display().to_string()))?;
+    let name = CString::new(name.as_encoded_bytes()).map_err(|_| Error::InvalidPath(target.display().to_string()))?;
+
+    debug!("Creating subvolume {:?}", target);
+    fs::create_dir_all(&parent)?;
+    let dir = fs::File::open(&parent)?;
+
+    let mut args = VolArgs { fd: 0, name: [0; 4088] };
+    let bytes = name.as_bytes();
+    if bytes.len() >= args.name.len() {
+        return Err(Error::InvalidPath(target.display().to_string()));
+    }
+    args.name[..bytes.len()].copy_from_slice(bytes);
+
+    let res = unsafe { libc::ioctl(dir.as_raw_fd(), BTRFS_IOC_SUBVOL_CREATE as _, &mut args) };
+    if res < 0 {
+        let err = io::Error::last_os_error();
+        error!("Failed to create subvolume {:?}: {}", target, err);
+        return Err(err.into());
+    }
+
+    info!("Created subvolume {:?}", target);
+    Ok(())
+}
+
+/// Select the subvolume at `target` as the default for the filesystem mounted at `root`
+#[cfg(feature = "linux")]
+fn set_default_subvolume(root: &Path, target: &Path) -> Result<(), Error> {
+    let subvol = fs::File::open(target)?;
+    let mut lookup = InoLookupArgs {
+        treeid: 0,
+        objectid: FIRST_FREE_OBJECTID,
+        name: [0; 4080],
+    };
+
+    let res = unsafe { libc::ioctl(subvol.as_raw_fd(), BTRFS_IOC_INO_LOOKUP as _, &mut lookup) };
+    if res < 0 {
+        let err = io::Error::last_os_error();
+        error!("Failed to look up subvolume ID for {:?}: {}", target, err);
+        return Err(err.into());
+    }
+
+    let fs_root = fs::File::open(root)?;
+    let id = lookup.treeid;
+    let res = unsafe { libc::ioctl(fs_root.as_raw_fd(), BTRFS_IOC_DEFAULT_SUBVOL as _, &id) };
+    if res < 0 {
+        let err = io::Error::last_os_error();
+        error!("Failed to set default subvolume {}: {}", id, err);
+        return Err(err.into());
+    }
+
+    info!("Set default subvolume to {:?} (id {})", target, id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_layout() {
+        let layout = SubvolumeLayout::standard();
+        assert!(layout.validate().is_ok());
+        assert_eq!(layout.subvolumes().len(), 3);
+        assert_eq!(layout.default_subvolume().map(|s| s.path.as_str()), Some("@"));
+        eprintln!("{}", layout.describe());
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        for path in ["", "/@", "../@", "@/../home"] {
+            let mut layout = SubvolumeLayout::new();
+            layout.add(Subvolume::new(path));
+            assert!(matches!(layout.validate(), Err(Error::InvalidPath(_))), "{path}");
+        }
+
+        let mut layout = SubvolumeLayout::standard();
+        layout.add(Subvolume::new("@home"));
+        assert!(matches!(layout.validate(), Err(Error::Duplicate(_))));
+
+        let mut layout = SubvolumeLayout::standard();
+        layout.add(Subvolume::new("@var").as_default());
+        assert!(matches!(layout.validate(), Err(Error::MultipleDefaults)));
+    }
+
+    #[test]
+    fn test_nested_layout() {
+        let mut layout = SubvolumeLayout::standard();
+        layout.add(Subvolume::new("@/var/lib/machines"));
+        assert!(layout.validate().is_ok());
+    }
+}
@@ -0,0 +1,136 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Cooperative cancellation of long running operations
+//!
+//! Wiping, copying and flashing can take minutes. Operations given a [`CancellationToken`]
+//! check it between chunks and stop with [`Cancelled`], which records how far they got.
+//! A token can also carry a deadline, after which it counts as cancelled.
+//!
+//! Operations that fail with `io::Error` wrap [`Cancelled`] in an error of kind
+//! [`io::ErrorKind::Interrupted`] or [`io::ErrorKind::TimedOut`], see [`Cancelled::from_io()`].
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+/// Shared flag to stop an operation early, optionally with a deadline
+///
+/// Clones share the flag, so one clone can be handed to the operation while another
+/// cancels it from a different thread. The default token is never cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+/// An operation was stopped before it completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("{} after {processed} bytes", if *.timed_out { "timed out" } else { "cancelled" })]
+pub struct Cancelled {
+    /// Whether the deadline passed rather than the token being cancelled
+    pub timed_out: bool,
+    /// Bytes processed by the interrupted step before it stopped
+    pub processed: u64,
+}
+
+impl CancellationToken {
+    /// Create a token that is only cancelled by [`CancellationToken::cancel()`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also consider the token cancelled once `timeout` has passed from now
+    ///
+    /// Clones made before this call keep their own deadline.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            deadline: Some(Instant::now() + timeout),
+            ..self
+        }
+    }
+
+    /// Ask every operation holding a clone of this token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the token was cancelled or its deadline has passed
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.timed_out()
+    }
+
+    /// Fail with [`Cancelled`] if the operation should stop, having processed `processed` bytes
+    pub fn check(&self, processed: u64) -> Result<(), Cancelled> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(Cancelled {
+                timed_out: false,
+                processed,
+            })
+        } else if self.timed_out() {
+            Err(Cancelled {
+                timed_out: true,
+                processed,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn timed_out(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl Cancelled {
+    /// Recover the cancellation from an `io::Error` produced by converting it
+    pub fn from_io(error: &io::Error) -> Option<Self> {
+        error.get_ref()?.downcast_ref::<Self>().copied()
+    }
+}
+
+impl From<Cancelled> for io::Error {
+    fn from(cancelled: Cancelled) -> Self {
+        let kind = if cancelled.timed_out {
+            io::ErrorKind::TimedOut
+        } else {
+            io::ErrorKind::Interrupted
+        };
+        io::Error::new(kind, cancelled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert_eq!(clone.check(0), Ok(()));
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert_eq!(
+            clone.check(42),
+            Err(Cancelled {
+                timed_out: false,
+                processed: 42
+            })
+        );
+
+        let expired = CancellationToken::new().with_timeout(Duration::ZERO);
+        let error = io::Error::from(expired.check(7).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(error.to_string(), "timed out after 7 bytes");
+        assert_eq!(Cancelled::from_io(&error).map(|c| c.processed), Some(7));
+    }
+}
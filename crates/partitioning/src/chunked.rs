@@ -0,0 +1,68 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Chunked I/O shared by copying and flashing
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+
+use crate::cancel::{CancellationToken, Cancelled};
+
+/// Checksum used to verify written data
+pub(crate) const CHECKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Fill `buffer` as far as possible, returning the number of bytes read
+pub(crate) fn read_chunk<R: Read + ?Sized>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+/// Write `chunk` at the current position of `writer`, returning whether it was skipped
+///
+/// With `sparse` a chunk that is entirely zero is skipped over rather than written.
+pub(crate) fn write_chunk<W: Write + Seek>(writer: &mut W, chunk: &[u8], sparse: bool) -> io::Result<bool> {
+    if sparse && chunk.iter().all(|b| *b == 0) {
+        writer.seek(SeekFrom::Current(chunk.len() as i64))?;
+        Ok(true)
+    } else {
+        writer.write_all(chunk)?;
+        Ok(false)
+    }
+}
+
+/// Compute the checksum of the first `length` bytes of `reader`
+pub(crate) fn read_checksum<R, E>(
+    reader: &mut R,
+    length: u64,
+    buffer: &mut [u8],
+    cancel: &CancellationToken,
+) -> Result<u32, E>
+where
+    R: Read + Seek,
+    E: From<io::Error> + From<Cancelled>,
+{
+    reader.rewind()?;
+    let mut digest = CHECKSUM.digest();
+    let mut remaining = length;
+
+    while remaining > 0 {
+        cancel.check(length - remaining)?;
+        let want = buffer.len().min(remaining as usize);
+        let len = read_chunk(reader, &mut buffer[..want])?;
+        if len == 0 {
+            break;
+        }
+        digest.update(&buffer[..len]);
+        remaining -= len as u64;
+    }
+
+    Ok(digest.finalize())
+}
@@ -0,0 +1,299 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Classify the partition table of a disk before planning on it
+//!
+//! The [`Planner`](crate::planner::Planner) works from the partitions the kernel reports,
+//! while the writer edits the GPT on disk. That only goes well if both agree and the table
+//! is one the writer understands. An MBR with logical partitions, a Windows dynamic disk
+//! (LDM) or a GPT with a damaged copy all look like ordinary partitions in sysfs, but
+//! editing them blindly destroys data.
+//!
+//! [`classify()`] reads the table and reports [`Finding`]s. Errors make changing the
+//! existing table unsafe, the writer refuses to do so unless forced, see
+//! [`DiskWriter::force()`](crate::writer::DiskWriter::force). Warnings are worth showing
+//! but don't stop anything. Replacing the table with a new one is always possible.
+
+use std::{
+    fmt,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use disks::{mbr, probe_cache, BlockDevice};
+use tracing::debug;
+use uuid::{uuid, Uuid};
+
+use crate::table;
+
+/// MBR partition type of Windows dynamic disks
+const MBR_TYPE_LDM: u8 = 0x42;
+
+/// GPT partition type of the LDM metadata partition
+const LDM_METADATA: Uuid = uuid!("5808c8aa-7e8f-42e0-85d2-e1e90434cfb3");
+
+/// GPT partition type of LDM data partitions
+const LDM_DATA: Uuid = uuid!("af9b60a0-1431-4f62-bc68-3311714a69ad");
+
+/// Kind of partition table found on a disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableKind {
+    /// No partition table
+    None,
+    /// A GUID Partition Table, including damaged ones
+    Gpt,
+    /// A Master Boot Record (msdos) partition table
+    Mbr,
+}
+
+impl fmt::Display for TableKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Gpt => f.write_str("gpt"),
+            Self::Mbr => f.write_str("msdos"),
+        }
+    }
+}
+
+/// Something about a partition table that makes planning on it risky
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// The MBR holds the given number of logical partitions
+    LogicalPartitions(usize),
+    /// The disk is a Windows dynamic disk managed by the Logical Disk Manager
+    DynamicDisk,
+    /// The GPT header is present but neither copy of the table can be read
+    CorruptGpt(String),
+    /// Only the backup GPT could be read
+    DamagedPrimaryGpt,
+    /// The backup GPT is missing or damaged
+    DamagedBackupGpt,
+    /// The protective MBR of the GPT lists further partitions
+    HybridMbr,
+    /// The partitions known to the kernel differ from the table on disk
+    StaleKernelView,
+}
+
+impl Finding {
+    /// Returns true if changing the existing table could destroy data
+    pub fn is_error(&self) -> bool {
+        match self {
+            Self::LogicalPartitions(_) | Self::DynamicDisk | Self::CorruptGpt(_) | Self::DamagedPrimaryGpt => true,
+            Self::DamagedBackupGpt | Self::HybridMbr | Self::StaleKernelView => false,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LogicalPartitions(count) => write!(f, "logical partitions in an MBR partition table ({count})"),
+            Self::DynamicDisk => f.write_str("Windows dynamic disk (LDM)"),
+            Self::CorruptGpt(reason) => write!(f, "corrupt GPT ({reason})"),
+            Self::DamagedPrimaryGpt => f.write_str("damaged primary GPT, only the backup is readable"),
+            Self::DamagedBackupGpt => f.write_str("missing or damaged backup GPT"),
+            Self::HybridMbr => f.write_str("hybrid MBR alongside the GPT"),
+            Self::StaleKernelView => f.write_str("partitions known to the kernel differ from the partition table"),
+        }
+    }
+}
+
+/// The partition table of a disk and what is wrong with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    /// Kind of table found
+    pub kind: TableKind,
+    /// Problems found, errors and warnings alike
+    pub findings: Vec<Finding>,
+}
+
+impl Classification {
+    /// Findings that make changing the existing table unsafe
+    pub fn errors(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.is_error())
+    }
+
+    /// Findings worth showing that don't stop planning
+    pub fn warnings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| !f.is_error())
+    }
+
+    /// Returns true if the existing table can be changed safely
+    pub fn is_safe(&self) -> bool {
+        self.errors().next().is_none()
+    }
+}
+
+/// Classify the partition table of `device`
+///
+/// Besides the table itself, the partitions the kernel reports are compared with those
+/// of a GPT, see [`Finding::StaleKernelView`].
+pub fn classify(device: &BlockDevice) -> io::Result<Classification> {
+    let mut reader = probe_cache::open(device.device())?;
+    let mut classification = classify_table(&mut reader)?;
+
+    if classification.kind == TableKind::Gpt && classification.is_safe() {
+        if let Ok(gpt) = table::read(&mut reader) {
+            let sectors = gpt.block_size / 512;
+            let on_disk = gpt
+                .entries
+                .iter()
+                .map(|e| (e.number, e.first_lba * sectors))
+                .collect::<Vec<_>>();
+            let kernel = device
+                .partitions()
+                .iter()
+                .map(|p| (p.number, p.start))
+                .collect::<Vec<_>>();
+            if on_disk != kernel {
+                debug!("Partitions on disk {:?} differ from the kernel's {:?}", on_disk, kernel);
+                classification.findings.push(Finding::StaleKernelView);
+            }
+        }
+    }
+
+    debug!(
+        "Partition table of {:?}: {} {:?}",
+        device.device(),
+        classification.kind,
+        classification.findings
+    );
+    Ok(classification)
+}
+
+/// Classify the partition table of a disk or image
+pub fn classify_table<R: Read + Seek>(reader: &mut R) -> io::Result<Classification> {
+    let mbr = mbr::read(reader)?;
+    let gpt = match table::read(reader) {
+        Ok(gpt) => Some(Ok(gpt)),
+        Err(table::Error::Io(e)) if e.kind() != io::ErrorKind::UnexpectedEof => return Err(e),
+        Err(table::Error::NotFound) => None,
+        Err(e) => Some(Err(e)),
+    };
+
+    let mut findings = vec![];
+    let kind = match (mbr, gpt) {
+        // The kernel prefers a real MBR over a stray GPT
+        (Some(mbr), _) if !mbr.is_protective() => {
+            let logical = mbr.entries.iter().filter(|e| e.is_logical()).count();
+            if logical > 0 {
+                findings.push(Finding::LogicalPartitions(logical));
+            }
+            if mbr.entries.iter().any(|e| e.partition_type == MBR_TYPE_LDM) {
+                findings.push(Finding::DynamicDisk);
+            }
+            TableKind::Mbr
+        }
+        (mbr, Some(Ok(gpt))) => {
+            if !gpt.is_primary() {
+                findings.push(Finding::DamagedPrimaryGpt);
+            } else if table::read_at(reader, gpt.block_size, gpt.header.alternate_lba).is_err() {
+                findings.push(Finding::DamagedBackupGpt);
+            }
+            if gpt
+                .entries
+                .iter()
+                .any(|e| e.type_guid == LDM_METADATA || e.type_guid == LDM_DATA)
+            {
+                findings.push(Finding::DynamicDisk);
+            }
+            if mbr.is_some_and(|m| m.entries.iter().any(|e| e.partition_type != mbr::TYPE_PROTECTIVE)) {
+                findings.push(Finding::HybridMbr);
+            }
+            TableKind::Gpt
+        }
+        (_, Some(Err(e))) => {
+            findings.push(Finding::CorruptGpt(e.to_string()));
+            TableKind::Gpt
+        }
+        (Some(_), None) => {
+            findings.push(Finding::CorruptGpt("protective MBR without a GPT header".to_owned()));
+            TableKind::Gpt
+        }
+        (None, None) => TableKind::None,
+    };
+
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(Classification { kind, findings })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, OpenOptions},
+        io::{Cursor, Write},
+    };
+
+    use super::*;
+    use crate::testing::ImageBuilder;
+
+    const MB: u64 = 1024 * 1024;
+
+    /// Write an MBR partition entry into the boot record at `sector`
+    fn set_entry(image: &mut [u8], sector: u64, slot: usize, kind: u8, start: u32, size: u32) {
+        let offset = sector as usize * 512 + 0x1be + slot * 16;
+        image[offset + 4] = kind;
+        image[offset + 8..offset + 12].copy_from_slice(&start.to_le_bytes());
+        image[offset + 12..offset + 16].copy_from_slice(&size.to_le_bytes());
+        image[sector as usize * 512 + 510..sector as usize * 512 + 512].copy_from_slice(&[0x55, 0xaa]);
+    }
+
+    fn corrupt(path: &std::path::Path, offset: u64) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(b"garbage!").unwrap();
+    }
+
+    #[test]
+    fn test_classify_mbr() {
+        let mut image = vec![0u8; 4 * MB as usize];
+        assert_eq!(classify_table(&mut Cursor::new(&image)).unwrap().kind, TableKind::None);
+
+        set_entry(&mut image, 0, 0, 0x83, 2048, 2048);
+        let classification = classify_table(&mut Cursor::new(&image)).unwrap();
+        assert_eq!(classification.kind, TableKind::Mbr);
+        assert!(classification.findings.is_empty());
+
+        // An extended partition at 4096 with one logical partition
+        set_entry(&mut image, 0, 1, 0x05, 4096, 4096);
+        set_entry(&mut image, 4096, 0, 0x83, 2048, 1024);
+        let classification = classify_table(&mut Cursor::new(&image)).unwrap();
+        assert_eq!(classification.findings, vec![Finding::LogicalPartitions(1)]);
+        assert!(!classification.is_safe());
+
+        set_entry(&mut image, 0, 2, MBR_TYPE_LDM, 1024, 512);
+        let classification = classify_table(&mut Cursor::new(&image)).unwrap();
+        assert!(classification.findings.contains(&Finding::DynamicDisk));
+        assert_eq!(
+            classification.errors().next().unwrap().to_string(),
+            "logical partitions in an MBR partition table (1)"
+        );
+    }
+
+    #[test]
+    fn test_classify_gpt() {
+        let image = ImageBuilder::new(16 * MB).partition(4 * MB).build().unwrap();
+        let mut file = fs::File::open(&image.path).unwrap();
+        let classification = classify_table(&mut file).unwrap();
+        assert_eq!(classification.kind, TableKind::Gpt);
+        assert!(classification.findings.is_empty());
+
+        corrupt(&image.path, 16 * MB - 512);
+        let classification = classify_table(&mut file).unwrap();
+        assert_eq!(classification.findings, vec![Finding::DamagedBackupGpt]);
+        assert!(classification.is_safe());
+
+        let image = ImageBuilder::new(16 * MB).partition(4 * MB).build().unwrap();
+        corrupt(&image.path, 512 + 16);
+        let mut file = fs::File::open(&image.path).unwrap();
+        let classification = classify_table(&mut file).unwrap();
+        assert_eq!(classification.findings, vec![Finding::DamagedPrimaryGpt]);
+        assert!(!classification.is_safe());
+
+        corrupt(&image.path, 16 * MB - 512 + 16);
+        let classification = classify_table(&mut file).unwrap();
+        assert_eq!(classification.kind, TableKind::Gpt);
+        assert!(matches!(classification.findings[..], [Finding::CorruptGpt(_)]));
+    }
+}
@@ -0,0 +1,247 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Copying partition contents between devices
+//!
+//! Used by migrate-in-place flows where an existing filesystem is copied into a partition
+//! of a new layout before the old one is removed.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Seek, SeekFrom},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+use tracing::{debug, info, instrument};
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    chunked::{read_checksum, read_chunk, write_chunk},
+    progress::{Event, NoProgress, ProgressSink},
+    throttle::Throttle,
+};
+
+/// Errors that can occur while copying a partition
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// The target cannot hold the contents of the source
+    #[error("target too small: {needed} bytes needed, {available} available")]
+    TargetTooSmall { needed: u64, available: u64 },
+    /// The data read back from the target does not match the source
+    #[error("verification failed: checksum {expected:08x} expected, found {actual:08x}")]
+    VerificationFailed { expected: u32, actual: u32 },
+    /// The copy was cancelled or timed out, the target holds a partial copy
+    #[error("copy {0}")]
+    Cancelled(#[from] Cancelled),
+}
+
+/// Options controlling [`copy_partition_with()`]
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    /// Size of each read/write in bytes
+    pub chunk_size: usize,
+    /// Skip writing chunks that are entirely zero
+    ///
+    /// Only safe when the target already reads back as zeroes, e.g. a fresh sparse
+    /// image or a device that was just discarded.
+    pub sparse: bool,
+    /// Read back the target after copying and compare checksums
+    pub verify: bool,
+    /// Checked before every chunk, including while verifying
+    pub cancel: CancellationToken,
+    /// Maximum bytes of the source copied per second, unlimited if `None`
+    pub rate_limit: Option<u64>,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 4 * 1024 * 1024,
+            sparse: false,
+            verify: false,
+            cancel: CancellationToken::default(),
+            rate_limit: None,
+        }
+    }
+}
+
+/// Summary of a completed copy
+#[derive(Debug, Clone)]
+pub struct CopyStats {
+    /// Total number of bytes in the source
+    pub total: u64,
+    /// Bytes skipped because they were zero (sparse copies only)
+    pub skipped: u64,
+    /// Time taken by the copy, excluding verification
+    pub elapsed: Duration,
+    /// Checksum of the copied data, when verification was requested
+    pub checksum: Option<u32>,
+}
+
+impl CopyStats {
+    /// Average throughput in bytes per second
+    pub fn rate(&self) -> u64 {
+        match self.elapsed.as_millis() {
+            0 => self.total,
+            ms => (self.total as u128 * 1000 / ms) as u64,
+        }
+    }
+}
+
+/// Copy the contents of `source` onto `target` using the default options
+pub fn copy_partition<S: AsRef<Path>, T: AsRef<Path>>(source: S, target: T) -> Result<CopyStats, Error> {
+    copy_partition_with(source, target, &CopyOptions::default(), &NoProgress)
+}
+
+/// Copy the contents of `source` onto `target`
+///
+/// The target must be at least as large as the source. Progress is reported in bytes
+/// of the source processed.
+#[instrument(name = "copy", skip_all, fields(source = %source.as_ref().display(), target = %target.as_ref().display()))]
+pub fn copy_partition_with<S: AsRef<Path>, T: AsRef<Path>>(
+    source: S,
+    target: T,
+    options: &CopyOptions,
+    progress: &dyn ProgressSink,
+) -> Result<CopyStats, Error> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    info!("Copying {:?} to {:?}", source, target);
+
+    let mut input = File::open(source)?;
+    let mut output = OpenOptions::new().read(true).write(true).open(target)?;
+
+    let total = input.seek(SeekFrom::End(0))?;
+    let available = output.seek(SeekFrom::End(0))?;
+    if available < total {
+        return Err(Error::TargetTooSmall {
+            needed: total,
+            available,
+        });
+    }
+    input.rewind()?;
+    output.rewind()?;
+
+    let step = format!("Copying {} to {}", source.display(), target.display());
+    progress.event(Event::StepStarted(step.clone()));
+
+    let started = Instant::now();
+    let throttle = Throttle::new(options.rate_limit);
+    let mut buffer = vec![0u8; options.chunk_size];
+    let mut processed = 0;
+    let mut skipped = 0;
+
+    while processed < total {
+        throttle.wait(processed, &options.cancel);
+        options.cancel.check(processed)?;
+        let len = read_chunk(&mut input, &mut buffer)?;
+        if len == 0 {
+            break;
+        }
+
+        if write_chunk(&mut output, &buffer[..len], options.sparse)? {
+            skipped += len as u64;
+        }
+
+        processed += len as u64;
+        progress.event(Event::Bytes { processed, total });
+    }
+    output.sync_all()?;
+    let elapsed = started.elapsed();
+
+    debug!(bytes = processed, skipped, ?elapsed, "Copied partition contents");
+
+    let checksum = if options.verify {
+        let expected = read_checksum::<_, Error>(&mut input, total, &mut buffer, &options.cancel)?;
+        let actual = read_checksum::<_, Error>(&mut output, total, &mut buffer, &options.cancel)?;
+        if expected != actual {
+            return Err(Error::VerificationFailed { expected, actual });
+        }
+        debug!("Verified copy with checksum {:08x}", expected);
+        Some(expected)
+    } else {
+        None
+    };
+
+    progress.event(Event::StepCompleted(step));
+    info!("Copied {:?} to {:?}", source, target);
+
+    Ok(CopyStats {
+        total,
+        skipped,
+        elapsed,
+        checksum,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("disks-rs-copy-{}-{}.img", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_sparse_verified_copy() {
+        let source = temp_path("source");
+        let target = temp_path("target");
+
+        let mut data = vec![0u8; 4 * MB];
+        data[..MB].fill(0xaa);
+        data[3 * MB..].fill(0x55);
+        std::fs::write(&source, &data).unwrap();
+        std::fs::File::create(&target).unwrap().set_len(8 * MB as u64).unwrap();
+
+        let options = CopyOptions {
+            chunk_size: MB,
+            sparse: true,
+            verify: true,
+            ..Default::default()
+        };
+        let stats = copy_partition_with(&source, &target, &options, &NoProgress).unwrap();
+        assert_eq!(stats.total, 4 * MB as u64);
+        assert_eq!(stats.skipped, 2 * MB as u64);
+        assert!(stats.checksum.is_some());
+        assert_eq!(std::fs::read(&target).unwrap()[..4 * MB], data[..]);
+
+        let options = CopyOptions {
+            chunk_size: MB,
+            cancel: CancellationToken::new().with_timeout(Duration::ZERO),
+            ..Default::default()
+        };
+        assert!(matches!(
+            copy_partition_with(&source, &target, &options, &NoProgress),
+            Err(Error::Cancelled(Cancelled {
+                timed_out: true,
+                processed: 0
+            }))
+        ));
+
+        // 4MiB at 16MiB/s, the last chunk is due after 3/16 of a second
+        let options = CopyOptions {
+            chunk_size: MB,
+            rate_limit: Some(16 * MB as u64),
+            ..Default::default()
+        };
+        let stats = copy_partition_with(&source, &target, &options, &NoProgress).unwrap();
+        assert!(stats.elapsed >= Duration::from_millis(180));
+
+        // The source no longer fits
+        std::fs::File::create(&target).unwrap().set_len(MB as u64).unwrap();
+        assert!(matches!(
+            copy_partition(&source, &target),
+            Err(Error::TargetTooSmall { .. })
+        ));
+
+        let _ = std::fs::remove_file(&source);
+        let _ = std::fs::remove_file(&target);
+    }
+}
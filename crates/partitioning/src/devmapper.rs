@@ -0,0 +1,554 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Device mapper control
+//!
+//! [`DeviceMapper`] speaks the ioctl interface of `/dev/mapper/control` directly, so
+//! opened LUKS containers (`crypt` targets) and flat test mappings (`linear` targets) can
+//! be created, suspended, reloaded and removed without `dmsetup` or `cryptsetup`. A new
+//! mapping is a `dm-N` block device as soon as it is created, so it is found by
+//! [`BlockDevice::discover()`] right away, see [`MappedDevice::block_device()`].
+//!
+//! Every request is a `struct dm_ioctl` header followed by a data area, encoded by hand
+//! at the offsets of the kernel ABI (version 4). Sizes and offsets of targets are in
+//! 512-byte sectors, as everywhere in the device mapper.
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use disks::BlockDevice;
+use linux_raw_sys::ioctl::{
+    DM_DEV_CREATE, DM_DEV_REMOVE, DM_DEV_STATUS, DM_DEV_SUSPEND, DM_LIST_DEVICES, DM_LIST_VERSIONS, DM_TABLE_LOAD,
+    DM_TABLE_STATUS, DM_VERSION,
+};
+use nix::libc;
+use tracing::{debug, error, info, instrument};
+
+/// Path of the control node
+const CONTROL: &str = "/dev/mapper/control";
+
+/// Interface version we speak, the kernel accepts any minor version
+const VERSION: [u32; 3] = [4, 0, 0];
+
+/// Size of `struct dm_ioctl`
+const HEADER_SIZE: usize = 312;
+/// Length of the name field, including the terminating NUL
+const NAME_LEN: usize = 128;
+/// Length of the uuid field, including the terminating NUL
+const UUID_LEN: usize = 129;
+/// Size of `struct dm_target_spec`, which is followed by the target parameters
+const SPEC_SIZE: usize = 40;
+/// Length of the target type field, including the terminating NUL
+const TYPE_LEN: usize = 16;
+
+/// Offsets of the `struct dm_ioctl` fields
+const OFFSET_DATA_SIZE: usize = 12;
+const OFFSET_DATA_START: usize = 16;
+const OFFSET_TARGET_COUNT: usize = 20;
+const OFFSET_OPEN_COUNT: usize = 24;
+const OFFSET_FLAGS: usize = 28;
+const OFFSET_EVENT_NR: usize = 32;
+const OFFSET_DEV: usize = 40;
+const OFFSET_NAME: usize = 48;
+const OFFSET_UUID: usize = OFFSET_NAME + NAME_LEN;
+
+const DM_READONLY_FLAG: u32 = 1 << 0;
+const DM_SUSPEND_FLAG: u32 = 1 << 1;
+const DM_STATUS_TABLE_FLAG: u32 = 1 << 4;
+const DM_ACTIVE_PRESENT_FLAG: u32 = 1 << 5;
+const DM_BUFFER_FULL_FLAG: u32 = 1 << 8;
+
+/// Has udev process the uevent like one sent by libdevmapper, creating `/dev/mapper` links
+const DM_UDEV_PRIMARY_SOURCE_FLAG: u32 = 0x0040 << 16;
+
+/// Initial size of request buffers, doubled while the kernel reports them as full
+const BUFFER_SIZE: usize = 16 * 1024;
+/// Largest buffer we are willing to allocate for a reply
+const MAX_BUFFER_SIZE: usize = 16 * 1024 * 1024;
+
+/// A single target of a device mapper table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// First sector of the mapped device covered by the target
+    pub start: u64,
+    /// Number of sectors covered by the target
+    pub length: u64,
+    /// Target type, e.g. `linear` or `crypt`
+    pub target_type: String,
+    /// Target parameters, as passed to `dmsetup create --table`
+    pub params: String,
+}
+
+impl Target {
+    /// Map `length` sectors onto `device`, starting at sector `offset` of it
+    pub fn linear(start: u64, length: u64, device: &Path, offset: u64) -> Self {
+        Self {
+            start,
+            length,
+            target_type: "linear".to_owned(),
+            params: format!("{} {}", device.display(), offset),
+        }
+    }
+
+    /// Decrypt `length` sectors of `device`, starting at sector `offset` of it
+    ///
+    /// `key` is the volume key as hex, e.g. from a LUKS2 keyslot, and `cipher` is in
+    /// kernel notation such as `aes-xts-plain64`.
+    pub fn crypt(start: u64, length: u64, cipher: &str, key: &str, device: &Path, offset: u64) -> Self {
+        Self {
+            start,
+            length,
+            target_type: "crypt".to_owned(),
+            params: format!("{} {} 0 {} {}", cipher, key, device.display(), offset),
+        }
+    }
+}
+
+/// State of a mapped device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedDevice {
+    /// Name of the mapping, as found in `/dev/mapper`
+    pub name: String,
+    /// UUID of the mapping, if one was set when it was created
+    pub uuid: Option<String>,
+    /// Major number of the block device
+    pub major: u32,
+    /// Minor number of the block device, `N` in `dm-N`
+    pub minor: u32,
+    /// Number of openers of the block device
+    pub open_count: i32,
+    /// Number of targets in the active table
+    pub target_count: u32,
+    /// The device holds I/O until it is resumed
+    pub suspended: bool,
+    /// The device rejects writes
+    pub read_only: bool,
+    /// The device has a table and can be used
+    pub active: bool,
+}
+
+impl MappedDevice {
+    /// Kernel name of the block device, e.g. `dm-0`
+    pub fn kernel_name(&self) -> String {
+        format!("dm-{}", self.minor)
+    }
+
+    /// Device node of the block device
+    ///
+    /// `/dev/dm-N` is created by the kernel, the `/dev/mapper` link only once udev ran.
+    pub fn device_path(&self) -> PathBuf {
+        Path::new("/dev").join(self.kernel_name())
+    }
+
+    /// Discover the mapped device as a block device
+    pub fn block_device(&self) -> io::Result<BlockDevice> {
+        BlockDevice::from_sysfs_path("/", self.kernel_name())
+    }
+}
+
+/// A device mapper request: the `struct dm_ioctl` header and its data area
+struct Request {
+    buffer: Vec<u8>,
+}
+
+impl Request {
+    /// Create a request for the mapping `name` with room for `size` bytes in total
+    fn new(name: Option<&str>, size: usize) -> io::Result<Self> {
+        let mut request = Self {
+            buffer: vec![0; size.max(HEADER_SIZE)],
+        };
+        for (i, part) in VERSION.iter().enumerate() {
+            request.set_u32(i * 4, *part);
+        }
+        request.set_u32(OFFSET_DATA_SIZE, request.buffer.len() as u32);
+        request.set_u32(OFFSET_DATA_START, HEADER_SIZE as u32);
+        if let Some(name) = name {
+            request.set_str(OFFSET_NAME, NAME_LEN, name)?;
+        }
+        Ok(request)
+    }
+
+    /// Create a request loading `targets` as the table of `name`
+    fn with_targets(name: &str, targets: &[Target]) -> io::Result<Self> {
+        let data = encode_targets(targets)?;
+        let mut request = Self::new(Some(name), HEADER_SIZE + data.len())?;
+        request.buffer[HEADER_SIZE..].copy_from_slice(&data);
+        request.set_u32(OFFSET_TARGET_COUNT, targets.len() as u32);
+        Ok(request)
+    }
+
+    fn u32(&self, offset: usize) -> u32 {
+        read_u32(&self.buffer, offset)
+    }
+
+    fn set_u32(&mut self, offset: usize, value: u32) {
+        self.buffer[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+
+    /// Write a NUL terminated string into a field of `len` bytes
+    fn set_str(&mut self, offset: usize, len: usize, value: &str) -> io::Result<()> {
+        if value.len() >= len || value.contains('\0') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid device mapper name or uuid: {value:?}"),
+            ));
+        }
+        self.buffer[offset..offset + value.len()].copy_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    fn flags(&self) -> u32 {
+        self.u32(OFFSET_FLAGS)
+    }
+
+    fn set_flags(&mut self, flags: u32) {
+        self.set_u32(OFFSET_FLAGS, flags);
+    }
+
+    /// The data area of a reply
+    fn data(&self) -> &[u8] {
+        let start = (self.u32(OFFSET_DATA_START) as usize).min(self.buffer.len());
+        let end = (self.u32(OFFSET_DATA_SIZE) as usize).clamp(start, self.buffer.len());
+        &self.buffer[start..end]
+    }
+
+    /// Decode the device state in a reply
+    fn device(&self) -> MappedDevice {
+        let (major, minor) = decode_dev(u64::from_ne_bytes(
+            self.buffer[OFFSET_DEV..OFFSET_DEV + 8].try_into().unwrap(),
+        ));
+        let flags = self.flags();
+        MappedDevice {
+            name: read_str(&self.buffer[OFFSET_NAME..OFFSET_NAME + NAME_LEN]),
+            uuid: Some(read_str(&self.buffer[OFFSET_UUID..OFFSET_UUID + UUID_LEN])).filter(|u| !u.is_empty()),
+            major,
+            minor,
+            open_count: self.u32(OFFSET_OPEN_COUNT) as i32,
+            target_count: self.u32(OFFSET_TARGET_COUNT),
+            suspended: flags & DM_SUSPEND_FLAG != 0,
+            read_only: flags & DM_READONLY_FLAG != 0,
+            active: flags & DM_ACTIVE_PRESENT_FLAG != 0,
+        }
+    }
+}
+
+/// A handle on the device mapper control node
+pub struct DeviceMapper {
+    control: File,
+}
+
+impl DeviceMapper {
+    /// Open the control node and check the kernel speaks our interface version
+    pub fn open() -> io::Result<Self> {
+        debug!("Opening device mapper control node");
+        let control = OpenOptions::new().read(true).write(true).open(CONTROL)?;
+        let dm = Self { control };
+        let version = dm.version()?;
+        debug!("Device mapper interface version {:?}", version);
+        Ok(dm)
+    }
+
+    /// Version of the kernel interface
+    pub fn version(&self) -> io::Result<[u32; 3]> {
+        let mut request = Request::new(None, HEADER_SIZE)?;
+        self.ioctl(DM_VERSION, &mut request)?;
+        Ok([request.u32(0), request.u32(4), request.u32(8)])
+    }
+
+    /// Issue a single ioctl on the control node
+    fn ioctl(&self, command: u32, request: &mut Request) -> io::Result<()> {
+        let res = unsafe { libc::ioctl(self.control.as_raw_fd(), command as _, request.buffer.as_mut_ptr()) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Issue a query, growing the buffer until the reply fits
+    fn query(&self, command: u32, name: Option<&str>, flags: u32) -> io::Result<Request> {
+        let mut size = BUFFER_SIZE;
+        loop {
+            let mut request = Request::new(name, size)?;
+            request.set_flags(flags);
+            self.ioctl(command, &mut request)?;
+            if request.flags() & DM_BUFFER_FULL_FLAG == 0 {
+                return Ok(request);
+            }
+            if size >= MAX_BUFFER_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::OutOfMemory,
+                    "device mapper reply too large",
+                ));
+            }
+            size *= 2;
+        }
+    }
+
+    /// Create and activate the mapping `name` with the given table
+    ///
+    /// The mapping is removed again if the table cannot be loaded or activated.
+    #[instrument(skip(self, targets))]
+    pub fn create(
+        &self,
+        name: &str,
+        uuid: Option<&str>,
+        targets: &[Target],
+        read_only: bool,
+    ) -> io::Result<MappedDevice> {
+        let mut request = Request::new(Some(name), HEADER_SIZE)?;
+        if let Some(uuid) = uuid {
+            request.set_str(OFFSET_UUID, UUID_LEN, uuid)?;
+        }
+        if let Err(e) = self.ioctl(DM_DEV_CREATE, &mut request) {
+            error!("Failed to create mapping {}: {}", name, e);
+            return Err(e);
+        }
+
+        if let Err(e) = self
+            .load_table(name, targets, read_only)
+            .and_then(|_| self.resume(name))
+        {
+            error!("Failed to activate mapping {}: {}", name, e);
+            let _ = self.remove(name);
+            return Err(e);
+        }
+
+        let device = self.status(name)?;
+        info!("Created mapping {} as {}", name, device.kernel_name());
+        Ok(device)
+    }
+
+    /// Load `targets` as the inactive table of `name`
+    ///
+    /// The table replaces the active one on the next [`DeviceMapper::resume()`].
+    #[instrument(skip(self, targets))]
+    pub fn load_table(&self, name: &str, targets: &[Target], read_only: bool) -> io::Result<()> {
+        let mut request = Request::with_targets(name, targets)?;
+        if read_only {
+            request.set_flags(DM_READONLY_FLAG);
+        }
+        self.ioctl(DM_TABLE_LOAD, &mut request)?;
+        debug!("Loaded {} targets", targets.len());
+        Ok(())
+    }
+
+    /// Suspend `name`, holding back new I/O and flushing what is in flight
+    #[instrument(skip(self))]
+    pub fn suspend(&self, name: &str) -> io::Result<()> {
+        let mut request = Request::new(Some(name), HEADER_SIZE)?;
+        request.set_flags(DM_SUSPEND_FLAG);
+        self.ioctl(DM_DEV_SUSPEND, &mut request)?;
+        info!("Suspended mapping {}", name);
+        Ok(())
+    }
+
+    /// Resume `name`, switching to its inactive table if one was loaded
+    #[instrument(skip(self))]
+    pub fn resume(&self, name: &str) -> io::Result<()> {
+        let mut request = Request::new(Some(name), HEADER_SIZE)?;
+        request.set_u32(OFFSET_EVENT_NR, DM_UDEV_PRIMARY_SOURCE_FLAG);
+        self.ioctl(DM_DEV_SUSPEND, &mut request)?;
+        info!("Resumed mapping {}", name);
+        Ok(())
+    }
+
+    /// Remove the mapping `name`, which fails while it is open
+    #[instrument(skip(self))]
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        let mut request = Request::new(Some(name), HEADER_SIZE)?;
+        request.set_u32(OFFSET_EVENT_NR, DM_UDEV_PRIMARY_SOURCE_FLAG);
+        if let Err(e) = self.ioctl(DM_DEV_REMOVE, &mut request) {
+            error!("Failed to remove mapping {}: {}", name, e);
+            return Err(e);
+        }
+        info!("Removed mapping {}", name);
+        Ok(())
+    }
+
+    /// Current state of the mapping `name`
+    pub fn status(&self, name: &str) -> io::Result<MappedDevice> {
+        let mut request = Request::new(Some(name), HEADER_SIZE)?;
+        self.ioctl(DM_DEV_STATUS, &mut request)?;
+        Ok(request.device())
+    }
+
+    /// State of every mapping
+    pub fn list(&self) -> io::Result<Vec<MappedDevice>> {
+        let request = self.query(DM_LIST_DEVICES, None, 0)?;
+        decode_names(request.data())
+            .into_iter()
+            .map(|name| self.status(&name))
+            .collect()
+    }
+
+    /// The active table of the mapping `name`
+    pub fn table(&self, name: &str) -> io::Result<Vec<Target>> {
+        let request = self.query(DM_TABLE_STATUS, Some(name), DM_STATUS_TABLE_FLAG)?;
+        decode_targets(request.data(), request.u32(OFFSET_TARGET_COUNT) as usize)
+    }
+
+    /// Target types known to the kernel with their versions
+    ///
+    /// Only loaded target modules are listed, loading a table requests its module.
+    pub fn target_types(&self) -> io::Result<Vec<(String, [u32; 3])>> {
+        let request = self.query(DM_LIST_VERSIONS, None, 0)?;
+        Ok(decode_versions(request.data()))
+    }
+}
+
+/// Encode the target specs of a table load, each followed by its parameters
+fn encode_targets(targets: &[Target]) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    for (i, target) in targets.iter().enumerate() {
+        if target.target_type.len() >= TYPE_LEN || target.params.contains('\0') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid device mapper target {:?}", target.target_type),
+            ));
+        }
+        let len = (SPEC_SIZE + target.params.len() + 1).next_multiple_of(8);
+        let mut spec = vec![0; len];
+        spec[0..8].copy_from_slice(&target.start.to_ne_bytes());
+        spec[8..16].copy_from_slice(&target.length.to_ne_bytes());
+        // Offset of the next spec from this one
+        let next = if i + 1 < targets.len() { len as u32 } else { 0 };
+        spec[20..24].copy_from_slice(&next.to_ne_bytes());
+        spec[24..24 + target.target_type.len()].copy_from_slice(target.target_type.as_bytes());
+        spec[SPEC_SIZE..SPEC_SIZE + target.params.len()].copy_from_slice(target.params.as_bytes());
+        data.extend_from_slice(&spec);
+    }
+    Ok(data)
+}
+
+/// Decode the target specs of a table status reply
+///
+/// Unlike in requests, `next` is the offset of the next spec from the start of the data.
+fn decode_targets(data: &[u8], count: usize) -> io::Result<Vec<Target>> {
+    let truncated = || io::Error::new(io::ErrorKind::InvalidData, "truncated device mapper table");
+    let mut targets = Vec::with_capacity(count);
+    let mut offset = 0;
+    for _ in 0..count {
+        let spec = data.get(offset..offset + SPEC_SIZE).ok_or_else(truncated)?;
+        let next = read_u32(spec, 20) as usize;
+        let end = if next > offset {
+            next.min(data.len())
+        } else {
+            data.len()
+        };
+        targets.push(Target {
+            start: u64::from_ne_bytes(spec[0..8].try_into().unwrap()),
+            length: u64::from_ne_bytes(spec[8..16].try_into().unwrap()),
+            target_type: read_str(&spec[24..24 + TYPE_LEN]),
+            params: read_str(data.get(offset + SPEC_SIZE..end).ok_or_else(truncated)?),
+        });
+        offset = next;
+    }
+    Ok(targets)
+}
+
+/// Decode the `struct dm_name_list` entries of a device list reply
+fn decode_names(data: &[u8]) -> Vec<String> {
+    let mut names = vec![];
+    let mut offset = 0;
+    // An empty list is a single entry without a device
+    while let Some(entry) = data.get(offset..offset + 12) {
+        if u64::from_ne_bytes(entry[0..8].try_into().unwrap()) == 0 {
+            break;
+        }
+        names.push(read_str(&data[offset + 12..]));
+        match read_u32(entry, 8) as usize {
+            0 => break,
+            next => offset += next,
+        }
+    }
+    names
+}
+
+/// Decode the `struct dm_target_versions` entries of a version list reply
+fn decode_versions(data: &[u8]) -> Vec<(String, [u32; 3])> {
+    let mut versions = vec![];
+    let mut offset = 0;
+    while let Some(entry) = data.get(offset..offset + 16) {
+        let version = [read_u32(entry, 4), read_u32(entry, 8), read_u32(entry, 12)];
+        versions.push((read_str(&data[offset + 16..]), version));
+        match read_u32(entry, 0) as usize {
+            0 => break,
+            next => offset += next,
+        }
+    }
+    versions
+}
+
+/// Split a kernel `huge_encode_dev` device number into major and minor
+fn decode_dev(dev: u64) -> (u32, u32) {
+    let major = (dev >> 8) & 0xfff;
+    let minor = (dev & 0xff) | ((dev >> 12) & 0xfff00);
+    (major as u32, minor as u32)
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+/// Read a NUL terminated string
+fn read_str(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_tables() {
+        let targets = vec![
+            Target::linear(0, 2048, Path::new("/dev/loop0"), 2048),
+            Target::crypt(2048, 4096, "aes-xts-plain64", "00ff", Path::new("/dev/loop1"), 32768),
+        ];
+        let request = Request::with_targets("test", &targets).unwrap();
+        assert_eq!(request.u32(OFFSET_TARGET_COUNT), 2);
+        assert_eq!(request.u32(OFFSET_DATA_SIZE) as usize, request.buffer.len());
+        assert_eq!(read_str(&request.buffer[OFFSET_NAME..]), "test");
+
+        // Replies count `next` from the start of the data, requests from the previous spec
+        let mut data = request.buffer[HEADER_SIZE..].to_vec();
+        let first = read_u32(&data, 20);
+        assert_eq!(first % 8, 0);
+        let end = data.len() as u32;
+        data[first as usize + 20..first as usize + 24].copy_from_slice(&end.to_ne_bytes());
+        assert_eq!(decode_targets(&data, 2).unwrap(), targets);
+        assert!(decode_targets(&data[..first as usize], 2).is_err());
+
+        assert!(Request::new(Some(&"x".repeat(NAME_LEN)), HEADER_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_decode_replies() {
+        // Two devices with 8 byte aligned entries, dm-0 and dm-300 on major 253
+        let mut data = vec![0u8; 48];
+        data[0..8].copy_from_slice(&(253u64 << 8).to_ne_bytes());
+        data[8..12].copy_from_slice(&24u32.to_ne_bytes());
+        data[12..16].copy_from_slice(b"root");
+        data[24..32].copy_from_slice(&((253u64 << 8) | (300 & 0xff) | ((300 & !0xff) << 12)).to_ne_bytes());
+        data[36..40].copy_from_slice(b"home");
+        assert_eq!(decode_names(&data), vec!["root", "home"]);
+        assert!(decode_names(&[0; 16]).is_empty());
+        assert_eq!(decode_dev(read_u64(&data, 24)), (253, 300));
+
+        let mut data = vec![0u8; 32];
+        data[4..8].copy_from_slice(&1u32.to_ne_bytes());
+        data[8..12].copy_from_slice(&4u32.to_ne_bytes());
+        data[16..22].copy_from_slice(b"linear");
+        assert_eq!(decode_versions(&data), vec![("linear".to_owned(), [1, 4, 0])]);
+    }
+
+    fn read_u64(buffer: &[u8], offset: usize) -> u64 {
+        u64::from_ne_bytes(buffer[offset..offset + 8].try_into().unwrap())
+    }
+}
@@ -0,0 +1,364 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Writing disk images onto devices
+//!
+//! Appliances are often provisioned by writing a prebuilt image rather than partitioning
+//! and formatting the disk. [`flash_with()`] streams a raw or zstd-compressed image onto a
+//! device, optionally checking it against a known checksum and reading the device back,
+//! and then asks the kernel to pick up the partition table of the image.
+
+use std::{
+    cell::Cell,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+use tracing::{debug, info, instrument};
+
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    chunked::{read_checksum, read_chunk, write_chunk, CHECKSUM},
+    progress::{Event, NoProgress, ProgressSink},
+    throttle::Throttle,
+};
+
+/// Magic number at the start of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Errors that can occur while flashing an image
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error, including corrupt compressed data
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// The target cannot hold the contents of the image
+    #[error("target too small: at least {needed} bytes needed, {available} available")]
+    TargetTooSmall { needed: u64, available: u64 },
+    /// The image does not have the expected checksum
+    #[error("image checksum mismatch: {expected:08x} expected, found {actual:08x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The data read back from the target does not match the image
+    #[error("verification failed: checksum {expected:08x} expected, found {actual:08x}")]
+    VerificationFailed { expected: u32, actual: u32 },
+    /// The flash was cancelled or timed out, the target holds a partial image
+    #[error("flash {0}")]
+    Cancelled(#[from] Cancelled),
+}
+
+/// How an image is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The image is a plain copy of a disk
+    None,
+    /// The image is compressed with zstd
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression of an image from its first bytes, leaving `reader` rewound
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.rewind()?;
+        let len = read_chunk(reader, &mut magic)?;
+        reader.rewind()?;
+        Ok(if len == magic.len() && magic == ZSTD_MAGIC {
+            Compression::Zstd
+        } else {
+            Compression::None
+        })
+    }
+}
+
+/// Options controlling [`flash_with()`]
+#[derive(Debug, Clone)]
+pub struct FlashOptions {
+    /// Size of each write in bytes
+    pub chunk_size: usize,
+    /// Skip writing chunks that are entirely zero, see [`CopyOptions::sparse`](crate::copy::CopyOptions::sparse)
+    pub sparse: bool,
+    /// Read back the target after writing and compare checksums
+    pub verify: bool,
+    /// Fail if the uncompressed image does not have this checksum
+    ///
+    /// The image is only known to be intact once it has been written completely, so a
+    /// mismatch leaves the target with a corrupt image.
+    pub checksum: Option<u32>,
+    /// Have the kernel read the partition table of block devices afterwards
+    pub rescan: bool,
+    /// Checked before every chunk, including while verifying
+    pub cancel: CancellationToken,
+    /// Maximum bytes written to the target per second, unlimited if `None`
+    pub rate_limit: Option<u64>,
+}
+
+impl Default for FlashOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 4 * 1024 * 1024,
+            sparse: false,
+            verify: false,
+            checksum: None,
+            rescan: true,
+            cancel: CancellationToken::default(),
+            rate_limit: None,
+        }
+    }
+}
+
+/// Summary of a completed flash
+#[derive(Debug, Clone)]
+pub struct FlashStats {
+    /// How the image was stored
+    pub compression: Compression,
+    /// Size of the image file in bytes
+    pub image_size: u64,
+    /// Bytes written to the target, i.e. the uncompressed image size
+    pub written: u64,
+    /// Bytes skipped because they were zero (sparse writes only)
+    pub skipped: u64,
+    /// Time taken to write the image, excluding verification
+    pub elapsed: Duration,
+    /// Checksum of the uncompressed image
+    pub checksum: u32,
+    /// Whether the kernel picked up the partition table of the image
+    pub rescanned: bool,
+}
+
+/// Write the image at `image` onto `target` using the default options
+pub fn flash<S: AsRef<Path>, T: AsRef<Path>>(image: S, target: T) -> Result<FlashStats, Error> {
+    flash_with(image, target, &FlashOptions::default(), &NoProgress)
+}
+
+/// Write the image at `image` onto `target`
+///
+/// The compression of the image is detected from its contents. Progress is reported in
+/// bytes of the image file processed, as the uncompressed size is not known up front.
+/// A failed rescan is logged but does not fail the flash, see [`FlashStats::rescanned`].
+#[instrument(name = "flash", skip_all, fields(image = %image.as_ref().display(), target = %target.as_ref().display()))]
+pub fn flash_with<S: AsRef<Path>, T: AsRef<Path>>(
+    image: S,
+    target: T,
+    options: &FlashOptions,
+    progress: &dyn ProgressSink,
+) -> Result<FlashStats, Error> {
+    let (image, target) = (image.as_ref(), target.as_ref());
+    info!("Flashing {:?} to {:?}", image, target);
+
+    let mut input = File::open(image)?;
+    let mut output = OpenOptions::new().read(true).write(true).open(target)?;
+
+    let image_size = input.seek(SeekFrom::End(0))?;
+    let available = output.seek(SeekFrom::End(0))?;
+    let compression = Compression::detect(&mut input)?;
+    if compression == Compression::None && available < image_size {
+        return Err(Error::TargetTooSmall {
+            needed: image_size,
+            available,
+        });
+    }
+    output.rewind()?;
+    debug!(?compression, image_size, "Detected image format");
+
+    let step = format!("Flashing {} to {}", image.display(), target.display());
+    progress.event(Event::StepStarted(step.clone()));
+
+    let consumed = Cell::new(0);
+    let counted = Counted {
+        inner: input,
+        count: &consumed,
+    };
+    let mut reader: Box<dyn Read + '_> = match compression {
+        Compression::None => Box::new(counted),
+        Compression::Zstd => Box::new(zstd::Decoder::new(counted)?),
+    };
+
+    let started = Instant::now();
+    let mut buffer = vec![0u8; options.chunk_size];
+    let mut digest = CHECKSUM.digest();
+    let mut written = 0;
+    let mut skipped = 0;
+
+    let throttle = Throttle::new(options.rate_limit);
+    loop {
+        throttle.wait(written, &options.cancel);
+        options.cancel.check(written)?;
+        let len = read_chunk(&mut reader, &mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        if written + len as u64 > available {
+            return Err(Error::TargetTooSmall {
+                needed: written + len as u64,
+                available,
+            });
+        }
+
+        let chunk = &buffer[..len];
+        digest.update(chunk);
+        if write_chunk(&mut output, chunk, options.sparse)? {
+            skipped += len as u64;
+        }
+
+        written += len as u64;
+        progress.event(Event::Bytes {
+            processed: consumed.get(),
+            total: image_size,
+        });
+    }
+    output.sync_all()?;
+    let elapsed = started.elapsed();
+    let checksum = digest.finalize();
+
+    debug!(bytes = written, skipped, ?elapsed, "Wrote image contents");
+
+    if let Some(expected) = options.checksum.filter(|e| *e != checksum) {
+        return Err(Error::ChecksumMismatch {
+            expected,
+            actual: checksum,
+        });
+    }
+
+    if options.verify {
+        let actual = read_checksum::<_, Error>(&mut output, written, &mut buffer, &options.cancel)?;
+        if actual != checksum {
+            return Err(Error::VerificationFailed {
+                expected: checksum,
+                actual,
+            });
+        }
+        debug!("Verified image with checksum {:08x}", checksum);
+    }
+
+    let rescanned = options.rescan && rescan(&output, target);
+
+    progress.event(Event::StepCompleted(step));
+    info!("Flashed {:?} to {:?}", image, target);
+
+    Ok(FlashStats {
+        compression,
+        image_size,
+        written,
+        skipped,
+        elapsed,
+        checksum,
+        rescanned,
+    })
+}
+
+/// Tell the kernel about the partitions of a freshly flashed block device
+#[cfg(feature = "linux")]
+pub(crate) fn rescan(device: &File, path: &Path) -> bool {
+    use std::os::{fd::AsFd, unix::fs::FileTypeExt};
+
+    use tracing::warn;
+
+    if !device.metadata().is_ok_and(|m| m.file_type().is_block_device()) {
+        return false;
+    }
+    match crate::blkpg::reread_partition_table(device.as_fd()) {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("Cannot reread partition table of {:?}: {}", path, e);
+            crate::blkpg::sync_gpt_partitions(path)
+                .inspect_err(|e| warn!("Failed to rescan partitions of {:?}: {}", path, e))
+                .is_ok()
+        }
+    }
+}
+
+#[cfg(not(feature = "linux"))]
+pub(crate) fn rescan(_: &File, _: &Path) -> bool {
+    false
+}
+
+/// Counts the bytes read through it
+struct Counted<'a, R> {
+    inner: R,
+    count: &'a Cell<u64>,
+}
+
+impl<R: Read> Read for Counted<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count.set(self.count.get() + len as u64);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("disks-rs-flash-{}-{}.img", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_flash_images() {
+        let raw = temp_path("raw");
+        let compressed = temp_path("zst");
+        let target = temp_path("target");
+
+        let mut data = vec![0u8; 4 * MB];
+        data[..MB].fill(0xaa);
+        data[3 * MB..].fill(0x55);
+        std::fs::write(&raw, &data).unwrap();
+        std::fs::write(&compressed, zstd::encode_all(&data[..], 3).unwrap()).unwrap();
+        let checksum = CHECKSUM.checksum(&data);
+
+        for (image, compression) in [(&raw, Compression::None), (&compressed, Compression::Zstd)] {
+            std::fs::File::create(&target).unwrap().set_len(8 * MB as u64).unwrap();
+            let options = FlashOptions {
+                chunk_size: MB,
+                sparse: true,
+                verify: true,
+                checksum: Some(checksum),
+                ..Default::default()
+            };
+            let stats = flash_with(image, &target, &options, &NoProgress).unwrap();
+            assert_eq!(stats.compression, compression);
+            assert_eq!(stats.written, 4 * MB as u64);
+            assert_eq!(stats.skipped, 2 * MB as u64);
+            assert_eq!(stats.checksum, checksum);
+            assert!(!stats.rescanned);
+            assert_eq!(std::fs::read(&target).unwrap()[..4 * MB], data[..]);
+        }
+
+        let options = FlashOptions {
+            checksum: Some(checksum ^ 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            flash_with(&compressed, &target, &options, &NoProgress),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let options = FlashOptions {
+            cancel,
+            ..Default::default()
+        };
+        assert!(matches!(
+            flash_with(&raw, &target, &options, &NoProgress),
+            Err(Error::Cancelled(Cancelled { timed_out: false, .. }))
+        ));
+
+        // Neither image fits, the compressed one is only caught while writing
+        std::fs::File::create(&target).unwrap().set_len(2 * MB as u64).unwrap();
+        for image in [&raw, &compressed] {
+            assert!(matches!(flash(image, &target), Err(Error::TargetTooSmall { .. })));
+        }
+
+        for path in [raw, compressed, target] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Creating filesystems on partitions
+//!
+//! Filesystems are created with the standard `mkfs` tools of each filesystem, which
+//! must be available in `PATH`.
+
+use std::{
+    fmt, io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use thiserror::Error;
+use tracing::{debug, error, info, instrument};
+
+use crate::{
+    cancel::Cancelled,
+    progress::{Event, NoProgress, ProgressSink},
+};
+
+/// Errors that can occur while creating a filesystem
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The mkfs tool could not be run
+    #[error("failed to run {tool}: {source}")]
+    Spawn { tool: &'static str, source: io::Error },
+    /// The mkfs tool reported a failure
+    #[error("{tool} failed: {stderr}")]
+    Failed { tool: &'static str, stderr: String },
+    /// The filesystem was not created because the operation was cancelled first
+    #[error("filesystem creation {0}")]
+    Cancelled(#[from] Cancelled),
+}
+
+/// Filesystems that can be created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilesystemType {
+    Btrfs,
+    Ext4,
+    F2fs,
+    Fat32,
+    Swap,
+    Xfs,
+}
+
+impl fmt::Display for FilesystemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Btrfs => f.write_str("btrfs"),
+            Self::Ext4 => f.write_str("ext4"),
+            Self::F2fs => f.write_str("f2fs"),
+            Self::Fat32 => f.write_str("vfat"),
+            Self::Swap => f.write_str("swap"),
+            Self::Xfs => f.write_str("xfs"),
+        }
+    }
+}
+
+impl FilesystemType {
+    /// The tool used to create this filesystem
+    pub(crate) fn tool(&self) -> &'static str {
+        match self {
+            Self::Btrfs => "mkfs.btrfs",
+            Self::Ext4 => "mkfs.ext4",
+            Self::F2fs => "mkfs.f2fs",
+            Self::Fat32 => "mkfs.fat",
+            Self::Swap => "mkswap",
+            Self::Xfs => "mkfs.xfs",
+        }
+    }
+
+    /// Arguments to overwrite any existing filesystem without prompting
+    fn force_args(&self) -> &'static [&'static str] {
+        match self {
+            Self::Btrfs | Self::F2fs | Self::Swap | Self::Xfs => &["-f"],
+            Self::Ext4 => &["-F"],
+            Self::Fat32 => &["-F", "32"],
+        }
+    }
+
+    /// Flag used to set the label
+    fn label_flag(&self) -> &'static str {
+        match self {
+            Self::F2fs => "-l",
+            Self::Fat32 => "-n",
+            _ => "-L",
+        }
+    }
+}
+
+/// A filesystem to create on a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    /// Type of filesystem to create
+    pub filesystem: FilesystemType,
+    /// Optional filesystem label
+    pub label: Option<String>,
+    /// Extra arguments passed to the mkfs tool before the device
+    pub options: Vec<String>,
+}
+
+impl Format {
+    /// Create a new unlabelled filesystem of the given type
+    pub fn new(filesystem: FilesystemType) -> Self {
+        Self {
+            filesystem,
+            label: None,
+            options: Vec::new(),
+        }
+    }
+
+    /// Set the filesystem label
+    pub fn with_label(self, label: impl Into<String>) -> Self {
+        Self {
+            label: Some(label.into()),
+            ..self
+        }
+    }
+
+    /// Build the mkfs invocation for `device`
+    pub fn command(&self, device: &Path) -> Command {
+        let mut command = Command::new(self.filesystem.tool());
+        command.args(self.filesystem.force_args());
+        if let Some(label) = &self.label {
+            command.arg(self.filesystem.label_flag()).arg(label);
+        }
+        command.args(&self.options).arg(device);
+        command
+    }
+
+    /// Create the filesystem on `device`
+    pub fn run(&self, device: &Path) -> Result<(), Error> {
+        self.run_with_progress(device, &NoProgress)
+    }
+
+    /// Like [`Format::run()`], reporting progress to `progress`
+    #[instrument(name = "format", skip_all, fields(filesystem = %self.filesystem, device = %device.display()))]
+    pub fn run_with_progress(&self, device: &Path, progress: &dyn ProgressSink) -> Result<(), Error> {
+        let tool = self.filesystem.tool();
+        let step = format!("Creating {} filesystem on {}", self.filesystem, device.display());
+        progress.event(Event::StepStarted(step.clone()));
+
+        let mut command = self.command(device);
+        debug!("Running {:?}", command);
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|source| Error::Spawn { tool, source })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            error!("{} failed on {:?}: {}", tool, device, stderr);
+            return Err(Error::Failed { tool, stderr });
+        }
+
+        info!("Created {} filesystem on {:?}", self.filesystem, device);
+        progress.event(Event::StepCompleted(step));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command.get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn test_format_commands() {
+        let device = Path::new("/dev/sda2");
+
+        let ext4 = Format::new(FilesystemType::Ext4).with_label("root");
+        let command = ext4.command(device);
+        assert_eq!(command.get_program(), "mkfs.ext4");
+        assert_eq!(args(&command), vec!["-F", "-L", "root", "/dev/sda2"]);
+
+        let mut fat = Format::new(FilesystemType::Fat32).with_label("ESP");
+        fat.options.push("-s1".into());
+        assert_eq!(
+            args(&fat.command(device)),
+            vec!["-F", "32", "-n", "ESP", "-s1", "/dev/sda2"]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Free space and fragmentation reports
+//!
+//! A disk can have plenty of free space and still not fit a partition, because the space
+//! is split across several gaps. [`Planner::free_space()`] lists every gap with the size a
+//! partition aligned to [`PARTITION_ALIGNMENT`] can actually use, and
+//! [`Planner::suggest_deletions()`] finds the existing partitions that would have to go to
+//! make room for a partition of a given size. Together they let installers explain "not
+//! enough contiguous space" instead of just failing.
+
+use std::fmt;
+
+use crate::planner::{format_size, Change, Planner, Region, PARTITION_ALIGNMENT};
+
+/// A gap in the partition layout
+#[derive(Debug, Clone)]
+pub struct FreeRegion {
+    /// The gap between partitions
+    pub region: Region,
+    /// Bytes a partition aligned to [`PARTITION_ALIGNMENT`] can use
+    pub usable: u64,
+}
+
+/// Free regions of a disk, in disk order
+#[derive(Debug, Clone, Default)]
+pub struct FreeSpaceReport {
+    /// Every gap in the current layout, including those too small to use
+    pub regions: Vec<FreeRegion>,
+}
+
+impl FreeSpaceReport {
+    /// Usable bytes across all regions
+    pub fn total(&self) -> u64 {
+        self.regions.iter().map(|r| r.usable).sum()
+    }
+
+    /// The region with the most usable space, the first one on ties
+    pub fn largest(&self) -> Option<&FreeRegion> {
+        self.regions
+            .iter()
+            .reduce(|largest, r| if r.usable > largest.usable { r } else { largest })
+    }
+
+    /// Returns true if `size` bytes are free in total, but not in a single region
+    pub fn is_fragmented_for(&self, size: u64) -> bool {
+        self.total() >= size && self.largest().is_none_or(|r| r.usable < size)
+    }
+}
+
+impl fmt::Display for FreeSpaceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.largest() {
+            Some(largest) => write!(
+                f,
+                "{} free in {} regions, largest {} at {}",
+                format_size(self.total()),
+                self.regions.len(),
+                format_size(largest.usable),
+                format_size(largest.region.start)
+            ),
+            None => f.write_str("No free space"),
+        }
+    }
+}
+
+/// Existing partitions whose deletion makes room for a partition
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    /// Original partition indices, as passed to [`Planner::plan_delete_partition()`]
+    pub delete: Vec<usize>,
+    /// The free region left once they are deleted
+    pub region: FreeRegion,
+}
+
+impl Planner {
+    /// Report the free regions of the current layout
+    pub fn free_space(&self) -> FreeSpaceReport {
+        FreeSpaceReport {
+            regions: self.free_regions().into_iter().map(free_region).collect(),
+        }
+    }
+
+    /// Find runs of adjacent existing partitions whose deletion frees `size` bytes
+    ///
+    /// Only partitions found on the disk are considered, planned additions are never
+    /// suggested for deletion. Suggestions with the fewest deletions come first, then
+    /// those deleting the least data. Nothing is suggested if `size` fits already.
+    pub fn suggest_deletions(&self, size: u64) -> Vec<Suggestion> {
+        if self.free_space().largest().is_some_and(|r| r.usable >= size) {
+            return vec![];
+        }
+
+        let (usable_start, usable_end) = self.offsets();
+        let deleted = self
+            .changes()
+            .iter()
+            .filter_map(|c| match c {
+                Change::DeletePartition { original_index } => Some(*original_index),
+                Change::AddPartition { .. } => None,
+            })
+            .collect::<Vec<_>>();
+
+        // The current layout with the original index of every partition still on disk
+        let mut layout = self
+            .current_layout()
+            .into_iter()
+            .map(|region| {
+                let original = self
+                    .original_regions()
+                    .iter()
+                    .enumerate()
+                    .find(|(i, r)| !deleted.contains(i) && (r.start, r.end) == (region.start, region.end))
+                    .map(|(i, _)| i);
+                (region, original)
+            })
+            .collect::<Vec<_>>();
+        layout.sort_by_key(|(r, _)| r.start);
+
+        let mut suggestions = vec![];
+        for first in 0..layout.len() {
+            let start = match first {
+                0 => usable_start,
+                _ => layout[first - 1].0.end.max(usable_start),
+            };
+            for last in first..layout.len() {
+                let Some(_) = layout[last].1 else {
+                    break;
+                };
+                let end = layout
+                    .get(last + 1)
+                    .map_or(usable_end, |(r, _)| r.start.min(usable_end));
+                let region = free_region(Region::new(start, end));
+                if region.usable >= size {
+                    suggestions.push(Suggestion {
+                        delete: layout[first..=last].iter().filter_map(|(_, i)| *i).collect(),
+                        region,
+                    });
+                    break;
+                }
+            }
+        }
+
+        let deleted_bytes =
+            |s: &Suggestion| -> u64 { s.delete.iter().map(|i| self.original_regions()[*i].size()).sum() };
+        suggestions.sort_by_key(|s| (s.delete.len(), deleted_bytes(s)));
+        suggestions
+    }
+}
+
+/// Compute the space an aligned partition can use within `region`
+fn free_region(region: Region) -> FreeRegion {
+    let start = region.start.div_ceil(PARTITION_ALIGNMENT) * PARTITION_ALIGNMENT;
+    let end = region.end / PARTITION_ALIGNMENT * PARTITION_ALIGNMENT;
+    FreeRegion {
+        usable: end.saturating_sub(start),
+        region,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+    const GB: u64 = 1024 * MB;
+
+    #[test]
+    fn test_fragmentation() {
+        // 10GiB free at the start, 20GiB between the partitions and 30GiB at the end
+        let mut disk = MockDisk::new(100 * GB);
+        disk.add_partition(10 * GB, 30 * GB);
+        disk.add_partition(50 * GB, 70 * GB + 512);
+        let planner = Planner::new(&BlockDevice::mock_device(disk));
+
+        let report = planner.free_space();
+        let usable = report.regions.iter().map(|r| r.usable).collect::<Vec<_>>();
+        assert_eq!(usable, vec![10 * GB, 20 * GB, 30 * GB - MB]);
+        assert_eq!(report.largest().unwrap().region.start, 70 * GB + 512);
+        assert!(report.is_fragmented_for(40 * GB));
+        assert!(!report.is_fragmented_for(20 * GB));
+        assert_eq!(
+            report.to_string(),
+            "60.0GiB free in 3 regions, largest 30.0GiB at 70.0GiB"
+        );
+
+        assert!(planner.suggest_deletions(30 * GB - MB).is_empty());
+        let suggestions = planner.suggest_deletions(45 * GB);
+        assert_eq!(suggestions.len(), 2);
+        // Either partition will do, the first one holds slightly less data
+        assert_eq!(suggestions[0].delete, vec![0]);
+        let region = &suggestions[0].region.region;
+        assert_eq!((region.start, region.end), (0, 50 * GB));
+        assert_eq!(suggestions[1].delete, vec![1]);
+        assert_eq!(suggestions[1].region.usable, 70 * GB);
+
+        assert_eq!(planner.suggest_deletions(95 * GB)[0].delete, vec![0, 1]);
+        assert!(planner.suggest_deletions(200 * GB).is_empty());
+    }
+
+    #[test]
+    fn test_planned_changes() {
+        let mut disk = MockDisk::new(100 * GB);
+        disk.add_partition(MB, 50 * GB);
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        assert!(planner.plan_add_partition(50 * GB, 60 * GB).is_ok());
+        assert!(planner.plan_delete_partition(0).is_ok());
+
+        let report = planner.free_space();
+        assert_eq!(report.regions.len(), 2);
+        assert_eq!(report.total(), 90 * GB);
+
+        // The planned partition is never suggested
+        assert!(planner.suggest_deletions(80 * GB).is_empty());
+        assert_eq!(FreeSpaceReport::default().to_string(), "No free space");
+    }
+}
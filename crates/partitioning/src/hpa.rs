@@ -0,0 +1,353 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detect capacity hidden by a host protected area or device configuration overlay
+//!
+//! ATA disks can be told to report less than their native capacity. A host protected
+//! area (HPA), or on newer disks the accessible max address, hides the end of the disk
+//! from the operating system until it is removed again. A device configuration overlay
+//! (DCO) goes further and lowers what the disk reports as its native capacity.
+//!
+//! The kernel only sees the accessible capacity, which is what the planner lays out
+//! partitions in. [`capacity()`] asks the disk for all three through SCSI/ATA translation
+//! (`SG_IO` with ATA PASS-THROUGH), so users can be told about space they could reclaim.
+//! NVMe and other non-ATA disks report no capacity.
+
+use std::{fs::File, io, os::fd::AsRawFd, path::Path, ptr};
+
+use nix::libc;
+use tracing::debug;
+
+/// `SG_IO`, not provided by `linux-raw-sys`
+const SG_IO: u32 = 0x2285;
+const SG_INTERFACE_ID: i32 = b'S' as i32;
+const SG_DXFER_NONE: i32 = -1;
+const SG_DXFER_FROM_DEV: i32 = -3;
+const SG_TIMEOUT_MS: u32 = 10_000;
+
+/// ATA PASS-THROUGH (16)
+const ATA_16: u8 = 0x85;
+
+const ATA_IDENTIFY: u8 = 0xec;
+const ATA_READ_NATIVE_MAX_EXT: u8 = 0x27;
+const ATA_GET_NATIVE_MAX_EXT: u8 = 0x78;
+const ATA_DEVICE_CONFIGURATION: u8 = 0xb1;
+const DCO_IDENTIFY: u8 = 0xc2;
+
+/// Descriptor format sense data
+const SENSE_DESCRIPTOR: u8 = 0x72;
+/// ATA Status Return sense descriptor
+const ATA_RETURN_DESCRIPTOR: u8 = 0x09;
+
+/// `struct sg_io_hdr`
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: i32,
+    dxfer_direction: i32,
+    cmd_len: u8,
+    mx_sb_len: u8,
+    iovec_count: u16,
+    dxfer_len: u32,
+    dxferp: *mut libc::c_void,
+    cmdp: *const u8,
+    sbp: *mut u8,
+    timeout: u32,
+    flags: u32,
+    pack_id: i32,
+    usr_ptr: *mut libc::c_void,
+    status: u8,
+    masked_status: u8,
+    msg_status: u8,
+    sb_len_wr: u8,
+    host_status: u16,
+    driver_status: u16,
+    resid: i32,
+    duration: u32,
+    info: u32,
+}
+
+/// Capacities of an ATA disk, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtaCapacity {
+    /// Capacity the disk currently exposes, as seen by the kernel
+    pub accessible: u64,
+    /// Capacity before any host protected area, as far as the disk admits
+    pub native: u64,
+    /// Capacity before any device configuration overlay, if the disk supports DCO
+    pub factory: Option<u64>,
+}
+
+impl AtaCapacity {
+    /// Bytes hidden by a host protected area or accessible max address
+    pub fn hpa_hidden(&self) -> u64 {
+        self.native.saturating_sub(self.accessible)
+    }
+
+    /// Bytes hidden by a device configuration overlay
+    pub fn dco_hidden(&self) -> u64 {
+        self.factory.map_or(0, |f| f.saturating_sub(self.native))
+    }
+
+    /// Total bytes the disk hides from the operating system
+    pub fn hidden(&self) -> u64 {
+        self.hpa_hidden() + self.dco_hidden()
+    }
+}
+
+/// Query the accessible, native and factory capacity of the ATA disk at `path`
+///
+/// Returns `Ok(None)` if the disk does not speak ATA, e.g. NVMe or virtual disks.
+pub fn capacity(path: &Path) -> io::Result<Option<AtaCapacity>> {
+    let file = File::open(path)?;
+
+    let mut identify = [0u8; 512];
+    if let Err(e) = ata_command(&file, pio_in(ATA_IDENTIFY, 0), &mut identify) {
+        debug!("{:?} does not answer ATA IDENTIFY: {}", path, e);
+        return Ok(None);
+    }
+    let identify = Identify(&identify);
+    let sector_size = identify.sector_size();
+    let accessible = identify.max_lba() + 1;
+
+    let command = if identify.amac_supported() {
+        Some(ATA_GET_NATIVE_MAX_EXT)
+    } else if identify.hpa_supported() {
+        Some(ATA_READ_NATIVE_MAX_EXT)
+    } else {
+        None
+    };
+    let native = match command.map(|c| native_max(&file, c)) {
+        Some(Ok(max)) => max + 1,
+        Some(Err(e)) => {
+            debug!("Cannot read native max address of {:?}: {}", path, e);
+            accessible
+        }
+        None => accessible,
+    };
+
+    let factory = if identify.dco_supported() {
+        let mut dco = [0u8; 512];
+        match ata_command(&file, pio_in(ATA_DEVICE_CONFIGURATION, DCO_IDENTIFY), &mut dco) {
+            Ok(_) => Some(dco_max_lba(&dco) + 1),
+            Err(e) => {
+                debug!("DCO IDENTIFY failed on {:?}: {}", path, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let capacity = AtaCapacity {
+        accessible: accessible * sector_size,
+        native: native * sector_size,
+        factory: factory.map(|f| f * sector_size),
+    };
+    debug!("ATA capacity of {:?}: {:?}", path, capacity);
+    Ok(Some(capacity))
+}
+
+/// Words of an ATA IDENTIFY DEVICE reply
+struct Identify<'a>(&'a [u8; 512]);
+
+impl Identify<'_> {
+    fn word(&self, index: usize) -> u16 {
+        u16::from_le_bytes([self.0[index * 2], self.0[index * 2 + 1]])
+    }
+
+    fn words(&self, index: usize, count: usize) -> u64 {
+        (0..count).fold(0, |value, i| value | (self.word(index + i) as u64) << (16 * i))
+    }
+
+    /// Highest addressable LBA, using 48-bit addressing where supported
+    fn max_lba(&self) -> u64 {
+        if self.word(83) & (1 << 10) != 0 {
+            self.words(100, 4) - 1
+        } else {
+            self.words(60, 2) - 1
+        }
+    }
+
+    /// Logical sector size in bytes
+    fn sector_size(&self) -> u64 {
+        let word = self.word(106);
+        if word & 0xc000 == 0x4000 && word & (1 << 12) != 0 {
+            self.words(117, 2) * 2
+        } else {
+            512
+        }
+    }
+
+    fn hpa_supported(&self) -> bool {
+        self.word(82) & (1 << 10) != 0
+    }
+
+    fn dco_supported(&self) -> bool {
+        self.word(83) & (1 << 11) != 0
+    }
+
+    /// Accessible Max Address Configuration, which replaces HPA from ACS-3 on
+    fn amac_supported(&self) -> bool {
+        let word = self.word(119);
+        word & 0xc000 == 0x4000 && word & (1 << 8) != 0
+    }
+}
+
+/// Highest LBA of the factory configuration from a DEVICE CONFIGURATION IDENTIFY reply
+fn dco_max_lba(data: &[u8; 512]) -> u64 {
+    Identify(data).words(3, 4)
+}
+
+/// CDB of a 28-bit PIO data-in command returning a single sector
+fn pio_in(command: u8, feature: u8) -> [u8; 16] {
+    let mut cdb = [0u8; 16];
+    cdb[0] = ATA_16;
+    // PIO data-in
+    cdb[1] = 4 << 1;
+    // T_DIR from device, BYT_BLK, T_LENGTH in the sector count
+    cdb[2] = 0x0e;
+    cdb[4] = feature;
+    cdb[6] = 1;
+    cdb[14] = command;
+    cdb
+}
+
+/// Issue a 48-bit non-data command returning the highest native LBA
+fn native_max(file: &File, command: u8) -> io::Result<u64> {
+    let mut cdb = [0u8; 16];
+    cdb[0] = ATA_16;
+    // Non-data, 48-bit
+    cdb[1] = (3 << 1) | 1;
+    // CK_COND, so the registers come back as sense data
+    cdb[2] = 0x20;
+    cdb[13] = 0x40;
+    cdb[14] = command;
+    let sense = ata_command(file, cdb, &mut [])?;
+    returned_lba(&sense).ok_or_else(|| io::Error::other("no ATA status return in sense data"))
+}
+
+/// LBA registers from the ATA Status Return descriptor of descriptor format sense data
+fn returned_lba(sense: &[u8; 32]) -> Option<u64> {
+    if sense[0] & 0x7f != SENSE_DESCRIPTOR {
+        return None;
+    }
+    let descriptors = &sense[8..8 + (sense[7] as usize).min(24)];
+    let desc = descriptors.get(..14).filter(|d| d[0] == ATA_RETURN_DESCRIPTOR)?;
+    Some(
+        desc[7] as u64
+            | (desc[9] as u64) << 8
+            | (desc[11] as u64) << 16
+            | (desc[6] as u64) << 24
+            | (desc[8] as u64) << 32
+            | (desc[10] as u64) << 40,
+    )
+}
+
+/// Send an ATA PASS-THROUGH command, reading into `data`, and return the sense data
+fn ata_command(file: &File, cdb: [u8; 16], data: &mut [u8]) -> io::Result<[u8; 32]> {
+    let mut sense = [0u8; 32];
+    let mut hdr = SgIoHdr {
+        interface_id: SG_INTERFACE_ID,
+        dxfer_direction: if data.is_empty() {
+            SG_DXFER_NONE
+        } else {
+            SG_DXFER_FROM_DEV
+        },
+        cmd_len: cdb.len() as u8,
+        mx_sb_len: sense.len() as u8,
+        iovec_count: 0,
+        dxfer_len: data.len() as u32,
+        dxferp: if data.is_empty() {
+            ptr::null_mut()
+        } else {
+            data.as_mut_ptr().cast()
+        },
+        cmdp: cdb.as_ptr(),
+        sbp: sense.as_mut_ptr(),
+        timeout: SG_TIMEOUT_MS,
+        flags: 0,
+        pack_id: 0,
+        usr_ptr: ptr::null_mut(),
+        status: 0,
+        masked_status: 0,
+        msg_status: 0,
+        sb_len_wr: 0,
+        host_status: 0,
+        driver_status: 0,
+        resid: 0,
+        duration: 0,
+        info: 0,
+    };
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), SG_IO as _, &mut hdr) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if hdr.host_status != 0 {
+        return Err(io::Error::other(format!("SG_IO host status {:#x}", hdr.host_status)));
+    }
+
+    // Sense data is expected with CK_COND, anything else is an error unless the
+    // returned ATA status is clean
+    let ata_error = match returned_status(&sense) {
+        Some(status) => status & 0x01 != 0,
+        None => hdr.status != 0,
+    };
+    if ata_error {
+        return Err(io::Error::other(format!("ATA command {:#x} aborted", cdb[14])));
+    }
+    Ok(sense)
+}
+
+/// ATA status register from the ATA Status Return descriptor, if present
+fn returned_status(sense: &[u8; 32]) -> Option<u8> {
+    if sense[0] & 0x7f != SENSE_DESCRIPTOR || sense[8] != ATA_RETURN_DESCRIPTOR {
+        return None;
+    }
+    Some(sense[8 + 13])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capacity_parsing() {
+        let mut data = [0u8; 512];
+        let mut set = |word: usize, value: u16| data[word * 2..word * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        // 48-bit and DCO supported, HPA supported
+        set(83, (1 << 10) | (1 << 11));
+        set(82, 1 << 10);
+        // 1000215216 sectors accessible
+        set(100, 0x12b0);
+        set(101, 0x3b9e);
+        let identify = Identify(&data);
+        assert_eq!(identify.max_lba(), 1000215215);
+        assert_eq!(identify.sector_size(), 512);
+        assert!(identify.hpa_supported() && identify.dco_supported());
+        assert!(!identify.amac_supported());
+
+        let mut sense = [0u8; 32];
+        sense[0] = SENSE_DESCRIPTOR;
+        sense[7] = 14;
+        sense[8] = ATA_RETURN_DESCRIPTOR;
+        sense[9] = 12;
+        // A native max LBA above the accessible one, split over the low and high register bytes
+        let lba: u64 = 0x3b9e_22af;
+        for (index, shift) in [(15, 0), (17, 8), (19, 16), (14, 24), (16, 32), (18, 40)] {
+            sense[index] = (lba >> shift) as u8;
+        }
+        sense[21] = 0x50;
+        assert_eq!(returned_lba(&sense), Some(lba));
+        assert_eq!(returned_status(&sense), Some(0x50));
+
+        let capacity = AtaCapacity {
+            accessible: 1000 * 512,
+            native: 1200 * 512,
+            factory: Some(1500 * 512),
+        };
+        assert_eq!(capacity.hpa_hidden(), 200 * 512);
+        assert_eq!(capacity.dco_hidden(), 300 * 512);
+        assert_eq!(capacity.hidden(), 500 * 512);
+    }
+}
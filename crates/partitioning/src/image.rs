@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Attaching disk images as block devices
+//!
+//! A loop device maps a file byte for byte, so attaching anything but a raw image gives a
+//! block device full of container metadata instead of a disk. [`Image::open()`] recognises
+//! the common virtual machine formats and refuses them with a hint to convert the image
+//! first. Raw images can be attached as a whole, or only a byte range of them, e.g. a
+//! single partition found in the GPT of the image with [`Image::with_partition()`].
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+use tracing::debug;
+
+use crate::table;
+
+/// Errors that can occur while opening or attaching an image
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// The image is not raw and would attach as garbage
+    #[error("unsupported image format {0}, convert it to a raw image first (e.g. `qemu-img convert -O raw`)")]
+    UnsupportedFormat(ImageFormat),
+    /// The requested range does not lie within the image
+    #[error("range of {size} bytes at offset {offset} exceeds the image size of {image_size} bytes")]
+    OutOfBounds { offset: u64, size: u64, image_size: u64 },
+    /// The partition table of the image cannot be read
+    #[error("partition table: {0}")]
+    Table(#[from] table::Error),
+    /// The partition table of the image has no such partition
+    #[error("image has no partition {0}")]
+    NoPartition(u32),
+}
+
+/// Container format of a disk image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// A plain copy of a disk
+    Raw,
+    /// QEMU copy-on-write, version 1 to 3
+    Qcow2,
+    /// VMware sparse extents or a descriptor file
+    Vmdk,
+    /// Hyper-V virtual hard disk
+    Vhdx,
+    /// VirtualBox disk image
+    Vdi,
+    /// A zstd-compressed raw image
+    Zstd,
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Raw => f.write_str("raw"),
+            Self::Qcow2 => f.write_str("qcow2"),
+            Self::Vmdk => f.write_str("vmdk"),
+            Self::Vhdx => f.write_str("vhdx"),
+            Self::Vdi => f.write_str("vdi"),
+            Self::Zstd => f.write_str("zstd"),
+        }
+    }
+}
+
+impl ImageFormat {
+    /// Detect the format of an image from its first bytes, leaving `reader` rewound
+    ///
+    /// Anything without a known signature is taken to be raw.
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> io::Result<Self> {
+        let mut head = Vec::with_capacity(128);
+        reader.rewind()?;
+        reader.take(128).read_to_end(&mut head)?;
+        reader.rewind()?;
+
+        let format = if head.starts_with(b"QFI\xfb") {
+            Self::Qcow2
+        } else if head.starts_with(b"KDMV") || head.starts_with(b"COWD") || head.starts_with(b"# Disk DescriptorFile") {
+            Self::Vmdk
+        } else if head.starts_with(b"vhdxfile") {
+            Self::Vhdx
+        } else if head.get(64..68) == Some(&[0x7f, 0x10, 0xda, 0xbe]) {
+            Self::Vdi
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else {
+            Self::Raw
+        };
+        Ok(format)
+    }
+}
+
+/// A raw disk image, or a byte range of one
+#[derive(Debug, Clone)]
+pub struct Image {
+    path: PathBuf,
+    size: u64,
+    offset: u64,
+    length: u64,
+}
+
+impl Image {
+    /// Open the image at `path`, refusing formats other than raw
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let format = ImageFormat::detect(&mut file)?;
+        debug!("Image {:?} has format {}", path, format);
+        if format != ImageFormat::Raw {
+            return Err(Error::UnsupportedFormat(format));
+        }
+
+        let size = file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            path: path.to_owned(),
+            size,
+            offset: 0,
+            length: size,
+        })
+    }
+
+    /// Restrict the image to `length` bytes at `offset`
+    pub fn with_range(self, offset: u64, length: u64) -> Result<Self, Error> {
+        if offset.checked_add(length).is_none_or(|end| end > self.size) {
+            return Err(Error::OutOfBounds {
+                offset,
+                size: length,
+                image_size: self.size,
+            });
+        }
+        Ok(Self { offset, length, ..self })
+    }
+
+    /// Restrict the image to partition `number` of the GPT inside it
+    pub fn with_partition(self, number: u32) -> Result<Self, Error> {
+        let table = table::read(&mut File::open(&self.path)?)?;
+        let entry = table.entry(number).ok_or(Error::NoPartition(number))?;
+        let offset = entry.first_lba * table.block_size;
+        let length = entry.sectors() * table.block_size;
+        self.with_range(offset, length)
+    }
+
+    /// Path of the image file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Offset of the attached range in bytes
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Length of the attached range in bytes
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns true if the attached range is empty
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Attach the image, or its range, to a new loop device
+    #[cfg(feature = "linux")]
+    pub fn attach(&self) -> Result<crate::loopback::LoopDevice, Error> {
+        let device = crate::loopback::LoopDevice::create()?;
+        let path = self
+            .path
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "image path is not valid UTF-8"))?;
+        // A size limit of zero maps everything past the offset
+        let limit = if self.offset + self.length == self.size {
+            0
+        } else {
+            self.length
+        };
+        device.attach_range(path, self.offset, limit)?;
+        Ok(device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        let detect = |head: &[u8]| {
+            let mut bytes = head.to_vec();
+            bytes.resize(512, 0);
+            ImageFormat::detect(&mut Cursor::new(bytes)).unwrap()
+        };
+        assert_eq!(detect(b"QFI\xfb\0\0\0\x03"), ImageFormat::Qcow2);
+        assert_eq!(detect(b"KDMV\x01\0\0\0"), ImageFormat::Vmdk);
+        assert_eq!(detect(b"# Disk DescriptorFile\nversion=1"), ImageFormat::Vmdk);
+        assert_eq!(detect(b"vhdxfile"), ImageFormat::Vhdx);
+        let mut vdi = b"<<< Oracle VM VirtualBox Disk Image >>>\n".to_vec();
+        vdi.resize(64, 0);
+        vdi.extend_from_slice(&[0x7f, 0x10, 0xda, 0xbe]);
+        assert_eq!(detect(&vdi), ImageFormat::Vdi);
+        assert_eq!(detect(&[0x28, 0xb5, 0x2f, 0xfd]), ImageFormat::Zstd);
+        assert_eq!(detect(b"\xeb\x3c\x90mkfs.fat"), ImageFormat::Raw);
+        assert_eq!(ImageFormat::detect(&mut Cursor::new(vec![])).unwrap(), ImageFormat::Raw);
+    }
+
+    #[test]
+    fn test_image_range() {
+        let dir = std::env::temp_dir().join(format!("disks-rs-image-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let raw = dir.join("disk.img");
+        std::fs::write(&raw, vec![0u8; 1024 * 1024]).unwrap();
+        let qcow = dir.join("disk.qcow2");
+        std::fs::write(&qcow, b"QFI\xfb\0\0\0\x03").unwrap();
+
+        let error = Image::open(&qcow).unwrap_err();
+        assert!(matches!(error, Error::UnsupportedFormat(ImageFormat::Qcow2)));
+        assert!(error.to_string().contains("convert it to a raw image first"));
+
+        let image = Image::open(&raw).unwrap();
+        assert_eq!(image.len(), 1024 * 1024);
+        let image = image.with_range(4096, 8192).unwrap();
+        assert_eq!((image.offset(), image.len()), (4096, 8192));
+        assert!(matches!(
+            image.clone().with_range(1024 * 1024 - 512, 1024),
+            Err(Error::OutOfBounds { .. })
+        ));
+        assert!(matches!(image.with_partition(1), Err(Error::Table(_))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
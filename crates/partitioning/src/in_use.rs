@@ -0,0 +1,244 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detect disks that are in use
+//!
+//! Repartitioning a disk while one of its filesystems is mounted, its swap is active or
+//! a device-mapper or RAID device sits on top of it corrupts data in ways no rollback can
+//! undo. [`check()`] looks at the mount table, `/proc/swaps` and the sysfs `holders` of
+//! the disk and each of its partitions, then tries to open every device with `O_EXCL`,
+//! which the kernel refuses while anything else claims it.
+//!
+//! A filesystem written to a disk without a partition table is not in use as far as the
+//! kernel is concerned, so [`check()`] does not report it. Callers that consider such a
+//! disk taken report it as [`Usage::Filesystem`], see [`BlockDevice::filesystem()`].
+//!
+//! Self-encrypting drives that are locked cannot be written either, they are reported as
+//! [`Usage::Locked`], see [`crate::opal`].
+
+use std::{
+    fmt, fs,
+    fs::OpenOptions,
+    io,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use disks::{
+    mount::{self, Mount},
+    BlockDevice,
+};
+use nix::libc;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::opal;
+
+/// Something using a disk or one of its partitions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Usage {
+    /// A filesystem on the device is mounted
+    Mounted { device: PathBuf, mountpoint: PathBuf },
+    /// The device is an active swap area
+    Swap { device: PathBuf },
+    /// Another block device is built on top of the device, e.g. `dm-0` or `md127`
+    Holder { device: PathBuf, holder: String },
+    /// The device is claimed exclusively by something not covered above
+    Exclusive { device: PathBuf },
+    /// The whole device holds a filesystem, without a partition table
+    Filesystem { device: PathBuf, kind: String },
+    /// The device is a locked Opal self-encrypting drive
+    Locked { device: PathBuf },
+}
+
+impl Usage {
+    /// The disk or partition in use
+    pub fn device(&self) -> &Path {
+        match self {
+            Usage::Mounted { device, .. }
+            | Usage::Swap { device }
+            | Usage::Holder { device, .. }
+            | Usage::Exclusive { device }
+            | Usage::Filesystem { device, .. }
+            | Usage::Locked { device } => device,
+        }
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Usage::Mounted { device, mountpoint } => {
+                write!(f, "{} is mounted at {}", device.display(), mountpoint.display())
+            }
+            Usage::Swap { device } => write!(f, "{} is active swap", device.display()),
+            Usage::Holder { device, holder } => write!(f, "{} is held by {holder}", device.display()),
+            Usage::Exclusive { device } => write!(f, "{} is opened exclusively", device.display()),
+            Usage::Filesystem { device, kind } => {
+                write!(
+                    f,
+                    "{} holds a {kind} filesystem without a partition table",
+                    device.display()
+                )
+            }
+            Usage::Locked { device } => write!(
+                f,
+                "{} is a locked self-encrypting drive, unlock it or erase it with a PSID revert",
+                device.display()
+            ),
+        }
+    }
+}
+
+/// A disk was found to be in use
+#[derive(Debug, Error)]
+#[error("{} is in use: {}", .device.display(), .usages.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+pub struct InUse {
+    /// The disk
+    pub device: PathBuf,
+    /// Everything found using the disk or its partitions
+    pub usages: Vec<Usage>,
+}
+
+/// Fail if anything uses `device` or one of its partitions
+pub fn check(device: &BlockDevice) -> Result<(), InUse> {
+    let usages = usages(device);
+    if usages.is_empty() {
+        Ok(())
+    } else {
+        warn!("{:?} is in use: {:?}", device.device(), usages);
+        Err(InUse {
+            device: device.device().to_owned(),
+            usages,
+        })
+    }
+}
+
+/// Everything using `device` or one of its partitions
+pub fn usages(device: &BlockDevice) -> Vec<Usage> {
+    let mounts = mount::mounts().unwrap_or_else(|e| {
+        warn!("Failed to read mount table: {}", e);
+        vec![]
+    });
+    let swaps = fs::read_to_string("/proc/swaps").unwrap_or_default();
+    let mut usages = usages_with(device, &mounts, &swaps, Path::new("/"));
+
+    match opal::status(device.device()) {
+        Ok(Some(status)) if status.is_locked() => usages.push(Usage::Locked {
+            device: device.device().to_owned(),
+        }),
+        Ok(_) => {}
+        Err(e) => debug!("Cannot query opal status of {:?}: {}", device.device(), e),
+    }
+
+    // Whatever is left can only be seen by trying to claim the devices
+    for path in devices(device) {
+        if usages.iter().any(|u| u.device() == path) {
+            continue;
+        }
+        if is_claimed(path) {
+            usages.push(Usage::Exclusive {
+                device: path.to_owned(),
+            });
+        }
+    }
+
+    usages
+}
+
+/// Usages visible in `mounts`, the contents of `/proc/swaps` and the sysfs below `sysroot`
+fn usages_with(device: &BlockDevice, mounts: &[Mount], swaps: &str, sysroot: &Path) -> Vec<Usage> {
+    let swaps = swaps
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+        .collect::<Vec<_>>();
+    let names = std::iter::once(device.name()).chain(device.partitions().iter().map(|p| p.name.as_str()));
+
+    let mut usages = vec![];
+    for (path, name) in devices(device).zip(names) {
+        for mount in mount::mounts_for(mounts, path, []) {
+            usages.push(Usage::Mounted {
+                device: path.to_owned(),
+                mountpoint: mount.mountpoint.clone(),
+            });
+        }
+        if swaps.iter().any(|s| Path::new(s) == path) {
+            usages.push(Usage::Swap {
+                device: path.to_owned(),
+            });
+        }
+
+        let holders = sysroot.join("sys/class/block").join(name).join("holders");
+        for holder in fs::read_dir(holders).into_iter().flatten().flatten() {
+            usages.push(Usage::Holder {
+                device: path.to_owned(),
+                holder: holder.file_name().to_string_lossy().into_owned(),
+            });
+        }
+    }
+
+    usages
+}
+
+/// Device nodes of the disk and its partitions
+fn devices(device: &BlockDevice) -> impl Iterator<Item = &Path> {
+    std::iter::once(device.device()).chain(device.partitions().iter().map(|p| p.device.as_path()))
+}
+
+/// Whether opening `path` exclusively fails because someone else holds it
+fn is_claimed(path: &Path) -> bool {
+    match OpenOptions::new().read(true).custom_flags(libc::O_EXCL).open(path) {
+        Ok(_) => false,
+        Err(e) if e.raw_os_error() == Some(libc::EBUSY) => true,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                debug!("Cannot open {:?} exclusively: {}", path, e);
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::mock::MockDisk;
+
+    use super::*;
+
+    #[test]
+    fn test_usages() {
+        let mut disk = MockDisk::new(10 * 1024 * 1024 * 1024);
+        disk.add_partition(1024 * 1024, 512 * 1024 * 1024);
+        disk.add_partition(512 * 1024 * 1024, 1024 * 1024 * 1024);
+        disk.add_partition(1024 * 1024 * 1024, 2048 * 1024 * 1024);
+        let device = BlockDevice::mock_device(disk);
+
+        let mounts = vec![Mount {
+            source: "/dev/mock0p1".into(),
+            mountpoint: "/boot".into(),
+            fstype: "vfat".into(),
+        }];
+        let swaps = "Filename\tType\tSize\tUsed\tPriority\n/dev/mock0p2 partition 1048572 0 -2\n";
+
+        let sysroot = std::env::temp_dir().join(format!("disks-rs-in-use-{}", std::process::id()));
+        let holders = sysroot.join("sys/class/block/mock0p3/holders/dm-0");
+        fs::create_dir_all(&holders).unwrap();
+        let usages = usages_with(&device, &mounts, swaps, &sysroot);
+        fs::remove_dir_all(&sysroot).unwrap();
+
+        assert_eq!(usages.len(), 3);
+        let error = InUse {
+            device: device.device().to_owned(),
+            usages,
+        };
+        assert_eq!(
+            error.to_string(),
+            "/dev/mock0 is in use: /dev/mock0p1 is mounted at /boot, /dev/mock0p2 is active swap, \
+             /dev/mock0p3 is held by dm-0"
+        );
+
+        assert!(usages_with(&device, &[], "", &std::env::temp_dir()).is_empty());
+    }
+}
@@ -2,11 +2,48 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
+#[cfg(feature = "linux")]
 pub mod blkpg;
+pub mod btrfs;
+pub mod cancel;
+mod chunked;
+pub mod classify;
+pub mod copy;
+#[cfg(feature = "linux")]
+pub mod devmapper;
+pub mod flash;
+pub mod format;
+pub mod free_space;
+#[cfg(feature = "linux")]
+pub mod hpa;
+pub mod image;
+#[cfg(feature = "linux")]
+pub mod in_use;
+pub mod locale;
+#[cfg(feature = "linux")]
 pub mod loopback;
+#[cfg(feature = "linux")]
+pub mod mount;
+#[cfg(feature = "linux")]
+pub mod namespace;
+#[cfg(feature = "linux")]
+pub mod opal;
+pub mod partition_type;
+pub mod partition_types;
+pub mod pending;
+pub mod progress;
+pub mod slots;
 pub mod sparsefile;
+pub mod swapfile;
+pub mod table;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod throttle;
+pub mod unique;
+pub mod wipe;
 
 pub use gpt;
 
 pub mod planner;
 pub mod strategy;
+pub mod writer;
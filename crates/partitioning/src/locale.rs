@@ -0,0 +1,357 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Localization hooks for user-facing text
+//!
+//! Descriptions produced by the planner and by provisioning reports are rendered through a
+//! [`Locale`]. Every sentence is a [`Message`] template with `{name}` placeholders, and sizes
+//! are formatted with the unit names and decimal separator of the locale. [`English`] is
+//! used unless a caller asks otherwise.
+//!
+//! Sizes are binary (`GiB`) by default. Disk vendors use decimal units, so a "500GB" disk
+//! shows as `465.8GiB`. [`set_size_format()`] switches every size rendered by the crate,
+//! including [`crate::planner::format_size()`], to decimal units or to both at once. A
+//! [`Catalog`] may pick its own [`SizeFormat`] instead.
+//!
+//! Installers that ship translations fill a [`Catalog`] from their own message files, any
+//! message left out falls back to English:
+//!
+//! ```
+//! use partitioning::locale::{Catalog, Locale, Message};
+//!
+//! let french = Catalog::new()
+//!     .with(Message::DeletePartition, "Supprimer la partition n°{number}")
+//!     .with_units(["o", "Kio", "Mio", "Gio", "Tio"])
+//!     .with_decimal_separator(',');
+//!
+//! assert_eq!(french.size(1536), "1,5Kio");
+//! assert_eq!(
+//!     french.format(Message::DeletePartition, &[("number", "3".into())]),
+//!     "Supprimer la partition n°3"
+//! );
+//! assert_eq!(french.template(Message::NoChanges), "No pending changes");
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Unit names of the binary unit system, smallest first
+const BINARY_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// Unit names of the decimal unit system, smallest first
+const DECIMAL_UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+
+/// The size format used by locales that don't pick their own, see [`set_size_format()`]
+static SIZE_FORMAT: AtomicU8 = AtomicU8::new(SizeFormat::Binary as u8);
+
+/// Unit system sizes are rendered in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeFormat {
+    /// Powers of 1024, e.g. `465.8GiB`
+    #[default]
+    Binary,
+    /// Powers of 1000 as used by disk vendors, e.g. `500.1GB`
+    Decimal,
+    /// Decimal followed by binary, e.g. `500.1GB (465.8GiB)`
+    Both,
+}
+
+/// Set the size format of every locale that doesn't pick its own
+///
+/// This affects [`crate::planner::format_size()`] and all descriptions and reports.
+///
+/// ```
+/// use partitioning::{locale::{set_size_format, SizeFormat}, planner::format_size};
+///
+/// set_size_format(SizeFormat::Both);
+/// assert_eq!(format_size(500_107_862_016), "500.1GB (465.8GiB)");
+/// ```
+pub fn set_size_format(format: SizeFormat) {
+    SIZE_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// The size format set with [`set_size_format()`]
+pub fn size_format() -> SizeFormat {
+    match SIZE_FORMAT.load(Ordering::Relaxed) {
+        1 => SizeFormat::Decimal,
+        2 => SizeFormat::Both,
+        _ => SizeFormat::Binary,
+    }
+}
+
+/// User-facing messages, each rendered from a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Message {
+    /// Empty change list
+    NoChanges,
+    /// Heading of the change list
+    PendingChanges,
+    /// `{size}`, `{region}`, `{position}`
+    AddPartition,
+    /// `{role}`, `{size}`, `{region}`, `{position}`
+    AddPartitionFor,
+    /// `{number}`
+    DeletePartition,
+    /// `{partitions}`, appended to an addition taking the place of deleted partitions
+    ReplacesPartitions,
+    /// `{size}`, `{start}`, `{end}`
+    Region,
+    /// `{percent}`, `{size}`
+    Position,
+    /// `{name}`, `{summary}`
+    Strategy,
+    /// `{level}`
+    RaidArray,
+    /// `{array}`
+    MemberOf,
+    /// `{policy}`
+    Erased,
+    /// Device gets a fresh partition table
+    NewTable,
+    /// Device is used as a whole, without a partition table
+    WholeDisk,
+    /// `{device}`, `{size}`
+    Destroyed,
+    /// `{device}`, appended to a new partition taking the place of an existing one
+    ReplacesDevice,
+    /// `{name}`, `{physical}`, `{logical}`
+    VolumeGroup,
+}
+
+impl Message {
+    /// The English template
+    pub fn english(&self) -> &'static str {
+        match self {
+            Message::NoChanges => "No pending changes",
+            Message::PendingChanges => "Pending changes:",
+            Message::AddPartition => "Add new partition: {size} ({region} at {position})",
+            Message::AddPartitionFor => "Add new partition for {role}: {size} ({region} at {position})",
+            Message::DeletePartition => "Delete partition #{number}",
+            Message::ReplacesPartitions => "(replaces partition {partitions})",
+            Message::Region => "{size} at {start}..{end}",
+            Message::Position => "{percent}% ({size})",
+            Message::Strategy => "Strategy: {name} ({summary})",
+            Message::RaidArray => "{level} array",
+            Message::MemberOf => "member of {array}",
+            Message::Erased => "erased ({policy})",
+            Message::NewTable => "new partition table",
+            Message::WholeDisk => "no partition table",
+            Message::Destroyed => "{device} ({size}) will be destroyed",
+            Message::ReplacesDevice => "replaces {device}",
+            Message::VolumeGroup => "Volume group {name} on {physical}: {logical}",
+        }
+    }
+}
+
+/// Renders sizes and messages for one language
+///
+/// All methods have English defaults, implementors override what their language needs.
+pub trait Locale {
+    /// Names of bytes, KiB, MiB, GiB and TiB
+    fn units(&self) -> [&str; 5] {
+        BINARY_UNITS
+    }
+
+    /// Names of bytes, kB, MB, GB and TB
+    fn decimal_units(&self) -> [&str; 5] {
+        DECIMAL_UNITS
+    }
+
+    /// Unit system of sizes, the process-wide [`size_format()`] unless overridden
+    fn size_format(&self) -> SizeFormat {
+        size_format()
+    }
+
+    /// Separator between the integral and fractional part of a number
+    fn decimal_separator(&self) -> char {
+        '.'
+    }
+
+    /// Template for `message`
+    fn template(&self, message: Message) -> Cow<'_, str> {
+        Cow::Borrowed(message.english())
+    }
+
+    /// Format a size in bytes with one decimal, e.g. `1.5KiB`
+    fn size(&self, bytes: u64) -> String {
+        let separator = self.decimal_separator();
+        let binary = || scale(bytes, 1024.0, self.units(), separator);
+        let decimal = || scale(bytes, 1000.0, self.decimal_units(), separator);
+        match self.size_format() {
+            SizeFormat::Binary => binary(),
+            SizeFormat::Decimal => decimal(),
+            // Both render the same below a kilobyte
+            SizeFormat::Both if bytes < 1000 => binary(),
+            SizeFormat::Both => format!("{} ({})", decimal(), binary()),
+        }
+    }
+
+    /// Render `message` with the given placeholder values
+    fn format(&self, message: Message, args: &[(&str, String)]) -> String {
+        fill(&self.template(message), args)
+    }
+}
+
+/// The built-in English locale
+#[derive(Debug, Clone, Copy, Default)]
+pub struct English;
+
+impl Locale for English {}
+
+/// A locale assembled from translated templates
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    templates: HashMap<Message, String>,
+    units: [String; 5],
+    decimal_units: [String; 5],
+    decimal_separator: char,
+    size_format: Option<SizeFormat>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self {
+            templates: HashMap::new(),
+            units: BINARY_UNITS.map(str::to_owned),
+            decimal_units: DECIMAL_UNITS.map(str::to_owned),
+            decimal_separator: '.',
+            size_format: None,
+        }
+    }
+}
+
+impl Catalog {
+    /// Create a catalog rendering everything in English
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `template` for `message`
+    pub fn with(mut self, message: Message, template: impl Into<String>) -> Self {
+        self.templates.insert(message, template.into());
+        self
+    }
+
+    /// Use the given names for bytes, KiB, MiB, GiB and TiB
+    pub fn with_units(self, units: [&str; 5]) -> Self {
+        Self {
+            units: units.map(str::to_owned),
+            ..self
+        }
+    }
+
+    /// Use the given names for bytes, kB, MB, GB and TB
+    pub fn with_decimal_units(self, units: [&str; 5]) -> Self {
+        Self {
+            decimal_units: units.map(str::to_owned),
+            ..self
+        }
+    }
+
+    /// Render sizes in `format` regardless of [`set_size_format()`]
+    pub fn with_size_format(self, format: SizeFormat) -> Self {
+        Self {
+            size_format: Some(format),
+            ..self
+        }
+    }
+
+    /// Use `separator` between the integral and fractional part of sizes
+    pub fn with_decimal_separator(self, separator: char) -> Self {
+        Self {
+            decimal_separator: separator,
+            ..self
+        }
+    }
+}
+
+impl Locale for Catalog {
+    fn units(&self) -> [&str; 5] {
+        [0, 1, 2, 3, 4].map(|i| self.units[i].as_str())
+    }
+
+    fn decimal_units(&self) -> [&str; 5] {
+        [0, 1, 2, 3, 4].map(|i| self.decimal_units[i].as_str())
+    }
+
+    fn size_format(&self) -> SizeFormat {
+        self.size_format.unwrap_or_else(size_format)
+    }
+
+    fn decimal_separator(&self) -> char {
+        self.decimal_separator
+    }
+
+    fn template(&self, message: Message) -> Cow<'_, str> {
+        match self.templates.get(&message) {
+            Some(template) => Cow::Borrowed(template),
+            None => Cow::Borrowed(message.english()),
+        }
+    }
+}
+
+/// Scale `bytes` to the largest unit it reaches, each unit being `step` times the previous
+fn scale(bytes: u64, step: f64, units: [&str; 5], separator: char) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= step && unit < units.len() - 1 {
+        value /= step;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, units[0])
+    } else {
+        let number = format!("{value:.1}");
+        format!("{}{}", number.replace('.', &separator.to_string()), units[unit])
+    }
+}
+
+/// Replace every `{name}` placeholder of `template` with its value
+pub fn fill(template: &str, args: &[(&str, String)]) -> String {
+    args.iter().fold(template.to_owned(), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_english() {
+        assert_eq!(English.size(0), "0B");
+        assert_eq!(English.size(1500000), "1.4MiB");
+        assert_eq!(English.size(3 * 1024 * 1024 * 1024 * 1024 * 1024), "3072.0TiB");
+        assert_eq!(
+            English.format(
+                Message::Destroyed,
+                &[("device", "/dev/sda1".into()), ("size", "1.0GiB".into())]
+            ),
+            "/dev/sda1 (1.0GiB) will be destroyed"
+        );
+        assert_eq!(fill("{a} {b} {a}", &[("a", "x".into())]), "x {b} x");
+    }
+
+    #[test]
+    fn test_size_format() {
+        const DISK: u64 = 500_107_862_016;
+
+        let decimal = Catalog::new().with_size_format(SizeFormat::Decimal);
+        assert_eq!(decimal.size(DISK), "500.1GB");
+        assert_eq!(decimal.size(999), "999B");
+
+        let both = Catalog::new()
+            .with_size_format(SizeFormat::Both)
+            .with_decimal_separator(',');
+        assert_eq!(both.size(DISK), "500,1GB (465,8GiB)");
+        assert_eq!(both.size(512), "512B");
+
+        // Catalogs without an explicit format follow the process-wide one
+        assert_eq!(Catalog::new().size(DISK), "465.8GiB");
+    }
+}
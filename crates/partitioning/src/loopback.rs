@@ -7,9 +7,28 @@ use std::{
     os::fd::{AsRawFd, OwnedFd},
 };
 
-use linux_raw_sys::loop_device::{LOOP_CLR_FD, LOOP_CTL_GET_FREE, LOOP_SET_FD, LOOP_SET_STATUS64};
-use log::{debug, error, info};
+use linux_raw_sys::{
+    ioctl::BLKGETSIZE64,
+    loop_device::{
+        LOOP_CHANGE_FD, LOOP_CLR_FD, LOOP_CTL_ADD, LOOP_CTL_GET_FREE, LOOP_SET_CAPACITY, LOOP_SET_FD,
+        LOOP_SET_STATUS64, LO_FLAGS_PARTSCAN, LO_FLAGS_READ_ONLY,
+    },
+};
 use nix::libc;
+use tracing::{debug, error, info, instrument};
+
+/// How a backing file is attached to a loop device, see [`LoopDevice::attach_with()`]
+#[derive(Debug, Clone, Default)]
+pub struct AttachOptions {
+    /// Offset of the attached range in bytes
+    pub offset: u64,
+    /// Length of the attached range in bytes, or 0 for the rest of the file
+    pub size_limit: u64,
+    /// Have the kernel scan the device for partitions, e.g. `/dev/loop0p1`
+    pub partscan: bool,
+    /// Open the backing file read-only and refuse writes to the device
+    pub read_only: bool,
+}
 
 /// Represents a loop device that can be used to mount files as block devices
 pub struct LoopDevice {
@@ -38,9 +57,47 @@ impl LoopDevice {
             return Err(io::Error::last_os_error());
         }
 
-        let path = format!("/dev/loop{}", devno);
+        Self::open(devno as u32)
+    }
+
+    /// Creates the loop device with the given index, e.g. `/dev/loop7` for 7, through
+    /// /dev/loop-control.
+    ///
+    /// An existing device with that index is used if nothing is attached to it.
+    ///
+    /// # Returns
+    /// `io::Result<LoopDevice>` containing the loop device on success, failing with
+    /// [`io::ErrorKind::AlreadyExists`] if the device exists and is in use
+    pub fn create_at(index: u32) -> io::Result<Self> {
+        use std::fs::OpenOptions;
+
+        debug!("Opening loop control device");
+        let ctrl = OpenOptions::new().read(true).write(true).open("/dev/loop-control")?;
+
+        let res = unsafe { libc::ioctl(ctrl.as_raw_fd(), LOOP_CTL_ADD as _, index as libc::c_ulong) };
+        if res < 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(libc::EEXIST) {
+                error!("Failed to add loop device {}", index);
+                return Err(e);
+            }
+            if fs::metadata(format!("/sys/block/loop{index}/loop/backing_file")).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("/dev/loop{index} is already in use"),
+                ));
+            }
+            debug!("Reusing unattached loop device {}", index);
+        }
+
+        Self::open(index)
+    }
+
+    /// Opens the loop device with the given index
+    fn open(index: u32) -> io::Result<Self> {
+        let path = format!("/dev/loop{}", index);
         debug!("Creating new loop device at {}", path);
-        let fd = OpenOptions::new().read(true).write(true).open(&path)?.into();
+        let fd = fs::OpenOptions::new().read(true).write(true).open(&path)?.into();
 
         info!("Successfully initialized loop device {}", path);
         Ok(LoopDevice { fd, path })
@@ -55,8 +112,45 @@ impl LoopDevice {
     /// # Returns
     /// `io::Result<()>` indicating success or failure
     pub fn attach(&self, backing_file: &str) -> io::Result<()> {
+        self.attach_with(backing_file, &AttachOptions::default())
+    }
+
+    /// Attaches a byte range of a backing file to this loop device, e.g. a single
+    /// partition inside a disk image.
+    ///
+    /// # Arguments
+    /// * `backing_file` - Path to the file to attach
+    /// * `offset` - Offset of the range in bytes
+    /// * `size_limit` - Length of the range in bytes, or 0 for the rest of the file
+    ///
+    /// # Returns
+    /// `io::Result<()>` indicating success or failure
+    pub fn attach_range(&self, backing_file: &str, offset: u64, size_limit: u64) -> io::Result<()> {
+        self.attach_with(
+            backing_file,
+            &AttachOptions {
+                offset,
+                size_limit,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Attaches a backing file to this loop device as described by `options`.
+    ///
+    /// # Arguments
+    /// * `backing_file` - Path to the file to attach
+    /// * `options` - Range and flags of the attachment
+    ///
+    /// # Returns
+    /// `io::Result<()>` indicating success or failure
+    #[instrument(skip(self), fields(device = %self.path))]
+    pub fn attach_with(&self, backing_file: &str, options: &AttachOptions) -> io::Result<()> {
         debug!("Attempting to attach backing file {} to {}", backing_file, self.path);
-        let f = fs::OpenOptions::new().read(true).write(true).open(backing_file)?;
+        let f = fs::OpenOptions::new()
+            .read(true)
+            .write(!options.read_only)
+            .open(backing_file)?;
 
         let file_fd = f.as_raw_fd();
         let our_fd = self.fd.as_raw_fd();
@@ -67,8 +161,16 @@ impl LoopDevice {
             return Err(io::Error::last_os_error());
         }
 
-        // Force loop device to immediately update by setting empty status
-        let info: linux_raw_sys::loop_device::loop_info64 = unsafe { std::mem::zeroed() };
+        // Force loop device to immediately update, applying the range and flags
+        let mut info: linux_raw_sys::loop_device::loop_info64 = unsafe { std::mem::zeroed() };
+        if options.partscan {
+            info.lo_flags |= LO_FLAGS_PARTSCAN as u32;
+        }
+        if options.read_only {
+            info.lo_flags |= LO_FLAGS_READ_ONLY as u32;
+        }
+        info.lo_offset = options.offset;
+        info.lo_sizelimit = options.size_limit;
         let res = unsafe { libc::ioctl(our_fd, LOOP_SET_STATUS64 as _, &info) };
         if res < 0 {
             error!("Failed to update loop device status - device may be in inconsistent state");
@@ -79,10 +181,72 @@ impl LoopDevice {
         Ok(())
     }
 
+    /// Makes the loop device pick up the current size of its backing file, e.g. after
+    /// growing a sparse image, without detaching it.
+    ///
+    /// # Returns
+    /// `io::Result<()>` indicating success or failure
+    #[instrument(skip(self), fields(device = %self.path))]
+    pub fn set_capacity(&self) -> io::Result<()> {
+        let res = unsafe { libc::ioctl(self.fd.as_raw_fd(), LOOP_SET_CAPACITY as _, 0) };
+        if res < 0 {
+            error!("Failed to update capacity of {}", self.path);
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Returns the current size of the loop device in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        let mut size = 0u64;
+        let res = unsafe { libc::ioctl(self.fd.as_raw_fd(), BLKGETSIZE64 as _, &mut size) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(size)
+    }
+
+    /// Updates the capacity from the backing file, see [`LoopDevice::set_capacity()`].
+    ///
+    /// # Returns
+    /// `io::Result<u64>` with the new size of the loop device in bytes
+    pub fn refresh(&self) -> io::Result<u64> {
+        self.set_capacity()?;
+        let size = self.size()?;
+        info!("Loop device {} now has {} bytes", self.path, size);
+        Ok(size)
+    }
+
+    /// Points this loop device at another backing file without detaching it.
+    ///
+    /// The kernel only allows this for a new file of the same size on a loop device that
+    /// was attached with [`AttachOptions::read_only`], e.g. to swap in an updated copy of
+    /// an image that is in use.
+    ///
+    /// # Arguments
+    /// * `backing_file` - Path to the new backing file
+    ///
+    /// # Returns
+    /// `io::Result<()>` indicating success or failure
+    #[instrument(skip(self), fields(device = %self.path))]
+    pub fn change_backing_file(&self, backing_file: &str) -> io::Result<()> {
+        debug!("Changing backing file of {} to {}", self.path, backing_file);
+        let f = fs::File::open(backing_file)?;
+        let res = unsafe { libc::ioctl(self.fd.as_raw_fd(), LOOP_CHANGE_FD as _, f.as_raw_fd()) };
+        if res < 0 {
+            error!("Failed to change backing file of {} to {}", self.path, backing_file);
+            return Err(io::Error::last_os_error());
+        }
+
+        info!("Loop device {} now backed by {}", self.path, backing_file);
+        Ok(())
+    }
+
     /// Detaches the current backing file from this loop device.
     ///
     /// # Returns
     /// `io::Result<()>` indicating success or failure
+    #[instrument(skip(self), fields(device = %self.path))]
     pub fn detach(&self) -> io::Result<()> {
         debug!("Initiating detachment of backing file from {}", self.path);
         let res = unsafe { libc::ioctl(self.fd.as_raw_fd(), LOOP_CLR_FD as _, 0) };
@@ -95,3 +259,110 @@ impl LoopDevice {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, os::unix::fs::MetadataExt, path::PathBuf};
+
+    use super::*;
+
+    /// Loop devices can only be set up by root
+    fn is_root() -> bool {
+        fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0) && fs::metadata("/dev/loop-control").is_ok()
+    }
+
+    /// A sparse file of `size` bytes in a per-test temporary directory
+    fn backing_file(test: &str, size: u64) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("disks-rs-loop-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("disk.img");
+        crate::sparsefile::create(&path, size).unwrap();
+        path
+    }
+
+    #[test]
+    #[ignore = "needs root and loop devices"]
+    fn test_create_at() {
+        if !is_root() {
+            eprintln!("Skipping, root privileges and loop devices are required");
+            return;
+        }
+        let file = backing_file("create-at", 1024 * 1024);
+
+        let device = LoopDevice::create_at(247).unwrap();
+        assert_eq!(device.path, "/dev/loop247");
+        device.attach(file.to_str().unwrap()).unwrap();
+        let error = LoopDevice::create_at(247).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        // Unattached devices are reused, the kernel detaches once the device is closed
+        device.detach().unwrap();
+        drop(device);
+        let again = LoopDevice::create_at(247).unwrap();
+        assert_eq!(again.path, "/dev/loop247");
+
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs root and loop devices"]
+    fn test_refresh() {
+        if !is_root() {
+            eprintln!("Skipping, root privileges and loop devices are required");
+            return;
+        }
+        let file = backing_file("refresh", 1024 * 1024);
+        let device = LoopDevice::create().unwrap();
+        device.attach(file.to_str().unwrap()).unwrap();
+        assert_eq!(device.size().unwrap(), 1024 * 1024);
+
+        // Growing the file is only seen after a refresh
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_len(4 * 1024 * 1024)
+            .unwrap();
+        assert_eq!(device.size().unwrap(), 1024 * 1024);
+        assert_eq!(device.refresh().unwrap(), 4 * 1024 * 1024);
+        assert_eq!(device.size().unwrap(), 4 * 1024 * 1024);
+
+        device.detach().unwrap();
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs root and loop devices"]
+    fn test_change_backing_file() {
+        if !is_root() {
+            eprintln!("Skipping, root privileges and loop devices are required");
+            return;
+        }
+        let file = backing_file("change-backing", 1024 * 1024);
+        let copy = file.with_file_name("copy.img");
+        crate::sparsefile::create(&copy, 1024 * 1024).unwrap();
+
+        // Writable devices keep their backing file
+        let device = LoopDevice::create().unwrap();
+        device.attach(file.to_str().unwrap()).unwrap();
+        assert!(device.change_backing_file(copy.to_str().unwrap()).is_err());
+        device.detach().unwrap();
+        drop(device);
+
+        let device = LoopDevice::create().unwrap();
+        let options = AttachOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        device.attach_with(file.to_str().unwrap(), &options).unwrap();
+        let mut writer = fs::OpenOptions::new().write(true).open(&device.path).unwrap();
+        assert!(writer.write_all(&[0xff; 512]).is_err());
+        device.change_backing_file(copy.to_str().unwrap()).unwrap();
+        let name = device.path.trim_start_matches("/dev/");
+        let backing = fs::read_to_string(format!("/sys/block/{name}/loop/backing_file")).unwrap();
+        assert_eq!(backing.trim(), copy.to_str().unwrap());
+
+        device.detach().unwrap();
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Temporary mounts that are guaranteed to go away
+//!
+//! A [`TempMount`] mounts a filesystem and unmounts it again when dropped, including
+//! on early returns and panics. Mounts made with [`TempMount::read_only()`] are
+//! read-only, nosuid, nodev and noexec, which is what detection code peeking at
+//! `os-release` or ESP contents needs.
+//!
+//! The mount lives in the mount namespace of the calling thread. Use
+//! [`crate::namespace::inspect()`] to keep it invisible to the rest of the system.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use nix::{
+    errno::Errno,
+    mount::{mount, umount2, MntFlags, MsFlags},
+};
+use tracing::{debug, error, warn};
+
+/// Flags of read-only inspection mounts
+const READ_ONLY: MsFlags = MsFlags::MS_RDONLY
+    .union(MsFlags::MS_NOSUID)
+    .union(MsFlags::MS_NODEV)
+    .union(MsFlags::MS_NOEXEC);
+
+/// Distinguishes the temporary mountpoints of one process
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A mounted filesystem, unmounted on drop
+#[derive(Debug)]
+pub struct TempMount {
+    /// Where the filesystem is mounted
+    mountpoint: PathBuf,
+    /// Whether the mountpoint was created by us and must be removed
+    created: bool,
+    /// Cleared once unmounted explicitly
+    mounted: bool,
+}
+
+impl TempMount {
+    /// Mount `device` read-only at a fresh directory below the temporary directory
+    pub fn read_only(device: &Path, fstype: &str, options: Option<&str>) -> io::Result<Self> {
+        Self::mount(device, fstype, options, READ_ONLY, None)
+    }
+
+    /// Mount `device` read-only at an existing `mountpoint`
+    ///
+    /// The directory is left in place after unmounting.
+    pub fn read_only_at(device: &Path, fstype: &str, options: Option<&str>, mountpoint: &Path) -> io::Result<Self> {
+        Self::mount(device, fstype, options, READ_ONLY, Some(mountpoint))
+    }
+
+    /// Mount `device` with `flags`, at `mountpoint` or a fresh temporary directory
+    pub(crate) fn mount(
+        device: &Path,
+        fstype: &str,
+        options: Option<&str>,
+        flags: MsFlags,
+        mountpoint: Option<&Path>,
+    ) -> io::Result<Self> {
+        let (mountpoint, created) = match mountpoint {
+            Some(path) => (path.to_owned(), false),
+            None => {
+                let name = format!(
+                    "disks-rs-{fstype}-{}-{}",
+                    std::process::id(),
+                    COUNTER.fetch_add(1, Ordering::Relaxed)
+                );
+                let path = std::env::temp_dir().join(name);
+                fs::create_dir_all(&path)?;
+                (path, true)
+            }
+        };
+
+        debug!("Mounting {:?} at {:?}", device, mountpoint);
+        if let Err(e) = mount(Some(device), &mountpoint, Some(fstype), flags, options) {
+            if created {
+                let _ = fs::remove_dir(&mountpoint);
+            }
+            return Err(e.into());
+        }
+
+        Ok(Self {
+            mountpoint,
+            created,
+            mounted: true,
+        })
+    }
+
+    /// Where the filesystem is mounted
+    pub fn path(&self) -> &Path {
+        &self.mountpoint
+    }
+
+    /// Unmount now, reporting failure instead of logging it
+    pub fn unmount(mut self) -> io::Result<()> {
+        self.mounted = false;
+        umount2(&self.mountpoint, MntFlags::empty())?;
+        Ok(())
+    }
+}
+
+impl Drop for TempMount {
+    fn drop(&mut self) {
+        if self.mounted {
+            match umount2(&self.mountpoint, MntFlags::empty()) {
+                Ok(()) => {}
+                // Something still holds files open, detach so the mount goes away once they're closed
+                Err(Errno::EBUSY) => {
+                    warn!("{:?} is busy, detaching it", self.mountpoint);
+                    if let Err(e) = umount2(&self.mountpoint, MntFlags::MNT_DETACH) {
+                        error!("Failed to detach {:?}: {}", self.mountpoint, e);
+                    }
+                }
+                Err(e) => error!("Failed to unmount {:?}: {}", self.mountpoint, e),
+            }
+        }
+        if self.created {
+            let _ = fs::remove_dir(&self.mountpoint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_mount_cleans_up() {
+        let prefix = format!("disks-rs-nonexistent-{}-", std::process::id());
+        let leftovers = || {
+            fs::read_dir(std::env::temp_dir())
+                .unwrap()
+                .filter_map(Result::ok)
+                .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+                .count()
+        };
+
+        assert!(TempMount::read_only(Path::new("/nonexistent"), "nonexistent", None).is_err());
+        assert_eq!(leftovers(), 0);
+
+        let dir = std::env::temp_dir().join(format!("{prefix}explicit"));
+        fs::create_dir_all(&dir).unwrap();
+        assert!(TempMount::read_only_at(Path::new("/nonexistent"), "nonexistent", None, &dir).is_err());
+        assert!(dir.is_dir(), "explicit mountpoints are left alone");
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Temporary mounts in a private mount namespace
+//!
+//! Provisioning a freshly created filesystem (subvolumes, swapfiles) requires mounting
+//! it. The mount is made inside a private mount namespace owned by a helper thread, so
+//! it is never visible to the rest of the system and is torn down with the namespace
+//! even if we fail halfway through.
+//!
+//! [`inspect()`] does the same read-only, for peeking into existing filesystems. The
+//! mounts themselves are [`TempMount`]s, so they are also released if `f` panics.
+
+use std::{io, path::Path, thread};
+
+use nix::{
+    mount::{mount, MsFlags},
+    sched::{unshare, CloneFlags},
+};
+use tracing::debug;
+
+use crate::mount::TempMount;
+
+/// Mount `device` privately and run `f` with the mount point
+///
+/// Child processes spawned by `f` share the private namespace and see the mount.
+pub(crate) fn with_private_mount<T, E>(
+    device: &Path,
+    fstype: &str,
+    options: Option<&str>,
+    f: impl FnOnce(&Path) -> Result<T, E> + Send,
+) -> Result<T, E>
+where
+    T: Send,
+    E: From<io::Error> + From<nix::Error> + Send,
+{
+    let flags = MsFlags::MS_NOSUID | MsFlags::MS_NODEV;
+    // Namespaces are per-thread, so confine the mount to a short-lived helper
+    thread::scope(|scope| {
+        scope
+            .spawn(|| mount_in_namespace(device, fstype, options, flags, f))
+            .join()
+            .map_err(|_| E::from(io::Error::other("mount namespace thread panicked")))?
+    })
+}
+
+/// Mount `device` read-only and privately, and run `f` with the mount point
+///
+/// Neither setuid binaries nor device nodes on the filesystem are honoured and nothing
+/// on it can be executed, so untrusted filesystems can be inspected safely.
+pub fn inspect<T: Send>(
+    device: &Path,
+    fstype: &str,
+    options: Option<&str>,
+    f: impl FnOnce(&Path) -> T + Send,
+) -> io::Result<T> {
+    thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                enter_namespace()?;
+                let mount = TempMount::read_only(device, fstype, options)?;
+                Ok(f(mount.path()))
+            })
+            .join()
+            .map_err(|_| io::Error::other("mount namespace thread panicked"))?
+    })
+}
+
+/// Enter a private mount namespace, mount the device and run `f`
+fn mount_in_namespace<T, E>(
+    device: &Path,
+    fstype: &str,
+    options: Option<&str>,
+    flags: MsFlags,
+    f: impl FnOnce(&Path) -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: From<io::Error> + From<nix::Error>,
+{
+    enter_namespace()?;
+    let mount = TempMount::mount(device, fstype, options, flags, None)?;
+    f(mount.path())
+}
+
+/// Move the calling thread into a private mount namespace
+fn enter_namespace() -> Result<(), nix::Error> {
+    debug!("Entering private mount namespace");
+    unshare(CloneFlags::CLONE_NEWNS)?;
+    mount(
+        None::<&str>,
+        "/",
+        None::<&str>,
+        MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+        None::<&str>,
+    )
+}
@@ -0,0 +1,138 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detect TCG Opal self-encrypting drives
+//!
+//! A self-encrypting drive (SED) whose locking ranges have been enabled by an owner stays
+//! locked after power on until it is unlocked with the owner's password. Reads and writes
+//! to locked ranges fail with I/O errors, which makes repartitioning such a drive fail in
+//! confusing ways. Without the password the only way back is a PSID revert, which erases
+//! the drive using the PSID printed on its label.
+//!
+//! The state is queried with the `IOC_OPAL_GET_STATUS` ioctl of the kernel SED layer
+//! (Linux 6.0, `CONFIG_BLK_SED_OPAL`), supported by NVMe and SATA disks. Kernels and
+//! drives without Opal support report no status at all.
+
+use std::{fmt, fs::File, io, os::fd::AsRawFd, path::Path};
+
+use nix::libc;
+use tracing::debug;
+
+/// `_IOR('p', 236, struct opal_status)`, not provided by `linux-raw-sys`
+const IOC_OPAL_GET_STATUS: u32 = (2 << 30) | ((std::mem::size_of::<OpalStatusArgs>() as u32) << 16) | (0x70 << 8) | 236;
+
+const OPAL_FL_SUPPORTED: u32 = 1 << 0;
+const OPAL_FL_LOCKING_SUPPORTED: u32 = 1 << 1;
+const OPAL_FL_LOCKING_ENABLED: u32 = 1 << 2;
+const OPAL_FL_LOCKED: u32 = 1 << 3;
+const OPAL_FL_MBR_ENABLED: u32 = 1 << 4;
+const OPAL_FL_MBR_DONE: u32 = 1 << 5;
+
+/// `struct opal_status`
+#[repr(C)]
+#[derive(Default)]
+struct OpalStatusArgs {
+    flags: u32,
+    reserved: u32,
+}
+
+/// Opal state of a drive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpalStatus {
+    /// The drive implements TCG Opal
+    pub supported: bool,
+    /// The drive supports locking ranges
+    pub locking_supported: bool,
+    /// An owner has taken ownership and enabled locking
+    pub locking_enabled: bool,
+    /// At least one locking range is currently locked
+    pub locked: bool,
+    /// The drive presents a shadow MBR (pre-boot authentication image) until unlocked
+    pub mbr_enabled: bool,
+    /// The shadow MBR has been dismissed and the real contents are visible
+    pub mbr_done: bool,
+}
+
+impl OpalStatus {
+    /// Decode the flags returned by `IOC_OPAL_GET_STATUS`
+    pub fn from_flags(flags: u32) -> Self {
+        Self {
+            supported: flags & OPAL_FL_SUPPORTED != 0,
+            locking_supported: flags & OPAL_FL_LOCKING_SUPPORTED != 0,
+            locking_enabled: flags & OPAL_FL_LOCKING_ENABLED != 0,
+            locked: flags & OPAL_FL_LOCKED != 0,
+            mbr_enabled: flags & OPAL_FL_MBR_ENABLED != 0,
+            mbr_done: flags & OPAL_FL_MBR_DONE != 0,
+        }
+    }
+
+    /// Returns true if someone owns the drive, i.e. unlocking it needs their password
+    pub fn is_owned(&self) -> bool {
+        self.locking_enabled
+    }
+
+    /// Returns true if the drive cannot be written until it is unlocked or reverted
+    ///
+    /// A drive showing its shadow MBR hides the real contents of the start of the disk,
+    /// so it counts as locked as well.
+    pub fn is_locked(&self) -> bool {
+        self.locked || (self.mbr_enabled && !self.mbr_done)
+    }
+}
+
+impl fmt::Display for OpalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.supported, self.is_owned(), self.is_locked()) {
+            (false, _, _) => f.write_str("no opal support"),
+            (true, _, true) => f.write_str("locked"),
+            (true, true, false) => f.write_str("owned, unlocked"),
+            (true, false, false) => f.write_str("not owned"),
+        }
+    }
+}
+
+/// Query the Opal state of the disk at `path`
+///
+/// Returns `Ok(None)` if the kernel or the drive does not support Opal.
+pub fn status(path: &Path) -> io::Result<Option<OpalStatus>> {
+    let file = File::open(path)?;
+    let mut args = OpalStatusArgs::default();
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), IOC_OPAL_GET_STATUS as _, &mut args) };
+    if res < 0 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::ENOTTY | libc::EOPNOTSUPP | libc::EINVAL) => {
+                debug!("{:?} does not support opal: {}", path, e);
+                Ok(None)
+            }
+            _ => Err(e),
+        };
+    }
+
+    let status = OpalStatus::from_flags(args.flags);
+    debug!("Opal status of {:?}: {}", path, status);
+    Ok(status.supported.then_some(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_flags() {
+        assert_eq!(IOC_OPAL_GET_STATUS, 0x800870ec);
+
+        let status = OpalStatus::from_flags(OPAL_FL_SUPPORTED | OPAL_FL_LOCKING_SUPPORTED);
+        assert!(!status.is_owned() && !status.is_locked());
+        assert_eq!(status.to_string(), "not owned");
+
+        let status = OpalStatus::from_flags(0x1f);
+        assert!(status.is_owned() && status.is_locked());
+        assert_eq!(status.to_string(), "locked");
+
+        let status = OpalStatus::from_flags(0x37);
+        assert!(status.is_owned() && !status.is_locked());
+        assert_eq!(status.to_string(), "owned, unlocked");
+    }
+}
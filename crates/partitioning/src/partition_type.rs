@@ -0,0 +1,185 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! GPT partition types for well known partition roles
+//!
+//! Type GUIDs follow the [Discoverable Partitions Specification] so that
+//! `systemd-gpt-auto-generator` and friends can find the root, home and swap partitions
+//! without an fstab. Root partitions have a distinct type for every CPU architecture.
+//!
+//! [Discoverable Partitions Specification]: https://uapi-group.org/specifications/specs/discoverable_partitions_specification/
+
+use std::{fmt, str::FromStr};
+
+use uuid::{uuid, Uuid};
+
+/// Attribute bit: the partition is required for the platform to function
+pub const ATTR_REQUIRED_PARTITION: u64 = 1 << 0;
+
+/// Attribute bit: the legacy BIOS may boot from this partition
+pub const ATTR_LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+/// Attribute bit: the partition was created by a provisioning run that has not completed
+///
+/// Bit 56 is type specific and unused by the Discoverable Partitions Specification.
+pub const ATTR_PENDING: u64 = 1 << 56;
+
+/// Attribute bit: the partition belongs to the active slot of an A/B pair
+///
+/// Bit 57 is type specific and unused by the Discoverable Partitions Specification, see
+/// [`crate::slots`].
+pub const ATTR_SLOT_ACTIVE: u64 = 1 << 57;
+
+/// Attribute bit (DPS): grow the filesystem to the partition size on first mount
+pub const ATTR_GROWFS: u64 = 1 << 59;
+
+/// Attribute bit (DPS): mount the partition read-only
+pub const ATTR_READ_ONLY: u64 = 1 << 60;
+
+/// Attribute bit (DPS): do not mount the partition automatically
+pub const ATTR_NO_AUTO: u64 = 1 << 63;
+
+/// Generic Linux filesystem data, used when no role applies
+pub const LINUX_FS: Uuid = uuid!("0fc63daf-8483-4772-8e79-3d69d8477de4");
+
+/// Roles with a well known partition type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// EFI System Partition
+    Esp,
+    /// Extended boot loader partition (XBOOTLDR)
+    ExtendedBoot,
+    /// BIOS boot partition, holding GRUB's core image on GPT disks booted in legacy mode
+    BiosBoot,
+    /// Root filesystem for the given architecture
+    Root(Architecture),
+    /// Home directories
+    Home,
+    /// Swap space
+    Swap,
+    /// Variable data (`/var`)
+    Var,
+    /// Server data (`/srv`)
+    Srv,
+}
+
+/// CPU architectures with a dedicated root partition type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Architecture {
+    X86,
+    X86_64,
+    Arm,
+    Aarch64,
+    Riscv64,
+}
+
+impl Architecture {
+    /// The architecture this crate was built for, if it has a root partition type
+    pub fn host() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86" => Some(Self::X86),
+            "x86_64" => Some(Self::X86_64),
+            "arm" => Some(Self::Arm),
+            "aarch64" => Some(Self::Aarch64),
+            "riscv64" => Some(Self::Riscv64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::X86 => f.write_str("x86"),
+            Self::X86_64 => f.write_str("x86-64"),
+            Self::Arm => f.write_str("arm"),
+            Self::Aarch64 => f.write_str("aarch64"),
+            Self::Riscv64 => f.write_str("riscv64"),
+        }
+    }
+}
+
+impl FromStr for Architecture {
+    type Err = String;
+
+    /// Parse an architecture name as used by Rust (`x86_64`) or the specification (`x86-64`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x86" | "i686" => Ok(Self::X86),
+            "x86_64" | "x86-64" => Ok(Self::X86_64),
+            "arm" => Ok(Self::Arm),
+            "aarch64" | "arm64" => Ok(Self::Aarch64),
+            "riscv64" => Ok(Self::Riscv64),
+            _ => Err(format!("no root partition type for architecture {s}")),
+        }
+    }
+}
+
+impl Role {
+    /// The partition type GUID for this role
+    pub const fn type_guid(&self) -> Uuid {
+        match self {
+            Self::Esp => uuid!("c12a7328-f81f-11d2-ba4b-00a0c93ec93b"),
+            Self::ExtendedBoot => uuid!("bc13c2ff-59e6-4262-a352-b275fd6f7172"),
+            Self::BiosBoot => uuid!("21686148-6449-6e6f-744e-656564454649"),
+            Self::Root(Architecture::X86) => uuid!("44479540-f297-41b2-9af7-d131d5f0458a"),
+            Self::Root(Architecture::X86_64) => uuid!("4f68bce3-e8cd-4db1-96e7-fbcaf984b709"),
+            Self::Root(Architecture::Arm) => uuid!("69dad710-2ce4-4e3c-b16c-21a1d49abed3"),
+            Self::Root(Architecture::Aarch64) => uuid!("b921b045-1df0-41c3-af44-4c6f280d3fae"),
+            Self::Root(Architecture::Riscv64) => uuid!("72ec70a6-cf74-40e6-bd49-4bda08e8f224"),
+            Self::Home => uuid!("933ac7e1-2eb4-4f13-b844-0e14e2aef915"),
+            Self::Swap => uuid!("0657fd6d-a4ab-43c4-84e5-0933c84b4f4f"),
+            Self::Var => uuid!("4d21b016-b534-45c2-a9fb-5c16e091fd2d"),
+            Self::Srv => uuid!("3b8f8425-20e0-4f3b-907f-1a25a76f98e8"),
+        }
+    }
+
+    /// Where `systemd-gpt-auto-generator` mounts partitions of this role, if anywhere
+    ///
+    /// The ESP is mounted at `/boot` instead when there's no extended boot partition.
+    pub fn mountpoint(&self) -> Option<&'static str> {
+        match self {
+            Self::Esp => Some("/efi"),
+            Self::ExtendedBoot => Some("/boot"),
+            Self::Root(_) => Some("/"),
+            Self::Home => Some("/home"),
+            Self::Var => Some("/var"),
+            Self::Srv => Some("/srv"),
+            Self::BiosBoot | Self::Swap => None,
+        }
+    }
+
+    /// Default GPT attribute bits for this role
+    ///
+    /// Boot partitions are marked as required so that partitioning tools leave them alone,
+    /// while data partitions have their filesystems grown when the partition is enlarged.
+    pub fn attributes(&self) -> u64 {
+        match self {
+            Self::Esp | Self::BiosBoot => ATTR_REQUIRED_PARTITION,
+            Self::Root(_) | Self::Home | Self::Var | Self::Srv => ATTR_GROWFS,
+            Self::ExtendedBoot | Self::Swap => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_types() {
+        assert_eq!(Role::Esp.type_guid(), gpt::partition_types::EFI.guid);
+        assert_eq!(Role::BiosBoot.type_guid(), gpt::partition_types::BIOS.guid);
+        assert_eq!(Role::Swap.type_guid(), gpt::partition_types::LINUX_SWAP.guid);
+        assert_eq!(
+            Role::Root(Architecture::X86_64).type_guid(),
+            gpt::partition_types::LINUX_ROOT_X64.guid
+        );
+        assert_eq!(LINUX_FS, gpt::partition_types::LINUX_FS.guid);
+        assert_eq!(Role::Esp.attributes(), ATTR_REQUIRED_PARTITION);
+        assert_eq!("x86_64".parse(), Ok(Architecture::X86_64));
+        assert_eq!(Architecture::Aarch64.to_string().parse(), Ok(Architecture::Aarch64));
+        assert!("sparc64".parse::<Architecture>().is_err());
+    }
+}
@@ -0,0 +1,255 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Database of well known partition types
+//!
+//! Maps GPT type GUIDs and MBR type bytes to human readable names and broad categories,
+//! for installer UIs and reports describing existing disks. Types with a
+//! [`crate::partition_type::Role`] link to it, so a type found on disk can be mapped back
+//! to its Discoverable Partitions Specification role, including the architecture of
+//! root partitions.
+//!
+//! Lookups work in every direction: [`by_guid()`], [`by_mbr()`], [`by_name()`] and
+//! [`by_role()`].
+
+use std::fmt;
+
+use uuid::{uuid, Uuid};
+
+use crate::partition_type::{Architecture, Role, LINUX_FS};
+
+/// Broad classification of a partition type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    /// EFI System Partition
+    Efi,
+    /// Linux data, boot and root partitions
+    Linux,
+    /// Windows data and system partitions, including FAT
+    Windows,
+    /// Software RAID members
+    Raid,
+    /// LVM physical volumes
+    Lvm,
+    /// Swap space
+    Swap,
+    /// Anything else, e.g. extended partitions or other operating systems
+    Other,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Efi => f.write_str("efi"),
+            Self::Linux => f.write_str("linux"),
+            Self::Windows => f.write_str("windows"),
+            Self::Raid => f.write_str("raid"),
+            Self::Lvm => f.write_str("lvm"),
+            Self::Swap => f.write_str("swap"),
+            Self::Other => f.write_str("other"),
+        }
+    }
+}
+
+/// A well known partition type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionType {
+    /// Human readable name
+    pub name: &'static str,
+    /// Broad classification
+    pub category: Category,
+    /// GPT type GUID, if the type exists on GPT disks
+    pub guid: Option<Uuid>,
+    /// MBR type byte, if the type exists on MBR disks
+    pub mbr: Option<u8>,
+    /// Discoverable Partitions Specification role, if any
+    pub role: Option<Role>,
+}
+
+impl fmt::Display for PartitionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+const fn entry(
+    name: &'static str,
+    category: Category,
+    guid: Option<Uuid>,
+    mbr: Option<u8>,
+    role: Option<Role>,
+) -> PartitionType {
+    PartitionType {
+        name,
+        category,
+        guid,
+        mbr,
+        role,
+    }
+}
+
+/// An entry for the type of a Discoverable Partitions Specification role
+const fn role(name: &'static str, category: Category, role: Role, mbr: Option<u8>) -> PartitionType {
+    entry(name, category, Some(role.type_guid()), mbr, Some(role))
+}
+
+const TYPES: &[PartitionType] = &[
+    role("EFI System", Category::Efi, Role::Esp, Some(0xef)),
+    role("Linux extended boot", Category::Linux, Role::ExtendedBoot, Some(0xea)),
+    role("BIOS boot", Category::Other, Role::BiosBoot, None),
+    role("Linux root (x86)", Category::Linux, Role::Root(Architecture::X86), None),
+    role(
+        "Linux root (x86-64)",
+        Category::Linux,
+        Role::Root(Architecture::X86_64),
+        None,
+    ),
+    role("Linux root (ARM)", Category::Linux, Role::Root(Architecture::Arm), None),
+    role(
+        "Linux root (ARM64)",
+        Category::Linux,
+        Role::Root(Architecture::Aarch64),
+        None,
+    ),
+    role(
+        "Linux root (RISC-V 64)",
+        Category::Linux,
+        Role::Root(Architecture::Riscv64),
+        None,
+    ),
+    role("Linux home", Category::Linux, Role::Home, None),
+    role("Linux swap", Category::Swap, Role::Swap, Some(0x82)),
+    role("Linux variable data", Category::Linux, Role::Var, None),
+    role("Linux server data", Category::Linux, Role::Srv, None),
+    entry("Linux filesystem", Category::Linux, Some(LINUX_FS), Some(0x83), None),
+    entry(
+        "Linux LUKS",
+        Category::Linux,
+        Some(uuid!("ca7d7ccb-63ed-4c53-861c-1742536059cc")),
+        Some(0xe8),
+        None,
+    ),
+    entry(
+        "Linux RAID",
+        Category::Raid,
+        Some(uuid!("a19d880f-05fc-4d3b-a006-743f0f84911e")),
+        Some(0xfd),
+        None,
+    ),
+    entry(
+        "Linux LVM",
+        Category::Lvm,
+        Some(uuid!("e6d6d379-f507-44c2-a23c-238f2a3df928")),
+        Some(0x8e),
+        None,
+    ),
+    entry(
+        "Microsoft basic data",
+        Category::Windows,
+        Some(uuid!("ebd0a0a2-b9e5-4433-87c0-68b6b72699c7")),
+        Some(0x07),
+        None,
+    ),
+    entry(
+        "Microsoft reserved",
+        Category::Windows,
+        Some(uuid!("e3c9e316-0b5c-4db8-817d-f92df00215ae")),
+        None,
+        None,
+    ),
+    entry(
+        "Windows recovery environment",
+        Category::Windows,
+        Some(uuid!("de94bba4-06d1-4d40-a16a-bfd50179d6ac")),
+        Some(0x27),
+        None,
+    ),
+    entry("FAT16", Category::Windows, None, Some(0x06), None),
+    entry("FAT32", Category::Windows, None, Some(0x0b), None),
+    entry("FAT32 (LBA)", Category::Windows, None, Some(0x0c), None),
+    entry("FAT16 (LBA)", Category::Windows, None, Some(0x0e), None),
+    entry("Extended", Category::Other, None, Some(0x05), None),
+    entry("Extended (LBA)", Category::Other, None, Some(0x0f), None),
+    entry("Linux extended", Category::Other, None, Some(0x85), None),
+    entry("GPT protective", Category::Other, None, Some(0xee), None),
+    entry(
+        "Apple HFS+",
+        Category::Other,
+        Some(uuid!("48465300-0000-11aa-aa11-00306543ecac")),
+        Some(0xaf),
+        None,
+    ),
+    entry(
+        "Apple APFS",
+        Category::Other,
+        Some(uuid!("7c3457ef-0000-11aa-aa11-00306543ecac")),
+        None,
+        None,
+    ),
+];
+
+/// Every known partition type
+pub fn all() -> &'static [PartitionType] {
+    TYPES
+}
+
+/// Look up a GPT partition type GUID
+pub fn by_guid(guid: &Uuid) -> Option<&'static PartitionType> {
+    TYPES.iter().find(|t| t.guid.as_ref() == Some(guid))
+}
+
+/// Look up an MBR partition type byte
+pub fn by_mbr(byte: u8) -> Option<&'static PartitionType> {
+    TYPES.iter().find(|t| t.mbr == Some(byte))
+}
+
+/// Look up a type by its name, ignoring case
+pub fn by_name(name: &str) -> Option<&'static PartitionType> {
+    TYPES.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+}
+
+/// Look up the type of a Discoverable Partitions Specification role
+pub fn by_role(role: Role) -> Option<&'static PartitionType> {
+    TYPES.iter().find(|t| t.role == Some(role))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_lookups() {
+        let esp = by_guid(&Role::Esp.type_guid()).unwrap();
+        assert_eq!(esp.category, Category::Efi);
+        assert_eq!(by_mbr(0xef), Some(esp));
+        assert_eq!(by_name("efi system"), Some(esp));
+
+        let root = by_role(Role::Root(Architecture::Aarch64)).unwrap();
+        assert_eq!(root.to_string(), "Linux root (ARM64)");
+        assert_eq!(
+            by_guid(&root.guid.unwrap()).unwrap().role,
+            Some(Role::Root(Architecture::Aarch64))
+        );
+
+        assert_eq!(by_mbr(0x83).unwrap().guid, Some(LINUX_FS));
+        assert_eq!(by_mbr(0xfd).unwrap().category, Category::Raid);
+        assert_eq!(
+            by_guid(&gpt::partition_types::LINUX_LVM.guid).unwrap().category,
+            Category::Lvm
+        );
+        assert!(by_mbr(0x42).is_none());
+    }
+
+    #[test]
+    fn test_unique() {
+        let guids = TYPES.iter().filter_map(|t| t.guid).collect::<Vec<_>>();
+        assert_eq!(guids.len(), guids.iter().collect::<HashSet<_>>().len());
+        let bytes = TYPES.iter().filter_map(|t| t.mbr).collect::<Vec<_>>();
+        assert_eq!(bytes.len(), bytes.iter().collect::<HashSet<_>>().len());
+        let names = TYPES.iter().map(|t| t.name.to_lowercase()).collect::<Vec<_>>();
+        assert_eq!(names.len(), names.iter().collect::<HashSet<_>>().len());
+    }
+}
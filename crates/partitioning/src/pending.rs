@@ -0,0 +1,176 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Markers for partitions created by unfinished provisioning runs
+//!
+//! Writing a partition table is only the first step of provisioning: filesystems and the
+//! like are created afterwards. Should that fail, or the machine go down halfway, the disk
+//! is left with partitions that look finished but are not.
+//!
+//! A [`crate::writer::DiskWriter`] with [`crate::writer::DiskWriter::mark_pending()`] sets
+//! [`ATTR_PENDING`] on every partition it creates. Once a partition is complete the marker
+//! is removed with [`clear_pending()`]. Any marked partitions found later with
+//! [`pending_partitions()`] were therefore left behind by a previous run, and can be
+//! finished or removed with [`remove_pending()`].
+
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use gpt::GptConfig;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    partition_type::ATTR_PENDING,
+    writer::{partition_device_path, WriteError},
+};
+
+/// Size of a logical block in bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// A partition marked as pending
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingPartition {
+    /// Partition number within the table
+    pub number: u32,
+    /// Name of the partition, the id of its tag when it was written
+    pub name: String,
+    /// Start of the partition in bytes
+    pub start: u64,
+    /// End of the partition in bytes (exclusive)
+    pub end: u64,
+    /// Unique GUID of the partition
+    pub guid: Uuid,
+    /// Device node of the partition
+    pub device: PathBuf,
+}
+
+/// Find the partitions on `device` still marked as pending
+///
+/// Disks without a GPT partition table have no pending partitions.
+pub fn pending_partitions(device: &Path) -> Result<Vec<PendingPartition>, WriteError> {
+    let file = File::open(device)?;
+    let table = match GptConfig::new().writable(false).open_from_device(file) {
+        Ok(table) => table,
+        Err(e) => {
+            debug!("No GPT partition table on {:?}: {}", device, e);
+            return Ok(vec![]);
+        }
+    };
+
+    Ok(table
+        .partitions()
+        .iter()
+        .filter(|(_, p)| p.flags & ATTR_PENDING != 0)
+        .map(|(number, p)| PendingPartition {
+            number: *number,
+            name: p.name.clone(),
+            start: p.first_lba * SECTOR_SIZE,
+            end: (p.last_lba + 1) * SECTOR_SIZE,
+            guid: p.part_guid,
+            device: partition_device_path(device, *number),
+        })
+        .collect())
+}
+
+/// Remove the pending marker from the given partitions of `device`
+pub fn clear_pending(device: &Path, numbers: &[u32]) -> Result<(), WriteError> {
+    let file = OpenOptions::new().read(true).write(true).open(device)?;
+    let mut table = GptConfig::new().writable(true).open_from_device(file)?;
+
+    let mut partitions = table.partitions().clone();
+    for number in numbers {
+        if let Some(partition) = partitions.get_mut(number) {
+            partition.flags &= !ATTR_PENDING;
+        }
+    }
+    table.update_partitions(partitions)?;
+    table.write()?;
+
+    info!("Cleared pending marker of partitions {:?} on {:?}", numbers, device);
+    Ok(())
+}
+
+/// Delete every pending partition from the table of `device`
+///
+/// Returns the numbers of the removed partitions. Only the table is changed, data on the
+/// disk is left as it is.
+pub fn remove_pending(device: &Path) -> Result<Vec<u32>, WriteError> {
+    let pending = pending_partitions(device)?;
+    if pending.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let file = OpenOptions::new().read(true).write(true).open(device)?;
+    let mut table = GptConfig::new().writable(true).open_from_device(file)?;
+    let numbers = pending
+        .iter()
+        .filter_map(|p| table.remove_partition(p.number))
+        .collect::<Vec<_>>();
+    table.write()?;
+
+    info!("Removed pending partitions {:?} from {:?}", numbers, device);
+    Ok(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use gpt::{mbr::ProtectiveMBR, partition_types};
+
+    use super::*;
+
+    /// Create a GPT disk image with one finished and two pending partitions
+    fn image(path: &Path) {
+        let file = File::create(path).unwrap();
+        file.set_len(64 * 1024 * 1024).unwrap();
+        let mut file = OpenOptions::new().read(true).write(true).open(path).unwrap();
+        ProtectiveMBR::with_lb_size(64 * 2048 - 1)
+            .overwrite_lba0(&mut file)
+            .unwrap();
+
+        let mut table = GptConfig::new().writable(true).create_from_device(file, None).unwrap();
+        for (number, name, flags) in [(1, "esp", 1), (2, "root", 1 | ATTR_PENDING), (3, "home", ATTR_PENDING)] {
+            let first_lba = 2048 * number as u64;
+            table
+                .add_partition_at(name, number, first_lba, 2048, partition_types::LINUX_FS, flags)
+                .unwrap();
+        }
+        table.write().unwrap();
+    }
+
+    #[test]
+    fn test_pending_markers() {
+        let path = std::env::temp_dir().join(format!("disks-rs-pending-test-{}", std::process::id()));
+        image(&path);
+
+        let pending = pending_partitions(&path).unwrap();
+        assert_eq!(
+            pending.iter().map(|p| (p.number, p.name.as_str())).collect::<Vec<_>>(),
+            vec![(2, "root"), (3, "home")]
+        );
+        assert_eq!(pending[0].start, 2 * 2048 * SECTOR_SIZE);
+        assert_eq!(pending[0].end, 3 * 2048 * SECTOR_SIZE);
+
+        // Other attributes survive
+        clear_pending(&path, &[2]).unwrap();
+        let table = GptConfig::new()
+            .writable(false)
+            .open_from_device(File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!(table.partitions()[&2].flags, 1);
+        drop(table);
+
+        assert_eq!(remove_pending(&path).unwrap(), vec![3]);
+        assert!(pending_partitions(&path).unwrap().is_empty());
+        let table = GptConfig::new()
+            .writable(false)
+            .open_from_device(File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!(table.partitions().keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -9,13 +9,29 @@
 //!
 //! - Plan new partition additions with proper alignment
 //! - Remove existing partitions
-//! - Track and undo changes
+//! - Start from the on-disk GPT, keeping partition types, names and attributes
+//! - Track, undo and redo changes
+//! - Record named checkpoints and roll back to them
 //! - Validate that changes won't conflict with existing partitions
-
-use disks::BlockDevice;
-use log::{debug, warn};
-use std::collections::VecDeque;
+//! - Summarise changes as structured data for user interfaces
+//! - Describe changes in other languages through a [`Locale`]
+//! - Replace partitions in place, the deletion is always planned before the addition reusing its space
+//! - Assign partition numbers up front, either requested explicitly or following a [`Numbering`]
+
+use crate::{
+    locale::{English, Locale, Message},
+    table,
+};
+use disks::{flags::PartitionFlags, BlockDevice};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::File,
+    io::{Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
+use tracing::{debug, warn};
+use uuid::Uuid;
 
 /// Errors that can occur while planning partition changes
 ///
@@ -29,6 +45,12 @@ pub enum PlanError {
     RegionOutOfBounds { start: u64, end: u64 },
     #[error("No free regions available")]
     NoFreeRegions,
+    #[error("Partition number {0} is already in use")]
+    NumberInUse(u32),
+    #[error("Partition number {number} is outside the table (1..={max})")]
+    InvalidNumber { number: u32, max: u32 },
+    #[error("No existing partition at index {0}")]
+    UnknownPartition(usize),
 }
 
 /// A planned modification to the disk's partition layout
@@ -39,7 +61,13 @@ pub enum PlanError {
 #[derive(Debug, Clone)]
 pub enum Change {
     /// Add a new partition
-    AddPartition { start: u64, end: u64 },
+    AddPartition {
+        start: u64,
+        end: u64,
+        tag: Option<PartitionTag>,
+        /// Partition number requested by the caller, see [`Planner::partition_numbers()`]
+        number: Option<u32>,
+    },
     /// Delete an existing partition
     DeletePartition { original_index: usize },
 }
@@ -47,14 +75,100 @@ pub enum Change {
 /// A disk partitioning planner.
 #[derive(Debug, Clone)]
 pub struct Planner {
+    /// Device node of the disk being planned
+    device: PathBuf,
     /// First usable LBA position on disk in bytes
     usable_start: u64,
     /// Last usable LBA position on disk in bytes
     usable_end: u64,
+    /// Total size of the disk in bytes
+    disk_size: u64,
+    /// Partition table format the usable region was derived from, if any
+    table: Option<TableType>,
     /// Stack of changes that can be undone
     changes: VecDeque<Change>,
+    /// Stack of undone changes that can be redone
+    undone: Vec<Change>,
     /// Original partition layout for reference
     original_regions: Vec<Region>,
+    /// Partition numbers of the original partitions, indexed like `original_regions`
+    original_numbers: Vec<u32>,
+    /// Number of entries in the existing partition table
+    entry_slots: u32,
+    /// How additions without an explicit number are numbered
+    numbering: Numbering,
+    /// Whether a fresh partition table replaces the existing one
+    new_table: bool,
+    /// Named snapshots of the planner state
+    checkpoints: Vec<Checkpoint>,
+}
+
+/// How partitions added without an explicit number are numbered
+///
+/// Both are resolved by the planner before anything is written, so the numbers shown
+/// to the user are the ones the writer and the kernel will use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Numbering {
+    /// Take the lowest free number, including numbers freed by deleted partitions
+    ///
+    /// This matches what `fdisk` and the `gpt` crate do on their own.
+    #[default]
+    ReuseFreed,
+    /// Continue after the highest number the table has held, leaving gaps alone
+    ///
+    /// Device nodes of the partitions that are kept never get reused by new ones.
+    Append,
+}
+
+/// What a [`ChangeSummary`] does to the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// A new partition is created in free space
+    Add,
+    /// An existing partition is removed
+    Delete,
+    /// A new partition is created in the space of removed partitions
+    Replace,
+}
+
+/// Structured description of a pending change, see [`Planner::summaries()`]
+///
+/// User interfaces bind list items to the `id` and render their own text, e.g. in the
+/// user's language, instead of parsing [`Planner::describe_changes()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeSummary {
+    /// Position of the change in the queue, stable until it is undone
+    pub id: usize,
+    /// What the change does
+    pub kind: ChangeKind,
+    /// Device node of the disk
+    pub device: PathBuf,
+    /// Start of the added or deleted region in bytes
+    pub start: u64,
+    /// End of the region in bytes
+    pub end: u64,
+    /// Size of the region in bytes
+    pub size: u64,
+    /// Role of an added partition, if tagged with one
+    pub role: Option<String>,
+    /// Flags of an added partition
+    pub flags: PartitionFlags,
+    /// Original indices of deleted or replaced partitions
+    pub partitions: Vec<usize>,
+    /// Number of the added partition, or of the deleted one
+    ///
+    /// `None` if an addition does not fit the partition table.
+    pub number: Option<u32>,
+}
+
+/// A named snapshot of the planner state, see [`Planner::checkpoint()`]
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    label: String,
+    changes: VecDeque<Change>,
+    original_regions: Vec<Region>,
+    original_numbers: Vec<u32>,
+    new_table: bool,
 }
 
 /// A contiguous region of disk space defined by absolute start and end positions
@@ -76,6 +190,98 @@ pub struct Region {
 
     /// The absolute end position of this region in bytes
     pub end: u64,
+
+    /// Metadata attached when the partition was planned, if any
+    pub(crate) tag: Option<PartitionTag>,
+}
+
+/// Caller supplied metadata carried alongside a planned partition
+///
+/// The planner never interprets the tag. It is kept attached to the partition through
+/// [`Planner::current_layout()`] and handed back by the writer, so consumers can tell
+/// which device node ended up holding e.g. the root filesystem. The writer uses the
+/// partition type and attributes when creating the partition.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartitionTag {
+    /// Caller defined identifier, e.g. the partition id from a provisioning strategy
+    pub id: Option<String>,
+    /// Intended role of the partition (`root`, `home`, ...)
+    pub role: Option<String>,
+    /// Filesystem the partition will be formatted with
+    pub filesystem: Option<String>,
+    /// Where the partition will be mounted
+    pub mountpoint: Option<String>,
+    /// GPT partition type, see [`crate::partition_type`]
+    pub partition_type: Option<Uuid>,
+    /// GPT partition name, the id is written when unset
+    ///
+    /// Names longer than 36 UTF-16 code units are truncated when written.
+    pub name: Option<String>,
+    /// GPT attribute bits
+    pub attributes: u64,
+}
+
+impl PartitionTag {
+    /// Flags set in the attribute bits
+    pub fn flags(&self) -> PartitionFlags {
+        PartitionFlags::from_gpt_attributes(self.attributes)
+    }
+
+    /// Replace the flags in the attribute bits, keeping bits that aren't flags
+    pub fn with_flags(self, flags: PartitionFlags) -> Self {
+        Self {
+            attributes: flags.apply_to_gpt_attributes(self.attributes),
+            ..self
+        }
+    }
+}
+
+/// Partition table formats with known on-disk overhead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableType {
+    /// GUID Partition Table, with a primary copy at the start and a backup at the end
+    Gpt,
+    /// Master Boot Record (msdos), limited to 2^32 sectors
+    Mbr,
+}
+
+/// Size of a logical block in bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// Partition entries in a GPT created by the writer
+const GPT_ENTRIES: u32 = 128;
+
+/// Primary partitions in an MBR
+const MBR_ENTRIES: u32 = 4;
+
+/// Sectors kept free at the end of the disk for the backup GPT
+///
+/// The backup header and its 128 entries occupy 33 sectors. One more is reserved as the
+/// `gpt` crate does not let a partition end on the last usable sector.
+const GPT_BACKUP_SECTORS: u64 = 34;
+
+impl TableType {
+    /// Compute the region of a disk of `disk_size` bytes that partitions may occupy
+    ///
+    /// The start is rounded up to [`PARTITION_ALIGNMENT`], as the space before it only
+    /// holds the MBR and primary GPT.
+    pub fn usable_region(&self, disk_size: u64) -> Region {
+        let end = match self {
+            TableType::Gpt => disk_size.saturating_sub(GPT_BACKUP_SECTORS * SECTOR_SIZE),
+            TableType::Mbr => disk_size.min(u32::MAX as u64 * SECTOR_SIZE),
+        };
+        Region::new(PARTITION_ALIGNMENT.min(end), end)
+    }
+
+    /// Bytes of a disk that partitions can never occupy
+    ///
+    /// This is the aligned space before the first partition plus the backup GPT, if any.
+    pub fn overhead(&self) -> u64 {
+        match self {
+            TableType::Gpt => PARTITION_ALIGNMENT + GPT_BACKUP_SECTORS * SECTOR_SIZE,
+            TableType::Mbr => PARTITION_ALIGNMENT,
+        }
+    }
 }
 
 /// Default alignment for partition boundaries (1MiB)
@@ -92,7 +298,17 @@ pub const PARTITION_ALIGNMENT: u64 = 1024 * 1024;
 impl Region {
     /// Create a new region with the given bounds
     pub fn new(start: u64, end: u64) -> Self {
-        Self { start, end }
+        Self { start, end, tag: None }
+    }
+
+    /// Attach metadata to this region
+    pub fn with_tag(self, tag: PartitionTag) -> Self {
+        Self { tag: Some(tag), ..self }
+    }
+
+    /// Metadata attached when the partition was planned, if any
+    pub fn tag(&self) -> Option<&PartitionTag> {
+        self.tag.as_ref()
     }
 
     /// Get the size of this region in bytes
@@ -107,11 +323,18 @@ impl Region {
 
     /// Get a human readable description of this region
     pub fn describe(&self, disk_size: u64) -> String {
-        format!(
-            "{} at {}..{}",
-            format_size(self.size()),
-            format_position(self.start, disk_size),
-            format_position(self.end, disk_size)
+        self.describe_with(disk_size, &English)
+    }
+
+    /// Describe this region in the language of `locale`
+    pub fn describe_with(&self, disk_size: u64, locale: &dyn Locale) -> String {
+        locale.format(
+            Message::Region,
+            &[
+                ("size", locale.size(self.size())),
+                ("start", format_position_with(self.start, disk_size, locale)),
+                ("end", format_position_with(self.end, disk_size, locale)),
+            ],
         )
     }
 }
@@ -119,6 +342,8 @@ impl Region {
 /// Format a size in bytes into a human readable string
 /// Format a byte size into a human-readable string with appropriate units
 ///
+/// Units are binary unless changed with [`crate::locale::set_size_format()`].
+///
 /// # Examples
 ///
 /// ```
@@ -127,23 +352,7 @@ impl Region {
 /// assert_eq!(format_size(1500000), "1.4MiB");
 /// ```
 pub fn format_size(size: u64) -> String {
-    const KB: f64 = 1024.0;
-    const MB: f64 = KB * 1024.0;
-    const GB: f64 = MB * 1024.0;
-    const TB: f64 = GB * 1024.0;
-
-    let size = size as f64;
-    if size >= TB {
-        format!("{:.1}TiB", size / TB)
-    } else if size >= GB {
-        format!("{:.1}GiB", size / GB)
-    } else if size >= MB {
-        format!("{:.1}MiB", size / MB)
-    } else if size >= KB {
-        format!("{:.1}KiB", size / KB)
-    } else {
-        format!("{}B", size)
-    }
+    English.size(size)
 }
 
 /// Format a disk position as a percentage and absolute size
@@ -159,12 +368,21 @@ pub fn format_size(size: u64) -> String {
 /// assert_eq!(format_position(500, total), "50% (500B)");
 /// ```
 pub fn format_position(pos: u64, total: u64) -> String {
-    format!("{}% ({})", (pos as f64 / total as f64 * 100.0) as u64, format_size(pos))
+    format_position_with(pos, total, &English)
+}
+
+/// Format a disk position in the language of `locale`
+pub fn format_position_with(pos: u64, total: u64, locale: &dyn Locale) -> String {
+    let percent = (pos as f64 / total as f64 * 100.0) as u64;
+    locale.format(
+        Message::Position,
+        &[("percent", percent.to_string()), ("size", locale.size(pos))],
+    )
 }
 
 /// Check if a value is already aligned to the given boundary
 fn is_aligned(value: u64, alignment: u64) -> bool {
-    value % alignment == 0
+    value.is_multiple_of(alignment)
 }
 
 /// Align up to the nearest multiple of alignment, unless already aligned
@@ -188,18 +406,30 @@ fn align_down(value: u64, alignment: u64) -> u64 {
 impl Change {
     /// Get a human readable description of this change
     pub fn describe(&self, disk_size: u64) -> String {
+        self.describe_with(disk_size, &English)
+    }
+
+    /// Describe this change in the language of `locale`
+    pub fn describe_with(&self, disk_size: u64, locale: &dyn Locale) -> String {
         match self {
-            Change::AddPartition { start, end } => {
-                format!(
-                    "Add new partition: {} ({} at {})",
-                    format_size(end - start),
-                    Region::new(*start, *end).describe(disk_size),
-                    format_position(*start, disk_size)
-                )
-            }
-            Change::DeletePartition { original_index } => {
-                format!("Delete partition #{}", original_index + 1)
+            Change::AddPartition { start, end, tag, .. } => {
+                let mut args = vec![
+                    ("size", locale.size(end - start)),
+                    ("region", Region::new(*start, *end).describe_with(disk_size, locale)),
+                    ("position", format_position_with(*start, disk_size, locale)),
+                ];
+                match tag.as_ref().and_then(|t| t.role.clone()) {
+                    Some(role) => {
+                        args.push(("role", role));
+                        locale.format(Message::AddPartitionFor, &args)
+                    }
+                    None => locale.format(Message::AddPartition, &args),
+                }
             }
+            Change::DeletePartition { original_index } => locale.format(
+                Message::DeletePartition,
+                &[("number", (original_index + 1).to_string())],
+            ),
         }
     }
 }
@@ -209,21 +439,81 @@ impl Planner {
     pub fn new(device: &BlockDevice) -> Self {
         debug!("Creating new partition planner for device of size {}", device.size());
 
-        // Extract original regions from device
+        // Extract original regions from device, converting from sectors to bytes
         let original_regions = device
             .partitions()
             .iter()
-            .map(|p| Region::new(p.start, p.end))
+            .map(|p| Region::new(p.start * 512, p.end * 512))
             .collect();
+        let original_numbers = device.partitions().iter().map(|p| p.number).collect();
 
         Self {
+            device: device.device().to_owned(),
             usable_start: 0,
             usable_end: device.size(),
+            disk_size: device.size(),
+            table: None,
             changes: VecDeque::new(),
+            undone: Vec::new(),
             original_regions,
+            original_numbers,
+            entry_slots: GPT_ENTRIES,
+            numbering: Numbering::default(),
+            new_table: false,
+            checkpoints: Vec::new(),
         }
     }
 
+    /// Creates a planner from the GPT of a disk or image
+    ///
+    /// Unlike [`Planner::new()`], which only sees the sector ranges the kernel reports,
+    /// every original region carries a tag with the partition type, name and attributes
+    /// found in the table. The usable region is taken from the GPT header.
+    pub fn from_gpt(path: impl AsRef<Path>) -> Result<Self, table::Error> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let disk_size = file.seek(SeekFrom::End(0))?;
+        let gpt = table::read(&mut file)?;
+        debug!(
+            "Creating partition planner from GPT of {:?} with {} partitions",
+            path,
+            gpt.entries.len()
+        );
+
+        let block_size = gpt.block_size;
+        let original_regions = gpt
+            .entries
+            .iter()
+            .map(|entry| Region {
+                start: entry.first_lba * block_size,
+                end: (entry.last_lba + 1) * block_size,
+                tag: Some(PartitionTag {
+                    partition_type: Some(entry.type_guid),
+                    name: Some(entry.name.clone()).filter(|n| !n.is_empty()),
+                    attributes: entry.attributes,
+                    ..Default::default()
+                }),
+            })
+            .collect();
+        let original_numbers = gpt.entries.iter().map(|entry| entry.number).collect();
+
+        Ok(Self {
+            device: path.to_owned(),
+            usable_start: gpt.header.first_usable_lba * block_size,
+            usable_end: (gpt.header.last_usable_lba + 1) * block_size,
+            disk_size,
+            table: Some(TableType::Gpt),
+            changes: VecDeque::new(),
+            undone: Vec::new(),
+            original_regions,
+            original_numbers,
+            entry_slots: gpt.header.num_entries,
+            numbering: Numbering::default(),
+            new_table: false,
+            checkpoints: Vec::new(),
+        })
+    }
+
     /// Set the usable disk region offsets
     pub fn with_start_offset(self, offset: u64) -> Self {
         Self {
@@ -240,21 +530,217 @@ impl Planner {
         }
     }
 
+    /// Derive the usable disk region from the overhead of the given table type
+    ///
+    /// Without a table type the whole disk is considered usable.
+    pub fn with_table(self, table: TableType) -> Self {
+        let region = table.usable_region(self.disk_size);
+        Self {
+            usable_start: region.start,
+            usable_end: region.end,
+            table: Some(table),
+            ..self
+        }
+    }
+
+    /// Returns the partition table type the planner was configured for
+    pub fn table(&self) -> Option<TableType> {
+        self.table
+    }
+
+    /// Number partitions added without an explicit number according to `numbering`
+    pub fn with_numbering(self, numbering: Numbering) -> Self {
+        Self { numbering, ..self }
+    }
+
+    /// Returns how partitions added without an explicit number are numbered
+    pub fn numbering(&self) -> Numbering {
+        self.numbering
+    }
+
+    /// Highest partition number the resulting table can hold
+    pub fn max_number(&self) -> u32 {
+        match self.table {
+            Some(TableType::Mbr) => MBR_ENTRIES,
+            _ if self.new_table => GPT_ENTRIES,
+            _ => self.entry_slots,
+        }
+    }
+
     /// Get a human readable description of pending changes
     pub fn describe_changes(&self) -> String {
+        self.describe_changes_with(&English)
+    }
+
+    /// Describe the pending changes in the language of `locale`
+    pub fn describe_changes_with(&self, locale: &dyn Locale) -> String {
         if self.changes.is_empty() {
-            return "No pending changes".to_string();
+            return locale.format(Message::NoChanges, &[]);
         }
 
-        let mut description = "Pending changes:\n".to_string();
+        let mut description = format!("{}\n", locale.format(Message::PendingChanges, &[]));
 
         for (i, change) in self.changes.iter().enumerate() {
-            description.push_str(&format!("  {}: {}\n", i + 1, change.describe(self.usable_size())));
+            description.push_str(&format!(
+                "  {}: {}",
+                i + 1,
+                change.describe_with(self.usable_size(), locale)
+            ));
+            let replaced = self.replaced_partitions(i);
+            if !replaced.is_empty() {
+                let numbers = replaced.iter().map(|n| format!("#{}", n + 1)).collect::<Vec<_>>();
+                let replaces = locale.format(Message::ReplacesPartitions, &[("partitions", numbers.join(", "))]);
+                description.push_str(&format!(" {replaces}"));
+            }
+            description.push('\n');
         }
 
         description
     }
 
+    /// Structured summaries of the pending changes, in planned order
+    pub fn summaries(&self) -> Vec<ChangeSummary> {
+        let numbers = self.partition_numbers();
+        self.changes
+            .iter()
+            .enumerate()
+            .map(|(id, change)| {
+                let number = match change {
+                    Change::AddPartition { .. } => numbers[id],
+                    Change::DeletePartition { original_index } => self.original_numbers.get(*original_index).copied(),
+                };
+                let (kind, region, tag, partitions) = match change {
+                    Change::AddPartition { start, end, tag, .. } => {
+                        let replaced = self.replaced_partitions(id);
+                        let kind = if replaced.is_empty() {
+                            ChangeKind::Add
+                        } else {
+                            ChangeKind::Replace
+                        };
+                        (kind, Region::new(*start, *end), tag.as_ref(), replaced)
+                    }
+                    Change::DeletePartition { original_index } => {
+                        let region = self.original_regions.get(*original_index).cloned();
+                        let region = region.unwrap_or_else(|| Region::new(0, 0));
+                        (ChangeKind::Delete, region, None, vec![*original_index])
+                    }
+                };
+                ChangeSummary {
+                    id,
+                    kind,
+                    device: self.device.clone(),
+                    start: region.start,
+                    end: region.end,
+                    size: region.size(),
+                    role: tag.and_then(|t| t.role.clone()),
+                    flags: tag.map_or(PartitionFlags::NONE, PartitionTag::flags),
+                    partitions,
+                    number,
+                }
+            })
+            .collect()
+    }
+
+    /// Indices of the changes that must be applied before the change at `index`
+    ///
+    /// An addition depends on every deletion of an original partition it overlaps, as the
+    /// space only becomes free once that partition is gone, and on the deletion of the
+    /// partition holding the number it asks for. Deletions have no dependencies.
+    ///
+    /// Space and numbers are only free to plan with once their deletion is planned, so
+    /// dependencies always come earlier and the changes apply in planned order.
+    pub fn dependencies(&self, index: usize) -> Vec<usize> {
+        let Some(Change::AddPartition { start, end, number, .. }) = self.changes.get(index) else {
+            return vec![];
+        };
+        let region = Region::new(*start, *end);
+
+        self.changes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, change)| match change {
+                Change::DeletePartition { original_index } => {
+                    let overlaps = self
+                        .original_regions
+                        .get(*original_index)
+                        .is_some_and(|r| r.overlaps_with(&region));
+                    let frees_number =
+                        number.is_some() && self.original_numbers.get(*original_index) == number.as_ref();
+                    (overlaps || frees_number).then_some(i)
+                }
+                Change::AddPartition { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Original indices of the partitions the change at `index` takes the place of
+    pub fn replaced_partitions(&self, index: usize) -> Vec<usize> {
+        self.dependencies(index)
+            .into_iter()
+            .filter_map(|i| match self.changes[i] {
+                Change::DeletePartition { original_index } => Some(original_index),
+                Change::AddPartition { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Partition number each pending change creates, indexed like [`Planner::changes()`]
+    ///
+    /// Numbers are assigned in planned order, so an addition can only reuse the number of a
+    /// partition deleted before it. Explicitly requested numbers are reserved first, the rest
+    /// follow the [`Numbering`] of the planner. Deletions, and additions for which no number
+    /// is left in the table, are `None`.
+    pub fn partition_numbers(&self) -> Vec<Option<u32>> {
+        let max = self.max_number();
+        let mut used = self.original_numbers.iter().copied().collect::<BTreeSet<_>>();
+        let reserved = self.requested_numbers();
+        let mut highest = used.last().copied().unwrap_or(0);
+        let mut numbers = vec![None; self.changes.len()];
+
+        for (id, change) in self.changes.iter().enumerate() {
+            match change {
+                Change::DeletePartition { original_index } => {
+                    if let Some(number) = self.original_numbers.get(*original_index) {
+                        used.remove(number);
+                    }
+                }
+                Change::AddPartition { number, .. } => {
+                    let first = match self.numbering {
+                        Numbering::ReuseFreed => 1,
+                        Numbering::Append => highest + 1,
+                    };
+                    let number = number.or_else(|| (first..=max).find(|n| !used.contains(n) && !reserved.contains(n)));
+                    if let Some(number) = number {
+                        debug!("Change {} creates partition {}", id + 1, number);
+                        used.insert(number);
+                        highest = highest.max(number);
+                    } else {
+                        warn!("No partition number left for change {}", id + 1);
+                    }
+                    numbers[id] = number;
+                }
+            }
+        }
+
+        numbers
+    }
+
+    /// Numbers explicitly requested by pending additions
+    fn requested_numbers(&self) -> BTreeSet<u32> {
+        self.changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::AddPartition { number, .. } => *number,
+                Change::DeletePartition { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Partition numbers of the original partitions, indexed like [`Planner::original_regions()`]
+    pub fn original_numbers(&self) -> &[u32] {
+        &self.original_numbers
+    }
+
     /// Returns the current effective layout after all pending changes
     pub fn current_layout(&self) -> Vec<Region> {
         let mut layout = self.original_regions.clone();
@@ -276,11 +762,12 @@ impl Planner {
 
         // Second pass: add new partitions
         for change in &self.changes {
-            if let Change::AddPartition { start, end } = change {
+            if let Change::AddPartition { start, end, tag, .. } = change {
                 debug!("Adding partition {}..{}", start, end);
                 layout.push(Region {
                     start: *start,
                     end: *end,
+                    tag: tag.clone(),
                 });
             }
         }
@@ -299,6 +786,63 @@ impl Planner {
     /// The partition will occupy the range [start, end).
     ///
     pub fn plan_add_partition(&mut self, start: u64, end: u64) -> Result<(), PlanError> {
+        self.add_partition(start, end, None, None)
+    }
+
+    /// Plan to add a new partition carrying the given tag
+    ///
+    /// Behaves exactly like [`Planner::plan_add_partition()`], the tag is carried
+    /// through to [`Planner::current_layout()`] and the writer.
+    pub fn plan_add_partition_with_tag(&mut self, start: u64, end: u64, tag: PartitionTag) -> Result<(), PlanError> {
+        self.add_partition(start, end, Some(tag), None)
+    }
+
+    /// Plan to add a new partition with the given partition number
+    ///
+    /// The number must fit the table and must not be held by a partition that is kept or
+    /// by another planned addition. The number of an original partition becomes available
+    /// once that partition is planned for deletion.
+    pub fn plan_add_numbered_partition(
+        &mut self,
+        start: u64,
+        end: u64,
+        number: u32,
+        tag: Option<PartitionTag>,
+    ) -> Result<(), PlanError> {
+        let max = self.max_number();
+        if number == 0 || number > max {
+            warn!("Partition number {} does not fit a table of {} entries", number, max);
+            return Err(PlanError::InvalidNumber { number, max });
+        }
+
+        let deleted = self
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::DeletePartition { original_index } => Some(*original_index),
+                Change::AddPartition { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        let kept = self
+            .original_numbers
+            .iter()
+            .enumerate()
+            .any(|(index, n)| *n == number && !deleted.contains(&index));
+        if kept || self.requested_numbers().contains(&number) {
+            warn!("Partition number {} is already in use", number);
+            return Err(PlanError::NumberInUse(number));
+        }
+
+        self.add_partition(start, end, tag, Some(number))
+    }
+
+    fn add_partition(
+        &mut self,
+        start: u64,
+        end: u64,
+        tag: Option<PartitionTag>,
+        number: Option<u32>,
+    ) -> Result<(), PlanError> {
         debug!("Planning to add partition {}..{}", start, end);
         debug!("Original size requested: {}", end - start);
 
@@ -359,9 +903,11 @@ impl Planner {
         }
 
         debug!("Adding new partition to change queue");
-        self.changes.push_back(Change::AddPartition {
+        self.push_change(Change::AddPartition {
             start: aligned_start,
             end: aligned_end,
+            tag,
+            number,
         });
         Ok(())
     }
@@ -379,15 +925,21 @@ impl Planner {
         }
 
         debug!("Adding partition deletion to change queue");
-        self.changes
-            .push_back(Change::DeletePartition { original_index: index });
+        self.push_change(Change::DeletePartition { original_index: index });
         Ok(())
     }
 
+    /// Queue a new change, discarding anything that could be redone
+    fn push_change(&mut self, change: Change) {
+        self.undone.clear();
+        self.changes.push_back(change);
+    }
+
     /// Undo the most recent change
     pub fn undo(&mut self) -> bool {
         if let Some(change) = self.changes.pop_back() {
             debug!("Undoing last change: {:?}", change);
+            self.undone.push(change);
             true
         } else {
             debug!("No changes to undo");
@@ -395,10 +947,109 @@ impl Planner {
         }
     }
 
+    /// Tags of the pending partition additions, in planned order
+    ///
+    /// Lets callers adjust partition types and attributes after planning.
+    pub fn tags_mut(&mut self) -> impl Iterator<Item = &mut PartitionTag> {
+        self.changes.iter_mut().filter_map(|change| match change {
+            Change::AddPartition { tag: Some(tag), .. } => Some(tag),
+            _ => None,
+        })
+    }
+
+    /// Set the flags of the partition added by the change with the given summary id
+    ///
+    /// Returns `false` if there is no such change or it doesn't add a partition.
+    pub fn set_partition_flags(&mut self, id: usize, flags: PartitionFlags) -> bool {
+        match self.changes.get_mut(id) {
+            Some(Change::AddPartition { tag, .. }) => {
+                debug!("Setting flags of change {} to {:?}", id, flags);
+                *tag = Some(tag.take().unwrap_or_default().with_flags(flags));
+                true
+            }
+            _ => {
+                debug!("No partition addition with id {} to flag", id);
+                false
+            }
+        }
+    }
+
+    /// Undo the change with the given summary id along with every change planned after it
+    ///
+    /// The undone changes can be redone in order. Returns `false` if there is no such change.
+    pub fn undo_to(&mut self, id: usize) -> bool {
+        if id >= self.changes.len() {
+            debug!("No change with id {} to undo", id);
+            return false;
+        }
+        while self.changes.len() > id {
+            self.undo();
+        }
+        true
+    }
+
+    /// Redo the most recently undone change
+    ///
+    /// Planning any new change discards the redo history.
+    pub fn redo(&mut self) -> bool {
+        if let Some(change) = self.undone.pop() {
+            debug!("Redoing change: {:?}", change);
+            self.changes.push_back(change);
+            true
+        } else {
+            debug!("No changes to redo");
+            false
+        }
+    }
+
+    /// Check if there are any undone changes that can be redone
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Record the current state under `label`, replacing any checkpoint with the same label
+    pub fn checkpoint(&mut self, label: impl Into<String>) {
+        let label = label.into();
+        debug!("Recording checkpoint {:?}", label);
+        self.checkpoints.retain(|c| c.label != label);
+        self.checkpoints.push(Checkpoint {
+            label,
+            changes: self.changes.clone(),
+            original_regions: self.original_regions.clone(),
+            original_numbers: self.original_numbers.clone(),
+            new_table: self.new_table,
+        });
+    }
+
+    /// Restore the state recorded by [`Planner::checkpoint()`]
+    ///
+    /// The checkpoint is kept so it can be rolled back to again. Returns `false`
+    /// if no checkpoint exists with the given label.
+    pub fn rollback_to(&mut self, label: &str) -> bool {
+        let Some(checkpoint) = self.checkpoints.iter().find(|c| c.label == label) else {
+            warn!("No checkpoint named {:?}", label);
+            return false;
+        };
+
+        debug!("Rolling back to checkpoint {:?}", label);
+        self.changes = checkpoint.changes.clone();
+        self.original_regions = checkpoint.original_regions.clone();
+        self.original_numbers = checkpoint.original_numbers.clone();
+        self.new_table = checkpoint.new_table;
+        self.undone.clear();
+        true
+    }
+
+    /// Get the labels of all recorded checkpoints, oldest first
+    pub fn checkpoints(&self) -> impl Iterator<Item = &str> {
+        self.checkpoints.iter().map(|c| c.label.as_str())
+    }
+
     /// Clear all planned changes
     pub fn reset(&mut self) {
         debug!("Resetting all planned changes");
         self.changes.clear();
+        self.undone.clear();
     }
 
     /// Check if there are any pending changes
@@ -410,6 +1061,39 @@ impl Planner {
         &self.changes
     }
 
+    /// Get the partitions found on the disk, indexed like [`Change::DeletePartition`]
+    pub fn original_regions(&self) -> &[Region] {
+        &self.original_regions
+    }
+
+    /// Find the gaps between the partitions of the current layout, in disk order
+    ///
+    /// Only the usable region is considered. Gaps are not aligned, see
+    /// [`Planner::free_space()`] for the space partitions can actually use.
+    pub fn free_regions(&self) -> Vec<Region> {
+        let mut regions = Vec::new();
+        let (mut current, end) = self.offsets();
+
+        // Sort existing partitions by start position
+        let mut layout = self.current_layout();
+        layout.sort_by_key(|r| r.start);
+
+        // Find gaps between partitions
+        for region in layout {
+            if region.start > current {
+                regions.push(Region::new(current, region.start));
+            }
+            current = current.max(region.end);
+        }
+
+        // Add final region if there's space after last partition
+        if current < end {
+            regions.push(Region::new(current, end));
+        }
+
+        regions
+    }
+
     /// Get the size of the usable disk region in bytes
     pub fn usable_size(&self) -> u64 {
         self.usable_end - self.usable_start
@@ -420,11 +1104,42 @@ impl Planner {
         (self.usable_start, self.usable_end)
     }
 
+    /// Returns true if the existing partition table is to be replaced by a new one
+    pub fn creates_new_table(&self) -> bool {
+        self.new_table
+    }
+
     /// Plan to initialize a clean partition layout
     pub fn plan_initialize_disk(&mut self) -> Result<(), PlanError> {
         debug!("Planning to create new GPT partition table");
         self.changes.clear(); // Clear any existing changes
+        self.undone.clear();
         self.original_regions.clear(); // Clear original partitions
+        self.original_numbers.clear();
+        self.new_table = true;
+        Ok(())
+    }
+
+    /// Plan to wipe every existing partition except those in `preserve`
+    ///
+    /// Unlike [`Planner::plan_initialize_disk`] the original layout is kept for reference
+    /// and each discarded partition is queued as a deletion, so the preserved partitions
+    /// continue to constrain where new partitions may be placed.
+    pub fn plan_initialize_disk_preserving(&mut self, preserve: &[usize]) -> Result<(), PlanError> {
+        debug!("Planning to wipe disk, preserving partitions {:?}", preserve);
+
+        if let Some(index) = preserve.iter().find(|i| **i >= self.original_regions.len()) {
+            warn!("Invalid partition index {} requested for preservation", index);
+            return Err(PlanError::UnknownPartition(*index));
+        }
+
+        self.changes.clear();
+        self.undone.clear();
+        for index in 0..self.original_regions.len() {
+            if !preserve.contains(&index) {
+                self.plan_delete_partition(index)?;
+            }
+        }
         Ok(())
     }
 }
@@ -432,6 +1147,7 @@ impl Planner {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::partition_type::{ATTR_GROWFS, ATTR_LEGACY_BIOS_BOOTABLE, ATTR_PENDING};
     use disks::mock::MockDisk;
     use test_log::test;
 
@@ -531,6 +1247,86 @@ mod tests {
         assert_eq!(layout[1].size(), 8 * GB);
     }
 
+    #[test]
+    fn test_replace_in_place() {
+        let disk = create_windows_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+
+        // Swap the Windows partition for a Linux root spanning the same region
+        assert!(planner.plan_add_partition(300 * GB, 310 * GB).is_ok());
+        assert!(planner.plan_add_partition(116 * MB, 200 * GB + 116 * MB).is_err());
+        assert!(planner.plan_delete_partition(2).is_ok());
+        assert!(planner.plan_add_partition(116 * MB, 200 * GB + 116 * MB).is_ok());
+
+        assert!(planner.dependencies(0).is_empty());
+        assert!(planner.dependencies(1).is_empty());
+        assert_eq!(planner.dependencies(2), vec![1]);
+        assert_eq!(planner.replaced_partitions(2), vec![2]);
+
+        assert!(planner.describe_changes().contains("(replaces partition #3)"));
+
+        let summaries = planner.summaries();
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(summaries[0].kind, ChangeKind::Add);
+        assert_eq!(summaries[1].kind, ChangeKind::Delete);
+        assert_eq!(summaries[1].size, 200 * GB);
+        assert_eq!(summaries[2].kind, ChangeKind::Replace);
+        assert_eq!(summaries[2].partitions, vec![2]);
+        assert_eq!(summaries[2].device, Path::new("/dev/mock0"));
+
+        // Undoing the deletion takes the replacement with it
+        assert!(planner.undo_to(1));
+        assert_eq!(planner.changes().len(), 1);
+        assert!(!planner.undo_to(1));
+        assert!(planner.redo());
+        assert_eq!(planner.summaries()[1].kind, ChangeKind::Delete);
+    }
+
+    #[test]
+    fn test_partition_numbers() {
+        let disk = create_windows_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        assert_eq!(planner.original_numbers(), &[1, 2, 3, 4]);
+
+        // Freed numbers are reused by default
+        assert!(planner.plan_delete_partition(1).is_ok());
+        assert!(planner.plan_add_partition(300 * GB, 310 * GB).is_ok());
+        assert!(planner.plan_add_partition(310 * GB, 320 * GB).is_ok());
+        assert_eq!(planner.partition_numbers(), vec![None, Some(2), Some(5)]);
+        assert_eq!(planner.summaries()[0].number, Some(2));
+
+        let mut appending = planner.clone().with_numbering(Numbering::Append);
+        assert_eq!(appending.partition_numbers(), vec![None, Some(5), Some(6)]);
+
+        // Explicit numbers are validated against the table and reserved first
+        assert!(matches!(
+            appending.plan_add_numbered_partition(320 * GB, 330 * GB, 3, None),
+            Err(PlanError::NumberInUse(3))
+        ));
+        assert!(matches!(
+            appending.plan_add_numbered_partition(320 * GB, 330 * GB, 129, None),
+            Err(PlanError::InvalidNumber { number: 129, max: 128 })
+        ));
+        assert!(appending
+            .plan_add_numbered_partition(320 * GB, 330 * GB, 6, None)
+            .is_ok());
+        assert!(matches!(
+            appending.plan_add_numbered_partition(330 * GB, 340 * GB, 6, None),
+            Err(PlanError::NumberInUse(6))
+        ));
+        assert_eq!(appending.partition_numbers(), vec![None, Some(5), Some(7), Some(6)]);
+
+        // The number of a partition is only free once its deletion is planned
+        assert!(planner
+            .plan_add_numbered_partition(320 * GB, 330 * GB, 4, None)
+            .is_err());
+        assert!(planner.plan_delete_partition(3).is_ok());
+        assert!(planner.plan_add_numbered_partition(320 * GB, 330 * GB, 4, None).is_ok());
+        assert_eq!(planner.dependencies(4), vec![3]);
+        assert_eq!(planner.partition_numbers(), vec![None, Some(2), Some(5), None, Some(4)]);
+        assert_eq!(planner.summaries()[3].number, Some(4));
+    }
+
     #[test]
     fn test_region_validation() {
         let disk = create_mock_disk();
@@ -572,6 +1368,137 @@ mod tests {
         assert!(!planner.undo());
     }
 
+    #[test]
+    fn test_redo_operations() {
+        let disk = create_mock_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+
+        assert!(planner.plan_add_partition(0, 100 * GB).is_ok());
+        assert!(planner.plan_add_partition(100 * GB, 200 * GB).is_ok());
+
+        // Undo both, then redo the first
+        assert!(planner.undo());
+        assert!(planner.undo());
+        assert!(planner.redo());
+        assert_eq!(planner.current_layout().len(), 1);
+        assert_eq!(planner.current_layout()[0].end, 100 * GB);
+        assert!(planner.can_redo());
+
+        // A new change discards the redo history
+        assert!(planner.plan_add_partition(300 * GB, 400 * GB).is_ok());
+        assert!(!planner.redo());
+        assert_eq!(planner.current_layout().len(), 2);
+    }
+
+    #[test]
+    fn test_partition_tags() {
+        let disk = create_mock_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+
+        let tag = PartitionTag {
+            id: Some("root".into()),
+            filesystem: Some("xfs".into()),
+            mountpoint: Some("/".into()),
+            ..Default::default()
+        };
+        assert!(planner.plan_add_partition(0, 100 * GB).is_ok());
+        assert!(planner
+            .plan_add_partition_with_tag(100 * GB, 200 * GB, tag.clone())
+            .is_ok());
+
+        let layout = planner.current_layout();
+        assert!(layout[0].tag().is_none());
+        assert_eq!(layout[1].tag(), Some(&tag));
+
+        // Flags replace flag bits only
+        let flags = PartitionFlags::READ_ONLY | PartitionFlags::NO_AUTOMOUNT;
+        assert!(planner.set_partition_flags(1, flags));
+        assert!(planner.set_partition_flags(0, PartitionFlags::HIDDEN));
+        assert!(!planner.set_partition_flags(2, flags));
+        let layout = planner.current_layout();
+        assert_eq!(layout[0].tag().unwrap().flags(), PartitionFlags::HIDDEN);
+        assert_eq!(layout[1].tag().unwrap().id.as_deref(), Some("root"));
+        assert_eq!(planner.summaries()[1].flags, flags);
+
+        let tag = PartitionTag {
+            attributes: ATTR_PENDING | ATTR_GROWFS,
+            ..Default::default()
+        }
+        .with_flags(PartitionFlags::BOOTABLE);
+        assert_eq!(tag.attributes, ATTR_PENDING | ATTR_LEGACY_BIOS_BOOTABLE);
+    }
+
+    #[test]
+    fn test_from_gpt() {
+        let image = crate::testing::ImageBuilder::new(64 * MB)
+            .partition(4 * MB)
+            .partition(8 * MB)
+            .build()
+            .unwrap();
+        let mut planner = Planner::from_gpt(&image.path).unwrap();
+        assert_eq!(planner.table(), Some(TableType::Gpt));
+
+        let layout = planner.current_layout();
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[0].start, image.partitions[0].start);
+        assert_eq!(layout[1].end, image.partitions[1].end);
+        let tag = layout[1].tag().unwrap();
+        assert_eq!(tag.name.as_deref(), Some("part2"));
+        assert_eq!(tag.partition_type, Some(crate::partition_type::LINUX_FS));
+
+        // Edits start from the table, new partitions stay clear of the backup GPT
+        assert!(planner.plan_delete_partition(0).is_ok());
+        assert!(planner.plan_add_partition(20 * MB, 40 * MB).is_ok());
+        assert!(matches!(
+            planner.plan_add_partition(40 * MB, 64 * MB),
+            Err(PlanError::RegionOutOfBounds { .. })
+        ));
+        assert!(Planner::from_gpt(image.path.with_file_name("missing.img")).is_err());
+    }
+
+    #[test]
+    fn test_table_overhead() {
+        let disk = create_mock_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk)).with_table(TableType::Gpt);
+        assert_eq!(planner.offsets(), (MB, 500 * GB - 34 * 512));
+
+        // The primary GPT lives in the first MiB, the backup in the last 34 sectors
+        assert!(planner.plan_add_partition(0, 100 * MB).is_err());
+        assert!(planner.plan_add_partition(MB, 100 * MB).is_ok());
+        assert!(planner.plan_add_partition(100 * MB, 500 * GB).is_err());
+        assert!(planner.plan_add_partition(100 * MB, 500 * GB - 34 * 512).is_ok());
+
+        // MBR cannot address beyond 2TiB
+        let disk = MockDisk::new(4096 * GB);
+        let planner = Planner::new(&BlockDevice::mock_device(disk)).with_table(TableType::Mbr);
+        assert_eq!(planner.offsets(), (MB, u32::MAX as u64 * 512));
+        assert_eq!(planner.table(), Some(TableType::Mbr));
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let disk = create_windows_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+
+        let start = 200 * GB + 616 * MB;
+        assert!(planner.plan_add_partition(start, start + 4 * GB).is_ok());
+        planner.checkpoint("suggestion");
+
+        // Wipe the disk entirely, then reset back to the suggested layout
+        assert!(planner.plan_initialize_disk().is_ok());
+        assert!(planner.plan_add_partition(0, 100 * GB).is_ok());
+        assert_eq!(planner.current_layout().len(), 1);
+
+        assert!(planner.rollback_to("suggestion"));
+        assert_eq!(planner.current_layout().len(), 5);
+        assert!(!planner.can_redo());
+
+        // Checkpoints survive a rollback and unknown labels are rejected
+        assert!(planner.rollback_to("suggestion"));
+        assert!(!planner.rollback_to("missing"));
+        assert_eq!(planner.checkpoints().collect::<Vec<_>>(), vec!["suggestion"]);
+    }
+
     #[test]
     fn test_partition_boundaries() {
         let disk = create_mock_disk();
@@ -599,6 +1526,34 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_initialize_preserving() {
+        let disk = create_windows_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+
+        // Keep the ESP and recovery partitions, drop MSR and Windows
+        assert!(planner.plan_initialize_disk_preserving(&[0, 3]).is_ok());
+        assert_eq!(planner.changes().len(), 2);
+
+        let layout = planner.current_layout();
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[0].end, 100 * MB);
+        assert_eq!(layout[1].start, 200 * GB + 116 * MB);
+
+        // The preserved partitions still block new allocations
+        assert!(matches!(
+            planner.plan_add_partition(0, 200 * MB),
+            Err(PlanError::RegionOverlap { .. })
+        ));
+        assert!(planner.plan_add_partition(100 * MB, 200 * GB + 116 * MB).is_ok());
+
+        // Unknown indices are rejected
+        assert!(matches!(
+            planner.plan_initialize_disk_preserving(&[4]),
+            Err(PlanError::UnknownPartition(4))
+        ));
+    }
+
     #[test]
     fn test_alignment() {
         let disk = create_mock_disk();
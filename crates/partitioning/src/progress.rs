@@ -0,0 +1,55 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Progress reporting for long running operations
+//!
+//! Operations that write to disks accept a [`ProgressSink`] and report what they are
+//! doing through it, allowing frontends to render progress bars rather than parsing
+//! log output. Closures and [`std::sync::mpsc::Sender`] implement the trait directly.
+
+use std::sync::mpsc::Sender;
+
+/// An event emitted while an operation is running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A named step has started
+    StepStarted(String),
+    /// A named step has finished successfully
+    StepCompleted(String),
+    /// A named step has failed, and the operation may stop here
+    StepFailed { step: String, error: String },
+    /// Progress within the current step, in bytes
+    Bytes { processed: u64, total: u64 },
+    /// A non-fatal problem was encountered
+    Warning(String),
+    /// An event of one of several disks handled at the same time, keyed by disk name
+    Device { device: String, event: Box<Event> },
+}
+
+/// Receives [`Event`]s from running operations
+pub trait ProgressSink {
+    /// Handle a single event
+    fn event(&self, event: Event);
+}
+
+/// A sink that discards all events
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn event(&self, _: Event) {}
+}
+
+impl<F: Fn(Event)> ProgressSink for F {
+    fn event(&self, event: Event) {
+        self(event)
+    }
+}
+
+impl ProgressSink for Sender<Event> {
+    fn event(&self, event: Event) {
+        // A disconnected receiver just means nobody is listening any more
+        let _ = self.send(event);
+    }
+}
@@ -0,0 +1,354 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! A/B slot layouts
+//!
+//! Image based update systems keep two copies of every updatable partition. The running
+//! system uses one slot while updates are written to the other, and switching slots
+//! finishes the update. Slots follow the Android naming convention: both partitions of a
+//! pair share a base name with an `_a` or `_b` suffix. The partitions of the active slot
+//! carry [`ATTR_SLOT_ACTIVE`] in their GPT attributes.
+//!
+//! [`Planner::plan_slot_pair()`] plans both partitions of a pair with the same size,
+//! [`slot_pairs()`] finds the pairs of an existing table and [`set_active_slot()`]
+//! switches slots on disk.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    path::Path,
+};
+
+use gpt::GptConfig;
+use tracing::{debug, info};
+
+use crate::{
+    partition_type::ATTR_SLOT_ACTIVE,
+    planner::{PartitionTag, PlanError, Planner, PARTITION_ALIGNMENT},
+    table::{self, Entry, Table},
+    writer::WriteError,
+};
+
+/// One of the two slots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    /// Both slots, in order
+    pub const ALL: [Slot; 2] = [Slot::A, Slot::B];
+
+    /// Suffix of the partition names in this slot
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Slot::A => "_a",
+            Slot::B => "_b",
+        }
+    }
+
+    /// The slot updates are written to while this one is running
+    pub fn other(&self) -> Slot {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    /// Split a partition name into its base name and slot
+    pub fn from_name(name: &str) -> Option<(&str, Slot)> {
+        Slot::ALL
+            .into_iter()
+            .find_map(|slot| name.strip_suffix(slot.suffix()).map(|base| (base, slot)))
+            .filter(|(base, _)| !base.is_empty())
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Slot::A => f.write_str("a"),
+            Slot::B => f.write_str("b"),
+        }
+    }
+}
+
+/// Both partitions of a slotted partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotPair {
+    /// Name shared by both partitions, without the slot suffix
+    pub name: String,
+    /// The partition in slot A
+    pub a: Entry,
+    /// The partition in slot B
+    pub b: Entry,
+}
+
+impl SlotPair {
+    /// The partition in `slot`
+    pub fn entry(&self, slot: Slot) -> &Entry {
+        match slot {
+            Slot::A => &self.a,
+            Slot::B => &self.b,
+        }
+    }
+
+    /// The active slot, unless neither or both partitions are marked active
+    pub fn active(&self) -> Option<Slot> {
+        let active = Slot::ALL
+            .into_iter()
+            .filter(|slot| self.entry(*slot).attributes & ATTR_SLOT_ACTIVE != 0)
+            .collect::<Vec<_>>();
+        match active[..] {
+            [slot] => Some(slot),
+            _ => None,
+        }
+    }
+
+    /// The slot to write updates to
+    pub fn inactive(&self) -> Option<Slot> {
+        self.active().map(|slot| slot.other())
+    }
+
+    /// Returns true if both partitions have the same size, so either can hold an image
+    pub fn sizes_match(&self) -> bool {
+        self.a.sectors() == self.b.sectors()
+    }
+}
+
+/// Find the slot pairs in `table`, ordered by the number of their A partition
+///
+/// Partitions with a slot suffix but no partner are ignored.
+pub fn slot_pairs(table: &Table) -> Vec<SlotPair> {
+    table
+        .entries
+        .iter()
+        .filter_map(|a| {
+            let (name, Slot::A) = Slot::from_name(&a.name)? else {
+                return None;
+            };
+            let b = table
+                .entries
+                .iter()
+                .find(|e| Slot::from_name(&e.name) == Some((name, Slot::B)))?;
+            Some(SlotPair {
+                name: name.to_owned(),
+                a: a.clone(),
+                b: b.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The slot every pair has active, if they agree
+///
+/// Returns `None` without pairs, or when a pair has no single active slot or differs from
+/// the others, e.g. after an interrupted switch.
+pub fn active_slot(pairs: &[SlotPair]) -> Option<Slot> {
+    let slot = pairs.first()?.active()?;
+    pairs.iter().all(|p| p.active() == Some(slot)).then_some(slot)
+}
+
+/// Read the slot pairs of the GPT on `device`
+pub fn read_slot_pairs(device: &Path) -> Result<Vec<SlotPair>, table::Error> {
+    let table = table::read(&mut File::open(device)?)?;
+    Ok(slot_pairs(&table))
+}
+
+/// Mark `slot` as active on every slot pair of `device`
+///
+/// The partitions of the other slot lose the marker, all other attributes are kept.
+/// Returns the names of the switched pairs.
+pub fn set_active_slot(device: &Path, slot: Slot) -> Result<Vec<String>, WriteError> {
+    let file = OpenOptions::new().read(true).write(true).open(device)?;
+    let mut table = GptConfig::new().writable(true).open_from_device(file)?;
+
+    let mut partitions = table.partitions().clone();
+    let names = partitions
+        .values()
+        .filter_map(|p| Slot::from_name(&p.name).map(|(name, _)| name.to_owned()))
+        .collect::<Vec<_>>();
+    let mut switched = vec![];
+    for partition in partitions.values_mut() {
+        let Some((name, partition_slot)) = Slot::from_name(&partition.name) else {
+            continue;
+        };
+        // Both halves of the pair must exist
+        if names.iter().filter(|n| *n == name).count() != 2 {
+            continue;
+        }
+        if partition_slot == slot {
+            partition.flags |= ATTR_SLOT_ACTIVE;
+            switched.push(name.to_owned());
+        } else {
+            partition.flags &= !ATTR_SLOT_ACTIVE;
+        }
+    }
+    table.update_partitions(partitions)?;
+    table.write()?;
+
+    info!("Activated slot {} of {:?} on {:?}", slot, switched, device);
+    Ok(switched)
+}
+
+impl Planner {
+    /// Plan both partitions of a slot pair in the first free region that fits them
+    ///
+    /// See [`Planner::plan_slot_pair_at()`].
+    pub fn plan_slot_pair(&mut self, size: u64, tag: PartitionTag) -> Result<(), PlanError> {
+        let size = size / PARTITION_ALIGNMENT * PARTITION_ALIGNMENT;
+        let region = self
+            .free_space()
+            .regions
+            .into_iter()
+            .find(|r| r.usable >= 2 * size)
+            .ok_or(PlanError::NoFreeRegions)?;
+        self.plan_slot_pair_at(region.region.start, size, tag)
+    }
+
+    /// Plan both partitions of a slot pair back to back from `start`
+    ///
+    /// `size` is rounded down to the partition alignment so both partitions end up with
+    /// exactly the same size. The id and name of `tag` get the slot suffix, and slot A
+    /// is marked active. Nothing is planned if either partition does not fit.
+    pub fn plan_slot_pair_at(&mut self, start: u64, size: u64, tag: PartitionTag) -> Result<(), PlanError> {
+        let start = start.div_ceil(PARTITION_ALIGNMENT) * PARTITION_ALIGNMENT;
+        let size = size / PARTITION_ALIGNMENT * PARTITION_ALIGNMENT;
+        if size == 0 {
+            return Err(PlanError::RegionOutOfBounds { start, end: start });
+        }
+
+        for (index, slot) in Slot::ALL.into_iter().enumerate() {
+            let slot_start = start + index as u64 * size;
+            let mut slot_tag = tag.clone();
+            slot_tag.id = tag.id.as_ref().map(|id| format!("{id}{}", slot.suffix()));
+            slot_tag.name = tag.name.as_ref().map(|name| format!("{name}{}", slot.suffix()));
+            if slot == Slot::A {
+                slot_tag.attributes |= ATTR_SLOT_ACTIVE;
+            }
+            debug!(%slot, start = slot_start, size, "Planning slot partition");
+            if let Err(e) = self.plan_add_partition_with_tag(slot_start, slot_start + size, slot_tag) {
+                if slot == Slot::B {
+                    self.undo();
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+    use gpt::{mbr::ProtectiveMBR, partition_types};
+
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+    const GB: u64 = 1024 * MB;
+
+    /// Create a GPT disk image with two slot pairs, slot B active, and an unpaired slot
+    fn image(path: &Path) {
+        let file = File::create(path).unwrap();
+        file.set_len(64 * MB).unwrap();
+        let mut file = OpenOptions::new().read(true).write(true).open(path).unwrap();
+        ProtectiveMBR::with_lb_size(64 * 2048 - 1)
+            .overwrite_lba0(&mut file)
+            .unwrap();
+
+        let mut table = GptConfig::new().writable(true).create_from_device(file, None).unwrap();
+        for (number, name, flags) in [
+            (1, "esp", 1),
+            (2, "root_a", 0),
+            (3, "root_b", ATTR_SLOT_ACTIVE),
+            (4, "usr_a", 0),
+            (5, "usr_b", ATTR_SLOT_ACTIVE | 1),
+            (6, "home_a", 0),
+        ] {
+            let first_lba = 2048 * number as u64;
+            table
+                .add_partition_at(name, number, first_lba, 2048, partition_types::LINUX_FS, flags)
+                .unwrap();
+        }
+        table.write().unwrap();
+    }
+
+    #[test]
+    fn test_slot_names() {
+        assert_eq!(Slot::from_name("root_b"), Some(("root", Slot::B)));
+        assert_eq!(Slot::from_name("root"), None);
+        assert_eq!(Slot::from_name("_a"), None);
+        assert_eq!(Slot::A.other(), Slot::B);
+    }
+
+    #[test]
+    fn test_plan_slot_pair() {
+        let mut disk = MockDisk::new(100 * GB);
+        disk.add_partition(MB, 513 * MB);
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let tag = PartitionTag {
+            id: Some("root".into()),
+            ..Default::default()
+        };
+        assert!(planner.plan_slot_pair(10 * GB + 1, tag.clone()).is_ok());
+
+        let slots = planner
+            .current_layout()
+            .iter()
+            .filter_map(|r| Some((r.tag()?, r)))
+            .map(|(t, r)| {
+                (
+                    t.id.clone().unwrap(),
+                    r.start,
+                    r.size(),
+                    t.attributes & ATTR_SLOT_ACTIVE != 0,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slots,
+            vec![
+                ("root_a".to_string(), 513 * MB, 10 * GB, true),
+                ("root_b".to_string(), 10 * GB + 513 * MB, 10 * GB, false),
+            ]
+        );
+
+        // Slot B would not fit, so neither is planned
+        let changes = planner.changes().len();
+        assert!(planner.plan_slot_pair_at(95 * GB, 4 * GB, tag).is_err());
+        assert_eq!(planner.changes().len(), changes);
+    }
+
+    #[test]
+    fn test_slot_queries() {
+        let path = std::env::temp_dir().join(format!("disks-rs-slots-test-{}", std::process::id()));
+        image(&path);
+
+        let pairs = read_slot_pairs(&path).unwrap();
+        assert_eq!(
+            pairs.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            vec!["root", "usr"]
+        );
+        assert!(pairs.iter().all(SlotPair::sizes_match));
+        assert_eq!(pairs[0].inactive(), Some(Slot::A));
+        assert_eq!(active_slot(&pairs), Some(Slot::B));
+
+        assert_eq!(set_active_slot(&path, Slot::A).unwrap(), vec!["root", "usr"]);
+        let pairs = read_slot_pairs(&path).unwrap();
+        assert_eq!(active_slot(&pairs), Some(Slot::A));
+        // Other attributes survive
+        assert_eq!(pairs[1].b.attributes, 1);
+
+        // An interrupted switch leaves the pairs disagreeing
+        let mut table = table::read(&mut File::open(&path).unwrap()).unwrap();
+        table.entries[2].attributes |= ATTR_SLOT_ACTIVE;
+        let pairs = slot_pairs(&table);
+        assert_eq!(pairs[0].active(), None);
+        assert_eq!(active_slot(&pairs), None);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -2,8 +2,8 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use log::{debug, info};
 use std::{fs, io, path::Path};
+use tracing::{debug, info};
 
 /// Creates a sparse file at the specified path with the given size.
 ///
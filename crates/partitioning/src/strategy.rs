@@ -18,24 +18,128 @@
 //! let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
 //!
 //! // Request needed partitions
-//! strategy.add_request(PartitionRequest {
-//!     size: SizeRequirement::Exact(512 * 1024 * 1024), // 512MB EFI partition
-//! });
-//! strategy.add_request(PartitionRequest {
-//!     size: SizeRequirement::Remaining, // Rest for root
-//! });
+//! strategy.add_request(PartitionRequest::new(
+//!     SizeRequirement::Exact(512 * 1024 * 1024), // 512MB EFI partition
+//! ));
+//! strategy.add_request(PartitionRequest::new(SizeRequirement::AtLeast(20 * 1024 * 1024 * 1024)).with_weight(3));
+//! strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining).with_weight(7));
 //! ```
+//!
+//! # Allocation algorithm
+//!
+//! Within a target region, partitions are laid out as follows:
+//!
+//! 1. The minimum sizes of all requests are summed; if they exceed the region the
+//!    strategy fails without planning anything.
+//! 2. [`SizeRequirement::Exact`] requests are placed first, in request order.
+//! 3. The flexible requests are then given their minimum size, and the space left
+//!    over is shared between them in proportion to their weight (default `1`, so an
+//!    equal share each). A request that reaches its maximum stops growing and its
+//!    unused share is redistributed among the others in the next round.
+//! 4. Any remainder lost to integer division goes to the last request that can
+//!    still grow. A weight of `0` means the request only receives its minimum while
+//!    a weighted request can still grow, and shares whatever is left once all of them
+//!    reached their maximum. If every flexible request has weight `0` they are all
+//!    treated equally. [`SizeRequirement::Remaining`] requests have no minimum, so they
+//!    are rejected with weight `0` next to weighted requests.
+//!
+//! For example a root partition with weight `3` and a home partition with weight `7`
+//! split the space left after their minimums 30/70.
+//!
+//! # Placement
+//!
+//! Requests placed with [`Placement::At`] are planned before anything else. Exact sizes
+//! are used as given, while flexible sizes extend to the end of the free region holding
+//! the offset, up to their maximum; the target region is then chosen from the space
+//! that is left.
+//! Sizes for the remaining requests are computed as above, after which
+//! [`Placement::Start`] requests are packed from the front of the region and
+//! [`Placement::End`] requests from the back, each group keeping request order.
+//!
+//! Requests created with [`PartitionRequest::reserve`] take part in all of the above
+//! like any other request, but their space is left unpartitioned. Reserved requests
+//! placed with [`Placement::At`] are resolved first and their gaps are cut out of the
+//! free space, so no other request is ever placed inside them.
+
+use std::fmt;
 
-use crate::planner::{PlanError, Planner};
+use thiserror::Error;
+
+use crate::planner::{format_size, PartitionTag, PlanError, Planner, PARTITION_ALIGNMENT};
 
 use crate::planner::Region;
 
+/// Errors that can occur while applying a strategy
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The planner rejected one of the planned changes
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    /// The requests do not fit in the available space
+    #[error("insufficient space: {0}")]
+    InsufficientSpace(Box<SpaceReport>),
+    /// A request taking the remaining space would never receive any
+    #[error("request {} takes the remaining space but has weight 0 next to weighted requests", .0 + 1)]
+    ZeroWeight(usize),
+}
+
+/// Details on why a set of requests could not be fitted onto a disk
+///
+/// Intended for installers to explain the failure to the user, e.g.
+/// "the root partition needs 12GiB more space".
+#[derive(Debug, Clone)]
+pub struct SpaceReport {
+    /// Index of the first request (in the order added) that could not be satisfied
+    pub request: usize,
+    /// Bytes needed by the requests competing for the same region
+    pub required: u64,
+    /// Bytes available in that region
+    pub available: u64,
+    /// Estimate of the smallest usable disk size on which the strategy would succeed
+    pub minimum_disk_size: u64,
+    /// Partitions bordering the region that prevent it from growing
+    pub constraints: Vec<Region>,
+}
+
+impl SpaceReport {
+    /// Bytes missing for the requests to fit
+    pub fn missing(&self) -> u64 {
+        self.required.saturating_sub(self.available)
+    }
+}
+
+impl fmt::Display for SpaceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "request {} needs {} more space ({} required, {} available), disk must be at least {}",
+            self.request + 1,
+            format_size(self.missing()),
+            format_size(self.required),
+            format_size(self.available),
+            format_size(self.minimum_disk_size)
+        )?;
+        if !self.constraints.is_empty() {
+            let regions = self
+                .constraints
+                .iter()
+                .map(|r| format!("{}..{}", format_size(r.start), format_size(r.end)))
+                .collect::<Vec<_>>();
+            write!(f, ", constrained by partitions at {}", regions.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
 /// Strategy for allocating partitions
 #[derive(Debug, Clone)]
 pub enum AllocationStrategy {
     /// Initialize a clean partition layout using the entire disk.
     /// This will remove all existing partitions and create a new layout.
     InitializeWholeDisk,
+    /// Remove all existing partitions except those listed (by original index),
+    /// packing new partitions into the space around the preserved ones.
+    InitializeWholeDiskPreserving(Vec<usize>),
     /// Use largest available free region on existing table
     LargestFree,
     /// Use first free region that fits on existing table
@@ -57,10 +161,76 @@ pub enum SizeRequirement {
     Remaining,
 }
 
+/// Where a partition should be placed within its allocated region
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Placement {
+    /// Pack from the start of the region
+    #[default]
+    Start,
+    /// Pack from the end of the region (e.g. recovery partitions)
+    End,
+    /// Place at an absolute offset in bytes from the start of the disk
+    At(u64),
+}
+
 /// A partition request for the strategy to plan
 #[derive(Debug, Clone)]
 pub struct PartitionRequest {
     pub size: SizeRequirement,
+    /// Relative share of the leftover space this request receives
+    pub weight: u64,
+    /// Where to place the partition
+    pub placement: Placement,
+    /// Leave this space unpartitioned rather than creating a partition
+    pub reserved: bool,
+    /// Metadata passed on to the planned partition
+    pub tag: Option<PartitionTag>,
+}
+
+impl PartitionRequest {
+    /// Create a new request with the default weight of 1
+    pub fn new(size: SizeRequirement) -> Self {
+        Self {
+            size,
+            weight: 1,
+            placement: Placement::Start,
+            reserved: false,
+            tag: None,
+        }
+    }
+
+    /// Reserve a gap of free space that is accounted for but never partitioned
+    ///
+    /// Useful for SSD over-provisioning or leaving room for future partitions.
+    pub fn reserve(size: SizeRequirement) -> Self {
+        Self {
+            reserved: true,
+            ..Self::new(size)
+        }
+    }
+
+    /// Set the relative weight used when sharing leftover space
+    pub fn with_weight(self, weight: u64) -> Self {
+        Self { weight, ..self }
+    }
+
+    /// Set where the partition should be placed
+    pub fn with_placement(self, placement: Placement) -> Self {
+        Self { placement, ..self }
+    }
+
+    /// Attach metadata to the planned partition
+    pub fn with_tag(self, tag: PartitionTag) -> Self {
+        Self { tag: Some(tag), ..self }
+    }
+
+    /// Plan this request as a partition spanning `start..end`
+    fn plan(&self, planner: &mut Planner, start: u64, end: u64) -> Result<(), PlanError> {
+        match &self.tag {
+            Some(tag) => planner.plan_add_partition_with_tag(start, end, tag.clone()),
+            None => planner.plan_add_partition(start, end),
+        }
+    }
 }
 
 /// Handles planning partition layouts according to specific strategies
@@ -84,37 +254,22 @@ impl Strategy {
         self.requests.push(request);
     }
 
-    /// Find available free regions on the disk
-    fn find_free_regions(&self, planner: &Planner) -> Vec<Region> {
-        let mut regions = Vec::new();
-        let (mut current, disk_size) = planner.offsets();
-
-        // Sort existing partitions by start position
-        let mut layout = planner.current_layout();
-        layout.sort_by_key(|r| r.start);
-
-        // Find gaps between partitions
-        for region in layout {
-            if region.start > current {
-                regions.push(Region::new(current, region.start));
-            }
-            current = region.end;
-        }
-
-        // Add final region if there's space after last partition
-        if current < disk_size {
-            regions.push(Region::new(current, disk_size));
-        }
-
-        regions
+    /// The partition requests of this strategy, in request order
+    pub fn requests(&self) -> &[PartitionRequest] {
+        &self.requests
     }
 
     /// Get a human readable description of this strategy
     pub fn describe(&self) -> String {
-        use crate::planner::format_size;
-
         let mut desc = match &self.allocation {
             AllocationStrategy::InitializeWholeDisk => "Initialize new partition layout on entire disk".to_string(),
+            AllocationStrategy::InitializeWholeDiskPreserving(preserve) => {
+                let preserved = preserve.iter().map(|i| format!("#{}", i + 1)).collect::<Vec<_>>();
+                format!(
+                    "Initialize new partition layout on entire disk, preserving partitions {}",
+                    preserved.join(", ")
+                )
+            }
             AllocationStrategy::LargestFree => "Use largest free region".to_string(),
             AllocationStrategy::FirstFit => "Use first available region".to_string(),
             AllocationStrategy::SpecificRegion(r) => format!("Use specific region: {}", r.describe(r.end - r.start)),
@@ -131,7 +286,26 @@ impl Strategy {
                     }
                     SizeRequirement::Remaining => "remaining space".to_string(),
                 };
-                desc.push_str(&format!("  {}: {}\n", i + 1, size_desc));
+                let mut notes = Vec::new();
+                if req.reserved {
+                    notes.push("reserved".to_string());
+                }
+                if let Some(role) = req.tag.as_ref().and_then(|t| t.role.as_ref()) {
+                    notes.push(role.clone());
+                }
+                if req.weight != 1 {
+                    notes.push(format!("weight {}", req.weight));
+                }
+                match req.placement {
+                    Placement::Start => {}
+                    Placement::End => notes.push("at end".to_string()),
+                    Placement::At(offset) => notes.push(format!("at {}", format_size(offset))),
+                }
+                if notes.is_empty() {
+                    desc.push_str(&format!("  {}: {}\n", i + 1, size_desc));
+                } else {
+                    desc.push_str(&format!("  {}: {} ({})\n", i + 1, size_desc, notes.join(", ")));
+                }
             }
         }
         desc
@@ -140,103 +314,401 @@ impl Strategy {
     /// Apply this strategy to a planner
     /// This will plan the necessary partition changes to fulfill the requirements
     /// Returns an error if the strategy cannot be applied due to insufficient space
-    /// or other constraints
-    pub fn apply(&self, planner: &mut Planner) -> Result<(), PlanError> {
+    /// or other constraints, see [`Error::InsufficientSpace`] for details on the former
+    pub fn apply(&self, planner: &mut Planner) -> Result<(), Error> {
+        self.validate()?;
+
+        match &self.allocation {
+            // Clear existing partitions and start fresh
+            AllocationStrategy::InitializeWholeDisk => planner.plan_initialize_disk()?,
+            AllocationStrategy::InitializeWholeDiskPreserving(preserve) => {
+                planner.plan_initialize_disk_preserving(preserve)?
+            }
+            _ => {}
+        }
+
+        // Gaps reserved at fixed offsets are kept out of the free space used below
+        let mut reserved: Vec<Region> = Vec::new();
+        for request in self.requests.iter().filter(|r| r.reserved) {
+            if let Placement::At(offset) = request.placement {
+                let free_regions = carve(planner.free_regions(), &reserved);
+                let end = Self::fixed_end(&free_regions, request, offset);
+                if !free_regions.iter().any(|r| r.start <= offset && end <= r.end) {
+                    return Err(PlanError::RegionOverlap { start: offset, end }.into());
+                }
+                reserved.push(Region::new(offset, end));
+            }
+        }
+
+        // Partitions at fixed offsets are planned next so they constrain everything else
+        for request in self.requests.iter().filter(|r| !r.reserved) {
+            if let Placement::At(offset) = request.placement {
+                let end = Self::fixed_end(&carve(planner.free_regions(), &reserved), request, offset);
+                if reserved.iter().any(|r| r.overlaps_with(&Region::new(offset, end))) {
+                    return Err(PlanError::RegionOverlap { start: offset, end }.into());
+                }
+                request.plan(planner, offset, end)?;
+            }
+        }
+
+        let requests = self
+            .requests
+            .iter()
+            .filter(|r| !matches!(r.placement, Placement::At(_)))
+            .collect::<Vec<_>>();
+        if requests.is_empty() {
+            return Ok(());
+        }
+
         // Determine the target region for our partitions
+        let free_regions = carve(planner.free_regions(), &reserved);
         let target = match &self.allocation {
-            AllocationStrategy::InitializeWholeDisk => {
-                // Clear existing partitions and start fresh
-                planner.plan_initialize_disk()?;
-                let (start, end) = planner.offsets();
-                Region::new(start, end)
+            AllocationStrategy::InitializeWholeDiskPreserving(_) => {
+                return self.apply_across_free_regions(planner, &requests, free_regions);
+            }
+            AllocationStrategy::InitializeWholeDisk | AllocationStrategy::LargestFree => free_regions
+                .into_iter()
+                .max_by_key(|r| r.size())
+                .ok_or(PlanError::NoFreeRegions)?,
+            AllocationStrategy::FirstFit => free_regions.into_iter().next().ok_or(PlanError::NoFreeRegions)?,
+            AllocationStrategy::SpecificRegion(region) => carve(vec![region.clone()], &reserved)
+                .into_iter()
+                .max_by_key(|r| r.size())
+                .ok_or(PlanError::NoFreeRegions)?,
+        };
+
+        self.allocate_in_region(planner, &target, &requests)
+    }
+
+    /// Reject requests that can never be satisfied, before anything is planned
+    fn validate(&self) -> Result<(), Error> {
+        let flexible = |r: &PartitionRequest| {
+            !matches!(r.size, SizeRequirement::Exact(_)) && !matches!(r.placement, Placement::At(_))
+        };
+        let weighted = self.requests.iter().any(|r| flexible(r) && r.weight > 0);
+        let starved = self
+            .requests
+            .iter()
+            .position(|r| flexible(r) && r.weight == 0 && matches!(r.size, SizeRequirement::Remaining));
+        match starved {
+            Some(index) if weighted => Err(Error::ZeroWeight(index)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Compute where a request placed at `offset` ends
+    ///
+    /// Flexible requests grow up to the end of the region in `free_regions` containing
+    /// `offset`, capped at their maximum size.
+    fn fixed_end(free_regions: &[Region], request: &PartitionRequest, offset: u64) -> u64 {
+        let minimum = offset + request.size.minimum();
+        let Some(region) = free_regions.iter().find(|r| r.start <= offset && offset < r.end) else {
+            return minimum;
+        };
+
+        match request.size {
+            SizeRequirement::Exact(_) => minimum,
+            SizeRequirement::AtLeast(_) | SizeRequirement::Remaining => region.end.max(minimum),
+            SizeRequirement::Range { max, .. } => region.end.min(offset + max).max(minimum),
+        }
+    }
+
+    /// Build a report for a request that did not fit within `region`
+    fn space_report(
+        &self,
+        planner: &Planner,
+        failed: &PartitionRequest,
+        required: u64,
+        region: Option<&Region>,
+    ) -> Box<SpaceReport> {
+        let (usable_start, _) = planner.offsets();
+        let layout = planner.current_layout();
+
+        // Partitions that survive the strategy take up space on any disk
+        let kept = match self.allocation {
+            AllocationStrategy::InitializeWholeDisk => 0,
+            _ => layout.iter().map(|r| r.size()).sum(),
+        };
+        let requested = self
+            .requests
+            .iter()
+            .filter(|r| !matches!(r.placement, Placement::At(_)))
+            .map(|r| r.size.minimum())
+            .sum::<u64>();
+        let fixed_end = self
+            .requests
+            .iter()
+            .filter_map(|r| match r.placement {
+                Placement::At(offset) => Some(offset + r.size.minimum()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+
+        let constraints = match region {
+            Some(region) => layout
+                .into_iter()
+                .filter(|r| r.end == region.start || r.start == region.end)
+                .collect(),
+            None => layout,
+        };
+
+        Box::new(SpaceReport {
+            request: self
+                .requests
+                .iter()
+                .position(|r| std::ptr::eq(r, failed))
+                .unwrap_or_default(),
+            required,
+            available: region.map(|r| r.size()).unwrap_or_default(),
+            minimum_disk_size: (usable_start + kept + requested).max(fixed_end),
+            constraints,
+        })
+    }
+
+    /// Distribute the requests over every free region, in disk order
+    ///
+    /// Each request is assigned to the first region that can still hold its minimum
+    /// size. Requests without a minimum are placed once the others have claimed their
+    /// space, and are given the region with the most space left.
+    fn apply_across_free_regions(
+        &self,
+        planner: &mut Planner,
+        requests: &[&PartitionRequest],
+        free_regions: Vec<Region>,
+    ) -> Result<(), Error> {
+        if free_regions.is_empty() {
+            return Err(PlanError::NoFreeRegions.into());
+        }
+
+        let mut available = free_regions.iter().map(|r| r.size()).collect::<Vec<_>>();
+        let mut assigned = vec![Vec::new(); free_regions.len()];
+        let (sized, remaining): (Vec<_>, Vec<_>) = (0..requests.len()).partition(|i| requests[*i].size.minimum() > 0);
+
+        for index in sized.into_iter().chain(remaining) {
+            let request = requests[index];
+            let needed = request.size.minimum();
+            let slot = if needed == 0 {
+                (0..free_regions.len())
+                    .max_by_key(|i| available[*i])
+                    .filter(|i| available[*i] >= PARTITION_ALIGNMENT)
+            } else {
+                available.iter().position(|space| *space >= needed)
+            };
+
+            match slot {
+                Some(i) => {
+                    available[i] -= needed;
+                    assigned[i].push(index);
+                }
+                None => {
+                    let best = (0..free_regions.len())
+                        .max_by_key(|i| available[*i])
+                        .unwrap_or_default();
+                    let required = needed.max(PARTITION_ALIGNMENT);
+                    let mut report = self.space_report(planner, request, required, Some(&free_regions[best]));
+                    report.available = available[best];
+                    return Err(Error::InsufficientSpace(report));
+                }
             }
-            AllocationStrategy::LargestFree => {
-                let free_regions = self.find_free_regions(planner);
-                free_regions
-                    .iter()
-                    .max_by_key(|r| r.size())
-                    .cloned()
-                    .ok_or(PlanError::NoFreeRegions)?
+        }
+
+        // Keep the requests within each region in the order they were added
+        let assigned = assigned.into_iter().map(|mut indices| {
+            indices.sort_unstable();
+            indices.into_iter().map(|i| requests[i]).collect::<Vec<_>>()
+        });
+
+        for (region, requests) in free_regions.iter().zip(assigned) {
+            if !requests.is_empty() {
+                self.allocate_in_region(planner, region, &requests)?;
             }
-            AllocationStrategy::FirstFit => {
-                let free_regions = self.find_free_regions(planner);
-                free_regions.first().cloned().ok_or(PlanError::NoFreeRegions)?
+        }
+
+        Ok(())
+    }
+
+    /// Plan the given requests within a single target region
+    fn allocate_in_region(
+        &self,
+        planner: &mut Planner,
+        target: &Region,
+        requests: &[&PartitionRequest],
+    ) -> Result<(), Error> {
+        let sizes = match Self::compute_sizes(target, requests) {
+            Ok(sizes) => sizes,
+            Err(failed) => {
+                let required = requests.iter().map(|r| r.size.minimum()).sum();
+                let report = self.space_report(planner, requests[failed], required, Some(target));
+                return Err(Error::InsufficientSpace(report));
             }
-            AllocationStrategy::SpecificRegion(region) => region.clone(),
         };
 
+        // Exact sized partitions are laid out before the flexible ones
+        let (exact, flexible): (Vec<_>, Vec<_>) =
+            (0..requests.len()).partition(|i| matches!(requests[*i].size, SizeRequirement::Exact(_)));
+        let order = exact.into_iter().chain(flexible).collect::<Vec<_>>();
+
+        // Pack from the front of the region
         let mut current = target.start;
-        let mut remaining = target.end - target.start;
+        for i in order.iter().filter(|i| requests[**i].placement == Placement::Start) {
+            if !requests[*i].reserved {
+                requests[*i].plan(planner, current, current + sizes[*i])?;
+            }
+            current += sizes[*i];
+        }
+
+        // Pack from the back of the region, keeping request order
+        let mut current = target.end;
+        for i in order.iter().rev().filter(|i| requests[**i].placement == Placement::End) {
+            if !requests[*i].reserved {
+                requests[*i].plan(planner, current - sizes[*i], current)?;
+            }
+            current -= sizes[*i];
+        }
+
+        Ok(())
+    }
+
+    /// Compute the size of each request when sharing the target region
+    ///
+    /// On failure returns the index of the first request whose minimum no longer fits.
+    fn compute_sizes(target: &Region, requests: &[&PartitionRequest]) -> Result<Vec<u64>, usize> {
+        let available = target.size();
 
         let mut flexible_requests = Vec::new();
         let mut total_fixed = 0u64;
         let mut min_flexible = 0u64;
 
-        // First pass: Calculate space requirements
-        for (current_idx, request) in self.requests.iter().enumerate() {
+        // Calculate space requirements
+        for request in requests {
             match &request.size {
                 SizeRequirement::Exact(size) => total_fixed += size,
                 SizeRequirement::AtLeast(min) => {
                     min_flexible += min;
-                    flexible_requests.push((current_idx, *min, None));
+                    flexible_requests.push((*min, None, request.weight));
                 }
                 SizeRequirement::Range { min, max } => {
                     min_flexible += min;
-                    flexible_requests.push((current_idx, *min, Some(*max)));
+                    flexible_requests.push((*min, Some(*max), request.weight));
                 }
                 SizeRequirement::Remaining => {
-                    flexible_requests.push((current_idx, 0, None));
+                    flexible_requests.push((0, None, request.weight));
                 }
             }
         }
 
         // Verify we have enough space for minimum requirements
-        if total_fixed + min_flexible > remaining {
-            return Err(PlanError::RegionOutOfBounds {
-                start: current,
-                end: current + total_fixed + min_flexible,
-            });
+        if total_fixed + min_flexible > available {
+            let mut used = 0u64;
+            let failed = requests
+                .iter()
+                .position(|r| {
+                    used += r.size.minimum();
+                    used > available
+                })
+                .unwrap_or_default();
+            return Err(failed);
         }
 
-        // First pass: allocate exact size partitions
-        for request in &self.requests {
-            if let SizeRequirement::Exact(size) = request.size {
-                planner.plan_add_partition(current, current + size)?;
-                current += size;
-                remaining -= size;
-            }
-        }
+        let mut flexible_sizes =
+            distribute_by_weight(&flexible_requests, available - total_fixed - min_flexible).into_iter();
+        let sizes = requests
+            .iter()
+            .map(|r| match r.size {
+                SizeRequirement::Exact(size) => size,
+                _ => flexible_sizes.next().unwrap_or_default(),
+            })
+            .collect();
 
-        // Second pass: allocate flexible partitions
-        let mut remaining_flexible = flexible_requests.len();
-        for (_idx, min, max_opt) in &flexible_requests {
-            remaining_flexible -= 1;
+        Ok(sizes)
+    }
+}
 
-            let size = if remaining_flexible == 0 {
-                // Last flexible partition gets all remaining space
-                let size = remaining;
-                if let Some(max) = max_opt {
-                    size.min(*max).max(*min)
-                } else {
-                    size.max(*min)
+/// Remove the `reserved` gaps from `regions`, splitting regions around them
+fn carve(regions: Vec<Region>, reserved: &[Region]) -> Vec<Region> {
+    reserved.iter().fold(regions, |regions, gap| {
+        regions
+            .into_iter()
+            .flat_map(|region| {
+                if !region.overlaps_with(gap) {
+                    return vec![region];
                 }
-            } else {
-                // Other flexible partitions get fair share plus minimum
-                let share = remaining / (remaining_flexible + 1) as u64;
-                let size = min + share;
-                if let Some(max) = max_opt {
-                    size.min(*max)
-                } else {
-                    size
+                [
+                    Region::new(region.start, gap.start.max(region.start)),
+                    Region::new(gap.end.min(region.end), region.end),
+                ]
+                .into_iter()
+                .filter(|r| r.size() > 0)
+                .collect()
+            })
+            .collect()
+    })
+}
+
+/// Share `spare` bytes between flexible requests of `(min, max, weight)`
+///
+/// Returns the final size of each request in order. See the module documentation
+/// for a description of the algorithm.
+fn distribute_by_weight(requests: &[(u64, Option<u64>, u64)], mut spare: u64) -> Vec<u64> {
+    let mut sizes = requests.iter().map(|(min, _, _)| *min).collect::<Vec<_>>();
+    let can_grow = |i: usize, sizes: &[u64]| requests[i].1.is_none_or(|max| sizes[i] < max);
+
+    // If nobody asked for a share, treat everybody equally
+    let mut equal = requests.iter().all(|(_, _, weight)| *weight == 0);
+
+    while spare > 0 {
+        let weight_of = |i: usize| if equal { 1 } else { requests[i].2 };
+        let growing = (0..requests.len())
+            .filter(|i| can_grow(*i, &sizes) && weight_of(*i) > 0)
+            .collect::<Vec<_>>();
+        let total_weight: u64 = growing.iter().map(|i| weight_of(*i)).sum();
+        if total_weight == 0 {
+            // Every weighted request is capped, the others share what is left
+            if equal {
+                break;
+            }
+            equal = true;
+            continue;
+        }
+
+        let mut handed_out = 0u64;
+        let mut capped = false;
+        for i in &growing {
+            let share = (spare as u128 * weight_of(*i) as u128 / total_weight as u128) as u64;
+            let grant = match requests[*i].1 {
+                Some(max) if sizes[*i] + share >= max => {
+                    capped = true;
+                    max - sizes[*i]
                 }
+                _ => share,
             };
+            sizes[*i] += grant;
+            handed_out += grant;
+        }
+        spare -= handed_out;
 
-            planner.plan_add_partition(current, current + size)?;
-            current += size;
-            remaining -= size;
+        // Nobody hit their maximum, so only the rounding remainder is left
+        if !capped {
+            if let Some(last) = growing.iter().rev().find(|i| can_grow(**i, &sizes)) {
+                sizes[*last] += spare;
+            }
+            break;
         }
+    }
 
-        Ok(())
+    sizes
+}
+
+impl SizeRequirement {
+    /// The minimum number of bytes this requirement can be satisfied with
+    pub fn minimum(&self) -> u64 {
+        match self {
+            SizeRequirement::Exact(size) => *size,
+            SizeRequirement::AtLeast(min) => *min,
+            SizeRequirement::Range { min, .. } => *min,
+            SizeRequirement::Remaining => 0,
+        }
     }
 }
 
@@ -260,50 +732,38 @@ mod tests {
 
     /// Creates a root partition request that uses remaining space with a minimum size
     fn root_partition() -> PartitionRequest {
-        PartitionRequest {
-            size: SizeRequirement::AtLeast(ROOT_MIN),
-        }
+        PartitionRequest::new(SizeRequirement::AtLeast(ROOT_MIN))
     }
 
     /// Creates a root partition request capped at 100GB, suitable for layouts with home partition
     fn capped_root_partition() -> PartitionRequest {
-        PartitionRequest {
-            size: SizeRequirement::Range {
-                min: ROOT_MIN,
-                max: ROOT_MAX,
-            },
-        }
+        PartitionRequest::new(SizeRequirement::Range {
+            min: ROOT_MIN,
+            max: ROOT_MAX,
+        })
     }
 
     /// Creates a standard EFI system partition request
     fn efi_partition() -> PartitionRequest {
-        PartitionRequest {
-            size: SizeRequirement::Exact(EFI_SIZE),
-        }
+        PartitionRequest::new(SizeRequirement::Exact(EFI_SIZE))
     }
 
     /// Creates a /boot partition request
     fn boot_partition() -> PartitionRequest {
-        PartitionRequest {
-            size: SizeRequirement::Exact(BOOT_SIZE),
-        }
+        PartitionRequest::new(SizeRequirement::Exact(BOOT_SIZE))
     }
 
     /// Creates a swap partition request that scales with system RAM
     fn swap_partition() -> PartitionRequest {
-        PartitionRequest {
-            size: SizeRequirement::Range {
-                min: SWAP_MIN,
-                max: SWAP_MAX,
-            },
-        }
+        PartitionRequest::new(SizeRequirement::Range {
+            min: SWAP_MIN,
+            max: SWAP_MAX,
+        })
     }
 
     /// Creates a home partition request that uses all remaining space
     fn home_partition() -> PartitionRequest {
-        PartitionRequest {
-            size: SizeRequirement::Remaining,
-        }
+        PartitionRequest::new(SizeRequirement::Remaining)
     }
     fn create_test_disk() -> MockDisk {
         MockDisk::new(500 * GB)
@@ -362,6 +822,309 @@ mod tests {
         assert_eq!(layout.len(), 5); // 3 Windows + 2 Linux partitions
     }
 
+    #[test]
+    fn test_preserve_partitions() {
+        // Test case: Reinstall keeping the existing ESP and a data partition at the end
+        let mut disk = create_test_disk();
+        disk.add_partition(0, EFI_SIZE); // ESP
+        disk.add_partition(EFI_SIZE, 100 * GB); // Old root
+        disk.add_partition(400 * GB, 500 * GB); // Data
+
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDiskPreserving(vec![0, 2]));
+
+        strategy.add_request(boot_partition());
+        strategy.add_request(swap_partition());
+        strategy.add_request(root_partition());
+
+        eprintln!("\nPreserving Strategy:\n{}", strategy.describe());
+        assert!(strategy.apply(&mut planner).is_ok());
+        eprintln!("{}", planner.describe_changes());
+
+        let mut layout = planner.current_layout();
+        layout.sort_by_key(|r| r.start);
+        assert_eq!(layout.len(), 5);
+        assert_eq!(layout[0].end, EFI_SIZE);
+        assert_eq!(layout[4].start, 400 * GB);
+        assert_eq!(layout[3].end, 400 * GB);
+    }
+
+    #[test]
+    fn test_preserve_remaining_placement() {
+        // Test case: the larger gap is mostly taken by a fixed size request
+        let mut disk = create_test_disk();
+        disk.add_partition(0, EFI_SIZE); // ESP
+        disk.add_partition(EFI_SIZE, 100 * GB); // Old root
+        disk.add_partition(100 * GB, 300 * GB); // Data
+
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDiskPreserving(vec![0, 2]));
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Exact(180 * GB)));
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining));
+        assert!(strategy.apply(&mut planner).is_ok());
+
+        // The remaining request is given the gap with the most space left
+        let mut layout = planner.current_layout();
+        layout.sort_by_key(|r| r.start);
+        assert_eq!(layout.len(), 4);
+        assert_eq!(layout[1].start, EFI_SIZE);
+        assert_eq!(layout[1].end, 100 * GB);
+        assert_eq!(layout[3].size(), 180 * GB);
+
+        // Nothing is left for a second remaining request once every gap is full
+        let mut planner = Planner::new(&BlockDevice::mock_device(create_test_disk()));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDiskPreserving(vec![]));
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Exact(planner.usable_size())));
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining));
+        assert!(matches!(
+            strategy.apply(&mut planner),
+            Err(Error::InsufficientSpace(report)) if report.request == 1
+        ));
+    }
+
+    #[test]
+    fn test_insufficient_space_report() {
+        // Test case: dual boot where the free space between Windows partitions is too small
+        let mut disk = create_test_disk();
+        disk.add_partition(0, EFI_SIZE);
+        disk.add_partition(EFI_SIZE, 480 * GB);
+
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::LargestFree);
+
+        strategy.add_request(boot_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::AtLeast(10 * GB)));
+        strategy.add_request(PartitionRequest::new(SizeRequirement::AtLeast(15 * GB)));
+
+        let err = strategy.apply(&mut planner).unwrap_err();
+        eprintln!("{err}");
+        let Error::InsufficientSpace(report) = err else {
+            panic!("expected an insufficient space report");
+        };
+        assert_eq!(report.request, 2);
+        assert_eq!(report.available, 20 * GB);
+        assert_eq!(report.missing(), report.required - 20 * GB);
+        assert_eq!(report.minimum_disk_size, 480 * GB + report.required);
+        assert_eq!(report.constraints.len(), 1);
+        assert_eq!(report.constraints[0].end, 480 * GB);
+    }
+
+    #[test]
+    fn test_tagged_requests() {
+        let disk = create_test_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+
+        let tag = |role: &str| PartitionTag {
+            role: Some(role.to_string()),
+            ..Default::default()
+        };
+        strategy.add_request(efi_partition().with_tag(tag("boot")));
+        strategy.add_request(swap_partition());
+        strategy.add_request(root_partition().with_tag(tag("root")));
+
+        assert!(strategy.apply(&mut planner).is_ok());
+        eprintln!("{}", planner.describe_changes());
+
+        let roles = planner
+            .current_layout()
+            .iter()
+            .map(|r| r.tag().and_then(|t| t.role.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(roles, vec![Some("boot".into()), None, Some("root".into())]);
+    }
+
+    #[test]
+    fn test_weighted_distribution() {
+        // Test case: root and home split the leftover space 30/70
+        let disk = create_test_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+
+        strategy.add_request(efi_partition());
+        strategy.add_request(root_partition().with_weight(3));
+        strategy.add_request(home_partition().with_weight(7));
+
+        eprintln!("\nWeighted Strategy:\n{}", strategy.describe());
+        assert!(strategy.apply(&mut planner).is_ok());
+        eprintln!("{}", planner.describe_changes());
+
+        let layout = planner.current_layout();
+        assert_eq!(layout.len(), 3);
+
+        let spare = 500 * GB - EFI_SIZE - ROOT_MIN;
+        let root_bonus = layout[1].size() - ROOT_MIN;
+        let home_bonus = layout[2].size();
+        assert!(root_bonus.abs_diff(spare * 3 / 10) <= MB);
+        assert!(home_bonus.abs_diff(spare * 7 / 10) <= MB);
+    }
+
+    #[test]
+    fn test_weighted_distribution_with_caps() {
+        // Capped requests hand their unused share back to the others
+        let sizes = distribute_by_weight(&[(10, Some(20), 1), (10, None, 1), (0, None, 0)], 100);
+        assert_eq!(sizes, vec![20, 100, 0]);
+
+        // All zero weights behave as equal weights
+        let sizes = distribute_by_weight(&[(0, None, 0), (0, None, 0)], 100);
+        assert_eq!(sizes, vec![50, 50]);
+
+        // Once every weighted request is capped, weight 0 requests take what is left
+        let sizes = distribute_by_weight(&[(10, Some(20), 1), (5, None, 0), (5, Some(30), 0)], 100);
+        assert_eq!(sizes, vec![20, 70, 30]);
+    }
+
+    #[test]
+    fn test_zero_weight_requests() {
+        // Test case: a capped root next to a data partition that only takes the leftover
+        let mut planner = Planner::new(&BlockDevice::mock_device(create_test_disk()));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+        strategy.add_request(capped_root_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::AtLeast(GB)).with_weight(0));
+        assert!(strategy.apply(&mut planner).is_ok());
+
+        let layout = planner.current_layout();
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[0].size(), ROOT_MAX);
+        assert_eq!(layout[1].end, planner.offsets().1);
+
+        // The remaining space would always go to the weighted root partition
+        let mut planner = Planner::new(&BlockDevice::mock_device(create_test_disk()));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+        strategy.add_request(root_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining).with_weight(0));
+        let error = strategy.apply(&mut planner).unwrap_err();
+        assert!(matches!(error, Error::ZeroWeight(1)));
+        assert!(!planner.has_changes());
+
+        // Without weighted requests there is nothing to starve it
+        let mut planner = Planner::new(&BlockDevice::mock_device(create_test_disk()));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+        strategy.add_request(efi_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining).with_weight(0));
+        assert!(strategy.apply(&mut planner).is_ok());
+        assert_eq!(planner.current_layout().len(), 2);
+    }
+
+    #[test]
+    fn test_placement_hints() {
+        // Test case: BIOS boot at a fixed offset, recovery at the end of the disk
+        let disk = create_test_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Exact(MB)).with_placement(Placement::At(MB)));
+        strategy.add_request(efi_partition());
+        strategy.add_request(root_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Exact(GB)).with_placement(Placement::End));
+
+        eprintln!("\nPlacement Strategy:\n{}", strategy.describe());
+        assert!(strategy.apply(&mut planner).is_ok());
+        eprintln!("{}", planner.describe_changes());
+
+        let mut layout = planner.current_layout();
+        layout.sort_by_key(|r| r.start);
+        assert_eq!(layout.len(), 4);
+        assert_eq!((layout[0].start, layout[0].end), (MB, 2 * MB));
+        assert_eq!(layout[1].start, 2 * MB);
+        assert_eq!(layout[1].size(), EFI_SIZE);
+        assert_eq!(layout[2].end, layout[3].start);
+        assert_eq!((layout[3].start, layout[3].end), (499 * GB, 500 * GB));
+    }
+
+    #[test]
+    fn test_flexible_fixed_placement() {
+        // Test case: a data partition at a fixed offset taking the rest of the disk
+        let disk = create_test_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+        strategy.add_request(efi_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining).with_placement(Placement::At(GB)));
+        assert!(strategy.apply(&mut planner).is_ok());
+
+        let mut layout = planner.current_layout();
+        layout.sort_by_key(|r| r.start);
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[1].start, GB);
+        assert_eq!(layout[1].end, planner.offsets().1);
+
+        // Ranges stop at their maximum
+        let disk = create_test_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+        strategy.add_request(
+            PartitionRequest::new(SizeRequirement::Range { min: GB, max: 10 * GB }).with_placement(Placement::At(GB)),
+        );
+        assert!(strategy.apply(&mut planner).is_ok());
+        assert_eq!(planner.current_layout()[0].size(), 10 * GB);
+    }
+
+    #[test]
+    fn test_reserved_space() {
+        // Test case: leave 10GB unpartitioned at the end for SSD over-provisioning
+        let disk = create_test_disk();
+        let mut planner = Planner::new(&BlockDevice::mock_device(disk));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+
+        strategy.add_request(efi_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining));
+        strategy.add_request(PartitionRequest::reserve(SizeRequirement::Exact(10 * GB)).with_placement(Placement::End));
+
+        eprintln!("\nReserved Space Strategy:\n{}", strategy.describe());
+        assert!(strategy.apply(&mut planner).is_ok());
+        eprintln!("{}", planner.describe_changes());
+
+        let layout = planner.current_layout();
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[1].end, 490 * GB);
+
+        // A reserved gap between partitions still counts towards the space used
+        let mut planner = Planner::new(&BlockDevice::mock_device(create_test_disk()));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+        strategy.add_request(efi_partition());
+        strategy.add_request(PartitionRequest::reserve(SizeRequirement::Exact(GB)));
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Exact(GB)));
+        assert!(strategy.apply(&mut planner).is_ok());
+
+        let layout = planner.current_layout();
+        assert_eq!(layout.len(), 2);
+        assert_eq!(layout[1].start, layout[0].end + GB);
+    }
+
+    #[test]
+    fn test_reserved_fixed_gap() {
+        // Test case: keep 10GB free in the middle of the disk, at 100GB
+        let mut planner = Planner::new(&BlockDevice::mock_device(create_test_disk()));
+        let mut strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
+        strategy.add_request(PartitionRequest::new(SizeRequirement::AtLeast(GB)).with_placement(Placement::At(MB)));
+        strategy.add_request(
+            PartitionRequest::reserve(SizeRequirement::Exact(10 * GB)).with_placement(Placement::At(100 * GB)),
+        );
+        strategy.add_request(efi_partition());
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining));
+        assert!(strategy.apply(&mut planner).is_ok());
+
+        let gap = Region::new(100 * GB, 110 * GB);
+        let mut layout = planner.current_layout();
+        layout.sort_by_key(|r| r.start);
+        assert_eq!(layout.len(), 3);
+        assert!(layout.iter().all(|r| !r.overlaps_with(&gap)));
+        // The fixed partition stops at the gap, the others fill the space after it
+        assert_eq!(layout[0].end, gap.start);
+        assert_eq!(layout[1].start, gap.end);
+        assert_eq!(layout[2].end, planner.offsets().1);
+
+        // Partitions at fixed offsets cannot claim the gap either
+        let mut planner = Planner::new(&BlockDevice::mock_device(create_test_disk()));
+        let mut strategy = Strategy::new(AllocationStrategy::LargestFree);
+        strategy.add_request(
+            PartitionRequest::reserve(SizeRequirement::Exact(10 * GB)).with_placement(Placement::At(100 * GB)),
+        );
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Exact(GB)).with_placement(Placement::At(105 * GB)));
+        let error = strategy.apply(&mut planner).unwrap_err();
+        assert!(matches!(error, Error::Plan(PlanError::RegionOverlap { .. })));
+    }
+
     #[test]
     fn test_minimal_server_install() {
         // Test case: Minimal server installation with single root partition
@@ -371,9 +1134,7 @@ mod tests {
 
         // Simple layout - just boot and root
         strategy.add_request(boot_partition());
-        strategy.add_request(PartitionRequest {
-            size: SizeRequirement::Remaining,
-        });
+        strategy.add_request(PartitionRequest::new(SizeRequirement::Remaining));
 
         eprintln!("\nMinimal Server Strategy:\n{}", strategy.describe());
         assert!(strategy.apply(&mut planner).is_ok());
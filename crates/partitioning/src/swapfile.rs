@@ -0,0 +1,390 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Swapfile provisioning
+//!
+//! Many desktop layouts prefer a swapfile on the root filesystem over a dedicated swap
+//! partition, as it can be resized or removed later. The kernel refuses to swap to files
+//! with holes or copy-on-write extents, so the file must be allocated carefully:
+//!
+//! - On btrfs the file is marked NOCOW while it is still empty, which also disables
+//!   compression for it. The file must not live in a subvolume that gets snapshotted,
+//!   so a dedicated subvolume (e.g. `@swap`) is recommended.
+//! - On btrfs, ext4 and xfs the space is reserved with `fallocate`.
+//! - On f2fs preallocated extents cannot be swapped to, so the file is written out.
+//!
+//! The filesystem is mounted inside a private mount namespace, so the temporary mount is
+//! never visible to the rest of the system.
+//!
+//! Hibernating to a swapfile needs the `resume_offset=` kernel argument besides the
+//! filesystem holding it. [`Swapfile::apply()`] returns the offset of the new file, and
+//! [`resume_offset()`] computes it for an existing one. For swap partitions the UUID in
+//! the swap header is all that's needed, see [`swap_uuid()`].
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::{Component, Path},
+};
+
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::format::{self, FilesystemType};
+
+#[cfg(feature = "linux")]
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+};
+
+#[cfg(feature = "linux")]
+use linux_raw_sys::{
+    general::FS_NOCOW_FL,
+    ioctl::{FS_IOC_FIEMAP, FS_IOC_SETFLAGS},
+};
+#[cfg(feature = "linux")]
+use nix::{
+    fcntl::{fallocate, FallocateFlags},
+    libc,
+    sys::statfs::{fstatfs, BTRFS_SUPER_MAGIC},
+};
+#[cfg(feature = "linux")]
+use tracing::{debug, info};
+
+#[cfg(feature = "linux")]
+use crate::{format::Format, namespace::with_private_mount};
+
+/// Smallest swapfile accepted, anything smaller is rejected by `mkswap`
+const MINIMUM_SIZE: u64 = 40 * 1024;
+
+/// Chunk size used when writing out a swapfile
+#[cfg(feature = "linux")]
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Signature at the end of the first page of a swap area
+const SWAP_SIGNATURE: &[u8; 10] = b"SWAPSPACE2";
+
+/// Offset of the UUID in the swap header, after the boot block, version, size and bad page count
+const SWAP_UUID_OFFSET: u64 = 1024 + 12;
+
+/// Page sizes a swap area may have been created with
+const PAGE_SIZES: [u64; 4] = [4096, 8192, 16384, 65536];
+
+/// Flush dirty data before mapping extents
+#[cfg(feature = "linux")]
+const FIEMAP_FLAG_SYNC: u32 = 1;
+
+/// Errors that can occur while creating a swapfile
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// Mount or namespace operation failed
+    #[cfg(feature = "linux")]
+    #[error("system call failed: {0}")]
+    Nix(#[from] nix::Error),
+    /// The path is empty, absolute or escapes the filesystem root
+    #[error("invalid swapfile path: {0}")]
+    InvalidPath(String),
+    /// The requested size is too small to hold a swap area
+    #[error("swapfile of {0} bytes is too small")]
+    TooSmall(u64),
+    /// The filesystem cannot hold a swapfile
+    #[error("swapfiles are not supported on {0}")]
+    Unsupported(FilesystemType),
+    /// Writing the swap signature failed
+    #[error(transparent)]
+    Format(#[from] format::Error),
+    /// The physical location of the swapfile cannot be determined
+    #[error("cannot determine resume offset: {0}")]
+    ResumeOffset(String),
+}
+
+/// A swapfile to create on a filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Swapfile {
+    /// Path of the file relative to the filesystem root (e.g. `@swap/swapfile` on btrfs)
+    pub path: String,
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+impl Swapfile {
+    /// Create a new swapfile description
+    pub fn new(path: impl Into<String>, size: u64) -> Self {
+        Self {
+            path: path.into(),
+            size,
+        }
+    }
+
+    /// Check that the path stays below the filesystem root and the size is usable
+    pub fn validate(&self) -> Result<(), Error> {
+        let path = Path::new(&self.path);
+        let valid = path.file_name().is_some() && path.components().all(|c| matches!(c, Component::Normal(_)));
+        if !valid {
+            return Err(Error::InvalidPath(self.path.clone()));
+        }
+        if self.size < MINIMUM_SIZE {
+            return Err(Error::TooSmall(self.size));
+        }
+        Ok(())
+    }
+
+    /// Returns true if a swapfile can be created on the given filesystem
+    pub fn is_supported(filesystem: FilesystemType) -> bool {
+        matches!(
+            filesystem,
+            FilesystemType::Btrfs | FilesystemType::Ext4 | FilesystemType::F2fs | FilesystemType::Xfs
+        )
+    }
+
+    /// Create the swapfile on `device`, which holds a filesystem of the given type
+    ///
+    /// Btrfs filesystems are mounted at their top-level subvolume, so the path is relative
+    /// to it. Parent directories are created as needed. Returns the resume offset of the
+    /// new file.
+    #[cfg(feature = "linux")]
+    pub fn apply<P: AsRef<Path>>(&self, device: P, filesystem: FilesystemType) -> Result<u64, Error> {
+        self.validate()?;
+        if !Self::is_supported(filesystem) {
+            return Err(Error::Unsupported(filesystem));
+        }
+
+        let device = device.as_ref();
+        info!("Creating {} byte swapfile {} on {:?}", self.size, self.path, device);
+
+        let options = (filesystem == FilesystemType::Btrfs).then_some("subvolid=5");
+        with_private_mount(device, &filesystem.to_string(), options, |root| {
+            self.create(root, filesystem)
+        })
+    }
+
+    /// Create the swapfile below the mounted filesystem root
+    #[cfg(feature = "linux")]
+    fn create(&self, root: &Path, filesystem: FilesystemType) -> Result<u64, Error> {
+        let target = root.join(&self.path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        debug!("Creating swapfile {:?}", target);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&target)?;
+
+        // Must happen before any data is written to take effect
+        if filesystem == FilesystemType::Btrfs {
+            set_nocow(&file)?;
+        }
+
+        match filesystem {
+            FilesystemType::F2fs => write_zeroes(&file, self.size)?,
+            _ => fallocate(file.as_raw_fd(), FallocateFlags::empty(), 0, self.size as libc::off_t)?,
+        }
+        file.sync_all()?;
+        drop(file);
+
+        Format::new(FilesystemType::Swap).run(&target)?;
+
+        let offset = resume_offset(&target)?;
+        info!("Created swapfile {:?} with resume offset {}", target, offset);
+        Ok(offset)
+    }
+}
+
+/// Compute the `resume_offset=` kernel argument for an existing swapfile
+///
+/// The offset is the first physical page of the file. Btrfs maps files through its own
+/// chunk tree, so there the answer comes from `btrfs inspect-internal map-swapfile`.
+#[cfg(feature = "linux")]
+pub fn resume_offset(path: impl AsRef<Path>) -> Result<u64, Error> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    if fstatfs(&file)?.filesystem_type() == BTRFS_SUPER_MAGIC {
+        return btrfs_resume_offset(path);
+    }
+
+    let physical = first_extent(&file)?.ok_or_else(|| Error::ResumeOffset(format!("{path:?} has no extents")))?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+    Ok(physical / page_size)
+}
+
+/// Physical byte offset of the first extent of `file`, `None` if nothing is allocated
+#[cfg(feature = "linux")]
+fn first_extent(file: &File) -> io::Result<Option<u64>> {
+    #[repr(C)]
+    #[derive(Default)]
+    struct Extent {
+        logical: u64,
+        physical: u64,
+        length: u64,
+        reserved64: [u64; 2],
+        flags: u32,
+        reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Fiemap {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+        extents: [Extent; 1],
+    }
+
+    let mut map = Fiemap {
+        length: u64::MAX,
+        flags: FIEMAP_FLAG_SYNC,
+        extent_count: 1,
+        ..Default::default()
+    };
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut map) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((map.mapped_extents > 0).then_some(map.extents[0].physical))
+}
+
+/// Ask btrfs-progs for the resume offset of a swapfile on btrfs
+#[cfg(feature = "linux")]
+fn btrfs_resume_offset(path: &Path) -> Result<u64, Error> {
+    const TOOL: &str = "btrfs";
+
+    let output = std::process::Command::new(TOOL)
+        .args(["inspect-internal", "map-swapfile", "-r"])
+        .arg(path)
+        .output()
+        .map_err(|source| format::Error::Spawn { tool: TOOL, source })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(format::Error::Failed { tool: TOOL, stderr }.into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .trim()
+        .parse()
+        .map_err(|_| Error::ResumeOffset(format!("unexpected output from {TOOL}: {}", stdout.trim())))
+}
+
+/// Read the UUID of the swap area on `reader`
+///
+/// Returns `None` if there is no swap signature, or the area was created without a UUID.
+pub fn swap_uuid<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Uuid>> {
+    let mut signature = [0u8; 10];
+    let mut found = false;
+    for page_size in PAGE_SIZES {
+        reader.seek(SeekFrom::Start(page_size - SWAP_SIGNATURE.len() as u64))?;
+        match reader.read_exact(&mut signature) {
+            Ok(()) if &signature == SWAP_SIGNATURE => {
+                found = true;
+                break;
+            }
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+    }
+    if !found {
+        return Ok(None);
+    }
+
+    let mut uuid = [0u8; 16];
+    reader.seek(SeekFrom::Start(SWAP_UUID_OFFSET))?;
+    reader.read_exact(&mut uuid)?;
+    Ok(Some(Uuid::from_bytes(uuid)).filter(|u| !u.is_nil()))
+}
+
+/// Disable copy-on-write (and with it compression) for an empty btrfs file
+#[cfg(feature = "linux")]
+fn set_nocow(file: &File) -> io::Result<()> {
+    let flags = FS_NOCOW_FL as libc::c_int;
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_SETFLAGS as _, &flags) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Allocate `size` bytes by writing zeroes
+#[cfg(feature = "linux")]
+fn write_zeroes(mut file: &File, size: u64) -> io::Result<()> {
+    let chunk = vec![0u8; CHUNK_SIZE];
+    let mut remaining = size;
+    while remaining > 0 {
+        let len = remaining.min(CHUNK_SIZE as u64) as usize;
+        file.write_all(&chunk[..len])?;
+        remaining -= len as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(Swapfile::new("@swap/swapfile", 8 * 1024 * 1024 * 1024)
+            .validate()
+            .is_ok());
+        assert!(Swapfile::new("swapfile", MINIMUM_SIZE).validate().is_ok());
+
+        for path in ["", "/swapfile", "../swapfile", "swap/../swapfile"] {
+            let swapfile = Swapfile::new(path, 1024 * 1024);
+            assert!(matches!(swapfile.validate(), Err(Error::InvalidPath(_))), "{path}");
+        }
+        assert!(matches!(
+            Swapfile::new("swapfile", 4096).validate(),
+            Err(Error::TooSmall(4096))
+        ));
+    }
+
+    #[test]
+    fn test_swap_uuid() {
+        let uuid = Uuid::from_u128(0x1234_5678_9abc_def0_1234_5678_9abc_def0);
+        let mut area = vec![0u8; 16384];
+        area[SWAP_UUID_OFFSET as usize..SWAP_UUID_OFFSET as usize + 16].copy_from_slice(uuid.as_bytes());
+        assert_eq!(swap_uuid(&mut io::Cursor::new(&area)).unwrap(), None);
+
+        // Swap areas of 16 KiB pages, e.g. on aarch64
+        area[16384 - 10..].copy_from_slice(SWAP_SIGNATURE);
+        assert_eq!(swap_uuid(&mut io::Cursor::new(&area)).unwrap(), Some(uuid));
+
+        area[SWAP_UUID_OFFSET as usize..SWAP_UUID_OFFSET as usize + 16].fill(0);
+        assert_eq!(swap_uuid(&mut io::Cursor::new(&area)).unwrap(), None);
+        assert_eq!(swap_uuid(&mut io::Cursor::new(vec![0u8; 512])).unwrap(), None);
+    }
+
+    #[cfg(feature = "linux")]
+    #[test]
+    fn test_unsupported_filesystem() {
+        let swapfile = Swapfile::new("swapfile", 1024 * 1024);
+        for filesystem in [FilesystemType::Fat32, FilesystemType::Swap] {
+            assert!(matches!(
+                swapfile.apply("/dev/null", filesystem),
+                Err(Error::Unsupported(f)) if f == filesystem
+            ));
+        }
+    }
+
+    #[cfg(feature = "linux")]
+    #[test]
+    fn test_write_zeroes() {
+        let path = std::env::temp_dir().join(format!("disks-rs-swapfile-test-{}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        write_zeroes(&file, CHUNK_SIZE as u64 + 512).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), CHUNK_SIZE as u64 + 512);
+        drop(file);
+        std::fs::remove_file(path).unwrap();
+    }
+}
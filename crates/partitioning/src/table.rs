@@ -0,0 +1,315 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Read-only GPT parsing
+//!
+//! [`read()`] parses the GPT header and partition entries from anything implementing
+//! `Read + Seek`, be it an image file, a block device or a buffer in memory. Unlike
+//! `gpt::GptConfig::open` it never needs write access or ownership of a device path,
+//! which makes it suitable for probing partition types and labels.
+//!
+//! Both the header and the entry array are verified against their CRC32 checksums. If
+//! the primary table is damaged the backup at the end of the device is used instead,
+//! see [`Table::is_primary()`].
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use disks::partition::{decode_gpt_name, GPT_NAME_LEN};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Checksum used for headers and entry arrays
+const CHECKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Signature at the start of every GPT header
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// Logical block sizes tried when reading a table
+const BLOCK_SIZES: [u64; 2] = [512, 4096];
+
+/// Smallest valid header, as defined by the UEFI specification
+const MIN_HEADER_SIZE: u32 = 92;
+
+/// Smallest valid partition entry
+const MIN_ENTRY_SIZE: u32 = 128;
+
+/// Refuse entry arrays larger than this, nothing sane comes close
+const MAX_ENTRIES_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Errors that can occur while reading a GPT
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// Neither the primary nor the backup location holds a GPT header
+    #[error("no GPT partition table found")]
+    NotFound,
+    /// A header is present but malformed
+    #[error("invalid GPT header: {0}")]
+    InvalidHeader(&'static str),
+    /// The header checksum does not match
+    #[error("GPT header checksum mismatch")]
+    HeaderChecksum,
+    /// The checksum of the partition entries does not match
+    #[error("GPT partition entries checksum mismatch")]
+    EntriesChecksum,
+}
+
+/// A GPT header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Revision of the specification, 0x00010000 for 1.0
+    pub revision: u32,
+    /// Sector holding this header
+    pub current_lba: u64,
+    /// Sector holding the other copy of the header
+    pub alternate_lba: u64,
+    /// First sector usable by partitions
+    pub first_usable_lba: u64,
+    /// Last sector usable by partitions
+    pub last_usable_lba: u64,
+    /// GUID of the disk
+    pub disk_guid: Uuid,
+    /// First sector of the partition entry array
+    pub entries_lba: u64,
+    /// Number of slots in the partition entry array
+    pub num_entries: u32,
+    /// Size of a single partition entry in bytes
+    pub entry_size: u32,
+}
+
+/// A used partition entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Partition number, the 1-based slot in the entry array
+    pub number: u32,
+    /// Partition type GUID
+    pub type_guid: Uuid,
+    /// Unique GUID of the partition
+    pub guid: Uuid,
+    /// First sector of the partition
+    pub first_lba: u64,
+    /// Last sector of the partition (inclusive)
+    pub last_lba: u64,
+    /// Attribute flags
+    pub attributes: u64,
+    /// Partition name
+    pub name: String,
+}
+
+impl Entry {
+    /// Size of the partition in sectors
+    pub fn sectors(&self) -> u64 {
+        self.last_lba - self.first_lba + 1
+    }
+}
+
+/// A GPT partition table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    /// Logical block size the table was found with
+    pub block_size: u64,
+    /// The header the entries were read from
+    pub header: Header,
+    /// Used partition entries, ordered by number
+    pub entries: Vec<Entry>,
+}
+
+impl Table {
+    /// Returns true if the table was read from the primary header
+    ///
+    /// False means the primary table is damaged and the backup was used.
+    pub fn is_primary(&self) -> bool {
+        self.header.current_lba == 1
+    }
+
+    /// Returns the entry for partition `number`, if any
+    pub fn entry(&self, number: u32) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.number == number)
+    }
+}
+
+/// Read the GPT of a device or image
+///
+/// The primary table is preferred, the backup is only used if the primary one is missing
+/// or fails verification. Both 512 and 4096 byte logical blocks are supported.
+pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Table, Error> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    let mut error = Error::NotFound;
+
+    for block_size in BLOCK_SIZES {
+        if size < block_size * 3 {
+            continue;
+        }
+        let last_lba = size / block_size - 1;
+        for lba in [1, last_lba] {
+            match read_at(reader, block_size, lba) {
+                Ok(table) => return Ok(table),
+                // Keep the most meaningful error, i.e. the first one about an existing header
+                Err(e) if matches!(error, Error::NotFound) => error = e,
+                Err(_) => {}
+            }
+        }
+    }
+    Err(error)
+}
+
+/// Read the table whose header is at `lba`
+pub(crate) fn read_at<R: Read + Seek>(reader: &mut R, block_size: u64, lba: u64) -> Result<Table, Error> {
+    let mut block = vec![0u8; block_size as usize];
+    reader.seek(SeekFrom::Start(lba * block_size))?;
+    reader.read_exact(&mut block)?;
+    if &block[0..8] != SIGNATURE {
+        return Err(Error::NotFound);
+    }
+
+    let header_size = u32_at(&block, 12);
+    if header_size < MIN_HEADER_SIZE || header_size as u64 > block_size {
+        return Err(Error::InvalidHeader("header size out of range"));
+    }
+    let header_crc = u32_at(&block, 16);
+    block[16..20].fill(0);
+    if CHECKSUM.checksum(&block[..header_size as usize]) != header_crc {
+        return Err(Error::HeaderChecksum);
+    }
+
+    let header = Header {
+        revision: u32_at(&block, 8),
+        current_lba: u64_at(&block, 24),
+        alternate_lba: u64_at(&block, 32),
+        first_usable_lba: u64_at(&block, 40),
+        last_usable_lba: u64_at(&block, 48),
+        disk_guid: guid_at(&block, 56),
+        entries_lba: u64_at(&block, 72),
+        num_entries: u32_at(&block, 80),
+        entry_size: u32_at(&block, 84),
+    };
+    if header.current_lba != lba {
+        return Err(Error::InvalidHeader("header is not at its own location"));
+    }
+    if header.entry_size < MIN_ENTRY_SIZE || !header.entry_size.is_multiple_of(8) {
+        return Err(Error::InvalidHeader("partition entry size out of range"));
+    }
+    let entries_size = header.num_entries as u64 * header.entry_size as u64;
+    if entries_size > MAX_ENTRIES_SIZE {
+        return Err(Error::InvalidHeader("partition entry array too large"));
+    }
+
+    let mut array = vec![0u8; entries_size as usize];
+    reader.seek(SeekFrom::Start(header.entries_lba * block_size))?;
+    reader.read_exact(&mut array)?;
+    if CHECKSUM.checksum(&array) != u32_at(&block, 88) {
+        return Err(Error::EntriesChecksum);
+    }
+
+    let entries = array
+        .chunks_exact(header.entry_size as usize)
+        .enumerate()
+        .filter(|(_, raw)| raw[0..16].iter().any(|b| *b != 0))
+        .map(|(i, raw)| Entry {
+            number: i as u32 + 1,
+            type_guid: guid_at(raw, 0),
+            guid: guid_at(raw, 16),
+            first_lba: u64_at(raw, 32),
+            last_lba: u64_at(raw, 40),
+            attributes: u64_at(raw, 48),
+            name: name_at(raw, 56),
+        })
+        .collect();
+
+    Ok(Table {
+        block_size,
+        header,
+        entries,
+    })
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// GUIDs are stored with their first three fields little endian
+fn guid_at(bytes: &[u8], offset: usize) -> Uuid {
+    Uuid::from_bytes_le(bytes[offset..offset + 16].try_into().unwrap())
+}
+
+/// Partition names are up to 36 UTF-16LE code units, NUL padded
+fn name_at(bytes: &[u8], offset: usize) -> String {
+    decode_gpt_name(&bytes[offset..offset + GPT_NAME_LEN * 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::Write,
+        os::unix::fs::FileExt,
+    };
+
+    use gpt::{partition_types, GptConfig};
+
+    use super::*;
+    use crate::testing::ImageBuilder;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_read_matches_gpt() {
+        let image = ImageBuilder::new(32 * MB)
+            .partition(4 * MB)
+            .partition(8 * MB)
+            .build()
+            .unwrap();
+        let expected = GptConfig::new()
+            .writable(false)
+            .open_from_device(File::open(&image.path).unwrap())
+            .unwrap();
+
+        let table = read(&mut File::open(&image.path).unwrap()).unwrap();
+        assert!(table.is_primary());
+        assert_eq!(table.block_size, 512);
+        assert_eq!(table.header.disk_guid, *expected.guid());
+        assert_eq!(table.entries.len(), 2);
+        for entry in &table.entries {
+            let partition = &expected.partitions()[&entry.number];
+            assert_eq!(entry.type_guid, partition_types::LINUX_FS.guid);
+            assert_eq!(entry.guid, partition.part_guid);
+            assert_eq!(entry.first_lba, partition.first_lba);
+            assert_eq!(entry.last_lba, partition.last_lba);
+            assert_eq!(entry.name, partition.name);
+        }
+        assert_eq!(table.entry(2).unwrap().sectors() * 512, 8 * MB);
+    }
+
+    #[test]
+    fn test_backup_fallback() {
+        let image = ImageBuilder::new(16 * MB).partition(4 * MB).build().unwrap();
+        let file = fs::OpenOptions::new().read(true).write(true).open(&image.path).unwrap();
+
+        // Damage the primary entry array
+        file.write_all_at(&[0xff; 16], 1024 + 16).unwrap();
+        let table = read(&mut &file).unwrap();
+        assert!(!table.is_primary());
+        assert_eq!(table.entries.len(), 1);
+
+        // Damage both headers
+        file.write_all_at(&[0xff; 4], 512 + 24).unwrap();
+        file.write_all_at(&[0xff; 4], 16 * MB - 512 + 24).unwrap();
+        assert!(matches!(read(&mut &file), Err(Error::HeaderChecksum)));
+    }
+
+    #[test]
+    fn test_blank() {
+        let path = std::env::temp_dir().join(format!("disks-rs-table-{}.img", std::process::id()));
+        File::create(&path).unwrap().write_all(&vec![0u8; MB as usize]).unwrap();
+        assert!(matches!(read(&mut File::open(&path).unwrap()), Err(Error::NotFound)));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,303 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Disk image fixtures for tests
+//!
+//! [`ImageBuilder`] creates a sparse disk image with a GPT partition table and formats
+//! the partitions with the requested filesystems. Every filesystem is first created in
+//! a standalone sparse image and then copied into place, so neither root privileges nor
+//! loop devices are needed. The standalone images are kept, letting tests read a single
+//! filesystem directly.
+//!
+//! Filesystems are created with their usual mkfs tools, so tests should check
+//! [`is_available()`] and skip filesystems whose tool is missing.
+//!
+//! This module is only built for tests and with the `testing` feature.
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use gpt::{mbr::ProtectiveMBR, partition_types, GptConfig};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    format::{self, FilesystemType, Format},
+    sparsefile,
+};
+
+/// Size of a logical block in bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// Partitions start on 1MiB boundaries, which also leaves room for the primary table
+const ALIGNMENT: u64 = 1024 * 1024;
+
+/// Space kept free at the end of the image for the backup table
+const BACKUP_TABLE_SIZE: u64 = 1024 * 1024;
+
+/// Chunk size used when copying filesystems into the image
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Distinguishes the fixtures of concurrently running tests
+static FIXTURE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors that can occur while building a fixture
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// GPT operation error
+    #[error("GPT error: {0}")]
+    Gpt(#[from] gpt::GptError),
+    /// Protective MBR operation error
+    #[error("MBR error: {0}")]
+    Mbr(#[from] gpt::mbr::MBRError),
+    /// Creating a filesystem failed
+    #[error(transparent)]
+    Format(#[from] format::Error),
+    /// The requested partitions do not fit in the image
+    #[error("image too small: {needed} bytes needed, {available} available")]
+    TooSmall { needed: u64, available: u64 },
+}
+
+/// A partition requested from an [`ImageBuilder`]
+#[derive(Debug, Clone)]
+struct PartitionSpec {
+    size: u64,
+    format: Option<Format>,
+}
+
+/// Builds sparse disk images for tests
+#[derive(Debug, Clone)]
+pub struct ImageBuilder {
+    size: u64,
+    partitions: Vec<PartitionSpec>,
+}
+
+/// A partition of a [`TestImage`]
+#[derive(Debug, Clone)]
+pub struct TestPartition {
+    /// Partition number within the table
+    pub number: u32,
+    /// Start of the partition in bytes
+    pub start: u64,
+    /// End of the partition in bytes (exclusive)
+    pub end: u64,
+    /// Standalone image of the filesystem copied into the partition, if formatted
+    pub filesystem: Option<PathBuf>,
+}
+
+/// A disk image built by an [`ImageBuilder`]
+///
+/// The image and its standalone filesystem images are removed when dropped.
+#[derive(Debug)]
+pub struct TestImage {
+    dir: PathBuf,
+    /// Path of the disk image
+    pub path: PathBuf,
+    /// Partitions in table order
+    pub partitions: Vec<TestPartition>,
+}
+
+impl ImageBuilder {
+    /// Start a new image of `size` bytes without any partitions
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            partitions: Vec::new(),
+        }
+    }
+
+    /// Add an unformatted partition of `size` bytes
+    pub fn partition(mut self, size: u64) -> Self {
+        self.partitions.push(PartitionSpec { size, format: None });
+        self
+    }
+
+    /// Add a partition of `size` bytes holding a labelled filesystem
+    pub fn filesystem(mut self, size: u64, filesystem: FilesystemType, label: &str) -> Self {
+        self.partitions.push(PartitionSpec {
+            size,
+            format: Some(Format::new(filesystem).with_label(label)),
+        });
+        self
+    }
+
+    /// Create the image in a fresh temporary directory
+    pub fn build(&self) -> Result<TestImage, Error> {
+        let needed = ALIGNMENT + self.partitions.iter().map(|p| align_up(p.size)).sum::<u64>() + BACKUP_TABLE_SIZE;
+        if needed > self.size {
+            return Err(Error::TooSmall {
+                needed,
+                available: self.size,
+            });
+        }
+
+        let dir = env::temp_dir().join(format!(
+            "disks-rs-fixture-{}-{}",
+            std::process::id(),
+            FIXTURE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        // Cleaned up on drop from here on
+        let mut image = TestImage {
+            path: dir.join("disk.img"),
+            dir,
+            partitions: Vec::new(),
+        };
+
+        sparsefile::create(&image.path, self.size)?;
+        let mut file = OpenOptions::new().read(true).write(true).open(&image.path)?;
+        let sectors = self.size / SECTOR_SIZE - 1;
+        ProtectiveMBR::with_lb_size(u32::try_from(sectors).unwrap_or(u32::MAX)).overwrite_lba0(&mut file)?;
+        let mut table = GptConfig::new().writable(true).create_from_device(file, None)?;
+
+        let mut start = ALIGNMENT;
+        for (i, spec) in self.partitions.iter().enumerate() {
+            let number = i as u32 + 1;
+            let partition_type = match spec.format.as_ref().map(|f| f.filesystem) {
+                Some(FilesystemType::Swap) => partition_types::LINUX_SWAP,
+                Some(FilesystemType::Fat32) => partition_types::EFI,
+                _ => partition_types::LINUX_FS,
+            };
+            let name = spec
+                .format
+                .as_ref()
+                .and_then(|f| f.label.clone())
+                .unwrap_or_else(|| format!("part{number}"));
+            table.add_partition_at(
+                &name,
+                number,
+                start / SECTOR_SIZE,
+                spec.size / SECTOR_SIZE,
+                partition_type,
+                0,
+            )?;
+            image.partitions.push(TestPartition {
+                number,
+                start,
+                end: start + spec.size,
+                filesystem: None,
+            });
+            start += align_up(spec.size);
+        }
+        table.write()?;
+
+        for (spec, partition) in self.partitions.iter().zip(&mut image.partitions) {
+            let Some(format) = &spec.format else {
+                continue;
+            };
+            let path = image.dir.join(format!("part{}.img", partition.number));
+            sparsefile::create(&path, spec.size)?;
+            format.run(&path)?;
+            copy_into(&path, &image.path, partition.start)?;
+            partition.filesystem = Some(path);
+        }
+
+        debug!("Built test image {:?}", image.path);
+        Ok(image)
+    }
+}
+
+impl Drop for TestImage {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Returns true if the tool creating `filesystem` can be found
+///
+/// `/sbin` and `/usr/sbin` are searched too, as they are often missing from `PATH`.
+pub fn is_available(filesystem: FilesystemType) -> bool {
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::split_paths(&path)
+        .chain([PathBuf::from("/sbin"), PathBuf::from("/usr/sbin")])
+        .any(|dir| dir.join(filesystem.tool()).is_file())
+}
+
+/// Round `size` up to the partition alignment
+fn align_up(size: u64) -> u64 {
+    size.div_ceil(ALIGNMENT) * ALIGNMENT
+}
+
+/// Copy `source` into `target` at `offset`, skipping zero chunks to keep it sparse
+fn copy_into(source: &Path, target: &Path, offset: u64) -> io::Result<()> {
+    let mut input = File::open(source)?;
+    let mut output = OpenOptions::new().write(true).open(target)?;
+    output.seek(SeekFrom::Start(offset))?;
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let len = input.read(&mut buffer)?;
+        if len == 0 {
+            return Ok(());
+        }
+        if buffer[..len].iter().all(|b| *b == 0) {
+            output.seek(SeekFrom::Current(len as i64))?;
+        } else {
+            output.write_all(&buffer[..len])?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_build_image() {
+        let builder = ImageBuilder::new(64 * MB).partition(8 * MB);
+        let builder = match is_available(FilesystemType::Ext4) {
+            true => builder.filesystem(32 * MB, FilesystemType::Ext4, "fixture"),
+            false => builder.partition(32 * MB),
+        };
+        let image = builder.build().unwrap();
+
+        let table = GptConfig::new()
+            .writable(false)
+            .open_from_device(File::open(&image.path).unwrap())
+            .unwrap();
+        let partitions = table.partitions();
+        assert_eq!(partitions.len(), 2);
+        assert_eq!(partitions[&1].first_lba * SECTOR_SIZE, MB);
+        assert_eq!(partitions[&2].first_lba * SECTOR_SIZE, 9 * MB);
+        assert_eq!(image.partitions[1].end, 41 * MB);
+
+        if let Some(filesystem) = &image.partitions[1].filesystem {
+            assert_eq!(partitions[&2].name, "fixture");
+
+            // The ext4 magic must have made it into the disk image
+            let mut magic = [0u8; 2];
+            let mut file = File::open(&image.path).unwrap();
+            file.seek(SeekFrom::Start(image.partitions[1].start + 1024 + 0x38))
+                .unwrap();
+            file.read_exact(&mut magic).unwrap();
+            assert_eq!(magic, [0x53, 0xef]);
+            assert!(filesystem.exists());
+        }
+
+        let dir = image.dir.clone();
+        drop(image);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_image_too_small() {
+        assert!(matches!(
+            ImageBuilder::new(8 * MB).partition(8 * MB).build(),
+            Err(Error::TooSmall {
+                needed,
+                available,
+            }) if needed == 10 * MB && available == 8 * MB
+        ));
+    }
+}
@@ -0,0 +1,83 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Limiting the throughput of bulk I/O
+//!
+//! Wiping, copying or flashing at full speed on a live system starves every other user
+//! of the disk. A [`Throttle`] paces an operation to a maximum number of bytes per second
+//! by sleeping between chunks whenever it is ahead of schedule.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::cancel::CancellationToken;
+
+/// Longest single sleep, so cancellation is still noticed promptly
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+/// Paces an operation to a maximum throughput
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    /// Bytes per second, unlimited if `None`
+    rate: Option<u64>,
+    started: Instant,
+}
+
+impl Throttle {
+    /// Start pacing at `rate` bytes per second, or not at all for `None`
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            rate: rate.filter(|r| *r > 0),
+            started: Instant::now(),
+        }
+    }
+
+    /// The time at which `processed` bytes are due, relative to the start
+    pub fn due(&self, processed: u64) -> Duration {
+        match self.rate {
+            Some(rate) => Duration::from_secs_f64(processed as f64 / rate as f64),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Sleep until `processed` bytes are within the rate, or `cancel` is cancelled
+    pub fn wait(&self, processed: u64, cancel: &CancellationToken) {
+        let due = self.started + self.due(processed);
+        loop {
+            let now = Instant::now();
+            if now >= due || cancel.is_cancelled() {
+                return;
+            }
+            thread::sleep((due - now).min(MAX_SLEEP));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        let unlimited = Throttle::new(None);
+        assert_eq!(unlimited.due(u64::MAX), Duration::ZERO);
+        assert_eq!(Throttle::new(Some(0)).due(1024), Duration::ZERO);
+
+        let throttle = Throttle::new(Some(1024 * 1024));
+        assert_eq!(throttle.due(512 * 1024), Duration::from_millis(500));
+
+        let started = Instant::now();
+        throttle.wait(50 * 1024, &CancellationToken::default());
+        assert!(started.elapsed() >= Duration::from_millis(40));
+
+        // A cancelled operation does not wait for the rate to catch up
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let started = Instant::now();
+        throttle.wait(u32::MAX as u64, &cancel);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,332 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Making cloned disks unique again
+//!
+//! A disk that was flashed from an image or cloned from another disk carries the same
+//! disk and partition GUIDs and filesystem UUIDs as its source. Attaching both to one
+//! machine confuses udev, `PARTUUID=` and `UUID=` mounts alike. [`make_unique()`] gives
+//! the disk new GPT GUIDs and asks the tools of each filesystem to pick a new UUID.
+//!
+//! Filesystem UUIDs are changed with `tune2fs` (ext4), `xfs_admin` (XFS), `btrfstune`
+//! (btrfs) and `fatlabel` (FAT serial numbers), which must be available in `PATH`. Other
+//! filesystems and LUKS2 containers are left alone and reported as skipped. `tune2fs`
+//! refuses filesystems that were not cleanly unmounted, run `e2fsck` on them first.
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use disks::BlockDevice;
+use gpt::GptConfig;
+use superblock::{Kind, Superblock};
+use thiserror::Error;
+use tracing::{debug, error, info, instrument};
+use uuid::Uuid;
+
+use crate::progress::{Event, NoProgress, ProgressSink};
+
+/// Errors that can occur while making a disk unique
+#[derive(Debug, Error)]
+pub enum Error {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// GPT-specific error
+    #[error("GPT error: {0}")]
+    Gpt(#[from] gpt::GptError),
+    /// The filesystem tool could not be run
+    #[error("failed to run {tool}: {source}")]
+    Spawn { tool: &'static str, source: io::Error },
+    /// The filesystem tool reported a failure
+    #[error("{tool} failed: {stderr}")]
+    Failed { tool: &'static str, stderr: String },
+    /// The disk or one of its partitions is in use
+    #[cfg(feature = "linux")]
+    #[error(transparent)]
+    InUse(#[from] crate::in_use::InUse),
+}
+
+/// A GUID replaced in the partition table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuidChange {
+    /// Number of the partition, or `None` for the disk GUID
+    pub partition: Option<u32>,
+    /// The GUID shared with the source of the clone
+    pub old: Uuid,
+    /// The newly generated GUID
+    pub new: Uuid,
+}
+
+/// A filesystem found while making a disk unique
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilesystemChange {
+    /// Device holding the filesystem
+    pub device: PathBuf,
+    /// Type of the filesystem
+    pub kind: Kind,
+    /// The UUID (or FAT serial) before the change, as used by `UUID=` in fstab
+    pub old: Option<String>,
+    /// The new UUID, or `None` if the filesystem does not support changing it
+    pub new: Option<String>,
+}
+
+/// Everything changed by [`make_unique()`]
+#[derive(Debug, Clone, Default)]
+pub struct UniqueReport {
+    /// Regenerated GPT GUIDs, the disk GUID first
+    pub guids: Vec<GuidChange>,
+    /// Filesystems found on the disk and its partitions
+    pub filesystems: Vec<FilesystemChange>,
+}
+
+impl UniqueReport {
+    /// Filesystems that kept their UUID
+    pub fn skipped(&self) -> impl Iterator<Item = &FilesystemChange> {
+        self.filesystems.iter().filter(|f| f.new.is_none())
+    }
+}
+
+/// The tool changing the UUID of `kind` and its arguments, if there is one
+fn uuid_tool(kind: &Kind) -> Option<(&'static str, &'static [&'static str])> {
+    match kind {
+        Kind::Ext4 => Some(("tune2fs", &["-U", "random"])),
+        Kind::XFS => Some(("xfs_admin", &["-U", "generate"])),
+        // Rewrites every metadata block, which is safe on an unmounted filesystem
+        Kind::Btrfs => Some(("btrfstune", &["-f", "-u"])),
+        Kind::FAT => Some(("fatlabel", &["--volume-id", "--reset"])),
+        Kind::F2FS | Kind::LUKS2 => None,
+    }
+}
+
+/// Build the invocation giving the filesystem on `device` a random UUID
+///
+/// Returns `None` for filesystems whose UUID cannot be changed.
+pub fn uuid_command(kind: &Kind, device: &Path) -> Option<Command> {
+    let (tool, args) = uuid_tool(kind)?;
+    let mut command = Command::new(tool);
+    command.args(args).arg(device);
+    Some(command)
+}
+
+/// Read the filesystem superblock of `device`, if it has one
+fn probe(device: &Path) -> io::Result<Option<Superblock>> {
+    let mut file = File::open(device)?;
+    Ok(Superblock::from_reader(&mut file).ok())
+}
+
+/// Give the filesystem on `device` a new UUID
+///
+/// Returns `None` if `device` holds no known filesystem.
+#[instrument(skip_all, fields(device = %device.as_ref().display()))]
+pub fn regenerate_filesystem_uuid<P: AsRef<Path>>(device: P) -> Result<Option<FilesystemChange>, Error> {
+    let device = device.as_ref();
+    let Some(superblock) = probe(device)? else {
+        debug!("No filesystem found on {:?}", device);
+        return Ok(None);
+    };
+    let kind = superblock.kind();
+    let tool = uuid_tool(&kind).map(|(tool, _)| tool);
+    let command = uuid_command(&kind, device);
+    let mut change = FilesystemChange {
+        device: device.to_owned(),
+        kind,
+        old: superblock.uuid_string().ok(),
+        new: None,
+    };
+    let kind = &change.kind;
+
+    let (Some(tool), Some(mut command)) = (tool, command) else {
+        info!("Cannot change the UUID of the {} filesystem on {:?}", kind, device);
+        return Ok(Some(change));
+    };
+    debug!("Running {:?}", command);
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|source| Error::Spawn { tool, source })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        error!("{} failed on {:?}: {}", tool, device, stderr);
+        return Err(Error::Failed { tool, stderr });
+    }
+
+    change.new = probe(device)?.and_then(|s| s.uuid_string().ok());
+    info!(
+        "Changed UUID of the {} filesystem on {:?} to {}",
+        kind,
+        device,
+        change.new.as_deref().unwrap_or("unknown")
+    );
+    Ok(Some(change))
+}
+
+/// Give the GPT at `path` and all of its partitions new random GUIDs
+///
+/// Returns the changes, the disk GUID first. The kernel is not notified.
+#[instrument(skip_all, fields(device = %path.as_ref().display()))]
+pub fn regenerate_gpt_guids<P: AsRef<Path>>(path: P) -> Result<Vec<GuidChange>, Error> {
+    let file = OpenOptions::new().read(true).write(true).open(path.as_ref())?;
+    let mut table = GptConfig::new().writable(true).open_from_device(file)?;
+
+    let old = *table.guid();
+    table.update_guid(None);
+    let mut changes = vec![GuidChange {
+        partition: None,
+        old,
+        new: *table.guid(),
+    }];
+
+    let mut partitions = table.partitions().clone();
+    for (number, partition) in partitions.iter_mut().filter(|(_, p)| p.is_used()) {
+        let new = Uuid::new_v4();
+        debug!(partition = number, %new, "Regenerating partition GUID");
+        changes.push(GuidChange {
+            partition: Some(*number),
+            old: partition.part_guid,
+            new,
+        });
+        partition.part_guid = new;
+    }
+    table.update_partitions(partitions)?;
+    table.write()?;
+
+    info!("Regenerated {} GUIDs on {:?}", changes.len(), path.as_ref());
+    Ok(changes)
+}
+
+/// Give a cloned disk new partition table GUIDs and filesystem UUIDs
+pub fn make_unique(device: &BlockDevice) -> Result<UniqueReport, Error> {
+    make_unique_with_progress(device, &NoProgress)
+}
+
+/// Like [`make_unique()`], reporting progress to `progress`
+///
+/// Disks with an MBR partition table only have their filesystems changed, disks without
+/// a partition table the filesystem written directly to them. Nothing is changed while
+/// the disk or one of its partitions is in use.
+#[instrument(name = "make_unique", skip_all, fields(device = %device.device().display()))]
+pub fn make_unique_with_progress(device: &BlockDevice, progress: &dyn ProgressSink) -> Result<UniqueReport, Error> {
+    #[cfg(feature = "linux")]
+    crate::in_use::check(device)?;
+
+    let mut report = UniqueReport::default();
+    let has_gpt = crate::table::read(&mut File::open(device.device())?).is_ok();
+    let mut devices = device
+        .partitions()
+        .iter()
+        .map(|p| p.device.as_path())
+        .collect::<Vec<_>>();
+    if devices.is_empty() {
+        if has_gpt {
+            // e.g. loop devices without partition scanning, the protective MBR is no filesystem
+            progress.event(Event::Warning(format!(
+                "The partitions of {} are unknown to the kernel, their filesystems keep their UUIDs",
+                device.device().display()
+            )));
+        } else {
+            devices.push(device.device());
+        }
+    }
+
+    for path in devices {
+        let step = format!("Changing filesystem UUID on {}", path.display());
+        progress.event(Event::StepStarted(step.clone()));
+        match regenerate_filesystem_uuid(path)? {
+            Some(change) if change.new.is_none() => {
+                progress.event(Event::Warning(format!(
+                    "The UUID of the {} filesystem on {} cannot be changed",
+                    change.kind,
+                    path.display()
+                )));
+                report.filesystems.push(change);
+            }
+            Some(change) => report.filesystems.push(change),
+            None => {}
+        }
+        progress.event(Event::StepCompleted(step));
+    }
+
+    if has_gpt {
+        let step = format!("Regenerating partition table GUIDs on {}", device.device().display());
+        progress.event(Event::StepStarted(step.clone()));
+        report.guids = regenerate_gpt_guids(device.device())?;
+
+        // Let udev pick up the new PARTUUIDs
+        #[cfg(feature = "linux")]
+        crate::flash::rescan(&File::open(device.device())?, device.device());
+        progress.event(Event::StepCompleted(step));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        format::FilesystemType,
+        testing::{is_available, ImageBuilder},
+    };
+
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn args(command: &Command) -> Vec<String> {
+        command.get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn test_uuid_commands() {
+        let device = Path::new("/dev/sda2");
+        let command = uuid_command(&Kind::XFS, device).unwrap();
+        assert_eq!(command.get_program(), "xfs_admin");
+        assert_eq!(args(&command), vec!["-U", "generate", "/dev/sda2"]);
+        assert_eq!(
+            args(&uuid_command(&Kind::FAT, device).unwrap()),
+            vec!["--volume-id", "--reset", "/dev/sda2"]
+        );
+        assert!(uuid_command(&Kind::LUKS2, device).is_none());
+    }
+
+    #[test]
+    fn test_regenerate_identifiers() {
+        let mut builder = ImageBuilder::new(64 * MB).partition(8 * MB);
+        let ext4 = is_available(FilesystemType::Ext4);
+        if ext4 {
+            builder = builder.filesystem(16 * MB, FilesystemType::Ext4, "root");
+        }
+        let image = builder.build().unwrap();
+
+        let before = GptConfig::new().open(&image.path).unwrap();
+        let changes = regenerate_gpt_guids(&image.path).unwrap();
+        let after = GptConfig::new().open(&image.path).unwrap();
+        assert_eq!(changes.len(), 1 + image.partitions.len());
+        assert_eq!(changes[0].old, *before.guid());
+        assert_eq!(changes[0].new, *after.guid());
+        assert_ne!(before.guid(), after.guid());
+        for change in &changes[1..] {
+            let number = change.partition.unwrap();
+            assert_eq!(before.partitions()[&number].part_guid, change.old);
+            assert_eq!(after.partitions()[&number].part_guid, change.new);
+            assert_ne!(change.old, change.new);
+            // Only the GUID changes
+            assert_eq!(
+                before.partitions()[&number].first_lba,
+                after.partitions()[&number].first_lba
+            );
+        }
+
+        if ext4 {
+            let path = image.partitions[1].filesystem.as_ref().unwrap();
+            let change = regenerate_filesystem_uuid(path).unwrap().unwrap();
+            assert_eq!(change.kind, Kind::Ext4);
+            assert!(change.new.is_some());
+            assert_ne!(change.old, change.new);
+        }
+    }
+}
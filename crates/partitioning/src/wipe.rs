@@ -0,0 +1,388 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Destroying partition tables and on-disk signatures
+//!
+//! [`zap()`] is the equivalent of `sgdisk --zap-all` followed by `wipefs --all`: it removes
+//! the MBR, both GPT headers with their entry arrays and the magic numbers of any known
+//! filesystem, RAID or LVM metadata so the device is seen as blank by every tool.
+//! [`find_signatures()`] lists what would be erased without touching the device.
+//!
+//! [`erase()`] applies an [`ErasePolicy`], which can go further than removing signatures
+//! by discarding or overwriting the whole device. It needs the `linux` feature. Its
+//! [`EraseOptions`] stop it between chunks once cancelled and can cap its throughput.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use tracing::{debug, info};
+
+#[cfg(feature = "linux")]
+use linux_raw_sys::ioctl::{BLKDISCARD, BLKSECDISCARD};
+#[cfg(feature = "linux")]
+use nix::libc;
+#[cfg(feature = "linux")]
+use std::os::fd::AsRawFd;
+#[cfg(feature = "linux")]
+use tracing::instrument;
+
+#[cfg(feature = "linux")]
+use crate::throttle::Throttle;
+use crate::{
+    cancel::CancellationToken,
+    progress::{Event, NoProgress, ProgressSink},
+};
+
+/// Size of a logical block in bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// Sectors covered by a GPT header plus its default 128 entry array
+const GPT_SECTORS: u64 = 33;
+
+/// Size of the buffer used when zeroing a device
+#[cfg(feature = "linux")]
+const ZERO_CHUNK: usize = 4 * 1024 * 1024;
+
+/// How thoroughly a device is erased before it is partitioned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErasePolicy {
+    /// Leave the device alone
+    #[default]
+    None,
+    /// Remove partition tables and known signatures, see [`zap()`]
+    Signatures,
+    /// Discard every block, then remove signatures
+    Discard,
+    /// Overwrite the whole device with zeroes
+    Zero,
+    /// Securely discard every block, so the data is unrecoverable, then remove signatures
+    SecureErase,
+}
+
+impl fmt::Display for ErasePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Signatures => f.write_str("signatures-only"),
+            Self::Discard => f.write_str("discard"),
+            Self::Zero => f.write_str("zero"),
+            Self::SecureErase => f.write_str("secure-erase"),
+        }
+    }
+}
+
+/// Options controlling [`erase()`]
+#[derive(Debug, Clone, Default)]
+pub struct EraseOptions {
+    /// Checked before every chunk written
+    pub cancel: CancellationToken,
+    /// Maximum bytes zeroed per second, unlimited if `None`
+    ///
+    /// Discards are a single request to the device and are not limited.
+    pub rate_limit: Option<u64>,
+}
+
+/// A known signature: name, offset from the start of the device and magic bytes
+const SIGNATURES: &[(&str, u64, &[u8])] = &[
+    ("xfs", 0, b"XFSB"),
+    ("luks", 0, b"LUKS\xba\xbe"),
+    ("luks2 (secondary header)", 0x4000, b"SKUL\xba\xbe"),
+    ("vfat", 0x36, b"FAT1"),
+    ("vfat", 0x52, b"FAT32"),
+    ("lvm2 label", 0x200, b"LABELONE"),
+    ("f2fs", 0x400, b"\x10\x20\xf5\xf2"),
+    ("ext4", 0x438, b"\x53\xef"),
+    ("linux_raid_member (1.2)", 0x1000, b"\xfc\x4e\x2b\xa9"),
+    ("swap", 0xff6, b"SWAPSPACE2"),
+    ("swap", 0xff6, b"SWAP-SPACE"),
+    ("iso9660", 0x8001, b"CD001"),
+    ("btrfs", 0x10040, b"_BHRfS_M"),
+];
+
+/// A region of the device holding a signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// Type of the signature, e.g. `gpt`, `ext4`
+    pub name: String,
+    /// Absolute offset in bytes
+    pub offset: u64,
+    /// Number of bytes that will be erased
+    pub length: u64,
+}
+
+/// List every signature that [`zap()`] would erase from the device at `path`
+pub fn find_signatures<P: AsRef<Path>>(path: P) -> io::Result<Vec<Signature>> {
+    let mut file = File::open(&path)?;
+    scan(&mut file)
+}
+
+/// Erase partition tables and all known signatures from the device at `path`
+///
+/// Returns the signatures that were erased.
+pub fn zap<P: AsRef<Path>>(path: P) -> io::Result<Vec<Signature>> {
+    zap_with_progress(path, &NoProgress)
+}
+
+/// Like [`zap()`], reporting progress to `progress`
+pub fn zap_with_progress<P: AsRef<Path>>(path: P, progress: &dyn ProgressSink) -> io::Result<Vec<Signature>> {
+    info!("Zapping all signatures on {:?}", path.as_ref());
+    let step = format!("Erasing signatures on {}", path.as_ref().display());
+    progress.event(Event::StepStarted(step.clone()));
+
+    let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+    let signatures = scan(&mut file)?;
+
+    let total = signatures.iter().map(|s| s.length).sum();
+    let mut processed = 0;
+    for signature in &signatures {
+        debug!(
+            "Erasing {} bytes of {} at offset {}",
+            signature.length, signature.name, signature.offset
+        );
+        file.seek(SeekFrom::Start(signature.offset))?;
+        file.write_all(&vec![0u8; signature.length as usize])?;
+        processed += signature.length;
+        progress.event(Event::Bytes { processed, total });
+    }
+    file.sync_all()?;
+    progress.event(Event::StepCompleted(step));
+
+    info!("Erased {} signatures from {:?}", signatures.len(), path.as_ref());
+    Ok(signatures)
+}
+
+/// Erase the device at `path` according to `policy`
+///
+/// Cancelling fails with an error of kind [`io::ErrorKind::Interrupted`] or
+/// [`io::ErrorKind::TimedOut`] wrapping [`crate::cancel::Cancelled`]. A discard is a single
+/// request to the device and cannot be interrupted once started.
+#[cfg(feature = "linux")]
+#[instrument(skip_all, fields(device = %path.as_ref().display(), %policy))]
+pub fn erase<P: AsRef<Path>>(
+    path: P,
+    policy: ErasePolicy,
+    options: &EraseOptions,
+    progress: &dyn ProgressSink,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let cancel = &options.cancel;
+    cancel.check(0)?;
+    match policy {
+        ErasePolicy::None => Ok(()),
+        ErasePolicy::Signatures => zap_with_progress(path, progress).map(|_| ()),
+        ErasePolicy::Discard => {
+            discard(path, BLKDISCARD, progress)?;
+            cancel.check(0)?;
+            zap_with_progress(path, progress).map(|_| ())
+        }
+        ErasePolicy::SecureErase => {
+            discard(path, BLKSECDISCARD, progress)?;
+            cancel.check(0)?;
+            zap_with_progress(path, progress).map(|_| ())
+        }
+        ErasePolicy::Zero => zero(path, options, progress),
+    }
+}
+
+/// Discard the whole device with the given discard ioctl
+#[cfg(feature = "linux")]
+fn discard(path: &Path, request: u32, progress: &dyn ProgressSink) -> io::Result<()> {
+    let step = format!("Discarding all blocks on {}", path.display());
+    progress.event(Event::StepStarted(step.clone()));
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let size = file.seek(SeekFrom::End(0))?;
+    let range: [u64; 2] = [0, size];
+    debug!(bytes = size, "Discarding device");
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), request as _, &range) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    info!("Discarded all blocks");
+    progress.event(Event::StepCompleted(step));
+    Ok(())
+}
+
+/// Overwrite the whole device with zeroes
+#[cfg(feature = "linux")]
+fn zero(path: &Path, options: &EraseOptions, progress: &dyn ProgressSink) -> io::Result<()> {
+    let step = format!("Zeroing {}", path.display());
+    progress.event(Event::StepStarted(step.clone()));
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let total = file.seek(SeekFrom::End(0))?;
+    file.rewind()?;
+
+    let throttle = Throttle::new(options.rate_limit);
+    let buffer = vec![0u8; ZERO_CHUNK];
+    let mut processed = 0;
+    while processed < total {
+        throttle.wait(processed, &options.cancel);
+        options.cancel.check(processed)?;
+        let len = (total - processed).min(ZERO_CHUNK as u64) as usize;
+        file.write_all(&buffer[..len])?;
+        processed += len as u64;
+        progress.event(Event::Bytes { processed, total });
+    }
+    file.sync_all()?;
+
+    info!(bytes = total, "Zeroed device");
+    progress.event(Event::StepCompleted(step));
+    Ok(())
+}
+
+/// Find all signatures within the given device
+fn scan<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<Signature>> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    let mut found = Vec::new();
+
+    if matches_at(reader, size, 0x1fe, b"\x55\xaa")? {
+        found.push(signature("mbr", 0, SECTOR_SIZE));
+    }
+
+    // Primary GPT header and entries, plus the backup pointed to by the header
+    let mut backup_lba = (size / SECTOR_SIZE).saturating_sub(1);
+    if matches_at(reader, size, SECTOR_SIZE, b"EFI PART")? {
+        found.push(signature("gpt", SECTOR_SIZE, GPT_SECTORS * SECTOR_SIZE));
+
+        let mut alternate = [0u8; 8];
+        reader.seek(SeekFrom::Start(SECTOR_SIZE + 32))?;
+        reader.read_exact(&mut alternate)?;
+        let alternate = u64::from_le_bytes(alternate);
+        if alternate > GPT_SECTORS && (alternate + 1) * SECTOR_SIZE <= size {
+            backup_lba = alternate;
+        }
+    }
+    let backup = backup_lba * SECTOR_SIZE;
+    if backup >= GPT_SECTORS * SECTOR_SIZE && matches_at(reader, size, backup, b"EFI PART")? {
+        let start = backup - (GPT_SECTORS - 1) * SECTOR_SIZE;
+        found.push(signature("gpt (backup)", start, GPT_SECTORS * SECTOR_SIZE));
+    }
+
+    for (name, offset, magic) in SIGNATURES {
+        if matches_at(reader, size, *offset, magic)? {
+            found.push(signature(name, *offset, magic.len() as u64));
+        }
+    }
+
+    // mdraid 0.90 and 1.0 superblocks live at the end of the device
+    let md_090 = (size & !0xffff).saturating_sub(0x10000);
+    if size >= 0x20000 && matches_at(reader, size, md_090, b"\xfc\x4e\x2b\xa9")? {
+        found.push(signature("linux_raid_member (0.90)", md_090, 4));
+    }
+    let md_10 = (size & !0xfff).saturating_sub(0x2000);
+    if size >= 0x3000 && matches_at(reader, size, md_10, b"\xfc\x4e\x2b\xa9")? {
+        found.push(signature("linux_raid_member (1.0)", md_10, 4));
+    }
+
+    Ok(found)
+}
+
+/// Check whether `magic` is present at `offset`
+fn matches_at<R: Read + Seek>(reader: &mut R, size: u64, offset: u64, magic: &[u8]) -> io::Result<bool> {
+    if offset + magic.len() as u64 > size {
+        return Ok(false);
+    }
+
+    let mut buf = vec![0u8; magic.len()];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut buf)?;
+    Ok(buf == magic)
+}
+
+fn signature(name: &str, offset: u64, length: u64) -> Signature {
+    Signature {
+        name: name.to_string(),
+        offset,
+        length,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    #[test]
+    fn test_scan_gpt_and_filesystem() {
+        let mut image = vec![0u8; 8 * MB];
+        let size = image.len() as u64;
+
+        image[0x1fe..0x200].copy_from_slice(b"\x55\xaa");
+        image[512..520].copy_from_slice(b"EFI PART");
+        let backup_lba = size / SECTOR_SIZE - 1;
+        image[512 + 32..512 + 40].copy_from_slice(&backup_lba.to_le_bytes());
+        let backup = (backup_lba * SECTOR_SIZE) as usize;
+        image[backup..backup + 8].copy_from_slice(b"EFI PART");
+        image[0x10040..0x10048].copy_from_slice(b"_BHRfS_M");
+
+        let found = scan(&mut Cursor::new(image)).unwrap();
+        let names = found.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["mbr", "gpt", "gpt (backup)", "btrfs"]);
+        assert_eq!(found[2].offset + found[2].length, size);
+    }
+
+    #[test]
+    fn test_zap_file() {
+        let path = std::env::temp_dir().join(format!("disks-rs-zap-{}.img", std::process::id()));
+        let mut image = vec![0u8; MB];
+        image[0x438..0x43a].copy_from_slice(b"\x53\xef");
+        image[0x1fe..0x200].copy_from_slice(b"\x55\xaa");
+        std::fs::write(&path, &image).unwrap();
+
+        let events = std::cell::RefCell::new(Vec::new());
+        let erased = zap_with_progress(&path, &|e| events.borrow_mut().push(e)).unwrap();
+        assert_eq!(erased.len(), 2);
+        assert!(find_signatures(&path).unwrap().is_empty());
+        assert!(events.borrow().contains(&Event::Bytes {
+            processed: 514,
+            total: 514
+        }));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "linux")]
+    #[test]
+    fn test_erase_zero() {
+        let path = std::env::temp_dir().join(format!("disks-rs-erase-{}.img", std::process::id()));
+        std::fs::write(&path, vec![0xffu8; 5 * MB]).unwrap();
+
+        erase(&path, ErasePolicy::Zero, &EraseOptions::default(), &NoProgress).unwrap();
+        assert!(std::fs::read(&path).unwrap().iter().all(|b| *b == 0));
+
+        std::fs::write(&path, vec![0xffu8; 5 * MB]).unwrap();
+        let options = EraseOptions::default();
+        let cancel = &options.cancel;
+        let seen = std::cell::Cell::new(0);
+        let progress = |event| {
+            // Cancel once the first chunk was written
+            if let Event::Bytes { processed, .. } = event {
+                seen.set(processed);
+                cancel.cancel();
+            }
+        };
+        let error = erase(&path, ErasePolicy::Zero, &options, &progress).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+        let cancelled = crate::cancel::Cancelled::from_io(&error).unwrap();
+        assert_eq!(cancelled.processed, seen.get());
+        assert_eq!(cancelled.processed, ZERO_CHUNK as u64);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_scan_blank() {
+        let found = scan(&mut Cursor::new(vec![0u8; MB])).unwrap();
+        assert!(found.is_empty());
+    }
+}
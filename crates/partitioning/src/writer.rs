@@ -0,0 +1,623 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Apply planned partition changes to a disk
+//!
+//! The [`DiskWriter`] takes the changes recorded by a [`Planner`] and turns them into a
+//! GPT partition table on the target device. Once written, the kernel must be told about
+//! the new layout, e.g. via [`crate::blkpg::sync_gpt_partitions`].
+//!
+//! New partitions are named after the id of their tag, if any. Provisioning tools can ask for
+//! them to be marked as pending with [`DiskWriter::mark_pending()`], see [`crate::pending`].
+//!
+//! Nothing is written while the disk or one of its partitions is mounted, active swap or
+//! otherwise in use, unless explicitly allowed with [`DiskWriter::allow_in_use()`].
+//!
+//! Existing tables that are foreign or corrupt, e.g. an MBR with logical partitions or a
+//! dynamic disk, are only edited when forced with [`DiskWriter::force()`], see
+//! [`crate::classify`]. Replacing them with a new table is always allowed.
+//!
+//! Disk and partition GUIDs are random by default. For reproducible images a seed can be
+//! supplied with [`DiskWriter::with_guid_seed()`], in which case every GUID is derived as a
+//! UUIDv5 of the seed and the partition number (or `disk` for the disk GUID).
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use disks::{partition::truncate_gpt_name, BlockDevice};
+use gpt::{mbr, partition_types, GptConfig, GptDisk};
+use thiserror::Error;
+use tracing::{debug, info, info_span, warn};
+use uuid::Uuid;
+
+use crate::{
+    classify::{self, Finding},
+    partition_type::{ATTR_PENDING, LINUX_FS},
+    planner::{Change, Planner, Region, TableType},
+    progress::{Event, NoProgress, ProgressSink},
+};
+
+/// Size of a logical block in bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// Sectors at the start of the disk holding the MBR and primary GPT
+const HEAD_SECTORS: u64 = 34;
+
+/// Sectors at the end of the disk holding the backup GPT
+const TAIL_SECTORS: u64 = 33;
+
+/// Checksum used for GPT headers and entry arrays
+const CHECKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Namespace for GUIDs derived from a seed
+const GUID_NAMESPACE: Uuid = Uuid::from_u128(0x5ae1_9c2e_0f6b_4d1a_9b3e_64d2_c1f0_8a47);
+
+/// Errors that can occur while writing a partition table
+#[derive(Debug, Error)]
+pub enum WriteError {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// GPT-specific error
+    #[error("GPT error: {0}")]
+    Gpt(#[from] gpt::GptError),
+    /// Protective MBR could not be written
+    #[error("MBR error: {0}")]
+    Mbr(#[from] mbr::MBRError),
+    /// A deletion refers to a partition the device does not have
+    #[error("unknown partition index {0}")]
+    UnknownPartition(usize),
+    /// No free partition entries remain in the table
+    #[error("partition table is full")]
+    TableFull,
+    /// The existing partition table cannot be changed safely
+    #[error("{0}, refusing to change the partition table")]
+    UnsafeTable(Finding),
+    /// The number the planner assigned is taken in the table on disk
+    #[error("partition number {0} is already in use")]
+    NumberInUse(u32),
+    /// The planner targets a partition table format the writer cannot produce
+    #[error("unsupported partition table type: {0:?}")]
+    UnsupportedTable(TableType),
+    /// The disk or one of its partitions is in use
+    #[cfg(feature = "linux")]
+    #[error(transparent)]
+    InUse(#[from] crate::in_use::InUse),
+}
+
+/// A partition created by the [`DiskWriter`]
+#[derive(Debug, Clone)]
+pub struct WrittenPartition {
+    /// Partition number within the table
+    pub number: u32,
+    /// Location of the partition, along with the tag it was planned with
+    pub region: Region,
+    /// Expected device node for the partition once the kernel has been notified
+    pub device: PathBuf,
+    /// Unique GUID of the partition
+    pub guid: Uuid,
+}
+
+/// Writes the changes from a [`Planner`] to a block device
+pub struct DiskWriter<'a> {
+    device: &'a BlockDevice,
+    planner: &'a Planner,
+    guid_seed: Option<String>,
+    mark_pending: bool,
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    allow_in_use: bool,
+    force: bool,
+    progress: &'a dyn ProgressSink,
+}
+
+impl<'a> DiskWriter<'a> {
+    /// Create a new writer for the given device and planner
+    pub fn new(device: &'a BlockDevice, planner: &'a Planner) -> Self {
+        Self {
+            device,
+            planner,
+            guid_seed: None,
+            mark_pending: false,
+            allow_in_use: false,
+            force: false,
+            progress: &NoProgress,
+        }
+    }
+
+    /// Report progress to the given sink
+    pub fn with_progress(self, progress: &'a dyn ProgressSink) -> Self {
+        Self { progress, ..self }
+    }
+
+    /// Derive all GUIDs from `seed` instead of generating random ones
+    ///
+    /// Writing the same plan with the same seed yields byte-identical tables.
+    pub fn with_guid_seed(self, seed: impl Into<String>) -> Self {
+        Self {
+            guid_seed: Some(seed.into()),
+            ..self
+        }
+    }
+
+    /// Mark every new partition as pending until [`crate::pending::clear_pending()`] is called
+    pub fn mark_pending(self) -> Self {
+        Self {
+            mark_pending: true,
+            ..self
+        }
+    }
+
+    /// Write even if the disk or its partitions are in use, see [`crate::in_use`]
+    pub fn allow_in_use(self) -> Self {
+        Self {
+            allow_in_use: true,
+            ..self
+        }
+    }
+
+    /// Edit the existing partition table even if it is foreign or corrupt, see [`crate::classify`]
+    pub fn force(self) -> Self {
+        Self { force: true, ..self }
+    }
+
+    /// Fail if the existing partition table cannot be changed safely, unless forced
+    ///
+    /// Nothing is checked when the planner creates a new table. Devices that cannot be
+    /// read are left to [`DiskWriter::write()`] to fail on.
+    pub fn check_table(&self) -> Result<(), WriteError> {
+        if self.force || self.planner.creates_new_table() {
+            return Ok(());
+        }
+        let classification = match classify::classify(self.device) {
+            Ok(classification) => classification,
+            Err(e) => {
+                debug!("Cannot classify partition table of {:?}: {}", self.device.device(), e);
+                return Ok(());
+            }
+        };
+        for finding in classification.warnings() {
+            warn!("Partition table of {:?}: {}", self.device.device(), finding);
+        }
+        if let Some(finding) = classification.errors().next() {
+            return Err(WriteError::UnsafeTable(finding.clone()));
+        }
+        Ok(())
+    }
+
+    /// Fail if anything uses the disk, unless allowed with [`DiskWriter::allow_in_use()`]
+    ///
+    /// [`DiskWriter::write()`] checks this itself. Callers that erase the disk before
+    /// writing should check first.
+    pub fn check_in_use(&self) -> Result<(), WriteError> {
+        #[cfg(feature = "linux")]
+        if !self.allow_in_use {
+            crate::in_use::check(self.device)?;
+        }
+        Ok(())
+    }
+
+    /// Compute the GUID for `name` when a seed has been set
+    fn seeded_guid(&self, name: &str) -> Option<Uuid> {
+        let seed = self.guid_seed.as_ref()?;
+        Some(Uuid::new_v5(&GUID_NAMESPACE, format!("{seed}:{name}").as_bytes()))
+    }
+
+    /// Capture the current partition table so a later write can be undone
+    pub fn backup(&self) -> Result<TableBackup, WriteError> {
+        Ok(TableBackup::capture(self.device.device())?)
+    }
+
+    /// Compute the resulting partitions without writing anything to disk
+    pub fn simulate(&self) -> Result<Vec<WrittenPartition>, WriteError> {
+        let file = File::open(self.device.device())?;
+        self.apply_changes(file, false)
+    }
+
+    /// Write the planned changes to the device
+    ///
+    /// Returns the partitions that were created, in the order they were planned.
+    pub fn write(&self) -> Result<Vec<WrittenPartition>, WriteError> {
+        self.check_in_use()?;
+        self.check_table()?;
+        let file = OpenOptions::new().read(true).write(true).open(self.device.device())?;
+        self.apply_changes(file, true)
+    }
+
+    /// Apply all changes to the table, writing it out if `writable`
+    fn apply_changes(&self, file: File, writable: bool) -> Result<Vec<WrittenPartition>, WriteError> {
+        let _span = info_span!("write", device = %self.device.device().display(), simulate = !writable).entered();
+        if let Some(table) = self.planner.table().filter(|t| *t != TableType::Gpt) {
+            return Err(WriteError::UnsupportedTable(table));
+        }
+
+        let config = GptConfig::new().writable(writable).change_partition_count(true);
+        let mut table = if self.planner.creates_new_table() {
+            info!("Creating new GPT partition table on {:?}", self.device.device());
+            config.create_from_device(file, self.seeded_guid("disk"))?
+        } else {
+            debug!("Opening existing GPT partition table on {:?}", self.device.device());
+            config.open_from_device(file)?
+        };
+
+        let numbers = self.planner.partition_numbers();
+        let mut written = Vec::new();
+        for (id, change) in self.planner.changes().iter().enumerate() {
+            let step = change.describe(self.planner.usable_size());
+            self.progress.event(Event::StepStarted(step.clone()));
+            match change {
+                Change::DeletePartition { original_index } => {
+                    // Original indices follow the planner's source, which need not match the device order
+                    let number = *self
+                        .planner
+                        .original_numbers()
+                        .get(*original_index)
+                        .ok_or(WriteError::UnknownPartition(*original_index))?;
+                    debug!("Removing partition {}", number);
+                    if table.remove_partition(number).is_none() {
+                        warn!("Partition {} not present in GPT table", number);
+                        self.progress
+                            .event(Event::Warning(format!("Partition {} not present in GPT table", number)));
+                    }
+                }
+                Change::AddPartition { start, end, tag, .. } => {
+                    let number = numbers[id].ok_or(WriteError::TableFull)?;
+                    if table.partitions().get(&number).is_some_and(|p| p.is_used()) {
+                        warn!("Partition {} planned for a number taken in the GPT table", number);
+                        return Err(WriteError::NumberInUse(number));
+                    }
+                    let first_lba = start / SECTOR_SIZE;
+                    let length_lba = (end - start) / SECTOR_SIZE;
+                    debug!(partition = number, first_lba, length_lba, "Adding partition");
+                    let part_type = partition_types::Type {
+                        guid: tag.as_ref().and_then(|t| t.partition_type).unwrap_or(LINUX_FS),
+                        os: partition_types::OperatingSystem::None,
+                    };
+                    let mut attributes = tag.as_ref().map_or(0, |t| t.attributes);
+                    if self.mark_pending {
+                        attributes |= ATTR_PENDING;
+                    }
+                    let full_name = tag
+                        .as_ref()
+                        .and_then(|t| t.name.as_deref().or(t.id.as_deref()))
+                        .unwrap_or_default();
+                    let name = truncate_gpt_name(full_name);
+                    if name.len() < full_name.len() {
+                        warn!("Truncating GPT partition name {:?} to {:?}", full_name, name);
+                    }
+                    table.add_partition_at(name, number, first_lba, length_lba, part_type, attributes)?;
+
+                    written.push(WrittenPartition {
+                        number,
+                        region: Region {
+                            start: *start,
+                            end: *end,
+                            tag: tag.clone(),
+                        },
+                        device: partition_device_path(self.device.device(), number),
+                        guid: table.partitions()[&number].part_guid,
+                    });
+                }
+            }
+            self.progress.event(Event::StepCompleted(step));
+        }
+
+        if self.guid_seed.is_some() {
+            let mut partitions = table.partitions().clone();
+            for partition in written.iter_mut() {
+                if let (Some(entry), Some(guid)) = (
+                    partitions.get_mut(&partition.number),
+                    self.seeded_guid(&partition.number.to_string()),
+                ) {
+                    debug!("Using seeded GUID {} for partition {}", guid, partition.number);
+                    entry.part_guid = guid;
+                    partition.guid = guid;
+                }
+            }
+            table.update_partitions(partitions)?;
+        }
+
+        if writable {
+            self.write_table(table)?;
+        }
+
+        Ok(written)
+    }
+
+    /// Persist the table, writing a protective MBR first for new tables
+    fn write_table(&self, mut table: GptDisk<File>) -> Result<(), WriteError> {
+        let step = "Writing partition table".to_string();
+        self.progress.event(Event::StepStarted(step.clone()));
+
+        if self.planner.creates_new_table() {
+            let sectors = (self.device.size() / SECTOR_SIZE).saturating_sub(1);
+            let mbr = mbr::ProtectiveMBR::with_lb_size(u32::try_from(sectors).unwrap_or(u32::MAX));
+            mbr.overwrite_lba0(table.device_mut())?;
+        }
+
+        let partitions = table.partitions().clone();
+        let mut file = table.write()?;
+        restore_entry_slots(&mut file, &partitions)?;
+        info!("Partition table written to {:?}", self.device.device());
+        self.progress.event(Event::StepCompleted(step));
+        Ok(())
+    }
+}
+
+/// Move the written partition entries back to the slots matching their numbers
+///
+/// The `gpt` crate packs the used entries at the start of the array, which would renumber
+/// every partition after a gap. Both arrays are rewritten with each entry at index
+/// `number - 1` and the header checksums updated to match.
+fn restore_entry_slots(
+    file: &mut File,
+    partitions: &BTreeMap<u32, gpt::partition::Partition>,
+) -> Result<(), WriteError> {
+    let used = partitions.iter().filter(|(_, p)| p.is_used()).collect::<Vec<_>>();
+    if used.iter().enumerate().all(|(i, (number, _))| **number == i as u32 + 1) {
+        return Ok(());
+    }
+    debug!("Restoring partition entry slots");
+
+    let mut header_lba = 1;
+    for _ in 0..2 {
+        let mut header = vec![0u8; SECTOR_SIZE as usize];
+        file.seek(SeekFrom::Start(header_lba * SECTOR_SIZE))?;
+        file.read_exact(&mut header)?;
+        let header_size = u32_at(&header, 12) as usize;
+        let alternate_lba = u64_at(&header, 32);
+        let entries_lba = u64_at(&header, 72);
+        let num_entries = u32_at(&header, 80);
+        let entry_size = u32_at(&header, 84);
+
+        let mut entries = vec![0u8; num_entries as usize * entry_size as usize];
+        file.seek(SeekFrom::Start(entries_lba * SECTOR_SIZE))?;
+        file.write_all(&entries)?;
+        for (number, partition) in &used {
+            partition.write_to_device(
+                file,
+                u64::from(**number - 1),
+                entries_lba,
+                gpt::disk::LogicalBlockSize::Lb512,
+                entry_size,
+            )?;
+        }
+        file.seek(SeekFrom::Start(entries_lba * SECTOR_SIZE))?;
+        file.read_exact(&mut entries)?;
+
+        header[88..92].copy_from_slice(&CHECKSUM.checksum(&entries).to_le_bytes());
+        header[16..20].fill(0);
+        let checksum = CHECKSUM.checksum(&header[..header_size]);
+        header[16..20].copy_from_slice(&checksum.to_le_bytes());
+        file.seek(SeekFrom::Start(header_lba * SECTOR_SIZE))?;
+        file.write_all(&header)?;
+
+        header_lba = alternate_lba;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// A raw copy of the areas of a device holding the partition table
+///
+/// Covers the MBR, the primary GPT and the backup GPT, so restoring it undoes anything
+/// written by [`DiskWriter::write()`].
+#[derive(Debug, Clone)]
+pub struct TableBackup {
+    path: PathBuf,
+    head: Vec<u8>,
+    tail: Vec<u8>,
+    tail_offset: u64,
+}
+
+impl TableBackup {
+    /// Read the partition table areas of the device at `path`
+    pub fn capture<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = File::open(&path)?;
+        let size = file.seek(SeekFrom::End(0))?;
+
+        let head_len = (HEAD_SECTORS * SECTOR_SIZE).min(size);
+        let tail_offset = size.saturating_sub(TAIL_SECTORS * SECTOR_SIZE).max(head_len);
+
+        let mut head = vec![0u8; head_len as usize];
+        file.rewind()?;
+        file.read_exact(&mut head)?;
+
+        let mut tail = vec![0u8; (size - tail_offset) as usize];
+        file.seek(SeekFrom::Start(tail_offset))?;
+        file.read_exact(&mut tail)?;
+
+        debug!("Captured partition table backup of {:?}", path.as_ref());
+        Ok(Self {
+            path: path.as_ref().to_owned(),
+            head,
+            tail,
+            tail_offset,
+        })
+    }
+
+    /// Write the captured areas back to the device
+    pub fn restore(&self) -> io::Result<()> {
+        info!("Restoring partition table of {:?}", self.path);
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        file.write_all(&self.head)?;
+        file.seek(SeekFrom::Start(self.tail_offset))?;
+        file.write_all(&self.tail)?;
+        file.sync_all()
+    }
+
+    /// Path of the device the backup was taken from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Compute the device node for partition `number` of `disk`
+///
+/// Disks whose name ends in a digit (`nvme0n1`, `loop0`, `mmcblk0`) use a `p` separator,
+/// see [`disks::naming`].
+pub fn partition_device_path(disk: &Path, number: u32) -> PathBuf {
+    disks::naming::partition_path(disk, number)
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, partition::Partition};
+
+    use super::*;
+    use crate::{partition_type::ATTR_NO_AUTO, planner::PartitionTag, table, testing::ImageBuilder};
+
+    const MB: u64 = 1024 * 1024;
+
+    /// Describe the image at `path` as a device, listing its partitions in disk order
+    fn image_device(path: &Path) -> BlockDevice {
+        let mut file = File::open(path).unwrap();
+        let mut disk = MockDisk::new(file.seek(SeekFrom::End(0)).unwrap());
+        disk.set_device(path);
+
+        let mut entries = table::read(&mut file).map(|gpt| gpt.entries).unwrap_or_default();
+        entries.sort_by_key(|e| e.first_lba);
+        for entry in entries {
+            let name = format!("mock0p{}", entry.number);
+            disk.push_partition(Partition {
+                number: entry.number,
+                start: entry.first_lba,
+                end: entry.last_lba + 1,
+                size: entry.last_lba + 1 - entry.first_lba,
+                node: PathBuf::from("/sys/class/block/mock0").join(&name),
+                device: PathBuf::from("/dev").join(&name),
+                name,
+                mbr: None,
+                flags: Default::default(),
+                gpt_name: None,
+            });
+        }
+        BlockDevice::mock_device(disk)
+    }
+
+    /// Read the primary and backup GPT areas of the image at `path`
+    fn table_bytes(path: &Path) -> Vec<u8> {
+        let backup = TableBackup::capture(path).unwrap();
+        // Skip the protective MBR, it is not part of the GPT
+        [&backup.head[SECTOR_SIZE as usize..], &backup.tail[..]].concat()
+    }
+
+    #[test]
+    fn test_written_tags() {
+        let image = ImageBuilder::new(64 * MB).build().unwrap();
+        let device = image_device(&image.path);
+        let tag = PartitionTag {
+            id: Some("esp".into()),
+            role: Some("boot".into()),
+            partition_type: Some(partition_types::EFI.guid),
+            attributes: ATTR_NO_AUTO,
+            ..Default::default()
+        };
+        let mut planner = Planner::new(&device).with_table(TableType::Gpt);
+        planner.plan_initialize_disk().unwrap();
+        planner.plan_add_partition_with_tag(MB, 9 * MB, tag.clone()).unwrap();
+        planner.plan_add_partition(9 * MB, 32 * MB).unwrap();
+        let written = DiskWriter::new(&device, &planner).allow_in_use().write().unwrap();
+
+        // Tags come back with the partitions they were planned for
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0].region.tag(), Some(&tag));
+        assert_eq!((written[0].region.start, written[0].region.end), (MB, 9 * MB));
+        assert!(written[1].region.tag().is_none());
+
+        let gpt = GptConfig::new().open(&image.path).unwrap();
+        let entry = &gpt.partitions()[&written[0].number];
+        assert_eq!(entry.part_type_guid.guid, partition_types::EFI.guid);
+        assert_eq!(entry.name, "esp");
+        assert_eq!(entry.flags, ATTR_NO_AUTO);
+        assert_eq!(gpt.partitions()[&written[1].number].part_type_guid.guid, LINUX_FS);
+    }
+
+    #[test]
+    fn test_guid_seed() {
+        let first = ImageBuilder::new(64 * MB).build().unwrap();
+        let second = ImageBuilder::new(64 * MB).build().unwrap();
+
+        let mut guids = Vec::new();
+        for image in [&first, &second] {
+            let device = image_device(&image.path);
+            let mut planner = Planner::new(&device).with_table(TableType::Gpt);
+            planner.plan_initialize_disk().unwrap();
+            planner.plan_add_partition(MB, 9 * MB).unwrap();
+            planner.plan_add_partition(9 * MB, 32 * MB).unwrap();
+            let written = DiskWriter::new(&device, &planner)
+                .allow_in_use()
+                .with_guid_seed("image")
+                .write()
+                .unwrap();
+
+            let gpt = GptConfig::new().open(&image.path).unwrap();
+            let partitions = gpt.partitions().values().map(|p| p.part_guid).collect::<Vec<_>>();
+            assert_eq!(partitions, written.iter().map(|p| p.guid).collect::<Vec<_>>());
+            guids.push((*gpt.guid(), partitions));
+        }
+
+        assert_eq!(guids[0], guids[1]);
+        assert_eq!(table_bytes(&first.path), table_bytes(&second.path));
+
+        // Another seed gives other GUIDs
+        let device = image_device(&second.path);
+        let mut planner = Planner::new(&device).with_table(TableType::Gpt);
+        planner.plan_initialize_disk().unwrap();
+        DiskWriter::new(&device, &planner)
+            .allow_in_use()
+            .with_guid_seed("other")
+            .write()
+            .unwrap();
+        assert_ne!(*GptConfig::new().open(&second.path).unwrap().guid(), guids[0].0);
+    }
+
+    #[test]
+    fn test_delete_out_of_order() {
+        let image = ImageBuilder::new(64 * MB)
+            .partition(4 * MB)
+            .partition(4 * MB)
+            .partition(4 * MB)
+            .build()
+            .unwrap();
+
+        // Swap the numbers of the first and last partition on disk
+        let mut gpt = GptConfig::new().writable(true).open(&image.path).unwrap();
+        let mut partitions = gpt.partitions().clone();
+        let first = partitions.remove(&1).unwrap();
+        let last = partitions.remove(&3).unwrap();
+        partitions.insert(1, last);
+        partitions.insert(3, first);
+        gpt.update_partitions(partitions).unwrap();
+        gpt.write().unwrap();
+
+        // The planner indexes partitions by number, the device lists them in disk order
+        let device = image_device(&image.path);
+        let mut planner = Planner::from_gpt(&image.path).unwrap();
+        assert_eq!(planner.original_numbers(), &[1, 2, 3]);
+        assert_eq!(device.partitions()[0].number, 3);
+        planner.plan_delete_partition(0).unwrap();
+        DiskWriter::new(&device, &planner).allow_in_use().write().unwrap();
+
+        // Partition 1 at the end of the disk is gone, the first one on disk remains
+        let gpt = table::read(&mut File::open(&image.path).unwrap()).unwrap();
+        assert!(gpt.entry(1).is_none());
+        assert_eq!(gpt.entry(3).unwrap().first_lba * SECTOR_SIZE, image.partitions[0].start);
+        assert!(gpt.entry(2).is_some());
+        let reopened = GptConfig::new().open(&image.path).unwrap();
+        assert_eq!(reopened.partitions().keys().copied().collect::<Vec<_>>(), vec![2, 3]);
+    }
+}
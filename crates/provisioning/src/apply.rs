@@ -0,0 +1,729 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Applying compiled plans to disks
+//!
+//! A plan may span several disks. To avoid leaving a machine with only some of its disks
+//! repartitioned, plans are applied in two phases:
+//!
+//! 1. Every disk is checked to not be in use, see [`crate::Provisioner::set_allow_in_use()`],
+//!    and to not hold a foreign or corrupt table that would be edited, see
+//!    [`crate::Provisioner::set_force()`]. Every device plan is simulated and the current
+//!    partition table of every disk is captured. Destructive changes are then confirmed with the provisioner's
+//!    [`crate::DeviceChooser`]. Nothing is written if any of this fails or is declined.
+//! 2. The tables are written, several disks at a time (see
+//!    [`crate::Provisioner::set_parallelism()`]), each disk first being erased according
+//!    to its erase policy. Should a write fail, disks not started yet are skipped and every
+//!    disk written (including the failing one) is restored from its captured table. Only
+//!    the tables are restored, so data destroyed by an erase is gone for good.
+//!
+//! Plans creating RAID arrays or LUKS2 volumes fail before anything is written, as neither
+//! can be created yet. Disks used with `format-whole-disk` get no table at all: they are
+//! erased and the whole device is formatted in place of a partition.
+//!
+//! Once every table has been written the kernel is notified of the new partitions and
+//! the requested filesystems, btrfs subvolumes and swapfiles are created, again for several
+//! disks at a time. Failures at this stage are reported but do not roll back the partition
+//! tables. Instead, new partitions are marked as pending until everything planned for them
+//! has been created, so a later run can pick up where this one stopped, see
+//! [`crate::RecoveryMode`].
+//!
+//! Applying stops early once the token given to [`crate::Provisioner::set_cancellation()`]
+//! is cancelled or times out. It is checked before each disk is validated and written,
+//! while erasing and before each filesystem is created. Until the last table has been
+//! written, cancelling rolls back like any failure. Afterwards the remaining filesystems
+//! fail with [`FormatError::Cancelled`] and their partitions are left pending.
+//!
+//! [`Plan::apply_with_progress()`] reports every step, e.g. writing one disk or creating
+//! one filesystem, as it starts and then completes or fails. Steps of the underlying
+//! operations, including byte progress while erasing, are reported in between.
+//!
+//! Applying plans needs the `linux` feature, the report types are always available.
+
+use std::{io, path::PathBuf};
+
+use partitioning::{
+    btrfs::Error as BtrfsError,
+    format::Error as FormatError,
+    swapfile::Error as SwapfileError,
+    writer::{WriteError, WrittenPartition},
+};
+
+#[cfg(feature = "linux")]
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+#[cfg(feature = "linux")]
+#[cfg(feature = "linux")]
+use partitioning::{
+    blkpg,
+    cancel::CancellationToken,
+    hpa,
+    in_use::{InUse, Usage},
+    pending,
+    planner::{PartitionTag, Region},
+    progress::{Event, NoProgress, ProgressSink},
+    wipe::{self, EraseOptions, ErasePolicy},
+    writer::{DiskWriter, TableBackup},
+};
+#[cfg(feature = "linux")]
+use tracing::{debug, error, info, info_span, instrument, warn, Span};
+
+#[cfg(feature = "linux")]
+use crate::{DevicePlan, Plan};
+
+/// How long to wait for the kernel to create the nodes of new partitions
+#[cfg(feature = "linux")]
+const PARTITION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of applying a plan to a single disk
+#[derive(Debug)]
+pub enum DeviceStatus {
+    /// The partition table was written
+    Written(Vec<WrittenPartition>),
+    /// This disk caused the plan to fail
+    Failed(WriteError),
+    /// The disk was written, then restored after another disk failed
+    RolledBack,
+    /// The disk was written but could not be restored after another disk failed
+    RestoreFailed(io::Error),
+    /// Nothing was done to the disk because an earlier step failed
+    Skipped,
+    /// The destructive changes to this disk were not confirmed
+    Declined,
+}
+
+/// Per-device results of [`Plan::apply()`]
+#[derive(Debug)]
+pub struct ApplyReport {
+    /// Results keyed by the disk name used in the strategy, in application order
+    pub devices: Vec<(String, PathBuf, DeviceStatus)>,
+
+    /// Filesystem creation results keyed by partition id
+    pub filesystems: Vec<(String, Result<(), FormatError>)>,
+
+    /// Btrfs subvolume creation results keyed by partition id
+    pub subvolumes: Vec<(String, Result<(), BtrfsError>)>,
+
+    /// Swapfile creation results keyed by partition id, with the resume offset of each file
+    pub swapfiles: Vec<(String, Result<u64, SwapfileError>)>,
+}
+
+impl ApplyReport {
+    /// Returns true if every disk was written and every filesystem, subvolume and swapfile created
+    pub fn is_success(&self) -> bool {
+        self.devices
+            .iter()
+            .all(|(_, _, status)| matches!(status, DeviceStatus::Written(_)))
+            && self.filesystems.iter().all(|(_, result)| result.is_ok())
+            && self.subvolumes.iter().all(|(_, result)| result.is_ok())
+            && self.swapfiles.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+#[cfg(feature = "linux")]
+impl Plan<'_> {
+    /// Write the plan to all assigned disks, all or nothing
+    ///
+    /// See the module documentation for the rollback semantics.
+    pub fn apply(&self) -> ApplyReport {
+        self.apply_with_progress(&NoProgress)
+    }
+
+    /// Like [`Plan::apply()`], reporting progress to `progress`
+    #[instrument(name = "apply", skip_all, fields(strategy = %self.strategy.name))]
+    pub fn apply_with_progress(&self, progress: &dyn ProgressSink) -> ApplyReport {
+        info!("Applying plan for strategy {}", self.strategy.name);
+
+        let assignments = self.writable_assignments();
+
+        let writers = assignments
+            .iter()
+            .map(|(name, plan)| {
+                (
+                    name.to_string(),
+                    disk_writer(plan, self.allow_in_use, self.force, progress),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut statuses = writers.iter().map(|_| DeviceStatus::Skipped).collect::<Vec<_>>();
+
+        let report = |statuses: Vec<DeviceStatus>| ApplyReport {
+            devices: assignments
+                .iter()
+                .zip(statuses)
+                .map(|((name, plan), status)| (name.to_string(), plan.device().device().to_owned(), status))
+                .collect(),
+            filesystems: vec![],
+            subvolumes: vec![],
+            swapfiles: vec![],
+        };
+
+        // Phase 1: validate and capture every disk before touching any of them
+        let mut backups = Vec::with_capacity(writers.len());
+        for (i, ((name, writer), (_, plan))) in writers.iter().zip(&assignments).enumerate() {
+            debug!("Validating plan for disk {}", name);
+            warn_hidden_capacity(name, plan, progress);
+            match step(progress, format!("Validating disk {name}"), || {
+                check_cancelled(&self.cancel)
+                    .and_then(|_| check_whole_disk_filesystem(plan, self.allow_in_use))
+                    .and_then(|_| writer.check_in_use())
+                    .and_then(|_| match plan.whole_disk() {
+                        Some(_) => Ok(vec![]),
+                        None => writer.check_table().and_then(|_| writer.simulate()),
+                    })
+                    .and_then(|_| writer.backup())
+            }) {
+                Ok(backup) => backups.push(backup),
+                Err(e) => {
+                    error!("Plan for disk {} cannot be applied: {}", name, e);
+                    statuses[i] = DeviceStatus::Failed(e);
+                    return report(statuses);
+                }
+            }
+        }
+
+        // Ask before destroying anything
+        let plan_report = self.report();
+        for (i, (name, _)) in writers.iter().enumerate() {
+            let Some(device) = plan_report.devices.iter().find(|d| d.name == *name) else {
+                continue;
+            };
+            if device.is_destructive() && !self.chooser.confirm(device) {
+                info!("Changes to disk {} were declined, nothing will be written", name);
+                progress.event(Event::Warning(format!("Changes to disk {name} were declined")));
+                statuses[i] = DeviceStatus::Declined;
+                return report(statuses);
+            }
+        }
+
+        // Phase 2: write, restoring everything written so far on failure
+        let names = writers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        let erase = EraseOptions {
+            cancel: self.cancel.clone(),
+            rate_limit: self.rate_limit,
+        };
+        let allow_in_use = self.allow_in_use;
+        let force = self.force;
+        let failed = AtomicBool::new(false);
+        let results = for_each_disk(&names, self.parallelism, progress, |i, progress| {
+            // Disks not started yet are left alone once one has failed
+            if failed.load(Ordering::Relaxed) {
+                return None;
+            }
+            let (name, plan) = assignments[i];
+            let result = write_disk(name, plan, &backups[i], allow_in_use, force, &erase, progress);
+            if result.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+            Some(result)
+        });
+        let failed = failed.into_inner();
+        for ((status, result), backup) in statuses.iter_mut().zip(results).zip(&backups) {
+            *status = match result {
+                Some(Ok(_)) if failed => rollback(backup),
+                Some(Ok(partitions)) => DeviceStatus::Written(partitions),
+                Some(Err(e)) => DeviceStatus::Failed(e),
+                None => DeviceStatus::Skipped,
+            };
+        }
+        if failed {
+            return report(statuses);
+        }
+
+        info!("Plan applied to {} disks", writers.len());
+
+        // Phase 3: create filesystems, subvolumes and swapfiles on the new partitions
+        let cancel = &self.cancel;
+        let mut finished = Finished::default();
+        for disk in for_each_disk(&names, self.parallelism, progress, |i, progress| match &statuses[i] {
+            DeviceStatus::Written(partitions) => {
+                let (name, plan) = assignments[i];
+                finish_device(name, plan, partitions, cancel, progress)
+            }
+            _ => Finished::default(),
+        }) {
+            finished.extend(disk);
+        }
+
+        ApplyReport {
+            filesystems: finished.filesystems,
+            subvolumes: finished.subvolumes,
+            swapfiles: finished.swapfiles,
+            ..report(statuses)
+        }
+    }
+
+    /// Device plans that get a partition table written, ordered by disk name
+    pub(crate) fn writable_assignments(&self) -> Vec<(&String, &DevicePlan<'_>)> {
+        // Deterministic ordering across runs
+        let mut assignments = self.device_assignments.iter().collect::<Vec<_>>();
+        assignments.sort_by_key(|(name, _)| name.as_str());
+        assignments
+    }
+
+    /// Create the filesystems, subvolumes and swapfiles planned for the given partitions
+    ///
+    /// The pending marker is removed from every partition that is complete afterwards.
+    pub(crate) fn finish_partitions(
+        &self,
+        name: &str,
+        plan: &DevicePlan<'_>,
+        partitions: &[WrittenPartition],
+        progress: &dyn ProgressSink,
+    ) -> Finished {
+        finish_device(name, plan, partitions, &self.cancel, progress)
+    }
+}
+
+/// Build the writer for the disk of `plan`
+#[cfg(feature = "linux")]
+fn disk_writer<'a>(
+    plan: &'a DevicePlan<'_>,
+    allow_in_use: bool,
+    force: bool,
+    progress: &'a dyn ProgressSink,
+) -> DiskWriter<'a> {
+    let mut writer = DiskWriter::new(plan.device(), plan.planner())
+        .with_progress(progress)
+        .mark_pending();
+    if allow_in_use {
+        writer = writer.allow_in_use();
+    }
+    if force {
+        writer = writer.force();
+    }
+    writer
+}
+
+/// Erase and partition the disk of `plan`, restoring its table from `backup` on failure
+#[cfg(feature = "linux")]
+fn write_disk(
+    name: &str,
+    plan: &DevicePlan<'_>,
+    backup: &TableBackup,
+    allow_in_use: bool,
+    force: bool,
+    erase: &EraseOptions,
+    progress: &dyn ProgressSink,
+) -> Result<Vec<WrittenPartition>, WriteError> {
+    let _span = info_span!("disk", %name, device = %plan.device().device().display()).entered();
+    let erased = match plan.erase() {
+        ErasePolicy::None => Ok(()),
+        policy => step(progress, format!("Erasing disk {name} ({policy})"), || {
+            wipe::erase(plan.device().device(), policy, erase, progress).map_err(WriteError::Io)
+        }),
+    };
+    debug!("Writing plan for disk {}", name);
+    let written = erased
+        .and_then(|_| check_cancelled(&erase.cancel))
+        .and_then(|_| match plan.whole_disk() {
+            Some(tag) => Ok(vec![whole_disk_partition(plan.device(), tag)]),
+            None => step(progress, format!("Partitioning disk {name}"), || {
+                disk_writer(plan, allow_in_use, force, progress).write()
+            }),
+        });
+    if let Err(e) = &written {
+        error!("Failed to write disk {}: {}", name, e);
+        if let Err(e) = backup.restore() {
+            warn!("Failed to restore disk {}: {}", name, e);
+            progress.event(Event::Warning(format!("Failed to restore disk {name}: {e}")));
+        }
+    }
+    written
+}
+
+#[cfg(feature = "linux")]
+/// Create the filesystems, subvolumes and swapfiles planned for the given partitions
+///
+/// The pending marker is removed from every partition that is complete afterwards.
+#[instrument(name = "finish", skip_all, fields(disk = %name, device = %plan.device().device().display()))]
+fn finish_device(
+    name: &str,
+    plan: &DevicePlan<'_>,
+    partitions: &[WrittenPartition],
+    cancel: &CancellationToken,
+    progress: &dyn ProgressSink,
+) -> Finished {
+    let mut finished = Finished::default();
+    let find = |id: &String| {
+        partitions
+            .iter()
+            .find(|p| p.region.tag().and_then(|t| t.id.as_ref()) == Some(id))
+    };
+
+    if plan.whole_disk().is_none()
+        && !(plan.filesystems().is_empty() && plan.subvolumes().is_empty() && plan.swapfiles().is_empty())
+    {
+        if let Err(e) = blkpg::sync_gpt_partitions(plan.device().device()) {
+            warn!("Failed to notify kernel of partitions on disk {}: {}", name, e);
+        }
+        for partition in partitions {
+            if let Err(e) = disks::naming::wait_for_device(&partition.device, PARTITION_TIMEOUT) {
+                warn!("Partition {} of disk {} is missing: {}", partition.number, name, e);
+            }
+        }
+    }
+    for (id, format) in plan.filesystems() {
+        match find(id) {
+            Some(partition) => {
+                let result = match cancel.check(0) {
+                    Ok(()) => step(progress, format!("Creating filesystem on {id}"), || {
+                        format.run_with_progress(&partition.device, progress)
+                    }),
+                    Err(cancelled) => {
+                        warn!("Not creating filesystem on {}: {}", id, cancelled);
+                        Err(cancelled.into())
+                    }
+                };
+                finished.filesystems.push((id.clone(), result));
+            }
+            None => warn!("Partition {} was not written, skipping filesystem", id),
+        }
+    }
+    for (id, layout) in plan.subvolumes() {
+        match find(id) {
+            Some(partition) if finished.is_formatted(id) => {
+                let result = step(progress, format!("Creating subvolumes on {id}"), || {
+                    layout.apply(&partition.device)
+                });
+                finished.subvolumes.push((id.clone(), result));
+            }
+            _ => warn!("Partition {} was not formatted, skipping subvolumes", id),
+        }
+    }
+    // Swapfiles may live in subvolumes, so they come last
+    for (id, swapfile) in plan.swapfiles() {
+        let filesystem = plan
+            .filesystems()
+            .iter()
+            .find(|(f, _)| f == id)
+            .map(|(_, f)| f.filesystem);
+        match (find(id), filesystem) {
+            (Some(partition), Some(filesystem)) if finished.is_formatted(id) => {
+                let result = step(progress, format!("Creating swapfile {} on {id}", swapfile.path), || {
+                    swapfile.apply(&partition.device, filesystem)
+                });
+                finished.swapfiles.push((id.clone(), result));
+            }
+            _ => warn!("Partition {} was not formatted, skipping swapfile", id),
+        }
+    }
+
+    let complete = partitions
+        .iter()
+        .filter(|p| {
+            let id = p.region.tag().and_then(|t| t.id.as_deref());
+            id.is_none_or(|id| !finished.has_failed(id))
+        })
+        .map(|p| p.number)
+        .collect::<Vec<_>>();
+    if !complete.is_empty() && plan.whole_disk().is_none() {
+        if let Err(e) = pending::clear_pending(plan.device().device(), &complete) {
+            warn!("Failed to clear pending partitions on disk {}: {}", name, e);
+        }
+    }
+
+    finished
+}
+
+/// Run `job` for every disk in `names`, on up to `limit` threads at once
+///
+/// Results are returned in the order of `names`. With more than one thread, events are
+/// wrapped in [`Event::Device`] and forwarded to `progress` from the calling thread.
+#[cfg(feature = "linux")]
+fn for_each_disk<T: Send>(
+    names: &[&str],
+    limit: usize,
+    progress: &dyn ProgressSink,
+    job: impl Fn(usize, &dyn ProgressSink) -> T + Sync,
+) -> Vec<T> {
+    if limit <= 1 || names.len() <= 1 {
+        return (0..names.len()).map(|i| job(i, progress)).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let parent = Span::current();
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        let workers = (0..limit.min(names.len()))
+            .map(|_| {
+                let (sender, next, job, parent) = (sender.clone(), &next, &job, &parent);
+                scope.spawn(move || {
+                    let _span = parent.clone().entered();
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(name) = names.get(i) else {
+                            return results;
+                        };
+                        let events = |event| {
+                            let _ = sender.send(Event::Device {
+                                device: name.to_string(),
+                                event: Box::new(event),
+                            });
+                        };
+                        results.push((i, job(i, &events)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Every worker holds a sender, so this ends once they are all done
+        drop(sender);
+        for event in receiver {
+            progress.event(event);
+        }
+
+        let mut results = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect::<Vec<_>>();
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    })
+}
+
+/// Fail with an IO error wrapping [`partitioning::cancel::Cancelled`] if applying should stop
+#[cfg(feature = "linux")]
+fn check_cancelled(cancel: &CancellationToken) -> Result<(), WriteError> {
+    cancel.check(0).map_err(|cancelled| WriteError::Io(cancelled.into()))
+}
+
+/// Results of creating filesystems, subvolumes and swapfiles, keyed by partition id
+#[cfg(feature = "linux")]
+#[derive(Debug, Default)]
+pub(crate) struct Finished {
+    pub(crate) filesystems: Vec<(String, Result<(), FormatError>)>,
+    pub(crate) subvolumes: Vec<(String, Result<(), BtrfsError>)>,
+    pub(crate) swapfiles: Vec<(String, Result<u64, SwapfileError>)>,
+}
+
+#[cfg(feature = "linux")]
+impl Finished {
+    /// Returns true if a filesystem was created on partition `id`
+    fn is_formatted(&self, id: &str) -> bool {
+        self.filesystems.iter().any(|(f, result)| f == id && result.is_ok())
+    }
+
+    /// Returns true if any step failed for partition `id`
+    fn has_failed(&self, id: &str) -> bool {
+        let failed = |(f, ok): (&String, bool)| f == id && !ok;
+        self.filesystems.iter().map(|(f, r)| (f, r.is_ok())).any(failed)
+            || self.subvolumes.iter().map(|(f, r)| (f, r.is_ok())).any(failed)
+            || self.swapfiles.iter().map(|(f, r)| (f, r.is_ok())).any(failed)
+    }
+
+    pub(crate) fn extend(&mut self, other: Finished) {
+        self.filesystems.extend(other.filesystems);
+        self.subvolumes.extend(other.subvolumes);
+        self.swapfiles.extend(other.swapfiles);
+    }
+}
+
+/// Run `f` as a named step, reporting its outcome to `progress`
+#[cfg(feature = "linux")]
+fn step<T, E: fmt::Display>(
+    progress: &dyn ProgressSink,
+    step: String,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    progress.event(Event::StepStarted(step.clone()));
+    let result = f();
+    match &result {
+        Ok(_) => progress.event(Event::StepCompleted(step)),
+        Err(e) => progress.event(Event::StepFailed {
+            step,
+            error: e.to_string(),
+        }),
+    }
+    result
+}
+
+/// The whole device in place of a partition, for plans without a partition table
+///
+/// It has number 0 and a nil GUID, as there is no table entry for it.
+#[cfg(feature = "linux")]
+fn whole_disk_partition(device: &disks::BlockDevice, tag: &PartitionTag) -> WrittenPartition {
+    WrittenPartition {
+        number: 0,
+        region: Region::new(0, device.size()).with_tag(tag.clone()),
+        device: device.device().to_owned(),
+        guid: uuid::Uuid::nil(),
+    }
+}
+
+/// Refuse to partition over a filesystem on the whole disk, unless the disk is wiped
+#[cfg(feature = "linux")]
+fn check_whole_disk_filesystem(plan: &DevicePlan<'_>, allow_in_use: bool) -> Result<(), WriteError> {
+    match plan.whole_disk_filesystem(allow_in_use) {
+        Some(kind) => {
+            let device = plan.device().device().to_owned();
+            Err(InUse {
+                usages: vec![Usage::Filesystem {
+                    device: device.clone(),
+                    kind: kind.to_string(),
+                }],
+                device,
+            }
+            .into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Tell about capacity hidden by a host protected area or device configuration overlay
+///
+/// Layouts only ever use the accessible capacity, the rest can be reclaimed with vendor
+/// or `hdparm` tools before installing.
+#[cfg(feature = "linux")]
+fn warn_hidden_capacity(name: &str, plan: &DevicePlan<'_>, progress: &dyn ProgressSink) {
+    match hpa::capacity(plan.device().device()) {
+        Ok(Some(capacity)) if capacity.hidden() > 0 => {
+            warn!("Disk {} hides part of its capacity: {:?}", name, capacity);
+            progress.event(Event::Warning(format!(
+                "Disk {name} hides {} bytes beyond its accessible {} bytes \
+                 ({} in a host protected area, {} in a device configuration overlay)",
+                capacity.hidden(),
+                capacity.accessible,
+                capacity.hpa_hidden(),
+                capacity.dco_hidden()
+            )));
+        }
+        Ok(_) => {}
+        Err(e) => debug!("Cannot read ATA capacity of disk {}: {}", name, e),
+    }
+}
+
+/// Restore a previously written disk
+#[cfg(feature = "linux")]
+fn rollback(backup: &TableBackup) -> DeviceStatus {
+    match backup.restore() {
+        Ok(()) => DeviceStatus::RolledBack,
+        Err(e) => {
+            error!("Failed to roll back {:?}: {}", backup.path(), e);
+            DeviceStatus::RestoreFailed(e)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "linux"))]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+    use test_log::test;
+
+    use super::*;
+    use crate::{Parser, Provisioner};
+
+    /// A provisioner with the strategies defined in `path` and `disk` as its only device
+    fn provisioner_for(path: &str, disk: MockDisk) -> Provisioner {
+        let test_strategies = Parser::new_for_path(path).unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(disk));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+        provisioner
+    }
+
+    #[test]
+    fn test_validation_failure_writes_nothing() {
+        let provisioner = provisioner_for("tests/use_whole_disk.kdl", MockDisk::new(150 * 1024 * 1024 * 1024));
+
+        // Mock devices have no device node, so validation must fail before any write
+        for plan in provisioner.plan() {
+            let report = plan.apply();
+            assert!(!report.is_success());
+            assert!(matches!(report.devices[0].2, DeviceStatus::Failed(WriteError::Io(_))));
+            assert!(report.devices[1..]
+                .iter()
+                .all(|(_, _, status)| matches!(status, DeviceStatus::Skipped)));
+        }
+    }
+
+    #[test]
+    fn test_progress_events() {
+        let provisioner = provisioner_for("tests/use_whole_disk.kdl", MockDisk::new(150 * 1024 * 1024 * 1024));
+
+        let plans = provisioner.plan();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        plans[0].apply_with_progress(&sender);
+        drop(sender);
+
+        let events = receiver.into_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], Event::StepStarted("Validating disk root_disk".into()));
+        assert!(matches!(&events[1], Event::StepFailed { step, .. } if step == "Validating disk root_disk"));
+    }
+
+    #[test]
+    fn test_for_each_disk() {
+        let names = ["sda", "sdb", "sdc"];
+        let (sender, receiver) = mpsc::channel();
+        let results = for_each_disk(&names, 2, &sender, |i, progress| {
+            progress.event(Event::StepStarted(format!("Writing disk {i}")));
+            i * 10
+        });
+        drop(sender);
+        assert_eq!(results, vec![0, 10, 20]);
+
+        let mut events = receiver.into_iter().collect::<Vec<_>>();
+        events.sort_by_key(|e| format!("{e:?}"));
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[1],
+            Event::Device {
+                device: "sdb".into(),
+                event: Box::new(Event::StepStarted("Writing disk 1".into())),
+            }
+        );
+
+        // One at a time, events are passed through as they are
+        let serial = |event| assert!(matches!(event, Event::StepStarted(_)));
+        let results = for_each_disk(&names, 1, &serial, |i, progress| {
+            progress.event(Event::StepStarted(format!("Writing disk {i}")));
+            i
+        });
+        assert_eq!(results, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_cancelled_before_validation() {
+        let mut provisioner = provisioner_for("tests/use_whole_disk.kdl", MockDisk::new(150 * 1024 * 1024 * 1024));
+        let cancel = partitioning::cancel::CancellationToken::new();
+        provisioner.set_cancellation(cancel.clone());
+        cancel.cancel();
+
+        let report = provisioner.plan()[0].apply();
+        let DeviceStatus::Failed(WriteError::Io(error)) = &report.devices[0].2 else {
+            panic!("unexpected status {:?}", report.devices[0].2);
+        };
+        assert!(partitioning::cancel::Cancelled::from_io(error).is_some());
+        let failures = report.into_failures();
+        assert_eq!(failures[0].code(), crate::ErrorCode::Cancelled);
+    }
+
+    #[test]
+    fn test_whole_disk_filesystem_in_use() {
+        let mut disk = MockDisk::new(150 * 1024 * 1024 * 1024);
+        disk.set_filesystem(Some(superblock::Kind::Ext4));
+        let provisioner = provisioner_for("tests/use_whole_disk.kdl", disk);
+
+        let report = provisioner.plan()[0].apply();
+        let DeviceStatus::Failed(WriteError::InUse(error)) = &report.devices[0].2 else {
+            panic!("unexpected status {:?}", report.devices[0].2);
+        };
+        assert_eq!(
+            error.to_string(),
+            "/dev/mock0 is in use: /dev/mock0 holds a ext4 filesystem without a partition table"
+        );
+    }
+}
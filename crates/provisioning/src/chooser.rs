@@ -0,0 +1,86 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Hooks for interactive installers
+//!
+//! By default the provisioner runs unattended: every combination of disks satisfying a
+//! `find-disk` search yields its own plan, and plans are applied without asking. An
+//! installer that wants to prompt the user instead can install a [`DeviceChooser`] with
+//! [`crate::Provisioner::set_chooser()`].
+
+use disks::BlockDevice;
+
+use crate::DeviceReport;
+
+/// Decides between candidate disks and confirms destructive changes
+pub trait DeviceChooser {
+    /// Choose the disks to bind to `names` when more `candidates` match than are needed
+    ///
+    /// Returns the indices of the chosen candidates, one per name and in the same order,
+    /// or `None` to plan for every combination.
+    fn choose(&self, names: &[String], candidates: &[&BlockDevice]) -> Option<Vec<usize>>;
+
+    /// Confirm that the changes to a disk, which destroy data, may be written
+    fn confirm(&self, device: &DeviceReport) -> bool;
+}
+
+/// Never asks: considers every candidate and confirms every change
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Unattended;
+
+impl DeviceChooser for Unattended {
+    fn choose(&self, _names: &[String], _candidates: &[&BlockDevice]) -> Option<Vec<usize>> {
+        None
+    }
+
+    fn confirm(&self, _device: &DeviceReport) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::mock::MockDisk;
+
+    use super::*;
+    use crate::{Parser, Provisioner};
+
+    /// Always picks the largest candidate
+    struct Largest;
+
+    impl DeviceChooser for Largest {
+        fn choose(&self, _names: &[String], candidates: &[&BlockDevice]) -> Option<Vec<usize>> {
+            let (index, _) = candidates.iter().enumerate().max_by_key(|(_, d)| d.size())?;
+            Some(vec![index])
+        }
+
+        fn confirm(&self, _device: &DeviceReport) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_chooser() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let test_strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * GIB)));
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(200 * GIB)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        // Unattended, every disk yields a plan
+        assert_eq!(provisioner.plan().len(), 4);
+
+        provisioner.set_chooser(Largest);
+        let plans = provisioner.plan();
+        assert_eq!(plans.len(), 2);
+        assert!(plans
+            .iter()
+            .all(|p| p.device_assignments["root_disk"].device().size() == 200 * GIB));
+    }
+}
@@ -5,16 +5,94 @@
 
 use crate::Context;
 
+mod adjust_partition;
+mod create_filesystem;
+mod create_logical_volume;
+mod create_luks;
 mod create_partition;
 mod create_partition_table;
+mod create_raid;
+mod create_subvolumes;
+mod create_swapfile;
+mod create_volume_group;
 mod find_disk;
+mod find_disks;
+mod format_whole_disk;
+mod remove_partition;
+mod when;
+mod wipe_disk;
 
 /// A command
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Command {
+    AdjustPartition(Box<adjust_partition::Command>),
+    CreateFilesystem(Box<create_filesystem::Command>),
+    CreateLogicalVolume(Box<create_logical_volume::Command>),
+    CreateLuks(Box<create_luks::Command>),
     CreatePartition(Box<create_partition::Command>),
     CreatePartitionTable(Box<create_partition_table::Command>),
+    CreateRaid(Box<create_raid::Command>),
+    CreateSubvolumes(Box<create_subvolumes::Command>),
+    CreateSwapfile(Box<create_swapfile::Command>),
+    CreateVolumeGroup(Box<create_volume_group::Command>),
     FindDisk(Box<find_disk::Command>),
+    FindDisks(Box<find_disks::Command>),
+    FormatWholeDisk(Box<format_whole_disk::Command>),
+    RemovePartition(Box<remove_partition::Command>),
+    When(Box<when::Command>),
+    WipeDisk(Box<wipe_disk::Command>),
+}
+
+impl Command {
+    /// Identity of the command when strategies inherit from each other
+    ///
+    /// A command replaces any earlier command with the same key, keeping its position.
+    pub(crate) fn key(&self) -> Option<(&'static str, String)> {
+        match self {
+            Command::CreateFilesystem(c) => Some(("filesystem", c.partition.clone())),
+            Command::CreateLogicalVolume(c) => Some(("logical-volume", format!("{}/{}", c.group, c.name))),
+            Command::CreateLuks(c) => Some(("luks", c.partition.clone())),
+            Command::CreatePartition(c) => Some(("partition", c.id.clone())),
+            Command::CreatePartitionTable(c) => Some(("partition-table", c.disk.clone())),
+            Command::CreateRaid(c) => Some(("raid", c.array.name.clone())),
+            Command::CreateSubvolumes(c) => Some(("subvolumes", c.partition.clone())),
+            Command::CreateSwapfile(c) => Some(("swapfile", format!("{}/{}", c.partition, c.path))),
+            Command::CreateVolumeGroup(c) => Some(("volume-group", c.name.clone())),
+            Command::FindDisk(c) => Some(("disk", c.name.clone())),
+            Command::FindDisks(c) => Some(("disks", c.name.clone())),
+            Command::FormatWholeDisk(c) => Some(("partition", c.id.clone())),
+            Command::WipeDisk(c) => Some(("wipe", c.disk.clone())),
+            Command::AdjustPartition(_) | Command::RemovePartition(_) | Command::When(_) => None,
+        }
+    }
+
+    /// The partition a command creates or refers to, if any
+    pub(crate) fn partition(&self) -> Option<&str> {
+        match self {
+            Command::CreateFilesystem(c) => Some(&c.partition),
+            Command::CreateLuks(c) => Some(&c.partition),
+            Command::CreatePartition(c) => Some(&c.id),
+            Command::FormatWholeDisk(c) => Some(&c.id),
+            Command::CreateSubvolumes(c) => Some(&c.partition),
+            Command::CreateSwapfile(c) => Some(&c.partition),
+            _ => None,
+        }
+    }
+
+    /// Why the command cannot be executed yet, with advice for the strategy author
+    fn unsupported(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Command::CreateLuks(_) => Some((
+                "creating LUKS2 volumes is not supported yet",
+                "remove create-luks and encrypt the partition after installation",
+            )),
+            Command::CreateRaid(_) => Some((
+                "creating RAID arrays is not supported yet",
+                "assemble the array before installation and find it with find-disk",
+            )),
+            _ => None,
+        }
+    }
 }
 
 /// Command execution function
@@ -23,17 +101,43 @@ type CommandExec = for<'a> fn(Context<'a>) -> Result<Command, crate::Error>;
 /// Map of command names to functions
 static COMMANDS: phf::Map<&'static str, CommandExec> = phf::phf_map! {
     "find-disk" => find_disk::parse,
+    "find-disks" => find_disks::parse,
     "create-partition" => create_partition::parse,
     "create-partition-table" => create_partition_table::parse,
+    "format-whole-disk" => format_whole_disk::parse,
+    "create-filesystem" => create_filesystem::parse,
+    "create-luks" => create_luks::parse,
+    "create-volume-group" => create_volume_group::parse,
+    "create-logical-volume" => create_logical_volume::parse,
+    "create-raid" => create_raid::parse,
+    "create-subvolumes" => create_subvolumes::parse,
+    "create-swapfile" => create_swapfile::parse,
+    "adjust-partition" => adjust_partition::parse,
+    "remove-partition" => remove_partition::parse,
+    "when" => when::parse,
+    "wipe-disk" => wipe_disk::parse,
 };
 
 /// Parse a command from a node if possible
 pub(crate) fn parse_command(context: Context<'_>) -> Result<Command, crate::Error> {
-    let name = context.node.name().value();
+    let node = context.node;
+    let name = node.name().value();
     let func = COMMANDS.get(name).ok_or_else(|| crate::UnsupportedNode {
-        at: context.node.span(),
+        at: node.span(),
         name: name.into(),
     })?;
 
-    func(context)
+    let command = func(context)?;
+
+    // Refuse up front rather than planning something that cannot be applied
+    if let Some((message, advice)) = command.unsupported() {
+        return Err(crate::Invalid {
+            at: node.span(),
+            message: message.into(),
+            advice: Some(advice.into()),
+        }
+        .into());
+    }
+
+    Ok(command)
 }
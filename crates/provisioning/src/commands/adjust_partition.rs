@@ -0,0 +1,50 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{get_kdl_property, get_property_str, Constraints, Context};
+
+/// Command to adjust a partition inherited from a parent strategy
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The reference ID of the partition to adjust
+    pub id: String,
+
+    /// New mount point, if changed
+    pub mountpoint: Option<String>,
+
+    /// New size constraints, if changed
+    pub constraints: Option<Constraints>,
+}
+
+/// Generate a command to adjust a partition
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let id = get_property_str(context.node, "id")?;
+    let mountpoint = if context.node.entry("mountpoint").is_some() {
+        let mountpoint = get_property_str(context.node, "mountpoint")?;
+        if !mountpoint.starts_with('/') {
+            return Err(crate::UnsupportedValue {
+                at: get_kdl_property(context.node, "mountpoint")?.span(),
+                advice: Some("mount points must be absolute paths".into()),
+            }
+            .into());
+        }
+        Some(mountpoint)
+    } else {
+        None
+    };
+
+    let constraints = context
+        .node
+        .iter_children()
+        .find(|n| n.name().value() == "constraints")
+        .map(Constraints::from_kdl_node)
+        .transpose()?;
+
+    Ok(super::Command::AdjustPartition(Box::new(Command {
+        id,
+        mountpoint,
+        constraints,
+    })))
+}
@@ -0,0 +1,50 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{get_kdl_property, get_property_str, kdl_value_to_string, Context, FilesystemType, FromKdlProperty};
+
+/// Command to create a filesystem on a partition
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The reference ID of the partition to format
+    pub partition: String,
+
+    /// The filesystem to create
+    pub filesystem: FilesystemType,
+
+    /// The filesystem label, if any
+    pub label: Option<String>,
+
+    /// Extra arguments passed to the mkfs tool
+    pub options: Vec<String>,
+}
+
+/// Generate a command to create a filesystem
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let partition = get_property_str(context.node, "partition")?;
+    let filesystem = FilesystemType::from_kdl_property(get_kdl_property(context.node, "type")?)?;
+    let label = if context.node.entry("label").is_some() {
+        Some(get_property_str(context.node, "label")?)
+    } else {
+        None
+    };
+
+    let options = match context.node.iter_children().find(|n| n.name().value() == "options") {
+        Some(node) => node
+            .entries()
+            .iter()
+            .filter(|e| e.name().is_none())
+            .map(kdl_value_to_string)
+            .collect::<Result<_, _>>()?,
+        None => vec![],
+    };
+
+    Ok(super::Command::CreateFilesystem(Box::new(Command {
+        partition,
+        filesystem,
+        label,
+        options,
+    })))
+}
@@ -0,0 +1,37 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{get_property_str, Constraints, Context};
+
+/// Command to create an LVM logical volume
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The volume group to create the logical volume in
+    pub group: String,
+
+    /// Name of the logical volume
+    pub name: String,
+
+    pub constraints: Constraints,
+}
+
+/// Generate a command to create a logical volume
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let group = get_property_str(context.node, "group")?;
+    let name = get_property_str(context.node, "name")?;
+
+    let constraints =
+        if let Some(constraints) = context.node.iter_children().find(|n| n.name().value() == "constraints") {
+            Constraints::from_kdl_node(constraints)?
+        } else {
+            return Err(crate::Error::MissingNode("constraints"));
+        };
+
+    Ok(super::Command::CreateLogicalVolume(Box::new(Command {
+        group,
+        name,
+        constraints,
+    })))
+}
@@ -0,0 +1,128 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use kdl::KdlNode;
+
+use crate::{
+    get_kdl_entry, get_property_str, kdl_value_to_bool, kdl_value_to_integer, kdl_value_to_storage_size,
+    kdl_value_to_string, Context, Enrollment, FromKdlProperty, KeySource, Luks, Pbkdf, PbkdfType,
+};
+
+/// Default cipher, matching cryptsetup
+const DEFAULT_CIPHER: &str = "aes-xts-plain64";
+
+/// Default key size in bits, matching cryptsetup
+const DEFAULT_KEY_SIZE: u32 = 512;
+
+/// Command to wrap a partition in LUKS2
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The reference ID of the partition to encrypt
+    pub partition: String,
+
+    /// Encryption settings
+    pub luks: Luks,
+}
+
+/// Generate a command to create a LUKS2 volume
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let node = context.node;
+    let partition = get_property_str(node, "partition")?;
+    let name = get_property_str(node, "name")?;
+    let cipher = if node.entry("cipher").is_some() {
+        get_property_str(node, "cipher")?
+    } else {
+        DEFAULT_CIPHER.to_owned()
+    };
+    let key_size = match node.entry("key-size") {
+        Some(entry) => kdl_value_to_integer(entry)? as u32,
+        None => DEFAULT_KEY_SIZE,
+    };
+    // `tpm=#true` is shorthand for enrolling the TPM2 with the default PCRs
+    let mut enroll = match node.entry("tpm") {
+        Some(entry) if kdl_value_to_bool(entry)? => vec![Enrollment::Tpm2 { pcrs: vec![] }],
+        _ => vec![],
+    };
+    for child in node.iter_children().filter(|n| n.name().value() == "enroll") {
+        enroll.push(parse_enroll(child)?);
+    }
+
+    let pbkdf = match node.iter_children().find(|n| n.name().value() == "pbkdf") {
+        Some(pbkdf) => parse_pbkdf(pbkdf)?,
+        None => Pbkdf::default(),
+    };
+    let key = match node.iter_children().find(|n| n.name().value() == "key") {
+        Some(key) => parse_key(key)?,
+        None => KeySource::default(),
+    };
+
+    Ok(super::Command::CreateLuks(Box::new(Command {
+        partition,
+        luks: Luks {
+            name,
+            cipher,
+            key_size,
+            pbkdf,
+            key,
+            enroll,
+        },
+    })))
+}
+
+// Parse `pbkdf "argon2id" memory=(MIB)1024 iterations=4`
+fn parse_pbkdf(node: &KdlNode) -> Result<Pbkdf, crate::Error> {
+    let kind = PbkdfType::from_kdl_property(get_kdl_entry(node, &0)?)?;
+    let memory = node.entry("memory").map(kdl_value_to_storage_size).transpose()?;
+    let iterations = node
+        .entry("iterations")
+        .map(|e| kdl_value_to_integer(e).map(|v| v as u32))
+        .transpose()?;
+    Ok(Pbkdf {
+        kind,
+        memory,
+        iterations,
+    })
+}
+
+// Parse `enroll "tpm2" pcrs="0+7"` or `enroll "fido2"`
+fn parse_enroll(node: &KdlNode) -> Result<Enrollment, crate::Error> {
+    let entry = get_kdl_entry(node, &0)?;
+    match kdl_value_to_string(entry)?.as_str() {
+        "tpm2" => {
+            let pcrs = match node.entry("pcrs") {
+                Some(pcrs) => kdl_value_to_string(pcrs)?
+                    .split('+')
+                    .map(|pcr| pcr.trim().parse::<u32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| crate::UnsupportedValue {
+                        at: pcrs.span(),
+                        advice: Some("PCRs are numbers separated by '+', e.g. \"0+7\"".into()),
+                    })?,
+                None => vec![],
+            };
+            Ok(Enrollment::Tpm2 { pcrs })
+        }
+        "fido2" => Ok(Enrollment::Fido2),
+        _ => Err(crate::UnsupportedValue {
+            at: entry.span(),
+            advice: Some("'tpm2' and 'fido2' are supported".into()),
+        }
+        .into()),
+    }
+}
+
+// Parse `key "prompt"` or `key "keyfile" path="..."`
+fn parse_key(node: &KdlNode) -> Result<KeySource, crate::Error> {
+    let entry = get_kdl_entry(node, &0)?;
+    match kdl_value_to_string(entry)?.as_str() {
+        "prompt" => Ok(KeySource::Prompt),
+        "keyfile" => Ok(KeySource::Keyfile(get_property_str(node, "path")?.into())),
+        _ => Err(crate::UnsupportedValue {
+            at: entry.span(),
+            advice: Some("'prompt' and 'keyfile' are supported".into()),
+        }
+        .into()),
+    }
+}
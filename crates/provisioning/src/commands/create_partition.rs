@@ -3,10 +3,15 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::{get_kdl_property, get_property_str, Constraints, Context, FromKdlProperty, PartitionRole};
+use disks::flags::PartitionFlags;
+use miette::SourceSpan;
+
+use crate::{
+    get_kdl_property, get_property_str, kdl_value_to_string, Constraints, Context, FromKdlProperty, PartitionRole,
+};
 
 /// Command to create a partition
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Command {
     /// The disk ID to create the partition on
     pub disk: String,
@@ -17,7 +22,16 @@ pub struct Command {
     /// The role, if any, of the partition
     pub role: Option<PartitionRole>,
 
+    /// Where the partition is mounted, defaulting to the mount point of the role
+    pub mountpoint: Option<String>,
+
     pub constraints: Constraints,
+
+    /// Flags set in addition to the defaults of the role
+    pub flags: PartitionFlags,
+
+    /// Location of the command in the strategy source
+    pub span: SourceSpan,
 }
 
 /// Generate a command to create a partition
@@ -29,6 +43,19 @@ pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error
     } else {
         None
     };
+    let mountpoint = if context.node.entry("mountpoint").is_some() {
+        let mountpoint = get_property_str(context.node, "mountpoint")?;
+        if !mountpoint.starts_with('/') {
+            return Err(crate::UnsupportedValue {
+                at: get_kdl_property(context.node, "mountpoint")?.span(),
+                advice: Some("mount points must be absolute paths".into()),
+            }
+            .into());
+        }
+        Some(mountpoint)
+    } else {
+        role.as_ref().and_then(PartitionRole::mountpoint).map(str::to_owned)
+    };
 
     let constraints =
         if let Some(constraints) = context.node.iter_children().find(|n| n.name().value() == "constraints") {
@@ -37,11 +64,29 @@ pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error
             return Err(crate::Error::MissingNode("constraints"));
         };
 
+    let mut flags = PartitionFlags::NONE;
+    if let Some(node) = context.node.iter_children().find(|n| n.name().value() == "flags") {
+        for entry in node.entries().iter().filter(|e| e.name().is_none()) {
+            flags |= kdl_value_to_string(entry)?
+                .parse::<PartitionFlags>()
+                .map_err(|_| crate::UnsupportedValue {
+                    at: entry.span(),
+                    advice: Some(
+                        "'bootable', 'required', 'growfs', 'read-only', 'hidden' and 'no-automount' are supported"
+                            .into(),
+                    ),
+                })?;
+        }
+    }
+
     // TODO: Load constraints etc
     Ok(super::Command::CreatePartition(Box::new(Command {
         disk,
         id,
         role,
+        mountpoint,
         constraints,
+        flags,
+        span: context.node.span(),
     })))
 }
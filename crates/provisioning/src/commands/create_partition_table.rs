@@ -2,15 +2,19 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
+use miette::SourceSpan;
+
 use crate::{get_kdl_property, FromKdlProperty, PartitionTableType};
 use crate::{get_property_str, Context};
 
 /// Command to create a partition table
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Command {
     /// The type of partition table to create
     pub table_type: PartitionTableType,
     pub disk: String,
+    /// Location of the command in the strategy source
+    pub span: SourceSpan,
 }
 
 /// Generate a command to create a partition table
@@ -22,5 +26,6 @@ pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error
     Ok(super::Command::CreatePartitionTable(Box::new(Command {
         table_type,
         disk,
+        span: context.node.span(),
     })))
 }
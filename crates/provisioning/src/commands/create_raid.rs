@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{
+    get_kdl_property, get_property_str, kdl_value_to_integer, kdl_value_to_string, Context, FromKdlProperty, RaidArray,
+    RaidLevel,
+};
+
+/// Command to create an mdraid array
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The array to create, members may name a set of disks from `find-disks`
+    pub array: RaidArray,
+}
+
+/// Generate a command to create an mdraid array
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let name = get_property_str(context.node, "name")?;
+    let level = RaidLevel::from_kdl_property(get_kdl_property(context.node, "level")?)?;
+    let spares = match context.node.entry("spares") {
+        Some(entry) => kdl_value_to_integer(entry)? as usize,
+        None => 0,
+    };
+
+    let members: Vec<String> = match context.node.iter_children().find(|n| n.name().value() == "members") {
+        Some(node) => node
+            .entries()
+            .iter()
+            .filter(|e| e.name().is_none())
+            .map(kdl_value_to_string)
+            .collect::<Result<_, _>>()?,
+        None => return Err(crate::Error::MissingNode("members")),
+    };
+
+    Ok(super::Command::CreateRaid(Box::new(Command {
+        array: RaidArray {
+            name,
+            level,
+            members,
+            spares,
+        },
+    })))
+}
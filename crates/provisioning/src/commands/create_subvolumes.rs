@@ -0,0 +1,50 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use partitioning::btrfs::{Subvolume, SubvolumeLayout};
+
+use crate::{get_kdl_entry, get_property_str, kdl_value_to_bool, kdl_value_to_string, Context};
+
+/// Command to create btrfs subvolumes on a partition
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The reference ID of the btrfs partition
+    pub partition: String,
+
+    /// The subvolumes to create, in creation order
+    pub layout: SubvolumeLayout,
+}
+
+/// Generate a command to create btrfs subvolumes
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let partition = get_property_str(context.node, "partition")?;
+
+    let mut layout = SubvolumeLayout::new();
+    for node in context.node.iter_children().filter(|n| n.name().value() == "subvolume") {
+        let mut subvolume = Subvolume::new(kdl_value_to_string(get_kdl_entry(node, &0)?)?);
+        if let Some(entry) = node.entry("default") {
+            if kdl_value_to_bool(entry)? {
+                subvolume = subvolume.as_default();
+            }
+        }
+        if node.entry("mountpoint").is_some() {
+            subvolume = subvolume.with_mountpoint(get_property_str(node, "mountpoint")?);
+        }
+        layout.add(subvolume);
+    }
+
+    if layout.subvolumes().is_empty() {
+        return Err(crate::Error::MissingNode("subvolume"));
+    }
+    layout.validate().map_err(|e| crate::InvalidArguments {
+        at: context.node.span(),
+        advice: Some(e.to_string()),
+    })?;
+
+    Ok(super::Command::CreateSubvolumes(Box::new(Command {
+        partition,
+        layout,
+    })))
+}
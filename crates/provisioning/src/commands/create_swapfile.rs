@@ -0,0 +1,47 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use partitioning::swapfile::Swapfile;
+
+use crate::{get_kdl_property, get_property_str, kdl_value_to_storage_size, Context, Expression};
+
+/// Command to create a swapfile on a formatted partition
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The reference ID of the partition holding the swapfile
+    pub partition: String,
+
+    /// Path of the swapfile relative to the filesystem root
+    pub path: String,
+
+    /// Size of the swapfile, e.g. `(GIB)8` or `"ram"`
+    pub size: Expression,
+}
+
+/// Generate a command to create a swapfile
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let partition = get_property_str(context.node, "partition")?;
+    let path = get_property_str(context.node, "path")?;
+
+    let entry = get_kdl_property(context.node, "size")?;
+    let size = match entry.value().is_string() {
+        true => Expression::from_kdl_entry(entry)?,
+        false => Expression::Value(kdl_value_to_storage_size(entry)?),
+    };
+
+    // Sizes are checked once the expression has been evaluated
+    Swapfile::new(&path, u64::MAX)
+        .validate()
+        .map_err(|e| crate::InvalidArguments {
+            at: context.node.span(),
+            advice: Some(e.to_string()),
+        })?;
+
+    Ok(super::Command::CreateSwapfile(Box::new(Command {
+        partition,
+        path,
+        size,
+    })))
+}
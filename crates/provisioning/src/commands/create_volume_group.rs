@@ -0,0 +1,48 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{get_property_str, kdl_value_to_string, Context};
+
+/// Command to create an LVM volume group
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// Name of the volume group
+    pub name: String,
+
+    /// Reference IDs of the partitions to use as physical volumes
+    pub physical_volumes: Vec<String>,
+}
+
+/// Generate a command to create a volume group
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let name = get_property_str(context.node, "name")?;
+
+    let physical_volumes: Vec<String> = match context
+        .node
+        .iter_children()
+        .find(|n| n.name().value() == "physical-volumes")
+    {
+        Some(node) => node
+            .entries()
+            .iter()
+            .filter(|e| e.name().is_none())
+            .map(kdl_value_to_string)
+            .collect::<Result<_, _>>()?,
+        None => return Err(crate::Error::MissingNode("physical-volumes")),
+    };
+
+    if physical_volumes.is_empty() {
+        return Err(crate::InvalidArguments {
+            at: context.node.span(),
+            advice: Some("physical-volumes <id>... - at least one partition id is required".into()),
+        }
+        .into());
+    }
+
+    Ok(super::Command::CreateVolumeGroup(Box::new(Command {
+        name,
+        physical_volumes,
+    })))
+}
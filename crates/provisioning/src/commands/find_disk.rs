@@ -3,13 +3,22 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use itertools::Itertools;
+use miette::SourceSpan;
 
-use crate::{Constraints, Context};
+use crate::{get_kdl_entry, kdl_value_to_storage_size, Constraints, Context, Exclusions};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Command {
     pub name: String,
     pub constraints: Option<Constraints>,
+
+    /// Disks that may not be selected
+    pub exclusions: Exclusions,
+
+    /// Bytes set aside before the size constraints are checked
+    pub reserve: u64,
+    /// Location of the command in the strategy source
+    pub span: SourceSpan,
 }
 
 /// Generate a command to find a disk
@@ -49,8 +58,20 @@ pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error
             None
         };
 
+    let exclusions = match context.node.iter_children().find(|n| n.name().value() == "exclude") {
+        Some(node) => Exclusions::from_kdl_node(node)?,
+        None => Exclusions::default(),
+    };
+    let reserve = match context.node.iter_children().find(|n| n.name().value() == "reserve") {
+        Some(node) => kdl_value_to_storage_size(get_kdl_entry(node, &0)?)?,
+        None => 0,
+    };
+
     Ok(super::Command::FindDisk(Box::new(Command {
         name: name.to_owned(),
         constraints,
+        exclusions,
+        reserve,
+        span: context.node.span(),
     })))
 }
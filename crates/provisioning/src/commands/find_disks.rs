@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use itertools::Itertools;
+use miette::SourceSpan;
+
+use crate::{
+    get_kdl_entry, get_kdl_property, kdl_value_to_integer, kdl_value_to_storage_size, Constraints, Context, Exclusions,
+};
+
+/// Command to find a set of disks
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// Name of the set, each disk is bound as `<name>.<index>`
+    pub name: String,
+
+    /// Number of disks in the set
+    pub count: usize,
+
+    /// Constraints every disk in the set must satisfy
+    pub constraints: Option<Constraints>,
+
+    /// Disks that may not be selected
+    pub exclusions: Exclusions,
+
+    /// Bytes set aside before the size constraints are checked
+    pub reserve: u64,
+    /// Location of the command in the strategy source
+    pub span: SourceSpan,
+}
+
+impl Command {
+    /// The names the disks of this set are bound to
+    pub fn disk_names(&self) -> Vec<String> {
+        (0..self.count).map(|i| format!("{}.{}", self.name, i)).collect()
+    }
+}
+
+/// Generate a command to find a set of disks
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let arguments = context
+        .node
+        .entries()
+        .iter()
+        .filter(|e| e.is_empty() || e.name().is_none())
+        .collect_vec();
+
+    let name = match arguments.len() {
+        1 => arguments[0].value().as_string().ok_or(crate::InvalidType {
+            at: arguments[0].span(),
+            expected_type: crate::KdlType::String,
+        })?,
+        _ => {
+            return Err(crate::InvalidArguments {
+                at: context.node.span(),
+                advice: Some("find-disks <name> count=<n> - you must provide a single name for the set".into()),
+            }
+            .into())
+        }
+    };
+
+    let count_entry = get_kdl_property(context.node, "count")?;
+    let count = kdl_value_to_integer(count_entry)?;
+    if count < 1 {
+        return Err(crate::UnsupportedValue {
+            at: count_entry.span(),
+            advice: Some("at least one disk must be requested".into()),
+        }
+        .into());
+    }
+
+    let constraints =
+        if let Some(constraints) = context.node.iter_children().find(|n| n.name().value() == "constraints") {
+            Some(Constraints::from_kdl_node(constraints)?)
+        } else {
+            None
+        };
+
+    let exclusions = match context.node.iter_children().find(|n| n.name().value() == "exclude") {
+        Some(node) => Exclusions::from_kdl_node(node)?,
+        None => Exclusions::default(),
+    };
+    let reserve = match context.node.iter_children().find(|n| n.name().value() == "reserve") {
+        Some(node) => kdl_value_to_storage_size(get_kdl_entry(node, &0)?)?,
+        None => 0,
+    };
+
+    Ok(super::Command::FindDisks(Box::new(Command {
+        name: name.to_owned(),
+        count: count as usize,
+        constraints,
+        exclusions,
+        reserve,
+        span: context.node.span(),
+    })))
+}
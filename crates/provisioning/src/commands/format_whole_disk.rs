@@ -0,0 +1,62 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use miette::SourceSpan;
+
+use crate::{get_kdl_property, get_property_str, Context, FromKdlProperty, PartitionRole};
+
+/// Command to use a whole disk without a partition table
+///
+/// The disk is referred to by `id` wherever commands expect a partition, so it is
+/// formatted with `create-filesystem`.
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The disk ID to use
+    pub disk: String,
+
+    /// The reference ID of the volume
+    pub id: String,
+
+    /// The role, if any, of the volume
+    pub role: Option<PartitionRole>,
+
+    /// Where the volume is mounted, defaulting to the mount point of the role
+    pub mountpoint: Option<String>,
+
+    /// Location of the command in the strategy source
+    pub span: SourceSpan,
+}
+
+/// Generate a command to format a whole disk
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let disk = get_property_str(context.node, "disk")?;
+    let id = get_property_str(context.node, "id")?;
+    let role = if let Ok(role) = get_kdl_property(context.node, "role") {
+        Some(PartitionRole::from_kdl_property(role)?)
+    } else {
+        None
+    };
+    let mountpoint = if context.node.entry("mountpoint").is_some() {
+        let mountpoint = get_property_str(context.node, "mountpoint")?;
+        if !mountpoint.starts_with('/') {
+            return Err(crate::UnsupportedValue {
+                at: get_kdl_property(context.node, "mountpoint")?.span(),
+                advice: Some("mount points must be absolute paths".into()),
+            }
+            .into());
+        }
+        Some(mountpoint)
+    } else {
+        role.as_ref().and_then(PartitionRole::mountpoint).map(str::to_owned)
+    };
+
+    Ok(super::Command::FormatWholeDisk(Box::new(Command {
+        disk,
+        id,
+        role,
+        mountpoint,
+        span: context.node.span(),
+    })))
+}
@@ -0,0 +1,21 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{get_property_str, Context};
+
+/// Command to remove a partition inherited from a parent strategy
+///
+/// Any filesystem, encryption or subvolumes requested for the partition are removed too.
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The reference ID of the partition to remove
+    pub id: String,
+}
+
+/// Generate a command to remove a partition
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let id = get_property_str(context.node, "id")?;
+    Ok(super::Command::RemovePartition(Box::new(Command { id })))
+}
@@ -0,0 +1,28 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{Condition, Context};
+
+/// Commands that only apply when a condition on the system facts holds
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The condition to check
+    pub condition: Condition,
+
+    /// Commands to evaluate if the condition holds
+    pub commands: Vec<super::Command>,
+}
+
+/// Generate a conditional block of commands
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let condition = Condition::from_kdl_node(context.node)?;
+    let commands = context
+        .node
+        .iter_children()
+        .map(|node| super::parse_command(Context { node }))
+        .collect::<Result<_, _>>()?;
+
+    Ok(super::Command::When(Box::new(Command { condition, commands })))
+}
@@ -0,0 +1,35 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use miette::SourceSpan;
+
+use crate::{get_property_str, Context, ErasePolicy, FromKdlProperty};
+
+/// Command to declare how a disk is erased before partitioning
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The disk to erase
+    pub disk: String,
+
+    /// How thoroughly to erase it, defaulting to removing signatures
+    pub policy: ErasePolicy,
+    /// Location of the command in the strategy source
+    pub span: SourceSpan,
+}
+
+/// Generate a command to wipe a disk
+pub(crate) fn parse(context: Context<'_>) -> Result<super::Command, crate::Error> {
+    let disk = get_property_str(context.node, "disk")?;
+    let policy = match context.node.entry("policy") {
+        Some(entry) => ErasePolicy::from_kdl_property(entry)?,
+        None => ErasePolicy::default(),
+    };
+
+    Ok(super::Command::WipeDisk(Box::new(Command {
+        disk,
+        policy,
+        span: context.node.span(),
+    })))
+}
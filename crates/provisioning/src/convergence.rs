@@ -0,0 +1,332 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Checking whether a plan has already been applied
+//!
+//! Re-running provisioning on an installed machine should leave it alone rather than
+//! repartition it. [`Provisioner::is_satisfied()`] compares a plan against the current
+//! state of each disk, as read by [`DiskState::read()`], and only reports success when
+//! every planned partition exists at the planned location with the planned partition
+//! type and filesystem.
+//!
+//! Swap areas carry no superblock we can detect, so their filesystem is not verified.
+
+use std::{fs::File, io, path::PathBuf};
+
+use disks::BlockDevice;
+use partitioning::{
+    format::FilesystemType,
+    gpt::GptConfig,
+    planner::{Region, TableType},
+};
+use superblock::{Kind, Superblock};
+use thiserror::Error;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{DevicePlan, Plan, Provisioner};
+
+/// Size of a logical block in bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// A partition currently present on a disk
+#[derive(Debug, Clone)]
+pub struct PartitionState {
+    /// Partition number within the table
+    pub number: u32,
+    /// Start of the partition in bytes
+    pub start: u64,
+    /// End of the partition in bytes (exclusive)
+    pub end: u64,
+    /// GPT partition type
+    pub partition_type: Uuid,
+    /// Filesystem detected on the partition, if any
+    pub filesystem: Option<Kind>,
+}
+
+/// The current layout of a disk
+#[derive(Debug, Clone)]
+pub struct DiskState {
+    /// Device node of the disk
+    pub device: PathBuf,
+    /// Partition table found on the disk, if any
+    pub table: Option<TableType>,
+    /// Partitions in the table
+    pub partitions: Vec<PartitionState>,
+}
+
+impl DiskState {
+    /// Read the partition table of `device` and probe each partition for a filesystem
+    pub fn read(device: &BlockDevice) -> io::Result<Self> {
+        let file = File::open(device.device())?;
+        let table = match GptConfig::new().writable(false).open_from_device(file) {
+            Ok(table) => table,
+            Err(e) => {
+                debug!("No GPT partition table on {:?}: {}", device.device(), e);
+                return Ok(Self {
+                    device: device.device().to_owned(),
+                    table: None,
+                    partitions: vec![],
+                });
+            }
+        };
+
+        let partitions = table
+            .partitions()
+            .iter()
+            .map(|(number, partition)| {
+                let filesystem = device
+                    .partitions()
+                    .iter()
+                    .find(|p| p.number == *number)
+                    .and_then(|p| File::open(&p.device).ok())
+                    .and_then(|mut f| Superblock::from_reader(&mut f).ok())
+                    .map(|sb| sb.kind());
+                PartitionState {
+                    number: *number,
+                    start: partition.first_lba * SECTOR_SIZE,
+                    end: (partition.last_lba + 1) * SECTOR_SIZE,
+                    partition_type: partition.part_type_guid.guid,
+                    filesystem,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            device: device.device().to_owned(),
+            table: Some(TableType::Gpt),
+            partitions,
+        })
+    }
+}
+
+/// A difference between the current state of a disk and a plan
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Mismatch {
+    /// The state of a planned disk was not supplied
+    #[error("no state for disk {disk}")]
+    UnknownDisk { disk: String },
+    /// The disk lacks the planned partition table
+    #[error("disk {disk} does not have a {expected:?} partition table")]
+    Table { disk: String, expected: TableType },
+    /// No partition exists at the planned location
+    #[error("partition {id} is missing from disk {disk}")]
+    MissingPartition { disk: String, id: String },
+    /// The partition exists with a different type
+    #[error("partition {id} on disk {disk} has type {found}, expected {expected}")]
+    PartitionType {
+        disk: String,
+        id: String,
+        expected: Uuid,
+        found: Uuid,
+    },
+    /// The partition holds a different filesystem, or none at all
+    #[error("partition {id} on disk {disk} has filesystem {}, expected {expected}", found.as_ref().map_or("none".into(), Kind::to_string))]
+    Filesystem {
+        disk: String,
+        id: String,
+        expected: Kind,
+        found: Option<Kind>,
+    },
+    /// A partition exists that a fresh table would not contain
+    #[error("disk {disk} has unplanned partition {number}")]
+    UnexpectedPartition { disk: String, number: u32 },
+}
+
+impl Provisioner {
+    /// Returns true if the disks already match the target layout of the plan
+    ///
+    /// Applying a satisfied plan would only destroy data, so callers should treat this as
+    /// a no-op. See [`Provisioner::mismatches()`] for the reasons a plan is not satisfied.
+    pub fn is_satisfied(&self, plan: &Plan<'_>, devices: &[DiskState]) -> bool {
+        let mismatches = self.mismatches(plan, devices);
+        for mismatch in &mismatches {
+            info!("Plan {} not satisfied: {}", plan.strategy.name, mismatch);
+        }
+        mismatches.is_empty()
+    }
+
+    /// List every way in which the disks differ from the target layout of the plan
+    pub fn mismatches(&self, plan: &Plan<'_>, devices: &[DiskState]) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+
+        // Arrays and their members are never written, so there is nothing to compare
+        let assignments = plan
+            .device_assignments
+            .iter()
+            .filter(|(_, p)| p.array().is_none() && p.member_of().is_none());
+        for (disk, device_plan) in assignments {
+            match devices.iter().find(|d| d.device == device_plan.device().device()) {
+                Some(state) => mismatches.extend(compare(disk, device_plan, state)),
+                None => mismatches.push(Mismatch::UnknownDisk { disk: disk.clone() }),
+            }
+        }
+
+        mismatches.sort_by_key(|m| m.to_string());
+        mismatches
+    }
+}
+
+/// Compare a single device plan with the current state of its disk
+fn compare(disk: &str, device_plan: &DevicePlan<'_>, state: &DiskState) -> Vec<Mismatch> {
+    let planner = device_plan.planner();
+    let mut mismatches = vec![];
+
+    if planner.creates_new_table() {
+        let expected = planner.table().unwrap_or(TableType::Gpt);
+        if state.table != Some(expected) {
+            return vec![Mismatch::Table {
+                disk: disk.to_owned(),
+                expected,
+            }];
+        }
+    }
+
+    let layout = planner.current_layout();
+    let find = |region: &Region| {
+        state
+            .partitions
+            .iter()
+            .find(|p| p.start == region.start && p.end == region.end)
+    };
+
+    for region in &layout {
+        let Some(tag) = region.tag() else {
+            continue;
+        };
+        let id = tag.id.clone().unwrap_or_else(|| format!("at {}", region.start));
+        let Some(partition) = find(region) else {
+            mismatches.push(Mismatch::MissingPartition {
+                disk: disk.to_owned(),
+                id,
+            });
+            continue;
+        };
+
+        if let Some(expected) = tag.partition_type.filter(|t| *t != partition.partition_type) {
+            mismatches.push(Mismatch::PartitionType {
+                disk: disk.to_owned(),
+                id: id.clone(),
+                expected,
+                found: partition.partition_type,
+            });
+        }
+
+        if let Some(expected) = expected_filesystem(device_plan, &id) {
+            if partition.filesystem.as_ref() != Some(&expected) {
+                mismatches.push(Mismatch::Filesystem {
+                    disk: disk.to_owned(),
+                    id,
+                    expected,
+                    found: partition.filesystem.clone(),
+                });
+            }
+        }
+    }
+
+    // A new table holds only the planned partitions
+    if planner.creates_new_table() {
+        for partition in &state.partitions {
+            if !layout
+                .iter()
+                .any(|r| r.start == partition.start && r.end == partition.end)
+            {
+                mismatches.push(Mismatch::UnexpectedPartition {
+                    disk: disk.to_owned(),
+                    number: partition.number,
+                });
+            }
+        }
+    }
+
+    mismatches
+}
+
+/// The superblock expected on partition `id`, if it can be detected
+fn expected_filesystem(device_plan: &DevicePlan<'_>, id: &str) -> Option<Kind> {
+    if device_plan.encryption().iter().any(|(p, _)| p == id) {
+        return Some(Kind::LUKS2);
+    }
+
+    let (_, format) = device_plan.filesystems().iter().find(|(p, _)| p == id)?;
+    match format.filesystem {
+        FilesystemType::Btrfs => Some(Kind::Btrfs),
+        FilesystemType::Ext4 => Some(Kind::Ext4),
+        FilesystemType::F2fs => Some(Kind::F2FS),
+        FilesystemType::Fat32 => Some(Kind::FAT),
+        FilesystemType::Xfs => Some(Kind::XFS),
+        FilesystemType::Swap => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+
+    use super::*;
+    use crate::Parser;
+
+    /// The state a disk would be in after applying `device_plan`
+    fn applied_state(device_plan: &DevicePlan<'_>) -> DiskState {
+        let partitions = device_plan
+            .planner()
+            .current_layout()
+            .iter()
+            .enumerate()
+            .map(|(i, region)| {
+                let tag = region.tag().unwrap();
+                PartitionState {
+                    number: i as u32 + 1,
+                    start: region.start,
+                    end: region.end,
+                    partition_type: tag.partition_type.unwrap_or_default(),
+                    filesystem: expected_filesystem(device_plan, tag.id.as_deref().unwrap()),
+                }
+            })
+            .collect();
+        DiskState {
+            device: device_plan.device().device().to_owned(),
+            table: Some(TableType::Gpt),
+            partitions,
+        }
+    }
+
+    #[test]
+    fn test_is_satisfied() {
+        let test_strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(200 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        let plan = plans.iter().find(|p| p.strategy.name == "whole_disk").unwrap();
+        let device_plan = &plan.device_assignments["root_disk"];
+
+        let mut state = applied_state(device_plan);
+        assert!(provisioner.is_satisfied(plan, std::slice::from_ref(&state)));
+
+        state.partitions[2].filesystem = Some(Kind::XFS);
+        state.partitions.pop();
+        let mismatches = provisioner.mismatches(plan, std::slice::from_ref(&state));
+        assert_eq!(mismatches.len(), 2);
+        assert!(matches!(
+            &mismatches[0],
+            Mismatch::Filesystem { id, expected: Kind::Ext4, found: Some(Kind::XFS), .. } if id == "root"
+        ));
+        assert!(matches!(&mismatches[1], Mismatch::MissingPartition { id, .. } if id == "var"));
+
+        // A blank disk satisfies nothing
+        state.table = None;
+        assert!(!provisioner.is_satisfied(plan, &[state]));
+        assert_eq!(
+            provisioner.mismatches(plan, &[]),
+            vec![Mismatch::UnknownDisk {
+                disk: "root_disk".into()
+            }]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::sync::Arc;
+
+use miette::{Diagnostic, NamedSource};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{Error, Warning};
+
+/// Collects non-fatal diagnostics for tooling
+///
+/// The sink is itself a [`Diagnostic`], so it can be rendered with miette alongside
+/// the source the diagnostics point into, if any.
+#[derive(Debug, Default, Diagnostic, Error)]
+#[error("{count} diagnostics", count = .diagnostics.len())]
+#[diagnostic(severity(warning))]
+pub struct DiagnosticSink {
+    #[source_code]
+    src: Option<NamedSource<Arc<String>>>,
+
+    #[related]
+    diagnostics: Vec<Error>,
+}
+
+impl DiagnosticSink {
+    /// Create a sink for diagnostics pointing into `src`
+    pub fn for_source(src: NamedSource<Arc<String>>) -> Self {
+        Self {
+            src: Some(src),
+            diagnostics: vec![],
+        }
+    }
+
+    /// Record a diagnostic
+    pub fn push(&mut self, diagnostic: impl Into<Error>) {
+        self.diagnostics.push(diagnostic.into());
+    }
+
+    /// Log and record a warning without a source location
+    pub fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        warn!("{}", message);
+        self.push(Warning {
+            at: None,
+            message,
+            advice: None,
+        });
+    }
+
+    /// The recorded diagnostics
+    pub fn diagnostics(&self) -> &[Error] {
+        &self.diagnostics
+    }
+
+    /// Returns true if nothing has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
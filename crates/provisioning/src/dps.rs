@@ -0,0 +1,252 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Discoverable Partitions Specification compliance
+//!
+//! `systemd-gpt-auto-generator` mounts the root, home, server data and variable data
+//! partitions by their type GUID, without an fstab, as long as they sit on the disk the
+//! ESP was booted from. [`Plan::conform_to_dps()`] gives every planned partition the type
+//! and attributes the specification expects for a target architecture, marks partitions
+//! mounted elsewhere as `no-automount`, and reports layouts the generator cannot boot.
+
+use disks::flags::PartitionFlags;
+use partitioning::{
+    partition_type::{Architecture, Role},
+    planner::{PartitionTag, TableType},
+};
+use tracing::debug;
+
+use crate::{PartitionRole, Plan};
+
+/// A planned partition with a DPS role
+struct Placed {
+    disk: String,
+    id: String,
+    role: Role,
+}
+
+impl Plan<'_> {
+    /// Apply DPS partition types and attributes for `architecture` and check the layout
+    ///
+    /// Partitions without a role or well known mount point are left alone, as are disks
+    /// getting an MBR table. Returns `true` if `systemd-gpt-auto-generator` can assemble
+    /// the system, otherwise the problems are recorded in [`Plan::diagnostics`].
+    pub fn conform_to_dps(&mut self, architecture: Architecture) -> bool {
+        let mut placed = vec![];
+        let mut disks = self.device_assignments.keys().cloned().collect::<Vec<_>>();
+        disks.sort();
+
+        for disk in disks {
+            let planner = self.device_assignments.get_mut(&disk).unwrap().planner_mut();
+            if planner.table() == Some(TableType::Mbr) {
+                continue;
+            }
+            for tag in planner.tags_mut() {
+                let Some(role) = dps_role(tag, architecture) else {
+                    continue;
+                };
+                conform(tag, role);
+                placed.push(Placed {
+                    disk: disk.clone(),
+                    id: tag.id.clone().unwrap_or_default(),
+                    role,
+                });
+            }
+        }
+
+        let mut compliant = true;
+        let esp = placed.iter().find(|p| p.role == Role::Esp);
+        let root = placed.iter().find(|p| p.role == Role::Root(architecture));
+        if esp.is_none() {
+            self.diagnostics
+                .warn("No EFI system partition is planned, the boot disk cannot be discovered");
+            compliant = false;
+        }
+        if root.is_none() {
+            self.diagnostics.warn(format!(
+                "No {architecture} root partition is planned for automatic discovery"
+            ));
+            compliant = false;
+        }
+
+        if let Some(esp) = esp {
+            if let Some(root) = root.filter(|r| r.disk != esp.disk) {
+                self.diagnostics.warn(format!(
+                    "Root partition {} is not on the disk of EFI system partition {}, it will not be discovered",
+                    root.id, esp.id
+                ));
+                compliant = false;
+            }
+
+            // Only the first partition of each type on the boot disk is used
+            let boot_disk = placed.iter().filter(|p| p.disk == esp.disk);
+            for (index, partition) in boot_disk.clone().enumerate() {
+                if let Some(first) = boot_disk.clone().take(index).find(|p| p.role == partition.role) {
+                    self.diagnostics.warn(format!(
+                        "Partition {} has the same type as {}, only the first is discovered",
+                        partition.id, first.id
+                    ));
+                    compliant = false;
+                }
+            }
+        }
+
+        compliant
+    }
+}
+
+/// The DPS role of a planned partition, from its role or mount point
+fn dps_role(tag: &PartitionTag, architecture: Architecture) -> Option<Role> {
+    let role = match tag.role.as_deref().map(str::parse::<PartitionRole>) {
+        Some(Ok(PartitionRole::Root)) => Some(Role::Root(architecture)),
+        Some(Ok(role)) => role.partition_type(),
+        _ => None,
+    };
+    role.or_else(|| match tag.mountpoint.as_deref()? {
+        "/" => Some(Role::Root(architecture)),
+        "/home" => Some(Role::Home),
+        "/var" => Some(Role::Var),
+        "/srv" => Some(Role::Srv),
+        _ => None,
+    })
+}
+
+/// Set the type and attributes of `role`, keeping flags requested by the strategy
+fn conform(tag: &mut PartitionTag, role: Role) {
+    let type_guid = role.type_guid();
+    if tag.partition_type != Some(type_guid) {
+        debug!(id = ?tag.id, %type_guid, "Setting DPS partition type");
+        tag.partition_type = Some(type_guid);
+    }
+    tag.attributes |= role.attributes();
+
+    // The generator would mount it at the wrong place
+    let elsewhere = match (tag.mountpoint.as_deref(), role.mountpoint()) {
+        (Some("/boot"), Some(_)) if role == Role::Esp => false,
+        (Some(mountpoint), Some(expected)) => mountpoint != expected,
+        _ => false,
+    };
+    if elsewhere {
+        debug!(id = ?tag.id, "Excluding partition from automatic mounting");
+        let mut flags = tag.flags();
+        flags.insert(PartitionFlags::NO_AUTOMOUNT);
+        *tag = tag.clone().with_flags(flags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+
+    use crate::{Parser, Provisioner};
+
+    use super::*;
+
+    fn plan_kdl(kdl: &str, test: impl FnOnce(&mut Plan<'_>)) {
+        let parser = Parser::new("dps.kdl".into(), kdl.into()).unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(200 * 1024 * 1024 * 1024)));
+        for strategy in parser.strategies {
+            provisioner.add_strategy(strategy);
+        }
+        let mut plans = provisioner.plan();
+        test(&mut plans[0]);
+    }
+
+    #[test]
+    fn test_conform_to_dps() {
+        let strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap().strategies;
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(150 * 1024 * 1024 * 1024)));
+        for strategy in strategies {
+            provisioner.add_strategy(strategy);
+        }
+        let mut plans = provisioner.plan();
+        let plan = &mut plans[0];
+        assert!(plan.conform_to_dps(Architecture::Aarch64));
+        assert!(plan.diagnostics.is_empty());
+
+        let layout = plan.device_assignments["root_disk"].planner().current_layout();
+        let tag = |id: &str| {
+            layout
+                .iter()
+                .filter_map(|r| r.tag())
+                .find(|t| t.id.as_deref() == Some(id))
+                .unwrap()
+        };
+        assert_eq!(
+            tag("root").partition_type,
+            Some(Role::Root(Architecture::Aarch64).type_guid())
+        );
+        assert!(tag("root").flags().contains(PartitionFlags::GROWFS));
+        assert!(!tag("esp").flags().contains(PartitionFlags::NO_AUTOMOUNT));
+    }
+
+    #[test]
+    fn test_dps_diagnostics() {
+        let kdl = r#"
+            strategy name="no_esp" summary="Root and a relocated home" {
+                find-disk "root_disk"
+                create-partition-table type="gpt" disk="root_disk"
+                create-partition disk="root_disk" role="root" id="root" {
+                    constraints {
+                        min (GIB)20
+                        max (GIB)40
+                    }
+                }
+                create-partition disk="root_disk" role="home" id="home" mountpoint="/data" {
+                    constraints {
+                        min (GIB)20
+                        max (GIB)40
+                    }
+                }
+                create-partition disk="root_disk" role="home" id="home2" {
+                    constraints {
+                        min (GIB)20
+                        max (GIB)40
+                    }
+                }
+            }
+        "#;
+        plan_kdl(kdl, |plan| {
+            assert!(!plan.conform_to_dps(Architecture::X86_64));
+            let warnings = plan
+                .diagnostics
+                .diagnostics()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                warnings,
+                vec!["No EFI system partition is planned, the boot disk cannot be discovered"]
+            );
+
+            let layout = plan.device_assignments["root_disk"].planner().current_layout();
+            let flags = layout
+                .iter()
+                .filter_map(|r| r.tag())
+                .map(|t| t.flags().contains(PartitionFlags::NO_AUTOMOUNT))
+                .collect::<Vec<_>>();
+            assert_eq!(flags, vec![false, true, false]);
+        });
+
+        let kdl = kdl.replace(
+            r#"create-partition-table type="gpt" disk="root_disk""#,
+            r#"create-partition-table type="gpt" disk="root_disk"
+                create-partition disk="root_disk" role="boot" id="esp" {
+                    constraints {
+                        exactly (GIB)1
+                    }
+                }"#,
+        );
+        plan_kdl(&kdl, |plan| {
+            assert!(!plan.conform_to_dps(Architecture::X86_64));
+            assert_eq!(
+                plan.diagnostics.diagnostics()[0].to_string(),
+                "Partition home2 has the same type as home, only the first is discovered"
+            );
+        });
+    }
+}
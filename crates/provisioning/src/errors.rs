@@ -2,12 +2,12 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{io, sync::Arc};
+use std::{io, path::PathBuf, sync::Arc};
 
 use miette::{Diagnostic, NamedSource, SourceSpan};
 use thiserror::Error;
 
-use crate::KdlType;
+use crate::{DiagnosticSink, KdlType};
 
 /// Error type for the provisioning crate
 #[derive(Diagnostic, Debug, Error)]
@@ -51,6 +51,27 @@ pub enum Error {
     #[diagnostic(transparent)]
     #[error(transparent)]
     UnsupportedValue(#[from] UnsupportedValue),
+
+    #[diagnostic(transparent)]
+    #[error(transparent)]
+    Warning(#[from] Warning),
+
+    #[diagnostic(transparent)]
+    #[error(transparent)]
+    Diagnostics(#[from] Box<DiagnosticSink>),
+
+    #[error("duplicate strategy {name}, first defined in {}", .first.display())]
+    DuplicateStrategy { name: String, first: PathBuf },
+
+    #[error("strategy {name} inherits unknown strategy {parent}")]
+    UnknownParent { name: String, parent: String },
+
+    #[error("strategy {0} inherits from itself")]
+    InheritanceCycle(String),
+
+    #[diagnostic(transparent)]
+    #[error(transparent)]
+    Invalid(#[from] Invalid),
 }
 
 /// Merged error for parsing failures
@@ -65,6 +86,29 @@ pub struct ParseError {
     pub diagnostics: Vec<Error>,
 }
 
+/// Semantic problems found in a strategy
+/// Returned by [`crate::Parser::validate()`]
+#[derive(Debug, Diagnostic, Error)]
+#[error("strategy {name} failed validation")]
+pub struct ValidationError {
+    pub name: String,
+    #[source_code]
+    pub src: NamedSource<Arc<String>>,
+    #[related]
+    pub diagnostics: Vec<Error>,
+}
+
+/// Failure to load a directory of strategies
+/// Holds the parse errors of every offending file
+#[derive(Debug, Diagnostic, Error)]
+#[error("failed to load strategies from {}", .path.display())]
+#[diagnostic(severity(error))]
+pub struct LoadError {
+    pub path: PathBuf,
+    #[related]
+    pub errors: Vec<ParseError>,
+}
+
 /// Error for invalid types
 #[derive(Debug, Diagnostic, Error)]
 #[error("invalid type, expected {expected_type}")]
@@ -151,3 +195,31 @@ pub struct MissingType {
     #[help]
     pub advice: Option<String>,
 }
+
+/// A strategy that parses but cannot be planned as written
+#[derive(Debug, Diagnostic, Error)]
+#[error("{message}")]
+#[diagnostic(severity(error))]
+pub struct Invalid {
+    #[label]
+    pub at: SourceSpan,
+
+    pub message: String,
+
+    #[help]
+    pub advice: Option<String>,
+}
+
+/// A non-fatal problem found while parsing or planning
+#[derive(Debug, Diagnostic, Error)]
+#[error("{message}")]
+#[diagnostic(severity(warning))]
+pub struct Warning {
+    #[label]
+    pub at: Option<SourceSpan>,
+
+    pub message: String,
+
+    #[help]
+    pub advice: Option<String>,
+}
@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Facts about the running system
+//!
+//! Strategies can depend on the machine they are evaluated on, e.g. sizing swap after
+//! the installed memory or only creating an ESP on UEFI systems. Facts are gathered
+//! once when the [`crate::Provisioner`] is created and can be overridden for testing.
+
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{kdl_value_to_string, FromKdlProperty};
+
+/// EFI variable holding the secure boot state (global variable GUID)
+const SECURE_BOOT_VAR: &str = "/sys/firmware/efi/efivars/SecureBoot-8be4df61-93ca-11d2-aa0d-00e098032b8c";
+
+/// DMI vendor and product strings reported by common hypervisors
+const HYPERVISOR_VENDORS: &[&str] = &["QEMU", "KVM", "VirtualBox", "VMware", "Xen", "Bochs", "Virtual Machine"];
+
+/// Firmware the system was booted with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Firmware {
+    /// UEFI firmware
+    Uefi,
+
+    /// Legacy BIOS, or UEFI in compatibility mode
+    Bios,
+}
+
+impl fmt::Display for Firmware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Uefi => f.write_str("uefi"),
+            Self::Bios => f.write_str("bios"),
+        }
+    }
+}
+
+impl FromStr for Firmware {
+    type Err = crate::Error;
+
+    /// Attempt to convert a string to a firmware type
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "uefi" => Ok(Self::Uefi),
+            "bios" => Ok(Self::Bios),
+            _ => Err(crate::Error::UnknownVariant),
+        }
+    }
+}
+
+impl FromKdlProperty<'_> for Firmware {
+    fn from_kdl_property(entry: &kdl::KdlEntry) -> Result<Self, crate::Error> {
+        let value = kdl_value_to_string(entry)?;
+        let v = value.parse().map_err(|_| crate::UnsupportedValue {
+            at: entry.span(),
+            advice: Some("'uefi' and 'bios' are supported".into()),
+        })?;
+        Ok(v)
+    }
+}
+
+/// Facts about the system strategies are evaluated on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Facts {
+    /// Total memory in bytes
+    pub memory: u64,
+
+    /// Firmware the system was booted with
+    pub firmware: Firmware,
+
+    /// Secure boot state, if it could be determined
+    pub secure_boot: Option<bool>,
+
+    /// CPU architecture, as in [`std::env::consts::ARCH`]
+    pub arch: String,
+
+    /// Whether the system is a virtual machine
+    pub virtualized: bool,
+}
+
+impl Facts {
+    /// Gather facts from the running system
+    pub fn gather() -> Self {
+        let memory = fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|m| parse_meminfo(&m))
+            .unwrap_or(0);
+        let firmware = if Path::new("/sys/firmware/efi").exists() {
+            Firmware::Uefi
+        } else {
+            Firmware::Bios
+        };
+        // 4 bytes of attributes followed by the value
+        let secure_boot = fs::read(SECURE_BOOT_VAR).ok().and_then(|v| v.get(4).map(|b| *b == 1));
+        let virtualized = fs::read_to_string("/proc/cpuinfo").is_ok_and(|c| c.contains(" hypervisor"))
+            || ["sys_vendor", "product_name"].iter().any(|key| {
+                fs::read_to_string(Path::new("/sys/class/dmi/id").join(key))
+                    .is_ok_and(|v| HYPERVISOR_VENDORS.iter().any(|h| v.contains(h)))
+            });
+
+        let facts = Self {
+            memory,
+            firmware,
+            secure_boot,
+            arch: std::env::consts::ARCH.to_owned(),
+            virtualized,
+        };
+        debug!("Gathered system facts: {:?}", facts);
+        facts
+    }
+}
+
+/// Extract the total memory in bytes from the contents of `/proc/meminfo`
+fn parse_meminfo(contents: &str) -> Option<u64> {
+    let line = contents.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let contents = "MemTotal:       16318396 kB\nMemFree:         1034212 kB\n";
+        assert_eq!(parse_meminfo(contents), Some(16318396 * 1024));
+        assert_eq!(parse_meminfo("MemFree: 12 kB\n"), None);
+    }
+}
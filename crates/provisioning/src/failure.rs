@@ -0,0 +1,382 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! A single error type for frontends
+//!
+//! Every crate of the workspace has its own error types, which is right for the crates
+//! but leaves frontends matching on a dozen enums. A [`Failure`] wraps any of them along
+//! with the [`Operation`] that failed and the device involved, and classifies it with a
+//! stable [`ErrorCode`] that frontends can map to messages or retries.
+
+use std::{fmt, io, path::PathBuf};
+
+use partitioning::{
+    btrfs, cancel::Cancelled, copy, format, planner::PlanError, strategy, swapfile, writer::WriteError,
+};
+use thiserror::Error;
+
+use crate::{ApplyReport, DeviceStatus, LoadError, ParseError, ValidationError};
+
+#[cfg(feature = "linux")]
+use partitioning::blkpg;
+
+#[cfg(feature = "linux")]
+use crate::RecoveryError;
+
+/// Stable classification of a failure
+///
+/// The string form returned by [`ErrorCode::as_str()`] never changes once released,
+/// while new codes may be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// Any IO error not covered by a more specific code
+    Io,
+    /// A device or file does not exist
+    NotFound,
+    /// Missing privileges to access a device
+    PermissionDenied,
+    /// The device is in use, e.g. mounted
+    DeviceBusy,
+    /// A strategy could not be parsed or loaded
+    Parse,
+    /// A strategy failed semantic validation
+    Validation,
+    /// The disk is too small for the requested layout
+    InsufficientSpace,
+    /// The requested layout is invalid for the disk
+    InvalidLayout,
+    /// The partition table could not be read or written
+    PartitionTable,
+    /// A required tool is not installed
+    ToolMissing,
+    /// A filesystem could not be created
+    Format,
+    /// Btrfs subvolumes could not be created
+    Subvolume,
+    /// A swapfile could not be created
+    Swapfile,
+    /// Copied data did not read back correctly
+    CopyVerification,
+    /// A superblock could not be read or was not recognised
+    Superblock,
+    /// The kernel could not be notified of partition changes
+    KernelSync,
+    /// A previous run cannot be recovered with the requested mode
+    Recovery,
+    /// The operation was cancelled or timed out
+    Cancelled,
+}
+
+impl ErrorCode {
+    /// The stable string form of this code
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Io => "io",
+            Self::NotFound => "not-found",
+            Self::PermissionDenied => "permission-denied",
+            Self::DeviceBusy => "device-busy",
+            Self::Parse => "parse",
+            Self::Validation => "validation",
+            Self::InsufficientSpace => "insufficient-space",
+            Self::InvalidLayout => "invalid-layout",
+            Self::PartitionTable => "partition-table",
+            Self::ToolMissing => "tool-missing",
+            Self::Format => "format",
+            Self::Subvolume => "subvolume",
+            Self::Swapfile => "swapfile",
+            Self::CopyVerification => "copy-verification",
+            Self::Superblock => "superblock",
+            Self::KernelSync => "kernel-sync",
+            Self::Recovery => "recovery",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// Returns true if trying again later may succeed without user intervention
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::DeviceBusy | Self::KernelSync)
+    }
+
+    /// Classify an IO error by its kind
+    fn from_io(error: &io::Error) -> Self {
+        if Cancelled::from_io(error).is_some() {
+            return Self::Cancelled;
+        }
+        match error.kind() {
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::ResourceBusy => Self::DeviceBusy,
+            _ => Self::Io,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The operation that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    Discover,
+    LoadStrategies,
+    Validate,
+    Plan,
+    Erase,
+    Partition,
+    SyncPartitions,
+    Format,
+    CreateSubvolumes,
+    CreateSwapfile,
+    Copy,
+    Probe,
+    Recover,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Discover => "discovering devices",
+            Self::LoadStrategies => "loading strategies",
+            Self::Validate => "validating strategy",
+            Self::Plan => "planning",
+            Self::Erase => "erasing",
+            Self::Partition => "partitioning",
+            Self::SyncPartitions => "notifying kernel of partitions",
+            Self::Format => "creating filesystem",
+            Self::CreateSubvolumes => "creating subvolumes",
+            Self::CreateSwapfile => "creating swapfile",
+            Self::Copy => "copying partition",
+            Self::Probe => "probing superblock",
+            Self::Recover => "recovering previous run",
+        })
+    }
+}
+
+/// The underlying error of a [`Failure`]
+#[derive(Debug, Error)]
+pub enum FailureSource {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    #[error(transparent)]
+    Strategy(#[from] strategy::Error),
+    #[error(transparent)]
+    Write(#[from] WriteError),
+    #[cfg(feature = "linux")]
+    #[error(transparent)]
+    Blkpg(#[from] blkpg::Error),
+    #[error(transparent)]
+    Format(#[from] format::Error),
+    #[error(transparent)]
+    Btrfs(#[from] btrfs::Error),
+    #[error(transparent)]
+    Swapfile(#[from] swapfile::Error),
+    #[error(transparent)]
+    Copy(#[from] copy::Error),
+    #[error(transparent)]
+    Superblock(#[from] superblock::Error),
+    #[cfg(feature = "linux")]
+    #[error(transparent)]
+    Recovery(#[from] RecoveryError),
+}
+
+impl FailureSource {
+    /// Classify the error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Io(e)
+            | Self::Write(WriteError::Io(e))
+            | Self::Btrfs(btrfs::Error::Io(e))
+            | Self::Swapfile(swapfile::Error::Io(e))
+            | Self::Copy(copy::Error::Io(e))
+            | Self::Superblock(superblock::Error::IO(e)) => ErrorCode::from_io(e),
+            Self::Parse(_) | Self::Load(_) => ErrorCode::Parse,
+            Self::Validation(_) => ErrorCode::Validation,
+            Self::Strategy(strategy::Error::InsufficientSpace(_)) | Self::Plan(PlanError::NoFreeRegions) => {
+                ErrorCode::InsufficientSpace
+            }
+            Self::Plan(_) | Self::Strategy(_) => ErrorCode::InvalidLayout,
+            Self::Write(_) => ErrorCode::PartitionTable,
+            #[cfg(feature = "linux")]
+            Self::Blkpg(_) => ErrorCode::KernelSync,
+            Self::Format(format::Error::Cancelled(_))
+            | Self::Swapfile(swapfile::Error::Format(format::Error::Cancelled(_)))
+            | Self::Copy(copy::Error::Cancelled(_)) => ErrorCode::Cancelled,
+            Self::Format(format::Error::Spawn { source, .. })
+            | Self::Swapfile(swapfile::Error::Format(format::Error::Spawn { source, .. }))
+                if source.kind() == io::ErrorKind::NotFound =>
+            {
+                ErrorCode::ToolMissing
+            }
+            Self::Format(_) => ErrorCode::Format,
+            Self::Btrfs(_) => ErrorCode::Subvolume,
+            Self::Swapfile(_) => ErrorCode::Swapfile,
+            Self::Copy(copy::Error::TargetTooSmall { .. }) => ErrorCode::InsufficientSpace,
+            Self::Copy(_) => ErrorCode::CopyVerification,
+            Self::Superblock(_) => ErrorCode::Superblock,
+            #[cfg(feature = "linux")]
+            Self::Recovery(RecoveryError::Pending {
+                source: WriteError::Io(e),
+                ..
+            }) => ErrorCode::from_io(e),
+            #[cfg(feature = "linux")]
+            Self::Recovery(RecoveryError::Pending { .. }) => ErrorCode::PartitionTable,
+            #[cfg(feature = "linux")]
+            Self::Recovery(_) => ErrorCode::Recovery,
+        }
+    }
+}
+
+/// Any failure of the provisioning stack, with a stable code and context
+#[derive(Debug, Error)]
+#[error("{operation}{} failed: {source}", .device.as_ref().map(|d| format!(" {}", d.display())).unwrap_or_default())]
+pub struct Failure {
+    /// What was being done
+    pub operation: Operation,
+    /// The disk or partition involved, if any
+    pub device: Option<PathBuf>,
+    /// The underlying error
+    #[source]
+    pub source: FailureSource,
+}
+
+impl Failure {
+    /// Wrap an error raised by `operation`
+    pub fn new(operation: Operation, source: impl Into<FailureSource>) -> Self {
+        Self {
+            operation,
+            device: None,
+            source: source.into(),
+        }
+    }
+
+    /// Attach the device involved
+    pub fn with_device(self, device: impl Into<PathBuf>) -> Self {
+        Self {
+            device: Some(device.into()),
+            ..self
+        }
+    }
+
+    /// Classify the failure
+    pub fn code(&self) -> ErrorCode {
+        self.source.code()
+    }
+
+    /// Returns true if trying again later may succeed without user intervention
+    pub fn is_retryable(&self) -> bool {
+        self.code().is_retryable()
+    }
+}
+
+impl ApplyReport {
+    /// Convert every failure in the report into a [`Failure`]
+    ///
+    /// Disks that were skipped, declined or rolled back did not fail themselves and are
+    /// left out. Filesystem, subvolume and swapfile failures carry the partition device
+    /// when it is known.
+    pub fn into_failures(self) -> Vec<Failure> {
+        let partition_device = |id: &str| {
+            self.devices.iter().find_map(|(_, _, status)| match status {
+                DeviceStatus::Written(partitions) => partitions
+                    .iter()
+                    .find(|p| p.region.tag().and_then(|t| t.id.as_deref()) == Some(id))
+                    .map(|p| p.device.clone()),
+                _ => None,
+            })
+        };
+        let with_partition = |failure: Failure, id: &str| match partition_device(id) {
+            Some(device) => failure.with_device(device),
+            None => failure,
+        };
+
+        let mut failures = vec![];
+        for (id, result) in self.filesystems {
+            if let Err(e) = result {
+                failures.push(with_partition(Failure::new(Operation::Format, e), &id));
+            }
+        }
+        for (id, result) in self.subvolumes {
+            if let Err(e) = result {
+                failures.push(with_partition(Failure::new(Operation::CreateSubvolumes, e), &id));
+            }
+        }
+        for (id, result) in self.swapfiles {
+            if let Err(e) = result {
+                failures.push(with_partition(Failure::new(Operation::CreateSwapfile, e), &id));
+            }
+        }
+
+        let disks = self.devices.into_iter().filter_map(|(_, device, status)| match status {
+            DeviceStatus::Failed(e) => Some(Failure::new(Operation::Partition, e).with_device(device)),
+            DeviceStatus::RestoreFailed(e) => Some(Failure::new(Operation::Partition, e).with_device(device)),
+            _ => None,
+        });
+        disks.chain(failures).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let busy =
+            Failure::new(Operation::Partition, io::Error::from(io::ErrorKind::ResourceBusy)).with_device("/dev/sda");
+        assert_eq!(busy.code(), ErrorCode::DeviceBusy);
+        assert!(busy.is_retryable());
+        assert_eq!(busy.to_string(), "partitioning /dev/sda failed: resource busy");
+
+        let missing = format::Error::Spawn {
+            tool: "mkfs.xfs",
+            source: io::Error::from(io::ErrorKind::NotFound),
+        };
+        assert_eq!(Failure::new(Operation::Format, missing).code(), ErrorCode::ToolMissing);
+        assert_eq!(
+            Failure::new(Operation::Plan, PlanError::NoFreeRegions).code().as_str(),
+            "insufficient-space"
+        );
+        assert_eq!(
+            Failure::new(Operation::Partition, WriteError::TableFull).code(),
+            ErrorCode::PartitionTable
+        );
+    }
+
+    #[cfg(feature = "linux")]
+    #[test]
+    fn test_report_failures() {
+        use disks::{mock::MockDisk, BlockDevice};
+
+        use crate::{Parser, Provisioner};
+
+        let test_strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(150 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        // Mock devices have no device node, so only the first disk fails
+        let plans = provisioner.plan();
+        let failures = plans[0].apply().into_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].operation, Operation::Partition);
+        assert_eq!(failures[0].code(), ErrorCode::NotFound);
+        assert!(failures[0].device.is_some());
+    }
+}
@@ -0,0 +1,348 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! fstab and crypttab generation
+//!
+//! Once a plan has been applied, [`Plan::mount_tables()`] produces the `/etc/fstab` and
+//! `/etc/crypttab` entries for the target root. Filesystems are referenced by the UUID
+//! read from their superblock, falling back to the partition GUID for filesystems without
+//! a readable UUID (e.g. swap).
+
+use std::{fmt, fs::File, path::Path};
+
+use partitioning::{btrfs::SubvolumeLayout, format::FilesystemType, writer::WrittenPartition};
+use superblock::Superblock;
+use tracing::{debug, warn};
+
+use crate::{ApplyReport, DeviceStatus, KeySource, Plan};
+
+/// A single `/etc/fstab` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstabEntry {
+    /// Device specification, e.g. `UUID=...`
+    pub source: String,
+    /// Mount point, or `none` for swap
+    pub mountpoint: String,
+    /// Filesystem type
+    pub fstype: String,
+    /// Mount options
+    pub options: Vec<String>,
+    /// Order for fsck at boot, 0 to skip
+    pub pass: u8,
+}
+
+impl fmt::Display for FstabEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} 0 {}",
+            self.source,
+            self.mountpoint,
+            self.fstype,
+            self.options.join(","),
+            self.pass
+        )
+    }
+}
+
+/// A single `/etc/crypttab` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrypttabEntry {
+    /// Name of the mapping under `/dev/mapper`
+    pub name: String,
+    /// Device specification of the encrypted partition
+    pub source: String,
+    /// Key file, or `none` to prompt
+    pub key: String,
+    /// Options
+    pub options: Vec<String>,
+}
+
+impl fmt::Display for CrypttabEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.name,
+            self.source,
+            self.key,
+            self.options.join(",")
+        )
+    }
+}
+
+/// Generated `/etc/fstab` and `/etc/crypttab` contents
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MountTables {
+    pub fstab: Vec<FstabEntry>,
+    pub crypttab: Vec<CrypttabEntry>,
+}
+
+impl MountTables {
+    /// Render the fstab entries, one per line
+    pub fn fstab(&self) -> String {
+        self.fstab.iter().map(|e| format!("{e}\n")).collect()
+    }
+
+    /// Render the crypttab entries, one per line
+    pub fn crypttab(&self) -> String {
+        self.crypttab.iter().map(|e| format!("{e}\n")).collect()
+    }
+}
+
+impl Plan<'_> {
+    /// Generate fstab and crypttab entries for the partitions written by [`Plan::apply()`]
+    ///
+    /// Only filesystems that were created successfully are included.
+    pub fn mount_tables(&self, report: &ApplyReport) -> MountTables {
+        self.mount_tables_with(report, read_uuid)
+    }
+
+    fn mount_tables_with(&self, report: &ApplyReport, uuid: impl Fn(&Path) -> Option<String>) -> MountTables {
+        let mut tables = MountTables::default();
+
+        for (name, _, status) in &report.devices {
+            let (Some(device_plan), DeviceStatus::Written(partitions)) = (self.device_assignments.get(name), status)
+            else {
+                continue;
+            };
+
+            for (id, format) in device_plan.filesystems() {
+                let formatted = report.filesystems.iter().any(|(f, result)| f == id && result.is_ok());
+                let partition = partitions
+                    .iter()
+                    .find(|p| p.region.tag().and_then(|t| t.id.as_ref()) == Some(id));
+                let Some(partition) = partition.filter(|_| formatted) else {
+                    debug!("Partition {} was not formatted, skipping fstab entry", id);
+                    continue;
+                };
+
+                // Filesystems on encrypted partitions live on the mapped device
+                let luks = device_plan.encryption().iter().find(|(p, _)| p == id).map(|(_, l)| l);
+                let source = match luks {
+                    Some(luks) => {
+                        tables.crypttab.push(CrypttabEntry {
+                            name: luks.name.clone(),
+                            source: device_source(partition, &uuid),
+                            key: match &luks.key {
+                                KeySource::Prompt => "none".into(),
+                                KeySource::Keyfile(path) => path.display().to_string(),
+                            },
+                            options: std::iter::once("luks".into())
+                                .chain(luks.enroll.iter().map(|token| format!("{token}-device=auto")))
+                                .collect(),
+                        });
+                        format!("/dev/mapper/{}", luks.name)
+                    }
+                    None => device_source(partition, &uuid),
+                };
+
+                let mountpoint = partition.region.tag().and_then(|t| t.mountpoint.clone());
+                if format.filesystem == FilesystemType::Swap {
+                    tables.fstab.push(FstabEntry {
+                        source,
+                        mountpoint: "none".into(),
+                        fstype: "swap".into(),
+                        options: vec!["defaults".into()],
+                        pass: 0,
+                    });
+                    continue;
+                }
+
+                // Subvolumes with mount points replace the mount of the whole filesystem
+                let subvolumes = device_plan
+                    .subvolumes()
+                    .iter()
+                    .filter(|(p, _)| p == id)
+                    .flat_map(|(_, layout)| layout.subvolumes())
+                    .filter_map(|s| Some((s.mountpoint.clone()?, vec![format!("subvol={}", s.path)])))
+                    .collect::<Vec<_>>();
+                let mounts = if subvolumes.is_empty() {
+                    mountpoint.clone().map(|m| (m, vec![])).into_iter().collect()
+                } else {
+                    subvolumes
+                };
+
+                for (mountpoint, extra) in mounts {
+                    let mut options = mount_options(format.filesystem, &mountpoint);
+                    options.extend(extra);
+                    tables.fstab.push(FstabEntry {
+                        source: source.clone(),
+                        pass: fsck_pass(format.filesystem, &mountpoint),
+                        mountpoint,
+                        fstype: format.filesystem.to_string(),
+                        options,
+                    });
+                }
+
+                let swapfiles = device_plan.swapfiles().iter().filter(|(p, _)| p == id);
+                for (_, swapfile) in swapfiles {
+                    let created = report.swapfiles.iter().any(|(p, result)| p == id && result.is_ok());
+                    let path = swapfile_path(device_plan.subvolumes(), id, mountpoint.as_deref(), &swapfile.path);
+                    let Some(path) = path.filter(|_| created) else {
+                        debug!("Swapfile {} is not mounted, skipping fstab entry", swapfile.path);
+                        continue;
+                    };
+                    tables.fstab.push(FstabEntry {
+                        source: path,
+                        mountpoint: "none".into(),
+                        fstype: "swap".into(),
+                        options: vec!["defaults".into()],
+                        pass: 0,
+                    });
+                }
+            }
+        }
+
+        // Parents must be mounted before their children
+        tables
+            .fstab
+            .sort_by_key(|e| (e.mountpoint.matches('/').count(), e.mountpoint.clone()));
+        tables
+    }
+}
+
+/// Default mount options for a filesystem at the given mount point
+fn mount_options(filesystem: FilesystemType, mountpoint: &str) -> Vec<String> {
+    match (filesystem, mountpoint) {
+        // The ESP and XBOOTLDR hold boot loaders and secrets, keep them private
+        (FilesystemType::Fat32, "/efi" | "/boot" | "/boot/efi") => {
+            vec!["umask=0077".into(), "noexec".into(), "nosuid".into(), "nodev".into()]
+        }
+        (_, "/") => vec!["defaults".into()],
+        _ => vec!["defaults".into(), "nofail".into()],
+    }
+}
+
+/// Path of a swapfile in the installed system
+///
+/// Swapfile paths are relative to the filesystem root, which on btrfs is the top-level
+/// subvolume, so the path must lie within a subvolume that has a mount point.
+fn swapfile_path(
+    subvolumes: &[(String, SubvolumeLayout)],
+    id: &str,
+    mountpoint: Option<&str>,
+    path: &str,
+) -> Option<String> {
+    let subvolumes = subvolumes
+        .iter()
+        .filter(|(p, _)| p == id)
+        .flat_map(|(_, layout)| layout.subvolumes())
+        .collect::<Vec<_>>();
+    let (mountpoint, relative) = if subvolumes.is_empty() {
+        (mountpoint?, Path::new(path))
+    } else {
+        subvolumes
+            .iter()
+            .filter_map(|s| Some((s.mountpoint.as_deref()?, Path::new(path).strip_prefix(&s.path).ok()?)))
+            .min_by_key(|(_, relative)| relative.components().count())?
+    };
+    Some(Path::new(mountpoint).join(relative).display().to_string())
+}
+
+/// fsck pass for a filesystem, the root is always checked first
+fn fsck_pass(filesystem: FilesystemType, mountpoint: &str) -> u8 {
+    match (filesystem, mountpoint) {
+        // fsck is a no-op for these
+        (FilesystemType::Btrfs | FilesystemType::Xfs, _) => 0,
+        (_, "/") => 1,
+        _ => 2,
+    }
+}
+
+/// Reference a partition by filesystem UUID, or else by its partition GUID
+pub(crate) fn device_source(partition: &WrittenPartition, uuid: &impl Fn(&Path) -> Option<String>) -> String {
+    match uuid(&partition.device) {
+        Some(uuid) => format!("UUID={uuid}"),
+        None => format!("PARTUUID={}", partition.guid),
+    }
+}
+
+/// Read the UUID of the filesystem or LUKS container on `device`
+pub(crate) fn read_uuid(device: &Path) -> Option<String> {
+    let mut file = File::open(device)
+        .inspect_err(|e| warn!("Failed to open {:?}: {}", device, e))
+        .ok()?;
+    Superblock::from_reader(&mut file).and_then(|s| s.uuid_string()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use disks::{mock::MockDisk, BlockDevice};
+    use uuid::Uuid;
+
+    use crate::{Parser, Provisioner};
+
+    use super::*;
+
+    #[test]
+    fn test_mount_tables() {
+        let test_strategies = Parser::new_for_path("tests/mount_tables.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+        let plans = provisioner.plan();
+        let plan = &plans[0];
+
+        // Pretend the plan was applied and every filesystem created
+        let partitions = plan.device_assignments["root_disk"]
+            .planner()
+            .current_layout()
+            .into_iter()
+            .enumerate()
+            .map(|(i, region)| WrittenPartition {
+                number: i as u32 + 1,
+                region,
+                device: PathBuf::from(format!("/dev/mock0p{}", i + 1)),
+                guid: Uuid::from_u128(i as u128 + 1),
+            })
+            .collect::<Vec<_>>();
+        let report = ApplyReport {
+            devices: vec![(
+                "root_disk".into(),
+                "/dev/mock0".into(),
+                DeviceStatus::Written(partitions),
+            )],
+            filesystems: ["esp", "swap", "root"].map(|id| (id.to_string(), Ok(()))).into(),
+            subvolumes: vec![],
+            swapfiles: vec![],
+        };
+
+        let tables = plan.mount_tables_with(&report, |device| {
+            (device != Path::new("/dev/mock0p2")).then(|| device.display().to_string().replace("/dev/", ""))
+        });
+        assert_eq!(
+            tables.fstab(),
+            "PARTUUID=00000000-0000-0000-0000-000000000002 none swap defaults 0 0\n\
+             UUID=mock0p3 / btrfs defaults,subvol=@ 0 0\n\
+             UUID=mock0p1 /efi vfat umask=0077,noexec,nosuid,nodev 0 2\n\
+             UUID=mock0p3 /home btrfs defaults,nofail,subvol=@home 0 0\n"
+        );
+        assert_eq!(tables.crypttab(), "");
+    }
+
+    #[test]
+    fn test_swapfile_path() {
+        let mut layout = SubvolumeLayout::standard();
+        layout.add(partitioning::btrfs::Subvolume::new("@swap").with_mountpoint("/swap"));
+        let subvolumes = [("root".to_string(), layout)];
+
+        let path = |path| swapfile_path(&subvolumes, "root", None, path);
+        assert_eq!(path("@swap/swapfile").as_deref(), Some("/swap/swapfile"));
+        assert_eq!(path("@/swapfile").as_deref(), Some("/swapfile"));
+        assert_eq!(path("swapfile"), None);
+
+        // Without subvolumes the path is relative to the mount point of the partition
+        assert_eq!(
+            swapfile_path(&[], "root", Some("/"), "swapfile").as_deref(),
+            Some("/swapfile")
+        );
+        assert_eq!(swapfile_path(&[], "root", None, "swapfile"), None);
+    }
+}
@@ -62,3 +62,13 @@ pub(crate) fn get_property_str(node: &KdlNode, name: &'static str) -> Result<Str
     let value = get_kdl_property(node, name).and_then(kdl_value_to_string)?;
     Ok(value.to_owned())
 }
+
+// Get a boolean property from a value
+pub(crate) fn kdl_value_to_bool(entry: &kdl::KdlEntry) -> Result<bool, Error> {
+    let value = entry.value().as_bool().ok_or(InvalidType {
+        at: entry.span(),
+        expected_type: KdlType::Boolean,
+    })?;
+
+    Ok(value)
+}
@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Serializable descriptions of discovered devices
+//!
+//! Front-ends that cannot link against this crate, e.g. C installers or D-Bus clients,
+//! receive the block devices of the system as [`DeviceInfo`] rendered to JSON. The same
+//! JSON read back with [`DeviceInfo::to_mock()`] recreates the devices for simulation.
+
+use std::path::PathBuf;
+
+use disks::{flags::PartitionFlags, mock::MockDisk, partition::Partition, BlockDevice};
+use serde::{Deserialize, Serialize};
+
+/// A partition of a discovered device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionInfo {
+    /// Kernel name of the partition
+    pub name: String,
+    /// Partition number on the disk
+    pub number: u32,
+    /// First sector
+    pub start: u64,
+    /// Last sector
+    pub end: u64,
+    /// Size in sectors
+    pub size: u64,
+    /// Device node
+    pub device: PathBuf,
+    /// Flags read from the partition table
+    #[serde(default, skip_serializing_if = "PartitionFlags::is_empty")]
+    pub flags: PartitionFlags,
+    /// Name from the GPT entry, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpt_name: Option<String>,
+}
+
+/// A discovered block device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// Kernel name of the device
+    pub name: String,
+    /// Device node
+    pub device: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+    /// Model reported by the device, if any
+    pub model: Option<String>,
+    /// Serial number reported by the device, if any
+    pub serial: Option<String>,
+    /// World Wide Name of the device, if any
+    pub wwn: Option<String>,
+    /// Whether the media can be removed
+    pub removable: bool,
+    /// Partitions currently known to the kernel
+    pub partitions: Vec<PartitionInfo>,
+}
+
+impl From<&BlockDevice> for DeviceInfo {
+    fn from(device: &BlockDevice) -> Self {
+        Self {
+            name: device.name().to_owned(),
+            device: device.device().to_owned(),
+            size: device.size(),
+            model: device.model().map(str::to_owned),
+            serial: device.serial().map(str::to_owned),
+            wwn: device.wwn().map(str::to_owned),
+            removable: device.is_removable(),
+            partitions: device
+                .partitions()
+                .iter()
+                .map(|p| PartitionInfo {
+                    name: p.name.clone(),
+                    number: p.number,
+                    start: p.start,
+                    end: p.end,
+                    size: p.size,
+                    device: p.device.clone(),
+                    flags: p.flags,
+                    gpt_name: p.gpt_name.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl DeviceInfo {
+    /// Recreate the device as a mock with the same name, size and partitions
+    ///
+    /// Nothing but the description is known, so the mock cannot be written to.
+    pub fn to_mock(&self) -> BlockDevice {
+        let mut disk = MockDisk::new(self.size);
+        disk.set_name(&self.name);
+        disk.set_model(self.model.clone());
+        if let Some(serial) = &self.serial {
+            disk.set_serial(serial);
+        }
+        if let Some(wwn) = &self.wwn {
+            disk.set_wwn(wwn);
+        }
+        disk.set_removable(self.removable);
+        for partition in &self.partitions {
+            disk.push_partition(Partition {
+                name: partition.name.clone(),
+                number: partition.number,
+                start: partition.start,
+                end: partition.end,
+                size: partition.size,
+                node: PathBuf::from("/sys/class/block").join(&self.name).join(&partition.name),
+                device: partition.device.clone(),
+                mbr: None,
+                flags: partition.flags,
+                gpt_name: partition.gpt_name.clone(),
+            });
+        }
+        BlockDevice::mock_device(disk)
+    }
+}
@@ -0,0 +1,149 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Discovering the devices created by an applied plan
+//!
+//! Installers refer to partitions by the ids used in the strategy, but mounting them and
+//! configuring the boot loader needs device nodes and UUIDs. [`Plan::layout()`] maps
+//! every partition id to what was actually created, re-reading filesystem UUIDs from
+//! the new superblocks.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::{read_uuid, ApplyReport, DeviceStatus, Plan};
+
+/// A partition created by applying a plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvisionedPartition {
+    /// Name of the disk in the strategy
+    pub disk: String,
+    /// Device node of the partition
+    pub device: PathBuf,
+    /// Unique GUID of the partition
+    pub partuuid: Uuid,
+    /// UUID of the filesystem or LUKS container, if one could be read
+    pub uuid: Option<String>,
+    /// Where the partition is mounted, if anywhere
+    pub mountpoint: Option<String>,
+}
+
+/// The partitions created by a plan, keyed by partition id
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProvisionedLayout {
+    pub partitions: BTreeMap<String, ProvisionedPartition>,
+}
+
+impl ProvisionedLayout {
+    /// Look up a partition by the id used in the strategy
+    pub fn get(&self, id: &str) -> Option<&ProvisionedPartition> {
+        self.partitions.get(id)
+    }
+
+    /// The partition mounted at `mountpoint`, if any
+    pub fn by_mountpoint(&self, mountpoint: &str) -> Option<&ProvisionedPartition> {
+        self.partitions
+            .values()
+            .find(|p| p.mountpoint.as_deref() == Some(mountpoint))
+    }
+}
+
+impl Plan<'_> {
+    /// Describe the partitions written by [`Plan::apply()`]
+    ///
+    /// Partitions on disks that were not written, e.g. after a rollback, are omitted.
+    pub fn layout(&self, report: &ApplyReport) -> ProvisionedLayout {
+        self.layout_with(report, read_uuid)
+    }
+
+    fn layout_with(&self, report: &ApplyReport, uuid: impl Fn(&Path) -> Option<String>) -> ProvisionedLayout {
+        let partitions = report
+            .devices
+            .iter()
+            .filter_map(|(name, _, status)| match status {
+                DeviceStatus::Written(partitions) => Some((name, partitions)),
+                _ => None,
+            })
+            .flat_map(|(name, partitions)| {
+                partitions.iter().filter_map(|partition| {
+                    let tag = partition.region.tag()?;
+                    let id = tag.id.clone()?;
+                    Some((
+                        id,
+                        ProvisionedPartition {
+                            disk: name.clone(),
+                            device: partition.device.clone(),
+                            partuuid: partition.guid,
+                            uuid: uuid(&partition.device),
+                            mountpoint: tag.mountpoint.clone(),
+                        },
+                    ))
+                })
+            })
+            .collect();
+
+        ProvisionedLayout { partitions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+    use partitioning::writer::WrittenPartition;
+
+    use super::*;
+    use crate::{Parser, Provisioner};
+
+    #[test]
+    fn test_layout() {
+        let test_strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+        let plans = provisioner.plan();
+        let plan = plans.iter().find(|p| p.strategy.name == "whole_disk").unwrap();
+
+        // Pretend the plan was applied
+        let partitions = plan.device_assignments["root_disk"]
+            .planner()
+            .current_layout()
+            .into_iter()
+            .enumerate()
+            .map(|(i, region)| WrittenPartition {
+                number: i as u32 + 1,
+                region,
+                device: PathBuf::from(format!("/dev/mock0p{}", i + 1)),
+                guid: Uuid::from_u128(i as u128 + 1),
+            })
+            .collect::<Vec<_>>();
+        let report = ApplyReport {
+            devices: vec![(
+                "root_disk".into(),
+                "/dev/mock0".into(),
+                DeviceStatus::Written(partitions),
+            )],
+            filesystems: vec![],
+            subvolumes: vec![],
+            swapfiles: vec![],
+        };
+
+        let layout = plan.layout_with(&report, |device| {
+            (device != Path::new("/dev/mock0p4")).then(|| device.display().to_string().replace("/dev/", ""))
+        });
+        assert_eq!(layout.partitions.len(), 4);
+        let root = layout.by_mountpoint("/").unwrap();
+        assert_eq!(root.device, Path::new("/dev/mock0p3"));
+        assert_eq!(root.partuuid, Uuid::from_u128(3));
+        assert_eq!(root.uuid.as_deref(), Some("mock0p3"));
+        assert_eq!(layout.get("var").unwrap().uuid, None);
+        assert_eq!(layout.get("esp").unwrap().disk, "root_disk");
+    }
+}
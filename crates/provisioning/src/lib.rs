@@ -2,18 +2,75 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{fs, path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use itertools::{Either, Itertools};
 use kdl::{KdlDocument, KdlNode};
-use miette::{Diagnostic, NamedSource, Severity};
+use miette::{Diagnostic, NamedSource, Severity, SourceSpan};
 
 mod provisioner;
 pub use provisioner::*;
 
+mod apply;
+pub use apply::*;
+
+mod report;
+pub use report::*;
+
+mod fstab;
+pub use fstab::*;
+
+mod resume;
+pub use resume::*;
+
+mod simulate;
+pub use simulate::*;
+
+mod layout;
+pub use layout::*;
+
+mod inventory;
+pub use inventory::*;
+
+#[cfg(feature = "linux")]
+mod os_detect;
+#[cfg(feature = "linux")]
+pub use os_detect::*;
+
+#[cfg(feature = "linux")]
+mod recovery;
+#[cfg(feature = "linux")]
+pub use recovery::*;
+
+mod failure;
+pub use failure::*;
+
+mod convergence;
+pub use convergence::*;
+
+mod chooser;
+pub use chooser::*;
+
+mod sizing;
+
+mod dps;
+
+mod validate;
+
+mod diagnostics;
+pub use diagnostics::*;
+
 mod errors;
 pub use errors::*;
 
+mod facts;
+pub use facts::*;
+
 mod helpers;
 use helpers::*;
 
@@ -43,12 +100,21 @@ pub struct StrategyDefinition {
 
     /// The commands to execute
     pub commands: Vec<Command>,
+
+    /// Location of the strategy in its source
+    pub span: SourceSpan,
 }
 
 /// A parser for provisioning strategies
 #[derive(Debug)]
 pub struct Parser {
     pub strategies: Vec<StrategyDefinition>,
+
+    /// Non-fatal diagnostics, e.g. unsupported nodes that were ignored
+    pub diagnostics: DiagnosticSink,
+
+    /// The source each strategy was parsed from, keyed by strategy name
+    sources: HashMap<String, NamedSource<Arc<String>>>,
 }
 
 impl Parser {
@@ -66,11 +132,124 @@ impl Parser {
         Self::new(name.to_string(), txt)
     }
 
+    /// Load every `*.kdl` file in a directory into a single catalog
+    ///
+    /// Files are read in name order, so distributions can ship strategies as drop-in
+    /// files. Strategies may inherit from strategies in other files, but names must be
+    /// unique across the directory and every parent must exist.
+    pub fn load_dir<P>(dir: P) -> Result<Self, LoadError>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let load_error = |errors| LoadError {
+            path: dir.to_owned(),
+            errors,
+        };
+        let io_error = |path: &Path, e: io::Error| ParseError {
+            src: NamedSource::new(path.to_string_lossy(), Arc::new("".to_string())),
+            diagnostics: vec![e.into()],
+        };
+
+        let mut files = fs::read_dir(dir)
+            .and_then(|entries| entries.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>())
+            .map_err(|e| load_error(vec![io_error(dir, e)]))?;
+        files.retain(|f| f.extension().is_some_and(|e| e == "kdl"));
+        files.sort();
+
+        let mut errors = vec![];
+        let mut strategies = vec![];
+        let mut diagnostics = DiagnosticSink::default();
+        let mut sources: HashMap<String, (PathBuf, NamedSource<Arc<String>>)> = HashMap::new();
+
+        for file in files {
+            let name = file.to_string_lossy().to_string();
+            let contents = match fs::read_to_string(&file) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    errors.push(io_error(&file, e));
+                    continue;
+                }
+            };
+            let src = NamedSource::new(&name, Arc::new(contents.clone())).with_language("KDL");
+            let parser = match Self::new(name, contents) {
+                Ok(parser) => parser,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+
+            if !parser.diagnostics.is_empty() {
+                diagnostics.push(Box::new(parser.diagnostics));
+            }
+            for strategy in parser.strategies {
+                if let Some((first, _)) = sources.get(&strategy.name) {
+                    errors.push(ParseError {
+                        src: src.clone(),
+                        diagnostics: vec![Error::DuplicateStrategy {
+                            name: strategy.name,
+                            first: first.clone(),
+                        }],
+                    });
+                    continue;
+                }
+                sources.insert(strategy.name.clone(), (file.clone(), src.clone()));
+                strategies.push(strategy);
+            }
+        }
+
+        // Every parent must exist and chains must terminate
+        let parents = strategies
+            .iter()
+            .filter_map(|s| Some((s.name.as_str(), s.inherits.as_deref()?)))
+            .collect::<HashMap<_, _>>();
+        let in_cycle = |name: &str| {
+            let mut seen = HashSet::new();
+            let mut current = name;
+            while let Some(parent) = parents.get(current) {
+                if *parent == name {
+                    return true;
+                }
+                if !seen.insert(*parent) {
+                    return false;
+                }
+                current = parent;
+            }
+            false
+        };
+        for strategy in &strategies {
+            let error = match strategy.inherits.as_deref() {
+                Some(parent) if !sources.contains_key(parent) => Error::UnknownParent {
+                    name: strategy.name.clone(),
+                    parent: parent.to_owned(),
+                },
+                Some(_) if in_cycle(&strategy.name) => Error::InheritanceCycle(strategy.name.clone()),
+                _ => continue,
+            };
+            errors.push(ParseError {
+                src: sources[&strategy.name].1.clone(),
+                diagnostics: vec![error],
+            });
+        }
+
+        if !errors.is_empty() {
+            return Err(load_error(errors));
+        }
+
+        Ok(Self {
+            strategies,
+            diagnostics,
+            sources: sources.into_iter().map(|(name, (_, src))| (name, src)).collect(),
+        })
+    }
+
     /// Create a new parser from a string
     pub fn new(name: String, contents: String) -> Result<Self, ParseError> {
         let source = Arc::new(contents.to_string());
         let ns = NamedSource::new(name, source).with_language("KDL");
         let mut errors = vec![];
+        let mut diagnostics = DiagnosticSink::for_source(ns.clone());
 
         // Parse the document and collect any errors
         let d = KdlDocument::parse_v2(ns.inner()).map_err(|e| ParseError {
@@ -83,18 +262,16 @@ impl Parser {
         for node in d.nodes() {
             match node.name().value() {
                 "strategy" => match Self::parse_strategy(node) {
-                    Ok(strategy) => strategies.push(strategy),
+                    Ok((strategy, warnings)) => {
+                        strategies.push(strategy);
+                        warnings.into_iter().for_each(|w| diagnostics.push(w));
+                    }
                     Err(e) => errors.extend(e),
                 },
-                _ => {
-                    errors.push(
-                        UnsupportedNode {
-                            at: node.span(),
-                            name: node.name().to_string(),
-                        }
-                        .into(),
-                    );
-                }
+                _ => diagnostics.push(UnsupportedNode {
+                    at: node.span(),
+                    name: node.name().to_string(),
+                }),
             }
         }
 
@@ -105,11 +282,16 @@ impl Parser {
             });
         }
 
-        Ok(Self { strategies })
+        let sources = strategies.iter().map(|s| (s.name.clone(), ns.clone())).collect();
+        Ok(Self {
+            strategies,
+            diagnostics,
+            sources,
+        })
     }
 
-    // Parse a strategy node
-    fn parse_strategy(node: &KdlNode) -> Result<StrategyDefinition, Vec<Error>> {
+    // Parse a strategy node, returning it along with any non-fatal diagnostics
+    fn parse_strategy(node: &KdlNode) -> Result<(StrategyDefinition, Vec<Error>), Vec<Error>> {
         let mut errors = vec![];
         let name = match get_property_str(node, "name") {
             Ok(name) => name,
@@ -152,8 +334,6 @@ impl Parser {
             .filter(|e| matches!(e.severity().unwrap_or(Severity::Error), Severity::Error));
 
         // If we have any fatal errors, bail out
-        // TODO: Add an error sink to allow bubbling up of warnings/diagnostics
-        // for tooling integration
         if fatal_errors.clone().next().is_some() {
             return Err(errors);
         }
@@ -163,15 +343,18 @@ impl Parser {
             summary,
             inherits,
             commands,
+            span: node.span(),
         };
 
-        Ok(strategy)
+        Ok((strategy, errors))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Parser;
+    use disks::{mock::MockDisk, BlockDevice};
+
+    use crate::{Parser, Provisioner};
 
     #[test]
     //#[should_panic]
@@ -180,4 +363,72 @@ mod tests {
         eprintln!("p: {_p:?}");
         Ok(())
     }
+
+    #[test]
+    fn test_diagnostics() {
+        let kdl = r#"
+            strategy name="small_esp" summary="An ESP that is too small" {
+                find-disk "root_disk"
+                frobnicate-disk disk="root_disk"
+                create-partition disk="root_disk" role="boot" id="esp" {
+                    constraints {
+                        exactly (MIB)100
+                    }
+                }
+            }
+        "#;
+        let p = Parser::new("small_esp.kdl".into(), kdl.into()).unwrap();
+        assert_eq!(p.diagnostics.diagnostics().len(), 1);
+        let rendered = format!("{:?}", miette::Report::new(p.diagnostics));
+        assert!(rendered.contains("unsupported node: frobnicate-disk"));
+
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * 1024 * 1024 * 1024)));
+        for def in p.strategies {
+            provisioner.add_strategy(def);
+        }
+        let plans = provisioner.plan();
+        let warnings = plans[0].diagnostics.diagnostics();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0]
+            .to_string()
+            .contains("smaller than the recommended 512.0MiB"));
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let p = Parser::load_dir("tests/strategies.d").unwrap();
+        let names = p.strategies.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["whole_disk", "whole_disk_with_swap"]);
+
+        // Inheritance resolves across files
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * 1024 * 1024 * 1024)));
+        for def in p.strategies {
+            provisioner.add_strategy(def);
+        }
+        let plans = provisioner.plan();
+        let plan = plans
+            .iter()
+            .find(|p| p.strategy.name == "whole_disk_with_swap")
+            .unwrap();
+        assert_eq!(plan.device_assignments["root_disk"].planner().current_layout().len(), 3);
+
+        let err = Parser::load_dir("tests/strategies.invalid").unwrap_err();
+        let errors = err
+            .errors
+            .iter()
+            .flat_map(|e| &e.diagnostics)
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "duplicate strategy whole_disk, first defined in tests/strategies.invalid/10-base.kdl",
+                "strategy orphan inherits unknown strategy missing",
+                "strategy ping inherits from itself",
+                "strategy pong inherits from itself",
+            ]
+        );
+    }
 }
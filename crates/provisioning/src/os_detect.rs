@@ -0,0 +1,329 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detecting operating systems already installed on a disk
+//!
+//! Installers offering dual boot need to tell the user what lives on a disk before it
+//! is touched, e.g. "Windows on nvme0n1p3". [`detect_os()`] combines three sources for
+//! every partition, from cheapest to most expensive:
+//!
+//! - The partition type from the GPT or MBR, see [`partitioning::partition_types`].
+//!   Apple partitions are reported from their type alone.
+//! - The filesystem, probed from its superblock. NTFS and BitLocker volumes are
+//!   recognised by their boot sector.
+//! - A handful of files, checked on a private read-only mount: the Windows Boot Manager
+//!   on an ESP, `Windows/System32` on NTFS and `os-release` on Linux filesystems.
+//!
+//! Nothing is written and mounts are never visible to the rest of the system. Partitions
+//! that cannot be inspected, e.g. LUKS containers or filesystems without a kernel driver,
+//! are skipped.
+
+use std::{
+    fmt, fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use disks::{partition::Partition, probe_cache, BlockDevice};
+use partitioning::{
+    namespace,
+    partition_types::{self, Category, PartitionType},
+    table,
+};
+use serde::Serialize;
+use superblock::{Kind, Superblock};
+use tracing::debug;
+
+/// OEM id of NTFS volumes, at offset 3 of the boot sector
+const NTFS_OEM_ID: &[u8; 8] = b"NTFS    ";
+
+/// OEM id of BitLocker encrypted volumes
+const BITLOCKER_OEM_ID: &[u8; 8] = b"-FVE-FS-";
+
+/// Windows Boot Manager on an EFI System Partition
+const WINDOWS_BOOT_MANAGER: &str = "EFI/Microsoft/Boot/bootmgfw.efi";
+
+/// Directory only present on Windows system volumes
+const WINDOWS_SYSTEM: &str = "Windows/System32";
+
+/// Locations of os-release, relative to the root of a Linux filesystem
+const OS_RELEASE: [&str; 2] = ["etc/os-release", "usr/lib/os-release"];
+
+/// Common root subvolumes, checked when the top level of a btrfs filesystem isn't a root
+const BTRFS_ROOTS: [&str; 3] = ["@", "root", "@rootfs"];
+
+/// Family of a detected operating system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OsFamily {
+    Windows,
+    Linux,
+    MacOs,
+}
+
+impl fmt::Display for OsFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Windows => f.write_str("windows"),
+            Self::Linux => f.write_str("linux"),
+            Self::MacOs => f.write_str("macos"),
+        }
+    }
+}
+
+/// An operating system, or its boot loader, found on a partition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DetectedOs {
+    /// Family of the operating system
+    pub family: OsFamily,
+    /// Human readable name, e.g. "Windows" or "Fedora Linux 41 (Workstation Edition)"
+    pub name: String,
+    /// Device node of the partition
+    pub partition: PathBuf,
+    /// Partition type, if known
+    pub partition_type: Option<&'static str>,
+    /// Filesystem of the partition, if known
+    pub filesystem: Option<String>,
+    /// True if the partition only holds the boot loader, e.g. an ESP
+    pub boot_loader: bool,
+    /// True if the contents are encrypted and could not be inspected
+    pub encrypted: bool,
+}
+
+impl fmt::Display for DetectedOs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let partition = self.partition.file_name().unwrap_or(self.partition.as_os_str());
+        write!(f, "{} on {}", self.name, partition.to_string_lossy())?;
+        if self.encrypted {
+            f.write_str(" (encrypted)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Filesystem of a partition, as far as detection is concerned
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filesystem {
+    /// Any filesystem known to the superblock crate
+    Known(Kind),
+    Ntfs,
+    BitLocker,
+}
+
+impl Filesystem {
+    fn name(&self) -> String {
+        match self {
+            Self::Known(kind) => kind.to_string(),
+            Self::Ntfs => "ntfs".to_owned(),
+            Self::BitLocker => "bitlocker".to_owned(),
+        }
+    }
+}
+
+/// Detect the operating systems installed on `device`
+///
+/// Partitions are inspected in order, so the result is ordered by partition number.
+pub fn detect_os(device: &BlockDevice) -> Vec<DetectedOs> {
+    let _cache = probe_cache::pass();
+    let gpt = probe_cache::open(device.device())
+        .map_err(table::Error::from)
+        .and_then(|mut f| table::read(&mut f))
+        .ok();
+
+    device
+        .partitions()
+        .iter()
+        .filter_map(|partition| {
+            let partition_type = match (&gpt, &partition.mbr) {
+                (Some(gpt), _) => gpt
+                    .entry(partition.number)
+                    .and_then(|e| partition_types::by_guid(&e.type_guid)),
+                (None, Some(entry)) => partition_types::by_mbr(entry.partition_type),
+                (None, None) => None,
+            };
+            detect_partition(partition, partition_type)
+        })
+        .collect()
+}
+
+/// Detect the operating system on a single partition
+fn detect_partition(partition: &Partition, partition_type: Option<&'static PartitionType>) -> Option<DetectedOs> {
+    let filesystem = probe_cache::open(&partition.device)
+        .ok()
+        .and_then(|mut f| probe_filesystem(&mut f).ok().flatten());
+    let detected = identify(partition_type, filesystem.as_ref(), &partition.device)?;
+
+    debug!(partition = %partition.name, name = %detected.0, "Detected operating system");
+    Some(DetectedOs {
+        family: detected.1,
+        name: detected.0,
+        partition: partition.device.clone(),
+        partition_type: partition_type.map(|t| t.name),
+        filesystem: filesystem.as_ref().map(Filesystem::name),
+        boot_loader: partition_type.is_some_and(|t| t.category == Category::Efi),
+        encrypted: filesystem == Some(Filesystem::BitLocker),
+    })
+}
+
+/// Name and family of the operating system on a partition, if any
+fn identify(
+    partition_type: Option<&PartitionType>,
+    filesystem: Option<&Filesystem>,
+    device: &Path,
+) -> Option<(String, OsFamily)> {
+    if let Some(t) = partition_type.filter(|t| t.name.starts_with("Apple")) {
+        return Some((format!("macOS ({})", t.name), OsFamily::MacOs));
+    }
+
+    let windows = || ("Windows".to_owned(), OsFamily::Windows);
+    match filesystem? {
+        Filesystem::BitLocker => Some(windows()),
+        Filesystem::Ntfs => inspect(device, &["ntfs3", "ntfs"], None, |root| {
+            root.join(WINDOWS_SYSTEM).is_dir().then(windows)
+        }),
+        Filesystem::Known(Kind::FAT) if partition_type.is_some_and(|t| t.category == Category::Efi) => {
+            inspect(device, &["vfat"], None, |root| {
+                root.join(WINDOWS_BOOT_MANAGER)
+                    .is_file()
+                    .then(|| ("Windows Boot Manager".to_owned(), OsFamily::Windows))
+            })
+        }
+        Filesystem::Known(Kind::Btrfs) => inspect(device, &["btrfs"], Some("subvolid=5"), |root| {
+            std::iter::once(root.to_owned())
+                .chain(BTRFS_ROOTS.iter().map(|s| root.join(s)))
+                .find_map(|r| linux_name(&r))
+        }),
+        Filesystem::Known(kind @ (Kind::Ext4 | Kind::XFS | Kind::F2FS)) => {
+            inspect(device, &[&kind.to_string()], None, linux_name)
+        }
+        _ => None,
+    }
+}
+
+/// Mount `device` read-only with the first working filesystem type and run `f`
+fn inspect<T: Send>(
+    device: &Path,
+    fstypes: &[&str],
+    options: Option<&str>,
+    f: impl Fn(&Path) -> Option<T> + Sync,
+) -> Option<T> {
+    fstypes
+        .iter()
+        .find_map(|fstype| match namespace::inspect(device, fstype, options, &f) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                debug!(device = %device.display(), fstype, "Cannot inspect filesystem: {}", e);
+                None
+            }
+        })
+        .flatten()
+}
+
+/// Name of the Linux distribution rooted at `root`
+fn linux_name(root: &Path) -> Option<(String, OsFamily)> {
+    OS_RELEASE
+        .iter()
+        .find_map(|path| fs::read_to_string(root.join(path)).ok())
+        .and_then(|contents| os_release_name(&contents))
+        .map(|name| (name, OsFamily::Linux))
+}
+
+/// Extract a display name from the contents of an os-release file
+///
+/// `PRETTY_NAME` is preferred, falling back to `NAME` and `VERSION`.
+fn os_release_name(contents: &str) -> Option<String> {
+    let field = |key: &str| {
+        contents.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+            let value = value.trim_matches(|c| c == '"' || c == '\'').trim();
+            (!value.is_empty()).then(|| value.to_owned())
+        })
+    };
+
+    field("PRETTY_NAME").or_else(|| match (field("NAME"), field("VERSION")) {
+        (Some(name), Some(version)) => Some(format!("{name} {version}")),
+        (name, _) => name,
+    })
+}
+
+/// Probe the filesystem of a partition
+fn probe_filesystem<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Filesystem>> {
+    let mut boot = [0u8; 11];
+    reader.seek(SeekFrom::Start(0))?;
+    match reader.read_exact(&mut boot) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    match &boot[3..11] {
+        id if id == NTFS_OEM_ID => return Ok(Some(Filesystem::Ntfs)),
+        id if id == BITLOCKER_OEM_ID => return Ok(Some(Filesystem::BitLocker)),
+        _ => {}
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(Superblock::from_reader(reader)
+        .ok()
+        .map(|sb| Filesystem::Known(sb.kind())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_os_release_name() {
+        let fedora = "NAME=\"Fedora Linux\"\nVERSION=\"41 (Workstation Edition)\"\n\
+                      PRETTY_NAME=\"Fedora Linux 41 (Workstation Edition)\"\n";
+        assert_eq!(
+            os_release_name(fedora).as_deref(),
+            Some("Fedora Linux 41 (Workstation Edition)")
+        );
+        assert_eq!(
+            os_release_name("NAME='Serpent OS'\nVERSION=0.24\n").as_deref(),
+            Some("Serpent OS 0.24")
+        );
+        assert_eq!(
+            os_release_name("NAME=Arch\nPRETTY_NAME=\"\"\n").as_deref(),
+            Some("Arch")
+        );
+        assert_eq!(os_release_name("ID=linux\n"), None);
+    }
+
+    #[test]
+    fn test_identify_without_mounting() {
+        let mut boot = vec![0u8; 4096];
+        boot[3..11].copy_from_slice(BITLOCKER_OEM_ID);
+        let filesystem = probe_filesystem(&mut Cursor::new(&boot)).unwrap();
+        assert_eq!(filesystem, Some(Filesystem::BitLocker));
+
+        let basic_data = partition_types::by_name("Microsoft basic data");
+        let (name, family) = identify(basic_data, filesystem.as_ref(), Path::new("/dev/sda3")).unwrap();
+        assert_eq!((name.as_str(), family), ("Windows", OsFamily::Windows));
+
+        let apfs = partition_types::by_name("Apple APFS");
+        let (_, family) = identify(apfs, None, Path::new("/dev/sda2")).unwrap();
+        assert_eq!(family, OsFamily::MacOs);
+
+        assert!(probe_filesystem(&mut Cursor::new(vec![0u8; 4096])).unwrap().is_none());
+        assert!(identify(basic_data, None, Path::new("/dev/sda3")).is_none());
+    }
+
+    #[test]
+    fn test_display() {
+        let detected = DetectedOs {
+            family: OsFamily::Windows,
+            name: "Windows".to_owned(),
+            partition: PathBuf::from("/dev/nvme0n1p3"),
+            partition_type: Some("Microsoft basic data"),
+            filesystem: Some("bitlocker".to_owned()),
+            boot_loader: false,
+            encrypted: true,
+        };
+        assert_eq!(detected.to_string(), "Windows on nvme0n1p3 (encrypted)");
+    }
+}
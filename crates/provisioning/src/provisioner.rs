@@ -3,16 +3,38 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use disks::BlockDevice;
-use log::{debug, info, trace, warn};
+use disks::{
+    md,
+    mount::{self, Mount},
+    BlockDevice,
+};
+use itertools::Itertools;
 use partitioning::{
-    planner::Planner,
+    btrfs::SubvolumeLayout,
+    cancel::CancellationToken,
+    classify::{self, Classification},
+    format::{self, Format},
+    partition_type::Role,
+    planner::{format_size, PartitionTag, Planner, TableType},
     strategy::{AllocationStrategy, PartitionRequest, SizeRequirement, Strategy},
+    swapfile::Swapfile,
+    wipe::ErasePolicy,
 };
+use tracing::{debug, info, instrument, trace, warn};
 
-use crate::{commands::Command, Constraints, StrategyDefinition};
+use crate::{
+    commands::Command, sizing::minimum_sizes, Constraints, DeviceChooser, DiagnosticSink, Exclusions, Facts,
+    FilesystemType, LogicalVolume, Luks, PartitionRole, PartitionTableType, RaidArray, StrategyDefinition, Unattended,
+    Variables, VolumeGroup,
+};
 
 /// Provisioner
 pub struct Provisioner {
@@ -21,19 +43,205 @@ pub struct Provisioner {
 
     /// Strategy configurations
     configs: HashMap<String, StrategyDefinition>,
+
+    /// Mounted filesystems, used by exclusion rules
+    mounts: Vec<Mount>,
+
+    /// Facts about the running system, used by conditions and memory constraints
+    facts: Facts,
+
+    /// Settles ambiguous disk searches and confirms destructive changes
+    chooser: Box<dyn DeviceChooser>,
+
+    /// Maximum number of plans generated per strategy, if limited
+    branch_limit: Option<usize>,
+
+    /// Whether to skip disk bindings equivalent to one already planned
+    deduplicate: bool,
+
+    /// Whether plans may be applied to disks that are in use
+    allow_in_use: bool,
+
+    /// Whether plans may edit partition tables that are foreign or corrupt
+    force: bool,
+
+    /// Partition tables of the pool devices, classified as they are pushed
+    tables: HashMap<PathBuf, Classification>,
+
+    /// Stops plans that are being applied
+    cancel: CancellationToken,
+
+    /// Maximum bytes per second written while erasing disks, if limited
+    rate_limit: Option<u64>,
+
+    /// Maximum number of disks written at the same time
+    parallelism: usize,
 }
 
+/// Where live installer media are commonly mounted
+const LIVE_MEDIUM_MOUNTPOINTS: &[&str] = &[
+    "/run/initramfs/live",
+    "/run/live/medium",
+    "/run/archiso/bootmnt",
+    "/run/rootfsbase",
+    "/cdrom",
+];
+
 /// Compiled plan
 pub struct Plan<'a> {
     pub strategy: &'a StrategyDefinition,
     pub device_assignments: HashMap<String, DevicePlan<'a>>,
+    /// LVM volume groups, which may span several disks
+    pub volume_groups: Vec<VolumeGroup>,
+    /// Problems found while building the plan
+    pub diagnostics: DiagnosticSink,
+    /// Confirms destructive changes before they are applied
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    pub(crate) chooser: &'a dyn DeviceChooser,
+    /// Whether disks may be written while in use
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    pub(crate) allow_in_use: bool,
+    /// Whether foreign or corrupt partition tables may be edited
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    pub(crate) force: bool,
+    /// Checked between the steps of applying the plan
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    pub(crate) cancel: CancellationToken,
+    /// Maximum bytes per second written while erasing disks
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    pub(crate) rate_limit: Option<u64>,
+    /// Maximum number of disks written at the same time
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    pub(crate) parallelism: usize,
+}
+
+/// The device a plan is built for
+#[derive(Debug, Clone)]
+enum PlanDevice<'a> {
+    /// A device from the provisioner pool
+    Pool(&'a BlockDevice),
+    /// A device that only exists once the plan is applied, e.g. a RAID array
+    Virtual(Arc<BlockDevice>),
+}
+
+impl Deref for PlanDevice<'_> {
+    type Target = BlockDevice;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            PlanDevice::Pool(device) => device,
+            PlanDevice::Virtual(device) => device,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DevicePlan<'a> {
-    device: &'a BlockDevice,
+    device: PlanDevice<'a>,
     planner: Planner,
     strategy: Strategy,
+    filesystems: Vec<(String, Format)>,
+    encryption: Vec<(String, Luks)>,
+    subvolumes: Vec<(String, SubvolumeLayout)>,
+    swapfiles: Vec<(String, Swapfile)>,
+    array: Option<RaidArray>,
+    member_of: Option<String>,
+    erase: ErasePolicy,
+    whole_disk: Option<PartitionTag>,
+}
+
+impl<'a> DevicePlan<'a> {
+    fn new(device: PlanDevice<'a>, strategy: Strategy) -> Self {
+        Self {
+            planner: Planner::new(&device),
+            device,
+            strategy,
+            filesystems: Vec::new(),
+            encryption: Vec::new(),
+            subvolumes: Vec::new(),
+            swapfiles: Vec::new(),
+            array: None,
+            member_of: None,
+            erase: ErasePolicy::None,
+            whole_disk: None,
+        }
+    }
+
+    /// The filesystem on the whole device that blocks the plan, if any
+    ///
+    /// Disks without a partition table but with a filesystem are treated as in use, unless
+    /// they are wiped or disks in use are allowed.
+    pub(crate) fn whole_disk_filesystem(&self, allow_in_use: bool) -> Option<&superblock::Kind> {
+        if allow_in_use || self.erase != ErasePolicy::None {
+            return None;
+        }
+        self.device.filesystem()
+    }
+
+    /// The device this plan applies to
+    pub fn device(&self) -> &BlockDevice {
+        &self.device
+    }
+
+    /// Btrfs subvolumes to create, keyed by partition id
+    pub fn subvolumes(&self) -> &[(String, SubvolumeLayout)] {
+        &self.subvolumes
+    }
+
+    /// Swapfiles to create, keyed by the id of the partition holding them
+    pub fn swapfiles(&self) -> &[(String, Swapfile)] {
+        &self.swapfiles
+    }
+
+    /// The array this plan creates, if the device is a planned RAID array
+    pub fn array(&self) -> Option<&RaidArray> {
+        self.array.as_ref()
+    }
+
+    /// The array this whole disk is a member of, if any
+    pub fn member_of(&self) -> Option<&str> {
+        self.member_of.as_deref()
+    }
+
+    /// How the device is erased before partitioning
+    pub fn erase(&self) -> ErasePolicy {
+        self.erase
+    }
+
+    /// The volume formatted on the whole device, without a partition table, if any
+    pub fn whole_disk(&self) -> Option<&PartitionTag> {
+        self.whole_disk.as_ref()
+    }
+
+    /// The planned changes for the device
+    pub fn planner(&self) -> &Planner {
+        &self.planner
+    }
+
+    /// Mutable access to the planned changes, for adjustments after planning
+    pub(crate) fn planner_mut(&mut self) -> &mut Planner {
+        &mut self.planner
+    }
+
+    /// Filesystems to create, keyed by partition id
+    pub fn filesystems(&self) -> &[(String, Format)] {
+        &self.filesystems
+    }
+
+    /// LUKS2 volumes to create, keyed by partition id
+    pub fn encryption(&self) -> &[(String, Luks)] {
+        &self.encryption
+    }
+
+    /// Returns true if a partition with the given id was requested on this device
+    fn has_partition(&self, id: &str) -> bool {
+        self.whole_disk.as_ref().and_then(|t| t.id.as_deref()) == Some(id)
+            || self
+                .strategy
+                .requests()
+                .iter()
+                .any(|r| r.tag.as_ref().and_then(|t| t.id.as_deref()) == Some(id))
+    }
 }
 
 impl Default for Provisioner {
@@ -46,21 +254,130 @@ impl Provisioner {
     /// Create a new provisioner
     pub fn new() -> Self {
         debug!("Creating new provisioner");
+        let mounts = mount::mounts().unwrap_or_else(|e| {
+            warn!("Failed to read mount table, mount exclusions will not apply: {}", e);
+            Vec::new()
+        });
         Self {
             devices: Vec::new(),
             configs: HashMap::new(),
+            mounts,
+            facts: Facts::gather(),
+            chooser: Box::new(Unattended),
+            branch_limit: None,
+            deduplicate: true,
+            allow_in_use: false,
+            force: false,
+            tables: HashMap::new(),
+            cancel: CancellationToken::default(),
+            rate_limit: None,
+            parallelism: std::thread::available_parallelism().map_or(1, |n| n.get()),
         }
     }
 
+    /// Replace the mount table used by exclusion rules
+    pub fn set_mounts(&mut self, mounts: Vec<Mount>) {
+        self.mounts = mounts;
+    }
+
+    /// Replace the system facts strategies are evaluated against
+    pub fn set_facts(&mut self, facts: Facts) {
+        self.facts = facts;
+    }
+
+    /// Use `chooser` to pick between candidate disks and confirm destructive changes
+    pub fn set_chooser(&mut self, chooser: impl DeviceChooser + 'static) {
+        self.chooser = Box::new(chooser);
+    }
+
+    /// Limit the number of plans generated for each strategy
+    ///
+    /// Every combination of matching disks yields a plan, which quickly explodes on
+    /// machines with many disks. Once the limit is reached no further disk combinations
+    /// are considered; disks are tried in order of their WWN, serial and device path, so
+    /// the same plans are produced on every run.
+    pub fn set_branch_limit(&mut self, limit: Option<usize>) {
+        self.branch_limit = limit;
+    }
+
+    /// Whether to plan only once for disks that are interchangeable (enabled by default)
+    ///
+    /// Disks are interchangeable when they have the same size, model, removability and
+    /// partition layout, as is common with arrays of identical disks. The plan for the
+    /// first such disk, in stable order, stands in for all of them.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.deduplicate = deduplicate;
+    }
+
+    /// Whether plans may be applied to disks that are mounted or otherwise in use (disabled by default)
+    ///
+    /// Only meant for tools that know what they are doing, e.g. when repartitioning the
+    /// free space of a disk the running system lives on.
+    pub fn set_allow_in_use(&mut self, allow: bool) {
+        self.allow_in_use = allow;
+    }
+
+    /// Whether plans may edit partition tables that are foreign or corrupt (disabled by default)
+    ///
+    /// Every pushed device has its partition table classified first, see
+    /// [`partitioning::classify`]. Plans that edit an MBR with logical partitions, a dynamic
+    /// disk or a damaged GPT get a diagnostic and fail to apply unless forced. Plans creating
+    /// a new table are not affected.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// Stop applying plans once `token` is cancelled or times out
+    ///
+    /// Applying checks the token between disks and steps, and while erasing. A cancelled
+    /// write rolls back like any other failure of [`Plan::apply()`]. Filesystems not yet
+    /// created fail with [`format::Error::Cancelled`] and their partitions stay pending.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    /// Limit the throughput of erasing disks to `rate` bytes per second (unlimited by default)
+    ///
+    /// Keeps the system responsive while a disk of a live system is zeroed in the background.
+    pub fn set_rate_limit(&mut self, rate: Option<u64>) {
+        self.rate_limit = rate;
+    }
+
+    /// Write and format at most `limit` disks at the same time (one per CPU by default)
+    ///
+    /// Disks are independent once validated, so servers with many drives are erased,
+    /// partitioned and formatted concurrently. With a limit of 1 disks are handled one
+    /// after another and progress events are not wrapped in [`partitioning::progress::Event::Device`].
+    pub fn set_parallelism(&mut self, limit: usize) {
+        self.parallelism = limit.max(1);
+    }
+
     /// Add a strategy configuration
     pub fn add_strategy(&mut self, config: StrategyDefinition) {
         info!("Adding strategy: {}", config.name);
         self.configs.insert(config.name.clone(), config);
     }
 
+    /// Compute the minimum size in bytes of each disk used by the named strategy
+    ///
+    /// Inherited commands and conditional blocks are resolved as they would be when
+    /// planning. Returns `None` if no such strategy has been added.
+    pub fn minimum_disk_size(&self, strategy: &str) -> Option<HashMap<String, u64>> {
+        let strategy = self.configs.get(strategy)?;
+        let chain = self.strategy_parents(strategy);
+        let commands = self.inherit_commands(&chain);
+        Some(minimum_sizes(commands.iter().map(|c| c.as_ref()), &self.facts))
+    }
+
     // Add a device to the provisioner pool
     pub fn push_device(&mut self, device: BlockDevice) {
         debug!("Adding device to pool: {:?}", device);
+        match classify::classify(&device) {
+            Ok(classification) => {
+                self.tables.insert(device.device().to_owned(), classification);
+            }
+            Err(e) => debug!("Cannot classify partition table of {:?}: {}", device.device(), e),
+        }
         self.devices.push(device)
     }
 
@@ -78,120 +395,816 @@ impl Provisioner {
     }
 
     /// Attempt all strategies on the pool of devices
-    pub fn plan(&self) -> Vec<Plan> {
+    pub fn plan(&self) -> Vec<Plan<'_>> {
         info!("Planning device provisioning");
         let mut plans = Vec::new();
-        for strategy in self.configs.values() {
+        for strategy in self.configs.values().sorted_by_key(|s| &s.name) {
             debug!("Attempting strategy: {}", strategy.name);
-            self.create_plans_for_strategy(strategy, &mut HashMap::new(), &mut plans);
+            self.create_plans_for_strategy(strategy, &mut plans);
         }
         debug!("Generated {} plans", plans.len());
         plans
     }
 
-    fn create_plans_for_strategy<'a>(
-        &'a self,
-        strategy: &'a StrategyDefinition,
-        device_assignments: &mut HashMap<String, DevicePlan<'a>>,
-        plans: &mut Vec<Plan<'a>>,
-    ) {
+    #[instrument(name = "plan", skip_all, fields(strategy = %strategy.name))]
+    fn create_plans_for_strategy<'a>(&'a self, strategy: &'a StrategyDefinition, plans: &mut Vec<Plan<'a>>) {
         trace!("Creating plans for strategy: {}", strategy.name);
         let chain = self.strategy_parents(strategy);
+        let commands = self.inherit_commands(&chain);
 
-        for command in chain.iter().flat_map(|s| &s.commands) {
-            match command {
-                Command::FindDisk(command) => {
-                    // Skip if already assigned
-                    if device_assignments.contains_key(&command.name) {
-                        trace!("Disk {} already assigned, skipping", command.name);
-                        continue;
-                    }
+        // Disks are bound up front so every other command runs once per binding
+        let mut searches: Vec<DiskSearch<'_>> = vec![];
+        for command in &commands {
+            let search = match command.as_ref() {
+                Command::FindDisk(command) => DiskSearch {
+                    names: vec![command.name.clone()],
+                    constraints: command.constraints.as_ref(),
+                    exclusions: &command.exclusions,
+                    reserve: command.reserve,
+                },
+                Command::FindDisks(command) => DiskSearch {
+                    names: command.disk_names(),
+                    constraints: command.constraints.as_ref(),
+                    exclusions: &command.exclusions,
+                    reserve: command.reserve,
+                },
+                _ => continue,
+            };
 
-                    // Find matching devices that haven't been assigned yet
-                    let matching_devices: Vec<_> = self
-                        .devices
-                        .iter()
-                        .filter(|d| match command.constraints.as_ref() {
-                            Some(Constraints::AtLeast(n)) => d.size() >= *n,
-                            Some(Constraints::Exact(n)) => d.size() == *n,
-                            Some(Constraints::Range { min, max }) => d.size() >= *min && d.size() <= *max,
-                            _ => true,
-                        })
-                        .filter(|d| {
-                            !device_assignments
-                                .values()
-                                .any(|assigned| std::ptr::eq(assigned.device, *d))
-                        })
-                        .collect();
+            // Skip if already bound by an earlier command (e.g. in a parent strategy)
+            if searches
+                .iter()
+                .any(|s| s.names.iter().any(|n| search.names.contains(n)))
+            {
+                trace!("Disks {:?} already bound, skipping", search.names);
+                continue;
+            }
+            searches.push(search);
+        }
 
-                    debug!("Found {} matching devices for {}", matching_devices.len(), command.name);
-
-                    // Branch for each matching device
-                    for device in matching_devices {
-                        trace!("Creating plan branch for device: {:?}", device);
-                        let mut new_assignments = device_assignments.clone();
-                        new_assignments.insert(
-                            command.name.clone(),
-                            DevicePlan {
-                                device,
-                                planner: Planner::new(device),
-                                strategy: Strategy::new(AllocationStrategy::LargestFree),
-                            },
-                        );
-                        self.create_plans_for_strategy(strategy, &mut new_assignments, plans);
-                    }
+        let mut bindings = vec![];
+        self.bind_disks(&searches, &mut vec![], &mut bindings);
+        debug!("Found {} disk bindings for strategy {}", bindings.len(), strategy.name);
+        if self.limit_reached(&bindings) {
+            warn!(
+                "Strategy {} reached the limit of {} plans, further disk combinations were not considered",
+                strategy.name,
+                bindings.len()
+            );
+        }
+
+        for binding in bindings {
+            plans.push(self.build_plan(strategy, &commands, binding));
+        }
+    }
 
-                    return;
+    /// Merge the commands of an inheritance chain, from the root strategy down
+    ///
+    /// Commands replace inherited commands with the same key in place, while
+    /// `remove-partition` and `adjust-partition` modify inherited partitions.
+    fn inherit_commands<'a>(&self, chain: &[&'a StrategyDefinition]) -> Vec<Cow<'a, Command>> {
+        let mut commands: Vec<Cow<'a, Command>> = vec![];
+        for strategy in chain {
+            let mut selected = vec![];
+            select_commands(&strategy.commands, &self.facts, &mut selected);
+
+            for command in selected {
+                match command {
+                    Command::RemovePartition(remove) => {
+                        let count = commands.len();
+                        commands.retain(|c| c.partition() != Some(remove.id.as_str()));
+                        if commands.len() == count {
+                            warn!("Could not find partition {} to remove in {}", remove.id, strategy.name);
+                        }
+                    }
+                    Command::AdjustPartition(adjust) => {
+                        let target = commands
+                            .iter_mut()
+                            .find(|c| matches!(c.as_ref(), Command::CreatePartition(p) if p.id == adjust.id));
+                        if let Some(Command::CreatePartition(partition)) = target.map(Cow::to_mut) {
+                            trace!("Adjusting partition {} in {}", adjust.id, strategy.name);
+                            if let Some(constraints) = &adjust.constraints {
+                                partition.constraints = constraints.clone();
+                            }
+                            if let Some(mountpoint) = &adjust.mountpoint {
+                                partition.mountpoint = Some(mountpoint.clone());
+                            }
+                        } else {
+                            warn!("Could not find partition {} to adjust in {}", adjust.id, strategy.name);
+                        }
+                    }
+                    command => {
+                        let key = command.key();
+                        match commands.iter_mut().find(|c| key.is_some() && c.key() == key) {
+                            Some(existing) => {
+                                trace!("Replacing inherited command {:?} in {}", key, strategy.name);
+                                *existing = Cow::Borrowed(command);
+                            }
+                            None => commands.push(Cow::Borrowed(command)),
+                        }
+                    }
                 }
+            }
+        }
+        commands
+    }
+
+    /// Variables for size expressions, with the size of the disk in scope if any
+    fn variables(&self, disk: Option<u64>) -> Variables {
+        Variables {
+            ram: self.facts.memory,
+            disk,
+        }
+    }
+
+    /// Recursively bind disks to the names of each search, branching over every choice
+    fn bind_disks<'a>(
+        &'a self,
+        searches: &[DiskSearch<'_>],
+        bound: &mut Vec<(String, &'a BlockDevice)>,
+        bindings: &mut Vec<Vec<(String, &'a BlockDevice)>>,
+    ) {
+        let Some((search, remaining)) = searches.split_first() else {
+            bindings.push(bound.clone());
+            return;
+        };
+
+        // Find matching devices that haven't been assigned yet, in a stable order
+        let matching_devices = self
+            .devices
+            .iter()
+            .filter(|d| search.matches(d, &self.mounts, &self.variables(Some(d.size()))))
+            .filter(|d| !bound.iter().any(|(_, assigned)| std::ptr::eq(*assigned, *d)))
+            .sorted_by_key(|d| (d.wwn(), d.serial(), d.device()))
+            .collect::<Vec<_>>();
+
+        debug!(
+            "Found {} matching devices for {:?}",
+            matching_devices.len(),
+            search.names
+        );
+
+        // A set is unordered, so branch over combinations rather than permutations
+        let needed = search.names.len();
+        let choice = if matching_devices.len() > needed {
+            self.chooser.choose(&search.names, &matching_devices)
+        } else {
+            None
+        };
+        let branches: Box<dyn Iterator<Item = Vec<&'a BlockDevice>>> = match choice {
+            Some(indices)
+                if indices.len() == needed
+                    && indices.iter().all_unique()
+                    && indices.iter().all(|i| *i < matching_devices.len()) =>
+            {
+                debug!("Chooser selected devices {:?} for {:?}", indices, search.names);
+                Box::new(std::iter::once(
+                    indices.into_iter().map(|i| matching_devices[i]).collect(),
+                ))
+            }
+            Some(indices) => {
+                warn!("Ignoring invalid choice {:?} for {:?}", indices, search.names);
+                self.branches(matching_devices, needed)
+            }
+            None => self.branches(matching_devices, needed),
+        };
+        for devices in branches {
+            if self.limit_reached(bindings) {
+                break;
+            }
+            trace!("Creating plan branch for devices: {:?}", devices);
+            let len = bound.len();
+            bound.extend(search.names.iter().cloned().zip(devices));
+            self.bind_disks(remaining, bound, bindings);
+            bound.truncate(len);
+        }
+    }
+
+    /// The sets of `needed` devices to branch over
+    ///
+    /// When deduplicating, only one set is produced per distinct mix of interchangeable
+    /// disks rather than one per combination.
+    fn branches<'a>(
+        &self,
+        devices: Vec<&'a BlockDevice>,
+        needed: usize,
+    ) -> Box<dyn Iterator<Item = Vec<&'a BlockDevice>> + 'a> {
+        if !self.deduplicate {
+            return Box::new(devices.into_iter().combinations(needed));
+        }
+
+        let mut classes: Vec<(DiskClass<'a>, Vec<&'a BlockDevice>)> = vec![];
+        for device in devices {
+            let class = DiskClass::of(device);
+            match classes.iter_mut().find(|(c, _)| *c == class) {
+                Some((_, members)) => members.push(device),
+                None => classes.push((class, vec![device])),
+            }
+        }
+
+        let classes = classes.into_iter().map(|(_, members)| members).collect::<Vec<_>>();
+        let mut sets = vec![];
+        distinct_sets(&classes, needed, &mut vec![], &mut sets);
+        Box::new(sets.into_iter())
+    }
+
+    /// Returns true if no further plans may be generated for a strategy
+    fn limit_reached<T>(&self, bindings: &[T]) -> bool {
+        self.branch_limit.is_some_and(|limit| bindings.len() >= limit)
+    }
+
+    /// Evaluate the commands of a strategy against a single disk binding
+    fn build_plan<'a>(
+        &'a self,
+        strategy: &'a StrategyDefinition,
+        commands: &[Cow<'_, Command>],
+        binding: Vec<(String, &'a BlockDevice)>,
+    ) -> Plan<'a> {
+        let mut device_assignments = binding
+            .into_iter()
+            .map(|(name, device)| {
+                let plan = DevicePlan::new(PlanDevice::Pool(device), Strategy::new(AllocationStrategy::LargestFree));
+                (name, plan)
+            })
+            .collect::<HashMap<_, _>>();
+        let mut volume_groups: Vec<VolumeGroup> = vec![];
+        let mut diagnostics = DiagnosticSink::default();
+
+        for command in commands {
+            match command.as_ref() {
+                Command::FindDisk(_)
+                | Command::FindDisks(_)
+                | Command::When(_)
+                | Command::AdjustPartition(_)
+                | Command::RemovePartition(_) => {}
                 Command::CreatePartitionTable(command) => {
                     if let Some(device_plan) = device_assignments.get_mut(&command.disk) {
                         debug!("Creating partition table on disk {}", command.disk);
+                        let table = match command.table_type {
+                            PartitionTableType::Gpt => TableType::Gpt,
+                            PartitionTableType::Msdos => TableType::Mbr,
+                        };
+                        device_plan.planner = Planner::new(&device_plan.device).with_table(table);
                         device_plan.strategy = Strategy::new(AllocationStrategy::InitializeWholeDisk);
                     } else {
-                        warn!("Could not find disk {} to create partition table", command.disk);
+                        diagnostics.warn(format!(
+                            "Could not find disk {} to create partition table",
+                            command.disk
+                        ));
                     }
                 }
+                Command::FormatWholeDisk(command) => match device_assignments.get_mut(&command.disk) {
+                    Some(device_plan) if device_plan.array.is_none() => {
+                        debug!("Using whole disk {} as {}", command.disk, command.id);
+                        // Existing partitions are lost along with the table
+                        let mut planner = Planner::new(&device_plan.device);
+                        for index in 0..device_plan.device.partitions().len() {
+                            if let Err(e) = planner.plan_delete_partition(index) {
+                                diagnostics.warn(format!("Failed to delete partition on disk {}: {}", command.disk, e));
+                            }
+                        }
+                        device_plan.planner = planner;
+                        // Stale partition table signatures would hide the new volume
+                        if device_plan.erase == ErasePolicy::None {
+                            device_plan.erase = ErasePolicy::Signatures;
+                        }
+                        device_plan.whole_disk = Some(PartitionTag {
+                            id: Some(command.id.clone()),
+                            role: command.role.as_ref().map(|r| r.to_string()),
+                            mountpoint: command.mountpoint.clone(),
+                            ..Default::default()
+                        });
+                    }
+                    Some(_) => diagnostics.warn(format!(
+                        "Cannot format array {} as a whole, it is not created yet",
+                        command.disk
+                    )),
+                    None => diagnostics.warn(format!("Could not find disk {} to format", command.disk)),
+                },
+                Command::WipeDisk(command) => match device_assignments.get_mut(&command.disk) {
+                    Some(device_plan) if device_plan.array.is_none() => {
+                        debug!("Erasing disk {} with policy {}", command.disk, command.policy);
+                        device_plan.erase = match command.policy {
+                            crate::ErasePolicy::None => ErasePolicy::None,
+                            crate::ErasePolicy::SignaturesOnly => ErasePolicy::Signatures,
+                            crate::ErasePolicy::Discard => ErasePolicy::Discard,
+                            crate::ErasePolicy::Zero => ErasePolicy::Zero,
+                            crate::ErasePolicy::SecureErase => ErasePolicy::SecureErase,
+                        };
+                    }
+                    Some(_) => diagnostics.warn(format!(
+                        "Cannot erase array {}, erase its members instead",
+                        command.disk
+                    )),
+                    None => diagnostics.warn(format!("Could not find disk {} to erase", command.disk)),
+                },
                 Command::CreatePartition(command) => {
                     if let Some(device_plan) = device_assignments.get_mut(&command.disk) {
                         debug!("Adding partition request for disk {}", command.disk);
-                        device_plan.strategy.add_request(PartitionRequest {
-                            size: match &command.constraints {
-                                Constraints::AtLeast(n) => SizeRequirement::AtLeast(*n),
-                                Constraints::Exact(n) => SizeRequirement::Exact(*n),
-                                Constraints::Range { min, max } => SizeRequirement::Range { min: *min, max: *max },
-                                _ => SizeRequirement::Remaining,
-                            },
+                        let variables = self.variables(Some(device_plan.device().size()));
+                        let Some(constraints) = command.constraints.resolve(&variables) else {
+                            diagnostics.warn(format!(
+                                "Could not evaluate size constraints of partition {}",
+                                command.id
+                            ));
+                            continue;
+                        };
+                        let size = match constraints {
+                            Constraints::AtLeast(n) => SizeRequirement::AtLeast(n),
+                            Constraints::Exact(n) => SizeRequirement::Exact(n),
+                            Constraints::Range { min, max } => SizeRequirement::Range { min, max },
+                            _ => SizeRequirement::Remaining,
+                        };
+                        let largest = match size {
+                            SizeRequirement::Exact(n) | SizeRequirement::Range { max: n, .. } => Some(n),
+                            _ => None,
+                        };
+                        let recommended = command.role.as_ref().and_then(|r| r.recommended_size());
+                        if let Some((largest, recommended)) = largest.zip(recommended).filter(|(l, r)| l < r) {
+                            diagnostics.warn(format!(
+                                "Partition {} is smaller than the recommended {} ({})",
+                                command.id,
+                                format_size(recommended),
+                                format_size(largest)
+                            ));
+                        }
+                        let partition_type = partition_type(command.role.as_ref(), command.mountpoint.as_deref());
+                        let tag = PartitionTag {
+                            id: Some(command.id.clone()),
+                            role: command.role.as_ref().map(|r| r.to_string()),
+                            mountpoint: command.mountpoint.clone(),
+                            partition_type: partition_type.map(|r| r.type_guid()),
+                            attributes: partition_type.map_or(0, |r| r.attributes()) | command.flags.gpt_attributes(),
+                            ..Default::default()
+                        };
+                        device_plan
+                            .strategy
+                            .add_request(PartitionRequest::new(size).with_tag(tag));
+                    } else {
+                        diagnostics.warn(format!("Could not find disk {} to create partition", command.disk));
+                    }
+                }
+                Command::CreateFilesystem(command) => {
+                    // Format on whichever disk the partition was requested on
+                    let owner = device_assignments
+                        .values_mut()
+                        .find(|p| p.has_partition(&command.partition));
+                    if let Some(device_plan) = owner {
+                        debug!(
+                            "Adding {} filesystem for partition {}",
+                            command.filesystem, command.partition
+                        );
+                        let filesystem = match command.filesystem {
+                            FilesystemType::Btrfs => format::FilesystemType::Btrfs,
+                            FilesystemType::Ext4 => format::FilesystemType::Ext4,
+                            FilesystemType::F2fs => format::FilesystemType::F2fs,
+                            FilesystemType::Fat32 => format::FilesystemType::Fat32,
+                            FilesystemType::Swap => format::FilesystemType::Swap,
+                            FilesystemType::Xfs => format::FilesystemType::Xfs,
+                        };
+                        let format = Format {
+                            filesystem,
+                            label: command.label.clone(),
+                            options: command.options.clone(),
+                        };
+                        device_plan.filesystems.push((command.partition.clone(), format));
+                    } else {
+                        diagnostics.warn(format!(
+                            "Could not find partition {} to create filesystem",
+                            command.partition
+                        ));
+                    }
+                }
+                Command::CreateLuks(command) => {
+                    let owner = device_assignments
+                        .values_mut()
+                        .find(|p| p.has_partition(&command.partition));
+                    if let Some(device_plan) = owner {
+                        debug!(
+                            "Adding LUKS2 volume {} on partition {}",
+                            command.luks.name, command.partition
+                        );
+                        device_plan
+                            .encryption
+                            .push((command.partition.clone(), command.luks.clone()));
+                    } else {
+                        diagnostics.warn(format!("Could not find partition {} to encrypt", command.partition));
+                    }
+                }
+                Command::CreateSubvolumes(command) => {
+                    let owner = device_assignments
+                        .values_mut()
+                        .find(|p| p.has_partition(&command.partition));
+                    if let Some(device_plan) = owner {
+                        let btrfs = device_plan
+                            .filesystems
+                            .iter()
+                            .any(|(id, f)| id == &command.partition && f.filesystem == format::FilesystemType::Btrfs);
+                        if !btrfs {
+                            diagnostics.warn(format!("Partition {} is not formatted as btrfs", command.partition));
+                        }
+                        debug!("Adding btrfs subvolumes for partition {}", command.partition);
+                        device_plan
+                            .subvolumes
+                            .push((command.partition.clone(), command.layout.clone()));
+                    } else {
+                        diagnostics.warn(format!(
+                            "Could not find partition {} to create subvolumes",
+                            command.partition
+                        ));
+                    }
+                }
+                Command::CreateSwapfile(command) => {
+                    let owner = device_assignments
+                        .values_mut()
+                        .find(|p| p.has_partition(&command.partition));
+                    let Some(device_plan) = owner else {
+                        diagnostics.warn(format!(
+                            "Could not find partition {} to create swapfile",
+                            command.partition
+                        ));
+                        continue;
+                    };
+                    let filesystem = device_plan
+                        .filesystems
+                        .iter()
+                        .find(|(id, _)| id == &command.partition)
+                        .map(|(_, f)| f.filesystem);
+                    if !filesystem.is_some_and(Swapfile::is_supported) {
+                        diagnostics.warn(format!(
+                            "Partition {} is not formatted with a filesystem supporting swapfiles",
+                            command.partition
+                        ));
+                    }
+                    let variables = self.variables(Some(device_plan.device().size()));
+                    let Some(size) = command.size.evaluate(&variables) else {
+                        diagnostics.warn(format!("Could not evaluate size of swapfile {}", command.path));
+                        continue;
+                    };
+                    let swapfile = Swapfile::new(&command.path, size);
+                    if let Err(e) = swapfile.validate() {
+                        diagnostics.warn(format!("Invalid swapfile {}: {}", command.path, e));
+                        continue;
+                    }
+                    debug!(
+                        "Adding {} swapfile {} on partition {}",
+                        format_size(size),
+                        command.path,
+                        command.partition
+                    );
+                    device_plan.swapfiles.push((command.partition.clone(), swapfile));
+                }
+                Command::CreateRaid(command) => {
+                    if device_assignments.contains_key(&command.array.name) {
+                        trace!("Array {} already assigned, skipping", command.array.name);
+                        continue;
+                    }
+
+                    // Expand disk sets into their members
+                    let mut array = command.array.clone();
+                    array.members = array
+                        .members
+                        .iter()
+                        .flat_map(|member| {
+                            let set = device_assignments
+                                .keys()
+                                .filter_map(|name| {
+                                    let index = name.strip_prefix(member.as_str())?.strip_prefix('.')?;
+                                    Some((index.parse::<usize>().ok()?, name.clone()))
+                                })
+                                .sorted()
+                                .map(|(_, name)| name)
+                                .collect::<Vec<_>>();
+                            if set.is_empty() || device_assignments.contains_key(member) {
+                                vec![member.clone()]
+                            } else {
+                                set
+                            }
+                        })
+                        .collect();
+
+                    if array.members.len() < array.level.min_members() + array.spares {
+                        diagnostics.warn(format!(
+                            "Array {} needs at least {} active members in addition to {} spares",
+                            array.name,
+                            array.level.min_members(),
+                            array.spares
+                        ));
+                        continue;
+                    }
+
+                    let missing = array.members.iter().find(|m| {
+                        !device_assignments.contains_key(*m) && !device_assignments.values().any(|p| p.has_partition(m))
+                    });
+                    if let Some(member) = missing {
+                        diagnostics.warn(format!(
+                            "Could not find disk or partition {} for array {}",
+                            member, array.name
+                        ));
+                        continue;
+                    }
+
+                    debug!("Adding {} array {}", array.level, array.name);
+                    for member in &array.members {
+                        if let Some(device_plan) = device_assignments.get_mut(member) {
+                            device_plan.member_of = Some(array.name.clone());
+                        }
+                    }
+
+                    // Sized once the members have been planned
+                    let device = BlockDevice::md_device(md::Disk::planned(&array.name, 0));
+                    let mut device_plan = DevicePlan::new(
+                        PlanDevice::Virtual(Arc::new(device)),
+                        Strategy::new(AllocationStrategy::LargestFree),
+                    );
+                    device_plan.array = Some(array.clone());
+                    device_assignments.insert(array.name.clone(), device_plan);
+                }
+                Command::CreateVolumeGroup(command) => {
+                    let missing = command
+                        .physical_volumes
+                        .iter()
+                        .find(|id| !device_assignments.values().any(|p| p.has_partition(id)));
+                    if let Some(id) = missing {
+                        diagnostics.warn(format!(
+                            "Could not find partition {} for volume group {}",
+                            id, command.name
+                        ));
+                    } else {
+                        debug!("Adding volume group {}", command.name);
+                        volume_groups.push(VolumeGroup {
+                            name: command.name.clone(),
+                            physical_volumes: command.physical_volumes.clone(),
+                            logical_volumes: vec![],
+                        });
+                    }
+                }
+                Command::CreateLogicalVolume(command) => {
+                    if let Some(group) = volume_groups.iter_mut().find(|g| g.name == command.group) {
+                        // No single disk is in scope for a volume group
+                        let Some(constraints) = command.constraints.resolve(&self.variables(None)) else {
+                            diagnostics.warn(format!(
+                                "Could not evaluate size constraints of logical volume {}",
+                                command.name
+                            ));
+                            continue;
+                        };
+                        debug!(
+                            "Adding logical volume {} to volume group {}",
+                            command.name, command.group
+                        );
+                        group.logical_volumes.push(LogicalVolume {
+                            name: command.name.clone(),
+                            constraints,
                         });
                     } else {
-                        warn!("Could not find disk {} to create partition", command.disk);
+                        diagnostics.warn(format!(
+                            "Could not find volume group {} to create logical volume",
+                            command.group
+                        ));
                     }
                 }
             }
         }
 
         // OK lets now apply amy mutations to the device assignments
-        for (disk_name, device_plan) in device_assignments.iter_mut() {
+        for (disk_name, device_plan) in device_assignments.iter_mut().filter(|(_, p)| p.array.is_none()) {
             debug!("Applying device plan for disk {}", disk_name);
             if let Err(e) = device_plan.strategy.apply(&mut device_plan.planner) {
-                warn!("Failed to apply strategy for disk {}: {:?}", disk_name, e);
+                diagnostics.warn(format!("Failed to apply strategy for disk {}: {}", disk_name, e));
+            }
+        }
+
+        // Erasing a disk only makes sense when it is repartitioned from scratch
+        for (disk_name, device_plan) in device_assignments.iter().sorted_by_key(|(name, _)| *name) {
+            if device_plan.whole_disk.is_some() {
+                if device_plan.planner.creates_new_table() || !device_plan.strategy.requests().is_empty() {
+                    diagnostics.warn(format!(
+                        "Disk {} is formatted as a whole and cannot be partitioned",
+                        disk_name
+                    ));
+                }
+            } else if device_plan.erase != ErasePolicy::None && !device_plan.planner.creates_new_table() {
+                diagnostics.warn(format!(
+                    "Disk {} is erased ({}) but no new partition table is created on it",
+                    disk_name, device_plan.erase
+                ));
+            }
+        }
+
+        // Editing a table the planner doesn't fully understand risks the data on it
+        for (disk_name, device_plan) in device_assignments.iter().sorted_by_key(|(name, _)| *name) {
+            let edits_table = device_plan.whole_disk.is_none()
+                && !device_plan.planner.creates_new_table()
+                && device_plan.planner.has_changes();
+            let Some(classification) = self.tables.get(device_plan.device.device()).filter(|_| edits_table) else {
+                continue;
+            };
+            for finding in &classification.findings {
+                if finding.is_error() && !self.force {
+                    diagnostics.warn(format!(
+                        "Partition table of disk {} is not changed without force: {}",
+                        disk_name, finding
+                    ));
+                } else {
+                    diagnostics.warn(format!("Partition table of disk {}: {}", disk_name, finding));
+                }
+            }
+        }
+
+        // A filesystem on the whole disk is data in use rather than free space
+        for (disk_name, device_plan) in device_assignments.iter().sorted_by_key(|(name, _)| *name) {
+            if let Some(kind) = device_plan.whole_disk_filesystem(self.allow_in_use) {
+                diagnostics.warn(format!(
+                    "Disk {} holds a {} filesystem without a partition table, wipe the disk to use it",
+                    disk_name, kind
+                ));
+            }
+        }
+
+        // Arrays can only be sized once their members have been planned
+        let arrays = device_assignments
+            .values()
+            .filter_map(|p| p.array.clone())
+            .collect::<Vec<_>>();
+        for array in arrays {
+            let sizes = array
+                .members
+                .iter()
+                .map(|m| member_size(&device_assignments, m))
+                .collect::<Vec<_>>();
+            let size = array.usable_size(&sizes);
+            debug!("Applying device plan for array {} ({} bytes)", array.name, size);
+
+            let Some(device_plan) = device_assignments.get_mut(&array.name) else {
+                continue;
+            };
+            let device = Arc::new(BlockDevice::md_device(md::Disk::planned(&array.name, size)));
+            let planner = Planner::new(&device);
+            device_plan.planner = match device_plan.planner.table() {
+                Some(table) => planner.with_table(table),
+                None => planner,
+            };
+            device_plan.device = PlanDevice::Virtual(device);
+            if let Err(e) = device_plan.strategy.apply(&mut device_plan.planner) {
+                diagnostics.warn(format!("Failed to apply strategy for array {}: {}", array.name, e));
             }
         }
 
         // All commands processed successfully - create a plan
         debug!("Creating final plan for strategy {}", strategy.name);
-        plans.push(Plan {
+        Plan {
             strategy,
-            device_assignments: device_assignments.clone(),
-        });
+            device_assignments,
+            volume_groups,
+            diagnostics,
+            chooser: self.chooser.as_ref(),
+            allow_in_use: self.allow_in_use,
+            force: self.force,
+            cancel: self.cancel.clone(),
+            rate_limit: self.rate_limit,
+            parallelism: self.parallelism,
+        }
+    }
+}
+
+/// Properties that make disks interchangeable for planning
+#[derive(Debug, PartialEq, Eq)]
+struct DiskClass<'a> {
+    size: u64,
+    model: Option<&'a str>,
+    removable: bool,
+    partitions: Vec<(u64, u64)>,
+}
+
+impl<'a> DiskClass<'a> {
+    fn of(device: &'a BlockDevice) -> Self {
+        Self {
+            size: device.size(),
+            model: device.model(),
+            removable: device.is_removable(),
+            partitions: device.partitions().iter().map(|p| (p.start, p.end)).collect(),
+        }
+    }
+}
+
+/// Collect every way of taking `needed` disks from the classes of interchangeable disks
+///
+/// Within a class the first disks are always taken, so sets only differ in how many
+/// disks they take from each class.
+fn distinct_sets<'a>(
+    classes: &[Vec<&'a BlockDevice>],
+    needed: usize,
+    taken: &mut Vec<&'a BlockDevice>,
+    sets: &mut Vec<Vec<&'a BlockDevice>>,
+) {
+    if needed == 0 {
+        sets.push(taken.clone());
+        return;
+    }
+    let Some((class, remaining)) = classes.split_first() else {
+        return;
+    };
+    for count in (0..=needed.min(class.len())).rev() {
+        let len = taken.len();
+        taken.extend(&class[..count]);
+        distinct_sets(remaining, needed - count, taken, sets);
+        taken.truncate(len);
+    }
+}
+
+/// A set of names to bind to disks satisfying the same constraints
+struct DiskSearch<'a> {
+    names: Vec<String>,
+    constraints: Option<&'a Constraints>,
+    exclusions: &'a Exclusions,
+    reserve: u64,
+}
+
+impl DiskSearch<'_> {
+    /// Returns true if the device satisfies the constraints and is not excluded
+    fn matches(&self, device: &BlockDevice, mounts: &[Mount], variables: &Variables) -> bool {
+        if self.exclusions.removable && device.is_removable() {
+            trace!("Excluding removable device {:?}", device.device());
+            return false;
+        }
+
+        let partitions = device.partitions().iter().map(|p| p.device.as_path());
+        let device_mounts = mount::mounts_for(mounts, device.device(), partitions);
+        if self.exclusions.mounted && !device_mounts.is_empty() {
+            trace!("Excluding mounted device {:?}", device.device());
+            return false;
+        }
+        if self.exclusions.live_medium
+            && device_mounts
+                .iter()
+                .any(|m| LIVE_MEDIUM_MOUNTPOINTS.iter().any(|p| m.mountpoint == Path::new(p)))
+        {
+            trace!("Excluding live medium {:?}", device.device());
+            return false;
+        }
+
+        let size = device.size().saturating_sub(self.reserve);
+        let Some(constraints) = self.constraints else {
+            return true;
+        };
+        match constraints.resolve(variables) {
+            Some(Constraints::AtLeast(n)) => size >= n,
+            Some(Constraints::Exact(n)) => size == n,
+            Some(Constraints::Range { min, max }) => size >= min && size <= max,
+            Some(_) => true,
+            None => {
+                warn!("Could not evaluate disk constraints for {:?}", self.names);
+                false
+            }
+        }
+    }
+}
+
+/// Collect the commands that apply to a system, expanding conditional blocks
+pub(crate) fn select_commands<'a>(commands: &'a [Command], facts: &Facts, selected: &mut Vec<&'a Command>) {
+    for command in commands {
+        match command {
+            Command::When(block) if block.condition.holds(facts) => select_commands(&block.commands, facts, selected),
+            Command::When(block) => trace!("Skipping commands, condition not met: {:?}", block.condition),
+            _ => selected.push(command),
+        }
+    }
+}
+
+/// The GPT partition type for a partition, from its role or else its mount point
+fn partition_type(role: Option<&PartitionRole>, mountpoint: Option<&str>) -> Option<Role> {
+    if let Some(role) = role {
+        return role.partition_type();
+    }
+
+    match mountpoint? {
+        "/home" => Some(Role::Home),
+        "/var" => Some(Role::Var),
+        "/srv" => Some(Role::Srv),
+        _ => None,
+    }
+}
+
+/// Size of a RAID member, either a whole disk or a planned partition
+fn member_size(device_assignments: &HashMap<String, DevicePlan<'_>>, member: &str) -> u64 {
+    if let Some(device_plan) = device_assignments.get(member) {
+        return device_plan.device.size();
     }
+
+    device_assignments
+        .values()
+        .flat_map(|p| p.planner.current_layout())
+        .find(|r| r.tag().and_then(|t| t.id.as_deref()) == Some(member))
+        .map_or(0, |r| r.size())
 }
 
 #[cfg(test)]
 mod tests {
-    use disks::mock::MockDisk;
+    use disks::{flags::PartitionFlags, mock::MockDisk};
     use test_log::test;
 
-    use crate::Parser;
+    use crate::{Firmware, Parser};
 
     use super::*;
 
@@ -217,7 +1230,478 @@ mod tests {
             for (disk, device_plan) in plan.device_assignments.iter() {
                 println!("strategy for {disk} is now: {}", device_plan.strategy.describe());
                 println!("After: {}", device_plan.planner.describe_changes());
+
+                // Every planned partition can be traced back to its strategy id
+                let layout = device_plan.planner.current_layout();
+                assert!(layout.iter().all(|r| r.tag().is_some_and(|t| t.id.is_some())));
+
+                // Mount points come from the role unless given explicitly
+                let mountpoint = |id: &str| {
+                    layout
+                        .iter()
+                        .filter_map(|r| r.tag())
+                        .find(|t| t.id.as_deref() == Some(id))
+                        .and_then(|t| t.mountpoint.clone())
+                };
+                assert_eq!(mountpoint("esp").as_deref(), Some("/efi"));
+                assert_eq!(mountpoint("root").as_deref(), Some("/"));
+                assert_eq!(mountpoint("var").as_deref(), Some("/var"));
+
+                // Partition types follow the role, or the mount point without one
+                let partition_type = |id: &str| {
+                    layout
+                        .iter()
+                        .filter_map(|r| r.tag())
+                        .find(|t| t.id.as_deref() == Some(id))
+                        .and_then(|t| t.partition_type)
+                };
+                assert_eq!(partition_type("esp"), Some(Role::Esp.type_guid()));
+                assert_eq!(partition_type("xbootldr"), Some(Role::ExtendedBoot.type_guid()));
+                assert_eq!(partition_type("var"), Some(Role::Var.type_guid()));
+
+                // Flags from the strategy add to the attributes of the role
+                let var = layout
+                    .iter()
+                    .filter_map(|r| r.tag())
+                    .find(|t| t.id.as_deref() == Some("var"));
+                assert_eq!(
+                    var.map(|t| t.flags()),
+                    Some(PartitionFlags::GROWFS | PartitionFlags::NO_AUTOMOUNT)
+                );
+
+                let filesystems = device_plan.filesystems();
+                assert!(filesystems
+                    .iter()
+                    .any(|(id, f)| id == "esp" && f.filesystem == format::FilesystemType::Fat32));
+                assert!(filesystems
+                    .iter()
+                    .any(|(id, f)| id == "root" && f.label.as_deref() == Some("root")));
+            }
+        }
+    }
+
+    #[test]
+    fn test_encrypted_root() {
+        // LUKS2 volumes cannot be created yet, so the strategy is refused while parsing
+        let err = Parser::new_for_path("tests/encrypted_root.kdl").unwrap_err();
+        let [error] = err.diagnostics.as_slice() else {
+            panic!("expected a single error");
+        };
+        assert_eq!(error.to_string(), "creating LUKS2 volumes is not supported yet");
+    }
+
+    #[test]
+    fn test_lvm_layout() {
+        let test_strategies = Parser::new_for_path("tests/lvm.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(150 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        assert_eq!(plans.len(), 1);
+
+        // The group referencing an unknown partition is dropped along with its volumes
+        let [group] = plans[0].volume_groups.as_slice() else {
+            panic!("expected a single volume group");
+        };
+        assert_eq!(group.name, "vg0");
+        assert_eq!(group.physical_volumes, vec!["pv0"]);
+        let names = group
+            .logical_volumes
+            .iter()
+            .map(|lv| lv.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["root", "home"]);
+        assert_eq!(group.logical_volumes[1].constraints, Constraints::Remaining);
+    }
+
+    #[test]
+    fn test_raid_array() {
+        // Arrays cannot be assembled yet, so the strategy is refused while parsing
+        let err = Parser::new_for_path("tests/raid.kdl").unwrap_err();
+        let [error] = err.diagnostics.as_slice() else {
+            panic!("expected a single error");
+        };
+        assert_eq!(error.to_string(), "creating RAID arrays is not supported yet");
+    }
+
+    #[test]
+    fn test_btrfs_subvolumes() {
+        let test_strategies = Parser::new_for_path("tests/btrfs_subvolumes.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(150 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        let [(id, layout)] = plans[0].device_assignments["root_disk"].subvolumes() else {
+            panic!("expected a single subvolume layout");
+        };
+        assert_eq!(id, "root");
+        assert_eq!(layout.default_subvolume().map(|s| s.path.as_str()), Some("@"));
+        let mountpoints = layout
+            .subvolumes()
+            .iter()
+            .map(|s| s.mountpoint.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(mountpoints, vec![Some("/"), Some("/home"), None]);
+    }
+
+    #[test]
+    fn test_swapfiles() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let test_strategies = Parser::new_for_path("tests/swapfile.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * GIB)));
+        provisioner.set_facts(Facts {
+            memory: 16 * GIB,
+            ..provisioner.facts.clone()
+        });
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        let swapfiles = |name: &str| {
+            let plan = plans.iter().find(|p| p.strategy.name == name).unwrap();
+            assert!(plan.diagnostics.is_empty());
+            plan.device_assignments["root_disk"].swapfiles().to_vec()
+        };
+        assert_eq!(
+            swapfiles("btrfs_swapfile"),
+            vec![("root".to_string(), Swapfile::new("@swap/swapfile", 16 * GIB))]
+        );
+        assert_eq!(
+            swapfiles("ext4_swapfile"),
+            vec![("root".to_string(), Swapfile::new("swapfile", 2 * GIB))]
+        );
+    }
+
+    #[test]
+    fn test_find_disks_set() {
+        let test_strategies = Parser::new_for_path("tests/disk_set.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        for size in [100, 200, 300] {
+            provisioner.push_device(BlockDevice::mock_device(MockDisk::new(size * 1024 * 1024 * 1024)));
+        }
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        // Every pair of the three disks, each only once regardless of order
+        let plans = provisioner.plan();
+        assert_eq!(plans.len(), 3);
+
+        let mut pairs = plans
+            .iter()
+            .map(|plan| {
+                let mut sizes = ["data.0", "data.1"].map(|n| plan.device_assignments[n].device().size() >> 30);
+                sizes.sort();
+                sizes
+            })
+            .collect::<Vec<_>>();
+        pairs.sort();
+        assert_eq!(pairs, vec![[100, 200], [100, 300], [200, 300]]);
+
+        for plan in &plans {
+            // Every command is evaluated exactly once per binding
+            assert_eq!(plan.device_assignments["data.0"].planner().current_layout().len(), 1);
+            assert!(plan.device_assignments["data.1"].planner().current_layout().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_branch_limits() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let test_strategies = Parser::new_for_path("tests/disk_set.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        // Four identical disks and a larger one, added out of WWN order
+        for wwn in ["wwn-4", "wwn-2", "wwn-3", "wwn-1"] {
+            let mut disk = MockDisk::new(100 * GIB);
+            disk.set_wwn(wwn);
+            provisioner.push_device(BlockDevice::mock_device(disk));
+        }
+        let mut disk = MockDisk::new(200 * GIB);
+        disk.set_wwn("wwn-0");
+        provisioner.push_device(BlockDevice::mock_device(disk));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        // Two small disks, or one small and the large one
+        let wwns = |plan: &Plan<'_>| {
+            ["data.0", "data.1"].map(|n| plan.device_assignments[n].device().wwn().unwrap().to_owned())
+        };
+        let plans = provisioner.plan();
+        assert_eq!(
+            plans.iter().map(wwns).collect::<Vec<_>>(),
+            vec![["wwn-0", "wwn-1"], ["wwn-1", "wwn-2"]]
+        );
+
+        provisioner.set_deduplicate(false);
+        assert_eq!(provisioner.plan().len(), 10);
+
+        provisioner.set_branch_limit(Some(3));
+        let plans = provisioner.plan();
+        assert_eq!(
+            plans.iter().map(wwns).collect::<Vec<_>>(),
+            vec![["wwn-0", "wwn-1"], ["wwn-0", "wwn-2"], ["wwn-0", "wwn-3"]]
+        );
+    }
+
+    #[test]
+    fn test_exclusions() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let test_strategies = Parser::new_for_path("tests/exclusions.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+
+        let mut removable = MockDisk::new(80 * GIB);
+        removable.set_removable(true);
+        provisioner.push_device(BlockDevice::mock_device(removable));
+
+        let mut mounted = MockDisk::new(80 * GIB);
+        mounted.add_partition(GIB, 10 * GIB);
+        provisioner.push_device(BlockDevice::mock_device(mounted));
+
+        // Too small once the reserve is taken away
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(45 * GIB)));
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(60 * GIB)));
+
+        provisioner.set_mounts(disks::mount::parse_mountinfo(
+            "40 22 8:1 / /run/initramfs/live ro - iso9660 /dev/mock0p1 ro\n",
+        ));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].device_assignments["root_disk"].device().size(), 60 * GIB);
+    }
+
+    #[test]
+    fn test_computed_constraints() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let test_strategies = Parser::new_for_path("tests/facts.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * GIB)));
+        provisioner.set_facts(Facts {
+            memory: 8 * GIB,
+            firmware: Firmware::Uefi,
+            secure_boot: Some(true),
+            arch: "x86_64".into(),
+            virtualized: false,
+        });
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        assert_eq!(plans.len(), 1);
+        let layout = plans[0].device_assignments["root_disk"].planner().current_layout();
+        let size = |id: &str| {
+            layout
+                .iter()
+                .find(|r| r.tag().and_then(|t| t.id.as_deref()) == Some(id))
+                .unwrap()
+                .size()
+        };
+        assert_eq!(size("swap"), 8 * GIB);
+        assert_eq!(size("root"), 26 * GIB);
+
+        // The disk must hold at least eight times the memory
+        provisioner.set_facts(Facts {
+            memory: 16 * GIB,
+            ..provisioner.facts.clone()
+        });
+        assert!(provisioner.plan().is_empty());
+    }
+
+    #[test]
+    fn test_conditional_blocks() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let ids = |facts: Facts| {
+            let test_strategies = Parser::new_for_path("tests/conditional.kdl").unwrap();
+            let mut provisioner = Provisioner::new();
+            provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * GIB)));
+            provisioner.set_facts(facts);
+            for def in test_strategies.strategies {
+                provisioner.add_strategy(def);
             }
+
+            let plans = provisioner.plan();
+            assert_eq!(plans.len(), 1);
+            plans[0].device_assignments["root_disk"]
+                .planner()
+                .current_layout()
+                .iter()
+                .filter_map(|r| r.tag()?.id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let uefi = Facts {
+            memory: 8 * GIB,
+            firmware: Firmware::Uefi,
+            secure_boot: Some(true),
+            arch: "x86_64".into(),
+            virtualized: false,
+        };
+        assert_eq!(ids(uefi.clone()), vec!["esp", "swap", "root"]);
+
+        let bios = Facts {
+            memory: 2 * GIB,
+            firmware: Firmware::Bios,
+            secure_boot: None,
+            ..uefi.clone()
+        };
+        assert_eq!(ids(bios), vec!["bios", "root"]);
+
+        let vm = Facts {
+            virtualized: true,
+            ..uefi
+        };
+        assert_eq!(ids(vm), vec!["esp", "root"]);
+    }
+
+    #[test]
+    fn test_inheritance_overrides() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let test_strategies = Parser::new_for_path("tests/inheritance.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * GIB)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        let plan = plans.iter().find(|p| p.strategy.name == "workstation").unwrap();
+        let device_plan = &plan.device_assignments["root_disk"];
+
+        let layout = device_plan
+            .planner()
+            .current_layout()
+            .into_iter()
+            .filter_map(|r| Some((r.tag()?.id.clone()?, r.size())))
+            .collect::<Vec<_>>();
+        assert_eq!(layout, vec![("esp".into(), GIB), ("root".into(), 40 * GIB)]);
+
+        let filesystems = device_plan
+            .filesystems()
+            .iter()
+            .map(|(id, f)| (id.as_str(), f.filesystem))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            filesystems,
+            vec![
+                ("esp", format::FilesystemType::Fat32),
+                ("root", format::FilesystemType::Btrfs)
+            ]
+        );
+
+        // The parent is untouched
+        let plan = plans
+            .iter()
+            .find(|p| p.strategy.name == "workstation_with_swap")
+            .unwrap();
+        assert_eq!(plan.device_assignments["root_disk"].filesystems().len(), 3);
+    }
+
+    #[test]
+    fn test_wipe_disk() {
+        let test_strategies = Parser::new_for_path("tests/wipe.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
         }
+
+        let plans = provisioner.plan();
+        let plan = plans.iter().find(|p| p.strategy.name == "wipe_whole_disk").unwrap();
+        assert_eq!(plan.device_assignments["root_disk"].erase(), ErasePolicy::Discard);
+        assert!(plan.diagnostics.is_empty());
+        assert_eq!(plan.report().devices[0].erase.as_deref(), Some("discard"));
+
+        // Erasing a disk that keeps its partition table is almost certainly a mistake
+        let plan = plans.iter().find(|p| p.strategy.name == "wipe_in_place").unwrap();
+        assert_eq!(plan.device_assignments["data_disk"].erase(), ErasePolicy::Zero);
+        let warnings = plan.diagnostics.diagnostics();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].to_string().contains("no new partition table"));
+    }
+
+    #[test]
+    fn test_format_whole_disk() {
+        let test_strategies = Parser::new_for_path("tests/whole_disk.kdl").unwrap();
+        let mut disk = MockDisk::new(200 * 1024 * 1024 * 1024);
+        disk.add_partition(1024 * 1024, 100 * 1024 * 1024 * 1024);
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(disk));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        let plan = &plans[0];
+        assert!(plan.diagnostics.diagnostics().is_empty());
+        let device_plan = &plan.device_assignments["data_disk"];
+        assert_eq!(
+            device_plan.whole_disk().unwrap().mountpoint.as_deref(),
+            Some("/srv/data")
+        );
+        assert_eq!(device_plan.erase(), ErasePolicy::Signatures);
+        assert!(!device_plan.planner().creates_new_table());
+
+        let report = plan.report();
+        let device = &report.devices[0];
+        assert!(device.whole_disk);
+        assert_eq!(device.destroyed.len(), 1);
+        assert_eq!(device.partitions.len(), 1);
+        assert_eq!(device.partitions[0].size, 200 * 1024 * 1024 * 1024);
+        assert_eq!(device.partitions[0].filesystem.as_deref(), Some("xfs"));
+        assert!(report
+            .to_string()
+            .contains("data_disk: /dev/mock0 (200.0GiB), erased (signatures-only), no partition table"));
+    }
+
+    #[test]
+    fn test_whole_disk_filesystem() {
+        let plan_with = |path: &str, strategy: &str, allow_in_use: bool| {
+            let mut disk = MockDisk::new(100 * 1024 * 1024 * 1024);
+            disk.set_filesystem(Some(superblock::Kind::FAT));
+            let mut provisioner = Provisioner::new();
+            provisioner.push_device(BlockDevice::mock_device(disk));
+            provisioner.set_allow_in_use(allow_in_use);
+            for def in Parser::new_for_path(path).unwrap().strategies {
+                provisioner.add_strategy(def);
+            }
+            provisioner
+                .plan()
+                .iter()
+                .find(|p| p.strategy.name == strategy)
+                .map(|p| {
+                    p.diagnostics
+                        .diagnostics()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap()
+        };
+
+        assert_eq!(
+            plan_with("tests/use_whole_disk.kdl", "whole_disk", false),
+            vec!["Disk root_disk holds a fat filesystem without a partition table, wipe the disk to use it"]
+        );
+        assert!(plan_with("tests/use_whole_disk.kdl", "whole_disk", true).is_empty());
+        assert!(plan_with("tests/wipe.kdl", "wipe_whole_disk", false).is_empty());
     }
 }
@@ -0,0 +1,209 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Recovering from partially applied plans
+//!
+//! [`Plan::apply()`] marks every partition it creates as pending and clears the marker
+//! once its filesystems, subvolumes and swapfiles exist. Partitions still marked when a
+//! plan is run again were left behind by an interrupted or failed run, and are listed by
+//! [`Plan::pending()`]. [`Plan::recover()`] then deals with them in one of three ways:
+//!
+//! - [`RecoveryMode::Resume`] keeps the partitions and finishes the work planned for them.
+//!   Partitions are matched to the plan by name, which the writer sets to the partition id.
+//! - [`RecoveryMode::Rollback`] deletes the partitions, leaving the rest of the table as
+//!   it was before the previous run.
+//! - [`RecoveryMode::StartOver`] applies the plan from scratch. The plan must replace
+//!   the partition table of every disk with pending partitions, as it was built with
+//!   them in place.
+
+use partitioning::{
+    pending::{self, PendingPartition},
+    planner::Region,
+    progress::{NoProgress, ProgressSink},
+    writer::{WriteError, WrittenPartition},
+};
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+
+use crate::{ApplyReport, DeviceStatus, Finished, Plan};
+
+/// How to deal with partitions left behind by a previous run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryMode {
+    /// Finish the work planned for the pending partitions
+    Resume,
+    /// Delete the pending partitions
+    Rollback,
+    /// Apply the plan again, replacing the pending partitions
+    StartOver,
+}
+
+/// Errors that can occur while recovering from a previous run
+#[derive(Debug, Error)]
+pub enum RecoveryError {
+    /// The partition table of a disk could not be read
+    #[error("failed to read pending partitions of disk {disk}: {source}")]
+    Pending { disk: String, source: WriteError },
+    /// The plan keeps the partition table of a disk holding pending partitions
+    #[error("disk {disk} has pending partitions, roll them back and plan again")]
+    Replan { disk: String },
+}
+
+impl Plan<'_> {
+    /// Find the partitions left pending by a previous run, keyed by disk name
+    ///
+    /// Disks without pending partitions are left out.
+    pub fn pending(&self) -> Result<Vec<(String, Vec<PendingPartition>)>, RecoveryError> {
+        let mut found = vec![];
+        for (name, plan) in self.writable_assignments() {
+            let partitions =
+                pending::pending_partitions(plan.device().device()).map_err(|source| RecoveryError::Pending {
+                    disk: name.clone(),
+                    source,
+                })?;
+            if !partitions.is_empty() {
+                found.push((name.clone(), partitions));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Deal with partitions left pending by a previous run
+    pub fn recover(&self, mode: RecoveryMode) -> Result<ApplyReport, RecoveryError> {
+        self.recover_with_progress(mode, &NoProgress)
+    }
+
+    /// Like [`Plan::recover()`], reporting progress to `progress`
+    #[instrument(name = "recover", skip_all, fields(strategy = %self.strategy.name, ?mode))]
+    pub fn recover_with_progress(
+        &self,
+        mode: RecoveryMode,
+        progress: &dyn ProgressSink,
+    ) -> Result<ApplyReport, RecoveryError> {
+        info!("Recovering plan for strategy {} ({:?})", self.strategy.name, mode);
+        let pending = self.pending()?;
+
+        match mode {
+            RecoveryMode::Resume => Ok(self.resume(pending, progress)),
+            RecoveryMode::Rollback => Ok(self.rollback_pending(pending)),
+            RecoveryMode::StartOver => {
+                let assignments = self.writable_assignments();
+                for (disk, _) in &pending {
+                    let replaced = assignments
+                        .iter()
+                        .any(|(name, plan)| *name == disk && plan.planner().creates_new_table());
+                    if !replaced {
+                        return Err(RecoveryError::Replan { disk: disk.clone() });
+                    }
+                }
+                Ok(self.apply_with_progress(progress))
+            }
+        }
+    }
+
+    /// Finish the pending partitions that are part of this plan
+    fn resume(&self, pending: Vec<(String, Vec<PendingPartition>)>, progress: &dyn ProgressSink) -> ApplyReport {
+        let mut devices = vec![];
+        let mut finished = Finished::default();
+
+        for (disk, partitions) in pending {
+            let Some(plan) = self.device_assignments.get(&disk) else {
+                continue;
+            };
+            let layout = plan.planner().current_layout();
+            let written = partitions
+                .into_iter()
+                .filter_map(|partition| {
+                    let Some(tag) = layout
+                        .iter()
+                        .filter_map(|r| r.tag())
+                        .find(|t| t.id.as_deref() == Some(partition.name.as_str()))
+                    else {
+                        warn!(
+                            "Pending partition {} on disk {} is not part of the plan, skipping",
+                            partition.number, disk
+                        );
+                        return None;
+                    };
+                    Some(WrittenPartition {
+                        number: partition.number,
+                        region: Region::new(partition.start, partition.end).with_tag(tag.clone()),
+                        device: partition.device,
+                        guid: partition.guid,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            info!("Resuming {} pending partitions on disk {}", written.len(), disk);
+            finished.extend(self.finish_partitions(&disk, plan, &written, progress));
+            devices.push((disk, plan.device().device().to_owned(), DeviceStatus::Written(written)));
+        }
+
+        ApplyReport {
+            devices,
+            filesystems: finished.filesystems,
+            subvolumes: finished.subvolumes,
+            swapfiles: finished.swapfiles,
+        }
+    }
+
+    /// Delete the pending partitions from every disk
+    fn rollback_pending(&self, pending: Vec<(String, Vec<PendingPartition>)>) -> ApplyReport {
+        let devices = pending
+            .into_iter()
+            .filter_map(|(disk, _)| {
+                let device = self.device_assignments.get(&disk)?.device().device().to_owned();
+                let status = match pending::remove_pending(&device) {
+                    Ok(numbers) => {
+                        info!("Removed pending partitions {:?} from disk {}", numbers, disk);
+                        DeviceStatus::RolledBack
+                    }
+                    Err(e) => {
+                        warn!("Failed to remove pending partitions from disk {}: {}", disk, e);
+                        DeviceStatus::Failed(e)
+                    }
+                };
+                Some((disk, device, status))
+            })
+            .collect();
+
+        ApplyReport {
+            devices,
+            filesystems: vec![],
+            subvolumes: vec![],
+            swapfiles: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+    use test_log::test;
+
+    use crate::{Parser, Provisioner};
+
+    use super::*;
+
+    #[test]
+    fn test_recover_unreadable_disk() {
+        let test_strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(150 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+
+        // Mock devices have no device node, so nothing can be recovered
+        let plans = provisioner.plan();
+        assert!(matches!(
+            plans[0].pending(),
+            Err(RecoveryError::Pending { disk, source: WriteError::Io(_) }) if disk == "root_disk"
+        ));
+        for mode in [RecoveryMode::Resume, RecoveryMode::Rollback, RecoveryMode::StartOver] {
+            assert!(matches!(plans[0].recover(mode), Err(RecoveryError::Pending { .. })));
+        }
+    }
+}
@@ -0,0 +1,394 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Dry-run reports for compiled plans
+//!
+//! A [`Report`] describes what applying a plan would do without touching any disk, for
+//! confirmation dialogs and audit logs. It renders as a human-readable summary through
+//! [`std::fmt::Display`] and as JSON through [`Report::to_json()`]. [`Report::render()`]
+//! produces the summary in another language, see [`partitioning::locale`].
+
+use std::{fmt, path::PathBuf};
+
+use partitioning::{
+    locale::{English, Locale, Message},
+    planner::Change,
+    wipe::ErasePolicy,
+};
+use serde::Serialize;
+
+use crate::Plan;
+
+/// Summary of the changes a plan makes
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// Name of the strategy the plan was built from
+    pub strategy: String,
+    /// Summary of the strategy
+    pub summary: String,
+    /// Changes per device, ordered by disk name
+    pub devices: Vec<DeviceReport>,
+    /// LVM volume groups to create
+    pub volume_groups: Vec<VolumeGroupReport>,
+}
+
+/// Changes made to a single device
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceReport {
+    /// Disk name used in the strategy
+    pub name: String,
+    /// Device node
+    pub device: PathBuf,
+    /// Size of the device in bytes
+    pub size: u64,
+    /// Whether a new partition table replaces the existing one
+    pub new_table: bool,
+    /// Whether the device is used as a whole, without a partition table
+    pub whole_disk: bool,
+    /// How the device is erased before partitioning, if at all
+    pub erase: Option<String>,
+    /// The RAID array this device is a member of, if any
+    pub member_of: Option<String>,
+    /// RAID level, if the device is a planned array
+    pub raid_level: Option<String>,
+    /// Existing partitions whose data will be destroyed
+    pub destroyed: Vec<DestroyedPartition>,
+    /// Partitions that will be created
+    pub partitions: Vec<PartitionReport>,
+}
+
+impl DeviceReport {
+    /// Returns true if applying the changes may destroy existing data
+    pub fn is_destructive(&self) -> bool {
+        self.new_table || self.erase.is_some() || !self.destroyed.is_empty()
+    }
+}
+
+/// An existing partition that will be removed
+#[derive(Debug, Clone, Serialize)]
+pub struct DestroyedPartition {
+    /// Partition number
+    pub number: u32,
+    /// Device node of the partition
+    pub device: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// A partition that will be created
+#[derive(Debug, Clone, Serialize)]
+pub struct PartitionReport {
+    /// Partition id from the strategy
+    pub id: Option<String>,
+    /// Partition number in the table, unset for whole disks and additions that don't fit
+    pub number: Option<u32>,
+    /// Start offset in bytes
+    pub start: u64,
+    /// Size in bytes
+    pub size: u64,
+    /// Role of the partition
+    pub role: Option<String>,
+    /// Where the partition will be mounted
+    pub mountpoint: Option<String>,
+    /// Filesystem to create
+    pub filesystem: Option<String>,
+    /// Filesystem label
+    pub label: Option<String>,
+    /// Name of the LUKS2 mapping, if encrypted
+    pub encrypted: Option<String>,
+    /// Device nodes of the existing partitions this one takes the place of
+    pub replaces: Vec<PathBuf>,
+}
+
+/// An LVM volume group that will be created
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeGroupReport {
+    /// Name of the volume group
+    pub name: String,
+    /// Partition ids used as physical volumes
+    pub physical_volumes: Vec<String>,
+    /// Names of the logical volumes
+    pub logical_volumes: Vec<String>,
+}
+
+impl Report {
+    /// Render the report as pretty-printed JSON
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl Plan<'_> {
+    /// Describe what applying this plan would do, without writing anything
+    pub fn report(&self) -> Report {
+        let mut devices = self
+            .device_assignments
+            .iter()
+            .map(|(name, device_plan)| {
+                let device = device_plan.device();
+                let planner = device_plan.planner();
+                let numbers = planner.partition_numbers();
+
+                let destroyed = if planner.creates_new_table() {
+                    device.partitions().iter().collect::<Vec<_>>()
+                } else {
+                    planner
+                        .changes()
+                        .iter()
+                        .filter_map(|change| match change {
+                            Change::DeletePartition { original_index } => device.partitions().get(*original_index),
+                            Change::AddPartition { .. } => None,
+                        })
+                        .collect()
+                };
+
+                // The whole device stands in for a partition without a table
+                let whole_disk = device_plan
+                    .whole_disk()
+                    .map(|tag| (usize::MAX, 0, device.size(), tag.clone()));
+                let partitions = planner
+                    .changes()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, change)| match change {
+                        Change::AddPartition { start, end, tag, .. } => {
+                            Some((index, *start, *end, tag.clone().unwrap_or_default()))
+                        }
+                        Change::DeletePartition { .. } => None,
+                    })
+                    .chain(whole_disk)
+                    .map(|(index, start, end, tag)| {
+                        let id = tag.id.as_deref();
+                        let format = device_plan
+                            .filesystems()
+                            .iter()
+                            .find(|(p, _)| Some(p.as_str()) == id)
+                            .map(|(_, f)| f);
+                        let encrypted = device_plan
+                            .encryption()
+                            .iter()
+                            .find(|(p, _)| Some(p.as_str()) == id)
+                            .map(|(_, l)| l.name.clone());
+                        PartitionReport {
+                            id: tag.id.clone(),
+                            number: numbers.get(index).copied().flatten(),
+                            start,
+                            size: end - start,
+                            role: tag.role,
+                            mountpoint: tag.mountpoint,
+                            filesystem: format.map(|f| f.filesystem.to_string()),
+                            label: format.and_then(|f| f.label.clone()),
+                            encrypted,
+                            replaces: planner
+                                .replaced_partitions(index)
+                                .into_iter()
+                                .filter_map(|i| device.partitions().get(i))
+                                .map(|p| p.device.clone())
+                                .collect(),
+                        }
+                    })
+                    .collect();
+
+                DeviceReport {
+                    name: name.clone(),
+                    device: device.device().to_owned(),
+                    size: device.size(),
+                    new_table: planner.creates_new_table(),
+                    whole_disk: device_plan.whole_disk().is_some(),
+                    erase: (device_plan.erase() != ErasePolicy::None).then(|| device_plan.erase().to_string()),
+                    member_of: device_plan.member_of().map(str::to_owned),
+                    raid_level: device_plan.array().map(|a| a.level.to_string()),
+                    destroyed: destroyed
+                        .into_iter()
+                        .map(|p| DestroyedPartition {
+                            number: p.number,
+                            device: p.device.clone(),
+                            size: p.size * 512,
+                        })
+                        .collect(),
+                    partitions,
+                }
+            })
+            .collect::<Vec<_>>();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Report {
+            strategy: self.strategy.name.clone(),
+            summary: self.strategy.summary.clone(),
+            devices,
+            volume_groups: self
+                .volume_groups
+                .iter()
+                .map(|g| VolumeGroupReport {
+                    name: g.name.clone(),
+                    physical_volumes: g.physical_volumes.clone(),
+                    logical_volumes: g.logical_volumes.iter().map(|l| l.name.clone()).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Report {
+    /// Render the human-readable summary in the language of `locale`
+    pub fn render(&self, locale: &dyn Locale) -> String {
+        let mut text = format!(
+            "{}\n",
+            locale.format(
+                Message::Strategy,
+                &[("name", self.strategy.clone()), ("summary", self.summary.clone())]
+            )
+        );
+
+        for device in &self.devices {
+            let mut notes = vec![];
+            if let Some(level) = &device.raid_level {
+                notes.push(locale.format(Message::RaidArray, &[("level", level.clone())]));
+            }
+            if let Some(array) = &device.member_of {
+                notes.push(locale.format(Message::MemberOf, &[("array", array.clone())]));
+            }
+            if let Some(policy) = &device.erase {
+                notes.push(locale.format(Message::Erased, &[("policy", policy.clone())]));
+            }
+            if device.new_table {
+                notes.push(locale.format(Message::NewTable, &[]));
+            }
+            if device.whole_disk {
+                notes.push(locale.format(Message::WholeDisk, &[]));
+            }
+            text.push_str(&format!(
+                "\n{}: {} ({})",
+                device.name,
+                device.device.display(),
+                locale.size(device.size)
+            ));
+            for note in notes {
+                text.push_str(&format!(", {note}"));
+            }
+            text.push('\n');
+
+            for partition in &device.destroyed {
+                let destroyed = locale.format(
+                    Message::Destroyed,
+                    &[
+                        ("device", partition.device.display().to_string()),
+                        ("size", locale.size(partition.size)),
+                    ],
+                );
+                text.push_str(&format!("  - {destroyed}\n"));
+            }
+
+            for partition in &device.partitions {
+                text.push_str(&format!(
+                    "  + {} ({})",
+                    partition.id.as_deref().unwrap_or("partition"),
+                    locale.size(partition.size)
+                ));
+                if let Some(number) = partition.number {
+                    text.push_str(&format!(" #{number}"));
+                }
+                if let Some(role) = &partition.role {
+                    text.push_str(&format!(" role={role}"));
+                }
+                if let Some(filesystem) = &partition.filesystem {
+                    text.push_str(&format!(" fs={filesystem}"));
+                }
+                if let Some(label) = &partition.label {
+                    text.push_str(&format!(" label={label}"));
+                }
+                if let Some(name) = &partition.encrypted {
+                    text.push_str(&format!(" luks={name}"));
+                }
+                if let Some(mountpoint) = &partition.mountpoint {
+                    text.push_str(&format!(" at {mountpoint}"));
+                }
+                for replaced in &partition.replaces {
+                    let replaces =
+                        locale.format(Message::ReplacesDevice, &[("device", replaced.display().to_string())]);
+                    text.push_str(&format!(", {replaces}"));
+                }
+                text.push('\n');
+            }
+        }
+
+        for group in &self.volume_groups {
+            let line = locale.format(
+                Message::VolumeGroup,
+                &[
+                    ("name", group.name.clone()),
+                    ("physical", group.physical_volumes.join(", ")),
+                    ("logical", group.logical_volumes.join(", ")),
+                ],
+            );
+            text.push_str(&format!("\n{line}\n"));
+        }
+
+        text
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(&English))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+    use partitioning::locale::{Catalog, Message};
+
+    use crate::{Parser, Provisioner};
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn test_report() {
+        let test_strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap();
+        let mut disk = MockDisk::new(150 * GIB);
+        disk.add_partition(GIB, 11 * GIB);
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(disk));
+        for def in test_strategies
+            .strategies
+            .into_iter()
+            .filter(|s| s.name == "whole_disk")
+        {
+            provisioner.add_strategy(def);
+        }
+
+        let plans = provisioner.plan();
+        let report = plans[0].report();
+        assert_eq!(report.strategy, "whole_disk");
+
+        let device = &report.devices[0];
+        assert!(device.new_table);
+        assert_eq!(device.destroyed.len(), 1);
+        assert_eq!(device.destroyed[0].size, 10 * GIB);
+        let root = device
+            .partitions
+            .iter()
+            .find(|p| p.id.as_deref() == Some("root"))
+            .unwrap();
+        assert_eq!(root.filesystem.as_deref(), Some("ext4"));
+        assert_eq!(root.mountpoint.as_deref(), Some("/"));
+        let numbers = device.partitions.iter().map(|p| p.number).collect::<Vec<_>>();
+        assert_eq!(numbers, (1..=numbers.len() as u32).map(Some).collect::<Vec<_>>());
+
+        let text = report.to_string();
+        assert!(text.contains("/dev/mock0p1 (10.0GiB) will be destroyed"));
+        assert!(text.contains("+ root"));
+
+        let german = Catalog::new()
+            .with(Message::Destroyed, "{device} ({size}) wird gelöscht")
+            .with_decimal_separator(',');
+        assert!(report.render(&german).contains("/dev/mock0p1 (10,0GiB) wird gelöscht"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["devices"][0]["name"], "root_disk");
+        assert_eq!(json["devices"][0]["partitions"][0]["id"], "esp");
+    }
+}
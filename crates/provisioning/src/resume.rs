@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Hibernation resume configuration
+//!
+//! Once a plan has been applied, [`Plan::resume_target()`] tells the installer where the system
+//! resumes from, for the boot loader configuration. Swap partitions are preferred and
+//! referenced by the UUID of their swap header. Swapfiles are referenced by the filesystem
+//! holding them plus the offset returned by [`partitioning::swapfile::Swapfile::apply()`].
+//! Swap on encrypted partitions resumes from the mapped device.
+
+use std::{fmt, fs::File, path::Path};
+
+use partitioning::{format::FilesystemType, swapfile};
+use tracing::debug;
+
+use crate::{
+    fstab::{device_source, read_uuid},
+    ApplyReport, DeviceStatus, Plan,
+};
+
+/// Where the system resumes from after hibernation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeTarget {
+    /// Device specification, e.g. `UUID=...` or `/dev/mapper/cryptroot`
+    pub device: String,
+    /// Page offset of the swapfile on the device, `None` for swap partitions
+    pub offset: Option<u64>,
+}
+
+impl ResumeTarget {
+    /// Kernel command line arguments, `resume=` and `resume_offset=` for swapfiles
+    pub fn kernel_args(&self) -> Vec<String> {
+        std::iter::once(format!("resume={}", self.device))
+            .chain(self.offset.map(|offset| format!("resume_offset={offset}")))
+            .collect()
+    }
+}
+
+impl fmt::Display for ResumeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.kernel_args().join(" "))
+    }
+}
+
+impl Plan<'_> {
+    /// Where to resume from after hibernating to the swap created by [`Plan::apply()`]
+    pub fn resume_target(&self, report: &ApplyReport) -> Option<ResumeTarget> {
+        self.resume_target_with(report, read_swap_uuid)
+    }
+
+    fn resume_target_with(&self, report: &ApplyReport, uuid: impl Fn(&Path) -> Option<String>) -> Option<ResumeTarget> {
+        let mut swapfile = None;
+
+        for (name, _, status) in &report.devices {
+            let (Some(device_plan), DeviceStatus::Written(partitions)) = (self.device_assignments.get(name), status)
+            else {
+                continue;
+            };
+
+            for (id, format) in device_plan.filesystems() {
+                let formatted = report.filesystems.iter().any(|(f, result)| f == id && result.is_ok());
+                let partition = partitions
+                    .iter()
+                    .find(|p| p.region.tag().and_then(|t| t.id.as_ref()) == Some(id));
+                let Some(partition) = partition.filter(|_| formatted) else {
+                    continue;
+                };
+
+                let luks = device_plan.encryption().iter().find(|(p, _)| p == id).map(|(_, l)| l);
+                let device = match luks {
+                    Some(luks) => format!("/dev/mapper/{}", luks.name),
+                    None => device_source(partition, &uuid),
+                };
+
+                if format.filesystem == FilesystemType::Swap {
+                    debug!("Resuming from swap partition {}", id);
+                    return Some(ResumeTarget { device, offset: None });
+                }
+
+                let offset = report
+                    .swapfiles
+                    .iter()
+                    .find_map(|(p, result)| result.as_ref().ok().filter(|_| p == id));
+                if let (None, Some(&offset)) = (&swapfile, offset) {
+                    swapfile = Some(ResumeTarget {
+                        device,
+                        offset: Some(offset),
+                    });
+                }
+            }
+        }
+
+        swapfile
+    }
+}
+
+/// Read the UUID of the swap area, filesystem or LUKS container on `device`
+fn read_swap_uuid(device: &Path) -> Option<String> {
+    let swap = File::open(device)
+        .and_then(|mut f| swapfile::swap_uuid(&mut f))
+        .ok()
+        .flatten();
+    swap.map(|u| u.to_string()).or_else(|| read_uuid(device))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use disks::{mock::MockDisk, BlockDevice};
+    use partitioning::writer::WrittenPartition;
+    use uuid::Uuid;
+
+    use crate::{Parser, Provisioner};
+
+    use super::*;
+
+    /// Pretend `strategy` was applied with the given filesystems and swapfiles created
+    fn resume(
+        path: &str,
+        strategy: &str,
+        filesystems: &[&str],
+        swapfiles: Vec<(String, Result<u64, swapfile::Error>)>,
+    ) -> Option<ResumeTarget> {
+        let test_strategies = Parser::new_for_path(path).unwrap();
+        let mut provisioner = Provisioner::new();
+        provisioner.push_device(BlockDevice::mock_device(MockDisk::new(100 * 1024 * 1024 * 1024)));
+        for def in test_strategies.strategies {
+            provisioner.add_strategy(def);
+        }
+        let plans = provisioner.plan();
+        let plan = plans.iter().find(|p| p.strategy.name == strategy).unwrap();
+
+        let partitions = plan.device_assignments["root_disk"]
+            .planner()
+            .current_layout()
+            .into_iter()
+            .enumerate()
+            .map(|(i, region)| WrittenPartition {
+                number: i as u32 + 1,
+                region,
+                device: PathBuf::from(format!("/dev/mock0p{}", i + 1)),
+                guid: Uuid::from_u128(i as u128 + 1),
+            })
+            .collect::<Vec<_>>();
+        let report = ApplyReport {
+            devices: vec![(
+                "root_disk".into(),
+                "/dev/mock0".into(),
+                DeviceStatus::Written(partitions),
+            )],
+            filesystems: filesystems.iter().map(|id| (id.to_string(), Ok(()))).collect(),
+            subvolumes: vec![],
+            swapfiles,
+        };
+
+        plan.resume_target_with(&report, |device| {
+            Some(device.display().to_string().replace("/dev/", ""))
+        })
+    }
+
+    #[test]
+    fn test_resume() {
+        let partition = resume(
+            "tests/mount_tables.kdl",
+            "mount_tables",
+            &["esp", "swap", "root"],
+            vec![],
+        );
+        assert_eq!(partition.unwrap().to_string(), "resume=UUID=mock0p2");
+
+        let swapfile = resume(
+            "tests/swapfile.kdl",
+            "ext4_swapfile",
+            &["esp", "root"],
+            vec![("root".into(), Ok(34816))],
+        );
+        assert_eq!(
+            swapfile.unwrap().kernel_args(),
+            vec!["resume=UUID=mock0p2", "resume_offset=34816"]
+        );
+
+        // Nothing to resume from unless the swap was created
+        let failed = resume(
+            "tests/swapfile.kdl",
+            "ext4_swapfile",
+            &["esp", "root"],
+            vec![("root".into(), Err(swapfile::Error::TooSmall(0)))],
+        );
+        assert_eq!(failed, None);
+        assert_eq!(
+            resume("tests/mount_tables.kdl", "mount_tables", &["esp", "root"], vec![]),
+            None
+        );
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Running strategies against captured machines
+//!
+//! Distributions ship strategy files for hardware they don't own. A [`MachineProfile`]
+//! holds the devices and [`Facts`] of a real system as JSON, and [`simulate()`] plans every
+//! strategy against mock copies of those devices. The resulting [`Simulation`] lists for
+//! each strategy whether it applies and the layouts it would produce, so strategy files
+//! can be checked in CI without touching a disk.
+//!
+//! Devices use the [`DeviceInfo`] format, so the output of device listings by the FFI or
+//! D-Bus front-ends can be pasted into a profile as is. Snapshots taken with
+//! [`disks::snapshot()`] are turned into profiles with [`MachineProfile::from_snapshot()`].
+
+use std::{fs, io, path::Path};
+
+use disks::snapshot::Snapshot;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::{DeviceInfo, Facts, Provisioner, Report, StrategyDefinition};
+
+/// Errors that can occur while loading a machine profile
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    /// IO operation error
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// The profile is not valid JSON or misses fields
+    #[error("invalid machine profile: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Devices and facts of a machine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineProfile {
+    /// Free-form description, e.g. the machine model
+    #[serde(default)]
+    pub description: String,
+    /// Facts strategies are evaluated with
+    pub facts: Facts,
+    /// Block devices of the machine
+    pub devices: Vec<DeviceInfo>,
+}
+
+impl MachineProfile {
+    /// Capture the running system
+    pub fn capture(description: impl Into<String>) -> io::Result<Self> {
+        let devices = disks::BlockDevice::discover()?;
+        Ok(Self {
+            description: description.into(),
+            facts: Facts::gather(),
+            devices: devices.iter().map(DeviceInfo::from).collect(),
+        })
+    }
+
+    /// Build a profile from a device snapshot and the facts of the machine it was taken on
+    pub fn from_snapshot(description: impl Into<String>, snapshot: &Snapshot, facts: Facts) -> Self {
+        Self {
+            description: description.into(),
+            facts,
+            devices: snapshot.mock_devices().iter().map(DeviceInfo::from).collect(),
+        }
+    }
+
+    /// Parse a profile from JSON
+    pub fn from_json(json: &str) -> Result<Self, ProfileError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Load a profile from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Render the profile as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("machine profiles always serialize")
+    }
+}
+
+/// Outcome of a single strategy
+#[derive(Debug, Clone, Serialize)]
+pub struct StrategyOutcome {
+    /// Name of the strategy
+    pub strategy: String,
+    /// Summary of the strategy
+    pub summary: String,
+    /// Reports of every plan the strategy produced, empty if it doesn't apply
+    pub plans: Vec<Report>,
+}
+
+impl StrategyOutcome {
+    /// Returns true if the strategy produced at least one plan
+    pub fn applies(&self) -> bool {
+        !self.plans.is_empty()
+    }
+}
+
+/// Outcome of every strategy on a machine
+#[derive(Debug, Clone, Serialize)]
+pub struct Simulation {
+    /// Description of the simulated machine
+    pub machine: String,
+    /// Outcomes ordered by strategy name
+    pub strategies: Vec<StrategyOutcome>,
+}
+
+impl Simulation {
+    /// Names of the strategies that apply
+    pub fn applicable(&self) -> impl Iterator<Item = &str> {
+        self.strategies
+            .iter()
+            .filter(|s| s.applies())
+            .map(|s| s.strategy.as_str())
+    }
+}
+
+/// Plan every strategy against the devices of `profile`
+///
+/// Mounts of the host are ignored, so exclusion rules only see what the profile holds.
+pub fn simulate(profile: &MachineProfile, strategies: impl IntoIterator<Item = StrategyDefinition>) -> Simulation {
+    info!("Simulating strategies on {:?}", profile.description);
+
+    let mut provisioner = Provisioner::new();
+    provisioner.set_mounts(vec![]);
+    provisioner.set_facts(profile.facts.clone());
+    for device in &profile.devices {
+        provisioner.push_device(device.to_mock());
+    }
+
+    let mut outcomes = strategies
+        .into_iter()
+        .map(|strategy| {
+            let outcome = StrategyOutcome {
+                strategy: strategy.name.clone(),
+                summary: strategy.summary.clone(),
+                plans: vec![],
+            };
+            provisioner.add_strategy(strategy);
+            outcome
+        })
+        .collect::<Vec<_>>();
+    outcomes.sort_by(|a, b| a.strategy.cmp(&b.strategy));
+
+    for plan in provisioner.plan() {
+        let report = plan.report();
+        if let Some(outcome) = outcomes.iter_mut().find(|o| o.strategy == report.strategy) {
+            outcome.plans.push(report);
+        }
+    }
+    for outcome in &outcomes {
+        debug!(
+            strategy = outcome.strategy,
+            plans = outcome.plans.len(),
+            "Simulated strategy"
+        );
+    }
+
+    Simulation {
+        machine: profile.description.clone(),
+        strategies: outcomes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_simulate() {
+        let profile = MachineProfile::load("tests/profiles/laptop.json").unwrap();
+        assert_eq!(profile.devices[0].partitions.len(), 4);
+
+        let strategies = Parser::new_for_path("tests/use_whole_disk.kdl").unwrap().strategies;
+        let simulation = simulate(&profile, strategies);
+        assert_eq!(
+            simulation.applicable().collect::<Vec<_>>(),
+            vec!["whole_disk", "whole_disk_with_swap"]
+        );
+
+        let plans = &simulation.strategies[0].plans;
+        assert_eq!(plans.len(), 1, "the USB stick is too small");
+        let device = &plans[0].devices[0];
+        assert_eq!(device.device, Path::new("/dev/nvme0n1"));
+        assert_eq!(device.destroyed.len(), 4);
+
+        // Round trip
+        let again = MachineProfile::from_json(&profile.to_json()).unwrap();
+        assert_eq!(again.facts, profile.facts);
+        assert!(matches!(MachineProfile::from_json("{}"), Err(ProfileError::Json(_))));
+    }
+}
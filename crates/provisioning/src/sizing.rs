@@ -0,0 +1,141 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Minimum disk sizes required by a strategy
+//!
+//! Installers can use these to hide strategies that cannot fit the available disks, and
+//! to tell users how much space is missing. The minimum for a disk is the larger of the
+//! lower bound of its `find-disk` constraints (plus any reserve) and the space needed
+//! by the lower bounds of its partitions, the partition table and alignment.
+//!
+//! Bounds that depend on the size of the disk itself are treated as zero.
+
+use std::collections::HashMap;
+
+use partitioning::planner::{TableType, PARTITION_ALIGNMENT};
+use tracing::debug;
+
+use crate::{
+    commands::Command, select_commands, Constraints, Facts, PartitionTableType, StrategyDefinition, Variables,
+};
+
+impl StrategyDefinition {
+    /// Compute the minimum size in bytes of each disk used by this strategy
+    ///
+    /// Only the commands of this strategy are considered, so a strategy that inherits from
+    /// another should be sized with [`Provisioner::minimum_disk_size()`] instead.
+    pub fn minimum_disk_size(&self, facts: &Facts) -> HashMap<String, u64> {
+        let mut selected = vec![];
+        select_commands(&self.commands, facts, &mut selected);
+        minimum_sizes(selected, facts)
+    }
+}
+
+/// Per-disk requirements gathered from the commands of a strategy
+#[derive(Debug, Default)]
+struct DiskRequirements {
+    search: u64,
+    table: Option<TableType>,
+    partitions: u64,
+}
+
+/// Compute the minimum size of every disk referenced by `commands`
+pub(crate) fn minimum_sizes<'a>(
+    commands: impl IntoIterator<Item = &'a Command>,
+    facts: &Facts,
+) -> HashMap<String, u64> {
+    let variables = Variables {
+        ram: facts.memory,
+        disk: None,
+    };
+    let minimum = |constraints: &Constraints| match constraints.resolve(&variables) {
+        Some(constraints) => constraints.minimum(),
+        None => {
+            debug!("Constraints {:?} depend on the disk size, ignoring", constraints);
+            0
+        }
+    };
+
+    let mut disks: HashMap<String, DiskRequirements> = HashMap::new();
+    for command in commands {
+        match command {
+            Command::FindDisk(command) => {
+                let search = command.constraints.as_ref().map_or(0, minimum);
+                disks.entry(command.name.clone()).or_default().search = search + command.reserve;
+            }
+            Command::FindDisks(command) => {
+                let search = command.constraints.as_ref().map_or(0, minimum);
+                for name in command.disk_names() {
+                    disks.entry(name).or_default().search = search + command.reserve;
+                }
+            }
+            Command::CreatePartitionTable(command) => {
+                disks.entry(command.disk.clone()).or_default().table = Some(match command.table_type {
+                    PartitionTableType::Gpt => TableType::Gpt,
+                    PartitionTableType::Msdos => TableType::Mbr,
+                });
+            }
+            Command::CreatePartition(command) => {
+                let size = minimum(&command.constraints).next_multiple_of(PARTITION_ALIGNMENT);
+                disks.entry(command.disk.clone()).or_default().partitions += size;
+            }
+            _ => {}
+        }
+    }
+
+    disks
+        .into_iter()
+        .map(|(name, disk)| {
+            let partitions = match disk.partitions {
+                0 => 0,
+                size => size + disk.table.unwrap_or(TableType::Gpt).overhead(),
+            };
+            (name, disk.search.max(partitions))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use disks::{mock::MockDisk, BlockDevice};
+
+    use super::*;
+    use crate::{Parser, Provisioner};
+
+    #[test]
+    fn test_minimum_disk_size() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+
+        let provisioner = |devices: Vec<BlockDevice>| {
+            let mut provisioner = Provisioner::new();
+            for def in Parser::new_for_path("tests/use_whole_disk.kdl").unwrap().strategies {
+                provisioner.add_strategy(def);
+            }
+            for device in devices {
+                provisioner.push_device(device);
+            }
+            provisioner
+        };
+
+        // ESP, XBOOTLDR, root and /var at their minimum sizes, plus the GPT
+        let empty = provisioner(vec![]);
+        let minimum = empty.minimum_disk_size("whole_disk").unwrap();
+        let expected = 43 * GIB + TableType::Gpt.overhead();
+        assert_eq!(minimum, HashMap::from([("root_disk".into(), expected)]));
+
+        // Swap is inherited on top
+        let minimum = empty.minimum_disk_size("whole_disk_with_swap").unwrap();
+        assert_eq!(minimum["root_disk"], expected + 4 * GIB);
+        assert!(empty.minimum_disk_size("missing").is_none());
+
+        // A disk of exactly the minimum size fits, one sector less does not
+        for (size, fits) in [(expected, true), (expected - 512, false)] {
+            let provisioner = provisioner(vec![BlockDevice::mock_device(MockDisk::new(size))]);
+            let plans = provisioner.plan();
+            let plan = plans.iter().find(|p| p.strategy.name == "whole_disk").unwrap();
+            assert_eq!(plan.diagnostics.is_empty(), fits);
+        }
+    }
+}
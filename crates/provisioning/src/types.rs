@@ -13,11 +13,27 @@ mod partition_table;
 pub use partition_table::*;
 mod partition_role;
 pub use partition_role::*;
+mod filesystem;
+pub use filesystem::*;
+mod erase;
+pub use erase::*;
+mod encryption;
+pub use encryption::*;
+mod lvm;
+pub use lvm::*;
+mod raid;
+pub use raid::*;
 
 mod units;
 pub use units::*;
 pub mod constraints;
 pub use constraints::*;
+mod exclusions;
+pub use exclusions::*;
+mod expression;
+pub use expression::*;
+mod condition;
+pub use condition::*;
 
 /// The type of a KDL value
 #[derive(Debug)]
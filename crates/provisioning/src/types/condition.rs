@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{kdl_value_to_bool, kdl_value_to_storage_size, kdl_value_to_string, Facts, Firmware, FromKdlProperty};
+
+/// A condition on the system facts, every given property must hold
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Condition {
+    /// Required firmware type
+    pub firmware: Option<Firmware>,
+
+    /// Required secure boot state
+    pub secure_boot: Option<bool>,
+
+    /// Required CPU architecture
+    pub arch: Option<String>,
+
+    /// Whether the system must (or must not) be a virtual machine
+    pub virtualized: Option<bool>,
+
+    /// Minimum amount of memory in bytes
+    pub min_memory: Option<u64>,
+}
+
+impl Condition {
+    pub fn from_kdl_node(node: &kdl::KdlNode) -> Result<Self, crate::Error> {
+        Ok(Self {
+            firmware: node.entry("firmware").map(Firmware::from_kdl_property).transpose()?,
+            secure_boot: node.entry("secure-boot").map(kdl_value_to_bool).transpose()?,
+            arch: node.entry("arch").map(kdl_value_to_string).transpose()?,
+            virtualized: node.entry("virtual").map(kdl_value_to_bool).transpose()?,
+            min_memory: node.entry("min-memory").map(kdl_value_to_storage_size).transpose()?,
+        })
+    }
+
+    /// Returns true if the condition holds for the given facts
+    pub fn holds(&self, facts: &Facts) -> bool {
+        self.firmware.is_none_or(|f| f == facts.firmware)
+            && self.secure_boot.is_none_or(|s| facts.secure_boot == Some(s))
+            && self.arch.as_ref().is_none_or(|a| *a == facts.arch)
+            && self.virtualized.is_none_or(|v| v == facts.virtualized)
+            && self.min_memory.is_none_or(|m| facts.memory >= m)
+    }
+}
@@ -2,11 +2,11 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::{get_kdl_entry, kdl_value_to_storage_size};
+use crate::{get_kdl_entry, kdl_value_to_storage_size, Expression, Variables};
 
 /// Constraints for partition size, 1:1 mapping to SizeRequirements in
 /// partitioning strategy internals.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Constraints {
     /// Exact size in bytes
     Exact(u64),
@@ -16,37 +16,106 @@ pub enum Constraints {
     Range { min: u64, max: u64 },
     /// Use all remaining space
     Remaining,
+    /// Exactly the amount of installed memory, e.g. swap for hibernation
+    Memory,
+    /// Bounds computed from expressions when the plan is built
+    Computed {
+        min: Option<Expression>,
+        max: Option<Expression>,
+        exact: Option<Expression>,
+    },
 }
 
 impl Constraints {
     pub fn from_kdl_node(node: &kdl::KdlNode) -> Result<Self, crate::Error> {
+        // Any string value turns the whole set of bounds into expressions
+        let bounds = ["min", "max", "exactly"].map(|name| {
+            node.iter_children()
+                .find(|n| n.name().value() == name)
+                .and_then(|n| n.entry(0))
+        });
+        if bounds.iter().flatten().any(|e| e.value().is_string()) {
+            let [min, max, exact] = bounds.map(|entry| {
+                entry
+                    .map(|e| match e.value().is_string() {
+                        true => Expression::from_kdl_entry(e),
+                        false => kdl_value_to_storage_size(e).map(Expression::Value),
+                    })
+                    .transpose()
+            });
+            return Ok(Self::Computed {
+                min: min?,
+                max: max?,
+                exact: exact?,
+            });
+        }
+
         let range = node
             .iter_children()
             .find(|n| n.name().value() == "min")
             .zip(node.iter_children().find(|n| n.name().value() == "max"));
 
         if let Some((min, max)) = range {
-            let min = kdl_value_to_storage_size(get_kdl_entry(min, &0)?)? as u64;
-            let max = kdl_value_to_storage_size(get_kdl_entry(max, &0)?)? as u64;
+            let min = kdl_value_to_storage_size(get_kdl_entry(min, &0)?)?;
+            let max = kdl_value_to_storage_size(get_kdl_entry(max, &0)?)?;
 
-            Ok(Self::Range {
-                min: min as u64,
-                max: max as u64,
-            })
+            Ok(Self::Range { min, max })
         } else if let Some(min) = node.iter_children().find(|n| n.name().value() == "min") {
-            let min = kdl_value_to_storage_size(get_kdl_entry(min, &0)?)? as u64;
-            Ok(Self::AtLeast(min as u64))
+            let min = kdl_value_to_storage_size(get_kdl_entry(min, &0)?)?;
+            Ok(Self::AtLeast(min))
         } else if let Some(exact) = node.iter_children().find(|n| n.name().value() == "exactly") {
-            let exact = kdl_value_to_storage_size(get_kdl_entry(exact, &0)?)? as u64;
-            Ok(Self::Exact(exact as u64))
+            let exact = kdl_value_to_storage_size(get_kdl_entry(exact, &0)?)?;
+            Ok(Self::Exact(exact))
         } else if node.iter_children().any(|n| n.name().value() == "remaining") {
             Ok(Self::Remaining)
+        } else if node.iter_children().any(|n| n.name().value() == "memory") {
+            Ok(Self::Memory)
         } else {
             Err(crate::Error::MissingProperty(crate::MissingProperty {
                 at: node.span(),
-                id: "min, max, exactly or remaining",
+                id: "min, max, exactly, remaining or memory",
                 advice: Some("add one of these properties".into()),
             }))
         }
     }
+
+    /// The smallest size in bytes that satisfies resolved constraints
+    ///
+    /// Remaining space, memory and computed constraints have no fixed lower bound.
+    pub fn minimum(&self) -> u64 {
+        match self {
+            Self::Exact(n) | Self::AtLeast(n) => *n,
+            Self::Range { min, .. } => *min,
+            Self::Remaining | Self::Memory | Self::Computed { .. } => 0,
+        }
+    }
+
+    /// Resolve memory and computed constraints to fixed sizes
+    ///
+    /// Returns `None` if an expression cannot be evaluated with the given variables.
+    pub fn resolve(&self, variables: &Variables) -> Option<Self> {
+        match self {
+            Self::Memory => Some(Self::Exact(variables.ram)),
+            Self::Computed { exact: Some(exact), .. } => Some(Self::Exact(exact.evaluate(variables)?)),
+            Self::Computed { min, max, .. } => {
+                let min = match min {
+                    Some(e) => Some(e.evaluate(variables)?),
+                    None => None,
+                };
+                let max = match max {
+                    Some(e) => Some(e.evaluate(variables)?),
+                    None => None,
+                };
+                match (min, max) {
+                    (min, Some(max)) => Some(Self::Range {
+                        min: min.unwrap_or(0),
+                        max,
+                    }),
+                    (Some(min), None) => Some(Self::AtLeast(min)),
+                    (None, None) => Some(Self::Remaining),
+                }
+            }
+            constraints => Some(constraints.clone()),
+        }
+    }
 }
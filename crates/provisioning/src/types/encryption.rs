@@ -0,0 +1,127 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use crate::kdl_value_to_string;
+
+use super::FromKdlProperty;
+
+/// Key derivation function used for a LUKS2 keyslot
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PbkdfType {
+    /// Argon2id, the LUKS2 default
+    #[default]
+    Argon2id,
+
+    /// Argon2i
+    Argon2i,
+
+    /// PBKDF2, for bootloaders without Argon2 support
+    Pbkdf2,
+}
+
+impl fmt::Display for PbkdfType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Argon2id => f.write_str("argon2id"),
+            Self::Argon2i => f.write_str("argon2i"),
+            Self::Pbkdf2 => f.write_str("pbkdf2"),
+        }
+    }
+}
+
+impl FromStr for PbkdfType {
+    type Err = crate::Error;
+
+    /// Attempt to convert a string to a key derivation function
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "argon2id" => Ok(Self::Argon2id),
+            "argon2i" => Ok(Self::Argon2i),
+            "pbkdf2" => Ok(Self::Pbkdf2),
+            _ => Err(crate::Error::UnknownVariant),
+        }
+    }
+}
+
+impl FromKdlProperty<'_> for PbkdfType {
+    fn from_kdl_property(entry: &kdl::KdlEntry) -> Result<Self, crate::Error> {
+        let value = kdl_value_to_string(entry)?;
+        let v = value.parse().map_err(|_| crate::UnsupportedValue {
+            at: entry.span(),
+            advice: Some("'argon2id', 'argon2i' and 'pbkdf2' are supported".into()),
+        })?;
+        Ok(v)
+    }
+}
+
+/// Key derivation parameters, left to cryptsetup when unset
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Pbkdf {
+    /// The key derivation function
+    pub kind: PbkdfType,
+
+    /// Memory cost in bytes (Argon2 only)
+    pub memory: Option<u64>,
+
+    /// Iteration count or time cost
+    pub iterations: Option<u32>,
+}
+
+/// Where the initial key for a LUKS2 volume comes from
+///
+/// The key itself is never part of a strategy; it is supplied by the installer
+/// at execution time.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// Ask the user for a passphrase
+    #[default]
+    Prompt,
+
+    /// Read the key from a file
+    Keyfile(PathBuf),
+}
+
+/// A token to enroll in a LUKS2 volume once it has been created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Enrollment {
+    /// Bind a keyslot to the TPM2, sealed against the given PCRs (the default set if empty)
+    Tpm2 { pcrs: Vec<u32> },
+
+    /// Bind a keyslot to a FIDO2 security key
+    Fido2,
+}
+
+impl fmt::Display for Enrollment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tpm2 { .. } => f.write_str("tpm2"),
+            Self::Fido2 => f.write_str("fido2"),
+        }
+    }
+}
+
+/// Settings for wrapping a partition in LUKS2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Luks {
+    /// Name of the mapped device, e.g. `cryptroot`
+    pub name: String,
+
+    /// Cipher specification
+    pub cipher: String,
+
+    /// Key size in bits
+    pub key_size: u32,
+
+    /// Key derivation parameters
+    pub pbkdf: Pbkdf,
+
+    /// Source of the initial key
+    pub key: KeySource,
+
+    /// Tokens to enroll after creation
+    pub enroll: Vec<Enrollment>,
+}
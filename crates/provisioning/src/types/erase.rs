@@ -0,0 +1,69 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{fmt, str::FromStr};
+
+use crate::kdl_value_to_string;
+
+use super::FromKdlProperty;
+
+/// How a disk is erased before it is partitioned
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ErasePolicy {
+    /// Leave existing data alone
+    None,
+
+    /// Remove partition tables and filesystem signatures
+    #[default]
+    SignaturesOnly,
+
+    /// Discard every block
+    Discard,
+
+    /// Overwrite the disk with zeroes
+    Zero,
+
+    /// Securely discard every block
+    SecureErase,
+}
+
+impl fmt::Display for ErasePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::SignaturesOnly => f.write_str("signatures-only"),
+            Self::Discard => f.write_str("discard"),
+            Self::Zero => f.write_str("zero"),
+            Self::SecureErase => f.write_str("secure-erase"),
+        }
+    }
+}
+
+impl FromStr for ErasePolicy {
+    type Err = crate::Error;
+
+    /// Attempt to convert a string to an erase policy
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Self::None),
+            "signatures-only" => Ok(Self::SignaturesOnly),
+            "discard" => Ok(Self::Discard),
+            "zero" => Ok(Self::Zero),
+            "secure-erase" => Ok(Self::SecureErase),
+            _ => Err(crate::Error::UnknownVariant),
+        }
+    }
+}
+
+impl FromKdlProperty<'_> for ErasePolicy {
+    fn from_kdl_property(entry: &kdl::KdlEntry) -> Result<Self, crate::Error> {
+        let value = kdl_value_to_string(entry)?;
+        let v = value.parse().map_err(|_| crate::UnsupportedValue {
+            at: entry.span(),
+            advice: Some("'none', 'signatures-only', 'discard', 'zero' and 'secure-erase' are supported".into()),
+        })?;
+        Ok(v)
+    }
+}
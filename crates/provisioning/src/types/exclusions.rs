@@ -0,0 +1,40 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+/// Rules excluding disks from selection, regardless of their size
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exclusions {
+    /// Skip disks reporting removable media
+    pub removable: bool,
+
+    /// Skip disks with any mounted filesystem
+    pub mounted: bool,
+
+    /// Skip the disk holding the live installer medium
+    pub live_medium: bool,
+}
+
+impl Exclusions {
+    pub fn from_kdl_node(node: &kdl::KdlNode) -> Result<Self, crate::Error> {
+        let mut exclusions = Self::default();
+
+        for child in node.iter_children() {
+            match child.name().value() {
+                "removable" => exclusions.removable = true,
+                "mounted" => exclusions.mounted = true,
+                "live-medium" => exclusions.live_medium = true,
+                _ => {
+                    return Err(crate::UnsupportedValue {
+                        at: child.span(),
+                        advice: Some("'removable', 'mounted' and 'live-medium' are supported".into()),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        Ok(exclusions)
+    }
+}
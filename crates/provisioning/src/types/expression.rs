@@ -0,0 +1,262 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Size expressions
+//!
+//! Constraint values may be given as a string expression instead of a fixed size, e.g.
+//! `min "ram * 2"` or `max "disk / 4 + 1GiB"`. Expressions support `+`, `-`, `*`, `/` and
+//! parentheses over integers (optionally suffixed with a storage unit) and the variables:
+//!
+//!  - `ram`: total memory of the system in bytes
+//!  - `disk`: size of the disk being matched or partitioned in bytes
+
+use std::{fmt, str::FromStr};
+
+use crate::{kdl_value_to_string, StorageUnit, UnsupportedValue};
+
+/// A variable usable within an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    /// Total memory of the system
+    Ram,
+    /// Size of the disk in question
+    Disk,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ram => f.write_str("ram"),
+            Self::Disk => f.write_str("disk"),
+        }
+    }
+}
+
+/// Arithmetic operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// Values of the variables an expression is evaluated against
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Variables {
+    /// Total memory in bytes
+    pub ram: u64,
+    /// Size of the disk in bytes, if there is one in scope
+    pub disk: Option<u64>,
+}
+
+/// A size expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    /// A fixed number of bytes (or a plain scalar)
+    Value(u64),
+    /// A variable resolved at evaluation time
+    Variable(Variable),
+    /// A binary operation
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub fn from_kdl_entry(entry: &kdl::KdlEntry) -> Result<Self, crate::Error> {
+        let value = kdl_value_to_string(entry)?;
+        let expression = value.parse().map_err(|e: String| UnsupportedValue {
+            at: entry.span(),
+            advice: Some(e),
+        })?;
+        Ok(expression)
+    }
+
+    /// Evaluate the expression, returning `None` if a variable is unavailable or the
+    /// arithmetic overflows or divides by zero
+    pub fn evaluate(&self, variables: &Variables) -> Option<u64> {
+        match self {
+            Self::Value(n) => Some(*n),
+            Self::Variable(Variable::Ram) => Some(variables.ram),
+            Self::Variable(Variable::Disk) => variables.disk,
+            Self::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(variables)?, rhs.evaluate(variables)?);
+                match op {
+                    Operator::Add => lhs.checked_add(rhs),
+                    Operator::Subtract => lhs.checked_sub(rhs),
+                    Operator::Multiply => lhs.checked_mul(rhs),
+                    Operator::Divide => lhs.checked_div(rhs),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(value)?;
+        let mut parser = ExpressionParser { tokens, pos: 0 };
+        let expression = parser.sum()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expression),
+            Some(token) => Err(format!("unexpected {token:?} in expression")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(u64),
+    Ident(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+/// Split an expression into tokens, applying unit suffixes to numbers
+fn tokenize(value: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '+' => Token::Operator(Operator::Add),
+            '-' => Token::Operator(Operator::Subtract),
+            '*' => Token::Operator(Operator::Multiply),
+            '/' => Token::Operator(Operator::Divide),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit() || *d == '_') {
+                    digits.push(d);
+                }
+                let mut unit = String::new();
+                while let Some(u) = chars.next_if(|u| u.is_ascii_alphabetic()) {
+                    unit.push(u);
+                }
+                let number = digits
+                    .replace('_', "")
+                    .parse::<u64>()
+                    .map_err(|e| format!("invalid number {digits}: {e}"))?;
+                let unit = if unit.is_empty() {
+                    1
+                } else {
+                    unit.to_lowercase()
+                        .parse::<StorageUnit>()
+                        .map_err(|_| format!("unknown unit {unit}"))? as u64
+                };
+                Token::Number(number.checked_mul(unit).ok_or("number too large")?)
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut ident = c.to_string();
+                while let Some(i) = chars.next_if(|i| i.is_ascii_alphanumeric() || *i == '_') {
+                    ident.push(i);
+                }
+                Token::Ident(ident)
+            }
+            c => return Err(format!("unexpected character '{c}' in expression")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, `*` and `/` binding tighter than `+` and `-`
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExpressionParser {
+    fn next_operator(&mut self, operators: &[Operator]) -> Option<Operator> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) if operators.contains(op) => {
+                self.pos += 1;
+                Some(*op)
+            }
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut lhs = self.product()?;
+        while let Some(op) = self.next_operator(&[Operator::Add, Operator::Subtract]) {
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut lhs = self.term()?;
+        while let Some(op) = self.next_operator(&[Operator::Multiply, Operator::Divide]) {
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expression, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expression::Value(n)),
+            Token::Ident(ident) => match ident.as_str() {
+                "ram" => Ok(Expression::Variable(Variable::Ram)),
+                "disk" => Ok(Expression::Variable(Variable::Disk)),
+                _ => Err(format!("unknown variable {ident}, 'ram' and 'disk' are supported")),
+            },
+            Token::Open => {
+                let inner = self.sum()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    _ => Err("missing closing parenthesis".into()),
+                }
+            }
+            token => Err(format!("unexpected {token:?} in expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn test_expressions() {
+        let variables = Variables {
+            ram: 8 * GIB,
+            disk: Some(100 * GIB),
+        };
+        let eval = |s: &str| s.parse::<Expression>().unwrap().evaluate(&variables);
+
+        assert_eq!(eval("ram * 2"), Some(16 * GIB));
+        assert_eq!(eval("disk / 4"), Some(25 * GIB));
+        assert_eq!(eval("ram + 2GiB * 2"), Some(12 * GIB));
+        assert_eq!(eval("(ram + 2GiB) * 2"), Some(20 * GIB));
+        assert_eq!(eval("1GB - 2GB"), None);
+        assert_eq!(eval("disk / 0"), None);
+
+        let no_disk = Variables {
+            disk: None,
+            ..variables
+        };
+        assert_eq!("disk / 2".parse::<Expression>().unwrap().evaluate(&no_disk), None);
+
+        assert!("swap * 2".parse::<Expression>().is_err());
+        assert!("ram * (2".parse::<Expression>().is_err());
+        assert!("ram 2".parse::<Expression>().is_err());
+        assert!("4parsecs".parse::<Expression>().is_err());
+    }
+}
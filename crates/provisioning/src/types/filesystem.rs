@@ -0,0 +1,73 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{fmt, str::FromStr};
+
+use crate::kdl_value_to_string;
+
+use super::FromKdlProperty;
+
+/// The type of filesystem to create on a partition
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilesystemType {
+    /// B-tree filesystem
+    Btrfs,
+
+    /// Fourth extended filesystem
+    Ext4,
+
+    /// Flash-friendly filesystem
+    F2fs,
+
+    /// FAT32, as required for an ESP
+    Fat32,
+
+    /// Swap space
+    Swap,
+
+    /// XFS
+    Xfs,
+}
+
+impl fmt::Display for FilesystemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Btrfs => f.write_str("btrfs"),
+            Self::Ext4 => f.write_str("ext4"),
+            Self::F2fs => f.write_str("f2fs"),
+            Self::Fat32 => f.write_str("fat32"),
+            Self::Swap => f.write_str("swap"),
+            Self::Xfs => f.write_str("xfs"),
+        }
+    }
+}
+
+impl FromStr for FilesystemType {
+    type Err = crate::Error;
+
+    /// Attempt to convert a string to a filesystem type
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "btrfs" => Ok(Self::Btrfs),
+            "ext4" => Ok(Self::Ext4),
+            "f2fs" => Ok(Self::F2fs),
+            "fat32" | "vfat" => Ok(Self::Fat32),
+            "swap" => Ok(Self::Swap),
+            "xfs" => Ok(Self::Xfs),
+            _ => Err(crate::Error::UnknownVariant),
+        }
+    }
+}
+
+impl FromKdlProperty<'_> for FilesystemType {
+    fn from_kdl_property(entry: &kdl::KdlEntry) -> Result<Self, crate::Error> {
+        let value = kdl_value_to_string(entry)?;
+        let v = value.parse().map_err(|_| crate::UnsupportedValue {
+            at: entry.span(),
+            advice: Some("'btrfs', 'ext4', 'f2fs', 'fat32', 'swap' and 'xfs' are supported".into()),
+        })?;
+        Ok(v)
+    }
+}
@@ -0,0 +1,29 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::Constraints;
+
+/// An LVM volume group spanning one or more partitions
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeGroup {
+    /// Name of the volume group, e.g. `vg0`
+    pub name: String,
+
+    /// Reference IDs of the partitions used as physical volumes
+    pub physical_volumes: Vec<String>,
+
+    /// Logical volumes carved from the group, in declaration order
+    pub logical_volumes: Vec<LogicalVolume>,
+}
+
+/// An LVM logical volume
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogicalVolume {
+    /// Name of the logical volume within its group
+    pub name: String,
+
+    /// Size of the logical volume
+    pub constraints: Constraints,
+}
@@ -5,12 +5,14 @@
 
 use std::{fmt, str::FromStr};
 
+use partitioning::partition_type::{Architecture, Role};
+
 use crate::kdl_value_to_string;
 
 use super::FromKdlProperty;
 
 /// The role assigned to a partition
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PartitionRole {
     /// Boot partition (usually ESP)
     Boot,
@@ -26,6 +28,45 @@ pub enum PartitionRole {
 
     /// Swap partition
     Swap,
+
+    /// BIOS boot partition for legacy boot from GPT disks
+    BiosBoot,
+}
+
+impl PartitionRole {
+    /// The conventional mount point for partitions with this role
+    ///
+    /// The ESP is mounted at `/efi` so that XBOOTLDR can own `/boot`.
+    pub fn mountpoint(&self) -> Option<&'static str> {
+        match self {
+            Self::Boot => Some("/efi"),
+            Self::ExtendedBoot => Some("/boot"),
+            Self::Root => Some("/"),
+            Self::Home => Some("/home"),
+            Self::Swap | Self::BiosBoot => None,
+        }
+    }
+
+    /// The smallest size recommended for partitions with this role, if any
+    pub fn recommended_size(&self) -> Option<u64> {
+        match self {
+            Self::Boot => Some(512 * 1024 * 1024),
+            Self::ExtendedBoot => Some(1024 * 1024 * 1024),
+            _ => None,
+        }
+    }
+
+    /// The GPT partition type role, if the root partition type is known for this architecture
+    pub fn partition_type(&self) -> Option<Role> {
+        match self {
+            Self::Boot => Some(Role::Esp),
+            Self::ExtendedBoot => Some(Role::ExtendedBoot),
+            Self::Root => Architecture::host().map(Role::Root),
+            Self::Home => Some(Role::Home),
+            Self::Swap => Some(Role::Swap),
+            Self::BiosBoot => Some(Role::BiosBoot),
+        }
+    }
 }
 
 impl fmt::Display for PartitionRole {
@@ -36,6 +77,7 @@ impl fmt::Display for PartitionRole {
             Self::Root => f.write_str("root"),
             Self::Home => f.write_str("home"),
             Self::Swap => f.write_str("swap"),
+            Self::BiosBoot => f.write_str("bios-boot"),
         }
     }
 }
@@ -51,6 +93,7 @@ impl FromStr for PartitionRole {
             "root" => Ok(Self::Root),
             "home" => Ok(Self::Home),
             "swap" => Ok(Self::Swap),
+            "bios-boot" => Ok(Self::BiosBoot),
             _ => Err(crate::Error::UnknownVariant),
         }
     }
@@ -61,7 +104,7 @@ impl FromKdlProperty<'_> for PartitionRole {
         let value = kdl_value_to_string(entry)?;
         let v = value.parse().map_err(|_| crate::UnsupportedValue {
             at: entry.span(),
-            advice: Some("'boot', 'extended-boot', 'root', 'home' and 'swap' are supported".into()),
+            advice: Some("'boot', 'extended-boot', 'root', 'home', 'swap' and 'bios-boot' are supported".into()),
         })?;
         Ok(v)
     }
@@ -10,7 +10,7 @@ use crate::kdl_value_to_string;
 use super::FromKdlProperty;
 
 /// The type of partition table to create
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PartitionTableType {
     /// GUID Partition Table
     Gpt,
@@ -0,0 +1,117 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{fmt, str::FromStr};
+
+use crate::kdl_value_to_string;
+
+use super::FromKdlProperty;
+
+/// Space reserved on each member for md metadata (mdadm's default data offset)
+const MD_METADATA_RESERVE: u64 = 128 * 1024 * 1024;
+
+/// The RAID level of an mdraid array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaidLevel {
+    /// Striping
+    Raid0,
+
+    /// Mirroring
+    Raid1,
+
+    /// Striping with single parity
+    Raid5,
+
+    /// Striping with double parity
+    Raid6,
+
+    /// Striped mirrors
+    Raid10,
+}
+
+impl RaidLevel {
+    /// Minimum number of active members for this level
+    pub fn min_members(&self) -> usize {
+        match self {
+            Self::Raid0 | Self::Raid1 | Self::Raid10 => 2,
+            Self::Raid5 => 3,
+            Self::Raid6 => 4,
+        }
+    }
+}
+
+impl fmt::Display for RaidLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Raid0 => f.write_str("raid0"),
+            Self::Raid1 => f.write_str("raid1"),
+            Self::Raid5 => f.write_str("raid5"),
+            Self::Raid6 => f.write_str("raid6"),
+            Self::Raid10 => f.write_str("raid10"),
+        }
+    }
+}
+
+impl FromStr for RaidLevel {
+    type Err = crate::Error;
+
+    /// Attempt to convert a string to a RAID level
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "raid0" => Ok(Self::Raid0),
+            "raid1" => Ok(Self::Raid1),
+            "raid5" => Ok(Self::Raid5),
+            "raid6" => Ok(Self::Raid6),
+            "raid10" => Ok(Self::Raid10),
+            _ => Err(crate::Error::UnknownVariant),
+        }
+    }
+}
+
+impl FromKdlProperty<'_> for RaidLevel {
+    fn from_kdl_property(entry: &kdl::KdlEntry) -> Result<Self, crate::Error> {
+        let value = kdl_value_to_string(entry)?;
+        let v = value.parse().map_err(|_| crate::UnsupportedValue {
+            at: entry.span(),
+            advice: Some("'raid0', 'raid1', 'raid5', 'raid6' and 'raid10' are supported".into()),
+        })?;
+        Ok(v)
+    }
+}
+
+/// An mdraid array built from disks or partitions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaidArray {
+    /// Name of the array, also usable as a disk name by later commands
+    pub name: String,
+
+    /// The RAID level
+    pub level: RaidLevel,
+
+    /// Disk names or partition reference IDs making up the array, spares last
+    pub members: Vec<String>,
+
+    /// Number of members kept as hot spares
+    pub spares: usize,
+}
+
+impl RaidArray {
+    /// Usable size in bytes, given the size of each member in member order
+    pub fn usable_size(&self, member_sizes: &[u64]) -> u64 {
+        let active = member_sizes.len().saturating_sub(self.spares) as u64;
+        let smallest = member_sizes
+            .iter()
+            .min()
+            .map_or(0, |s| s.saturating_sub(MD_METADATA_RESERVE));
+
+        match self.level {
+            RaidLevel::Raid0 => smallest * active,
+            RaidLevel::Raid1 => smallest,
+            RaidLevel::Raid5 => smallest * active.saturating_sub(1),
+            RaidLevel::Raid6 => smallest * active.saturating_sub(2),
+            RaidLevel::Raid10 => smallest * active / 2,
+        }
+    }
+}
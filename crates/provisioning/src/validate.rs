@@ -0,0 +1,317 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Semantic validation of parsed strategies
+//!
+//! A strategy can be syntactically valid and still be impossible to plan, e.g. when it
+//! creates partitions on a disk it never looks for. [`Parser::validate()`] finds such
+//! problems up front and points at the offending node.
+//!
+//! Commands in sibling `when` blocks are alternatives, so they may reuse partition ids
+//! and roles without conflicting.
+
+use std::collections::HashSet;
+
+use miette::SourceSpan;
+use partitioning::planner::format_size;
+
+use crate::{
+    commands::Command, Constraints, Error, Firmware, Invalid, Parser, PartitionRole, PartitionTableType,
+    StrategyDefinition, ValidationError, Warning,
+};
+
+/// A command and the conditional block it appears in
+#[derive(Clone, Copy)]
+struct Scoped<'a> {
+    command: &'a Command,
+    strategy: &'a str,
+    /// Index of the top-level `when` block, if any
+    block: Option<usize>,
+    /// Firmware required by the enclosing blocks, if any
+    firmware: Option<&'a Firmware>,
+}
+
+impl Scoped<'_> {
+    /// Returns true if both commands can apply to the same system
+    fn overlaps(&self, other: &Scoped<'_>) -> bool {
+        self.block.is_none() || other.block.is_none() || (self.strategy, self.block) == (other.strategy, other.block)
+    }
+
+    /// Returns true if the command can apply to a system with the given firmware
+    fn applies_to(&self, firmware: Firmware) -> bool {
+        self.firmware.is_none_or(|f| *f == firmware)
+    }
+}
+
+impl Parser {
+    /// Check every strategy for semantic problems
+    ///
+    /// Reports duplicate partition ids, references to undeclared disks, size constraints
+    /// whose minimum exceeds their maximum, roles used twice on one disk and GPT layouts
+    /// lacking an EFI system partition. Returns one error per affected strategy.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        self.strategies
+            .iter()
+            .filter_map(|strategy| {
+                let diagnostics = self.validate_strategy(strategy);
+                (!diagnostics.is_empty()).then(|| ValidationError {
+                    name: strategy.name.clone(),
+                    src: self.sources[&strategy.name].clone(),
+                    diagnostics,
+                })
+            })
+            .collect()
+    }
+
+    /// The strategy and its ancestors, oldest first
+    fn chain<'a>(&'a self, strategy: &'a StrategyDefinition) -> Vec<&'a StrategyDefinition> {
+        let mut chain = vec![strategy];
+        let mut seen = HashSet::from([strategy.name.as_str()]);
+        while let Some(parent) = chain[0]
+            .inherits
+            .as_deref()
+            .filter(|p| seen.insert(p))
+            .and_then(|p| self.strategies.iter().find(|s| s.name == p))
+        {
+            chain.insert(0, parent);
+        }
+        chain
+    }
+
+    fn validate_strategy(&self, strategy: &StrategyDefinition) -> Vec<Error> {
+        let mut diagnostics = vec![];
+        let chain = self.chain(strategy);
+        let mut inherited = vec![];
+        for s in &chain {
+            flatten(&s.commands, &s.name, None, None, &mut inherited);
+        }
+        let own = inherited
+            .iter()
+            .copied()
+            .filter(|c| c.strategy == strategy.name)
+            .collect::<Vec<_>>();
+
+        // Disks may be declared anywhere in the chain, arrays act as disks too
+        let mut declared = HashSet::new();
+        for scoped in &inherited {
+            match scoped.command {
+                Command::FindDisk(c) => {
+                    declared.insert(c.name.clone());
+                }
+                Command::FindDisks(c) => declared.extend(c.disk_names()),
+                Command::CreateRaid(c) => {
+                    declared.insert(c.array.name.clone());
+                }
+                _ => {}
+            }
+        }
+
+        for (i, scoped) in own.iter().enumerate() {
+            let (disk, span) = match scoped.command {
+                Command::CreatePartition(c) => {
+                    let duplicate = own[..i].iter().any(|other| {
+                        matches!(other.command, Command::CreatePartition(o) if o.id == c.id) && other.overlaps(scoped)
+                    });
+                    if duplicate {
+                        diagnostics.push(invalid(
+                            c.span,
+                            format!("duplicate partition id {}", c.id),
+                            "partition ids must be unique within a strategy",
+                        ));
+                    }
+                    check_range(&c.constraints, c.span, &mut diagnostics);
+                    (&c.disk, c.span)
+                }
+                Command::CreatePartitionTable(c) => (&c.disk, c.span),
+                Command::WipeDisk(c) => (&c.disk, c.span),
+                Command::FormatWholeDisk(c) => (&c.disk, c.span),
+                Command::FindDisk(c) => {
+                    if let Some(constraints) = &c.constraints {
+                        check_range(constraints, c.span, &mut diagnostics);
+                    }
+                    continue;
+                }
+                Command::FindDisks(c) => {
+                    if let Some(constraints) = &c.constraints {
+                        check_range(constraints, c.span, &mut diagnostics);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if !declared.contains(disk) {
+                diagnostics.push(invalid(
+                    span,
+                    format!("disk {disk} is not declared"),
+                    "declare the disk with find-disk or find-disks first",
+                ));
+            }
+        }
+
+        // Roles and the ESP depend on the partitions left once inheritance is resolved
+        let partitions = effective_partitions(&inherited);
+        for (i, scoped) in partitions.iter().enumerate() {
+            let Command::CreatePartition(c) = scoped.command else {
+                continue;
+            };
+            let Some(role) = &c.role else {
+                continue;
+            };
+            let duplicate = partitions[..i].iter().any(|other| {
+                matches!(other.command, Command::CreatePartition(o) if o.disk == c.disk && o.role.as_ref() == Some(role))
+                    && other.overlaps(scoped)
+            });
+            if duplicate && scoped.strategy == strategy.name {
+                diagnostics.push(
+                    Warning {
+                        at: Some(c.span),
+                        message: format!("role {role} is used more than once on disk {}", c.disk),
+                        advice: Some("each role should only be given to one partition per disk".into()),
+                    }
+                    .into(),
+                );
+            }
+        }
+
+        let uefi_gpt = inherited.iter().any(|s| {
+            matches!(s.command, Command::CreatePartitionTable(c) if c.table_type == PartitionTableType::Gpt)
+                && s.applies_to(Firmware::Uefi)
+        });
+        let has_role = |role: PartitionRole, firmware: Option<Firmware>| {
+            partitions.iter().any(|s| {
+                matches!(s.command, Command::CreatePartition(c) if c.role.as_ref() == Some(&role))
+                    && firmware.is_none_or(|f| s.applies_to(f))
+                    && (firmware.is_some() || s.block.is_none())
+            })
+        };
+        if uefi_gpt && !has_role(PartitionRole::Boot, Some(Firmware::Uefi)) && !has_role(PartitionRole::BiosBoot, None)
+        {
+            diagnostics.push(
+                Warning {
+                    at: Some(strategy.span),
+                    message: format!("strategy {} has no EFI system partition", strategy.name),
+                    advice: Some("add a partition with role=\"boot\" so UEFI systems can boot".into()),
+                }
+                .into(),
+            );
+        }
+
+        diagnostics
+    }
+}
+
+/// Collect commands, expanding conditional blocks while remembering their scope
+fn flatten<'a>(
+    commands: &'a [Command],
+    strategy: &'a str,
+    block: Option<usize>,
+    firmware: Option<&'a Firmware>,
+    out: &mut Vec<Scoped<'a>>,
+) {
+    for (i, command) in commands.iter().enumerate() {
+        match command {
+            Command::When(when) => flatten(
+                &when.commands,
+                strategy,
+                block.or(Some(i)),
+                when.condition.firmware.as_ref().or(firmware),
+                out,
+            ),
+            command => out.push(Scoped {
+                command,
+                strategy,
+                block,
+                firmware,
+            }),
+        }
+    }
+}
+
+/// The partitions a strategy creates, after overrides and removals
+fn effective_partitions<'a>(commands: &[Scoped<'a>]) -> Vec<Scoped<'a>> {
+    let mut partitions: Vec<Scoped<'a>> = vec![];
+    for scoped in commands {
+        match scoped.command {
+            Command::RemovePartition(r) => {
+                partitions.retain(|p| !matches!(p.command, Command::CreatePartition(c) if c.id == r.id));
+            }
+            Command::CreatePartition(c) => {
+                let existing = partitions.iter_mut().find(|p| {
+                    matches!(p.command, Command::CreatePartition(o) if o.id == c.id) && p.strategy != scoped.strategy
+                });
+                match existing {
+                    Some(existing) => *existing = *scoped,
+                    None => partitions.push(*scoped),
+                }
+            }
+            _ => {}
+        }
+    }
+    partitions
+}
+
+/// Report a range whose minimum exceeds its maximum
+fn check_range(constraints: &Constraints, at: SourceSpan, diagnostics: &mut Vec<Error>) {
+    if let Constraints::Range { min, max } = constraints {
+        if min > max {
+            diagnostics.push(invalid(
+                at,
+                format!(
+                    "minimum size {} exceeds maximum size {}",
+                    format_size(*min),
+                    format_size(*max)
+                ),
+                "swap the min and max constraints",
+            ));
+        }
+    }
+}
+
+fn invalid(at: SourceSpan, message: String, advice: &str) -> Error {
+    Invalid {
+        at,
+        message,
+        advice: Some(advice.into()),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Parser;
+
+    #[test]
+    fn test_validate() {
+        for fixture in [
+            "use_whole_disk",
+            "conditional",
+            "inheritance",
+            "lvm",
+            "facts",
+            "swapfile",
+            "whole_disk",
+        ] {
+            let p = Parser::new_for_path(format!("tests/{fixture}.kdl")).unwrap();
+            let errors = p.validate();
+            assert!(errors.is_empty(), "{fixture}: {errors:?}");
+        }
+
+        let p = Parser::new_for_path("tests/validation.kdl").unwrap();
+        let errors = p.validate();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].name, "broken");
+        let messages = errors[0].diagnostics.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "minimum size 55.9GiB exceeds maximum size 27.9GiB",
+                "disk rootdisk is not declared",
+                "duplicate partition id root",
+                "role root is used more than once on disk root_disk",
+                "strategy broken has no EFI system partition",
+            ]
+        );
+    }
+}
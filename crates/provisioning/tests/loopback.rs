@@ -0,0 +1,132 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+// SPDX-FileCopyrightText: Copyright © 2025 AerynOS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! End-to-end tests on loop devices
+//!
+//! These run the whole pipeline against a real block device: a sparse file is attached
+//! to a loop device, a strategy is planned and applied, and the resulting partition
+//! table and filesystems are probed. They need root privileges and the mkfs tools, so
+//! they are ignored by default:
+//!
+//! ```sh
+//! sudo -E cargo test -p provisioning --test loopback -- --ignored
+//! ```
+
+#![cfg(feature = "linux")]
+
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use disks::{loopback, BlockDevice};
+use partitioning::{
+    gpt::{partition_types, GptConfig},
+    loopback::{AttachOptions, LoopDevice},
+    partition_type::ATTR_PENDING,
+    sparsefile,
+};
+use provisioning::{DeviceStatus, Parser, Provisioner};
+use superblock::{Kind, Superblock};
+
+const MIB: u64 = 1024 * 1024;
+
+/// A sparse file attached to a loop device, detached and removed on drop
+struct LoopImage {
+    file: PathBuf,
+    device: LoopDevice,
+}
+
+impl LoopImage {
+    fn new(size: u64) -> Self {
+        let file = std::env::temp_dir().join(format!("disks-rs-loopback-{}", std::process::id()));
+        sparsefile::create(&file, size).expect("Failed to create sparse file");
+        let device = LoopDevice::create().expect("Failed to create loop device");
+        // The tests probe the partitions the kernel finds on the device
+        let options = AttachOptions {
+            partscan: true,
+            ..Default::default()
+        };
+        device
+            .attach_with(file.to_str().unwrap(), &options)
+            .expect("Failed to attach loop device");
+        Self { file, device }
+    }
+
+    fn path(&self) -> &Path {
+        Path::new(&self.device.path)
+    }
+}
+
+impl Drop for LoopImage {
+    fn drop(&mut self) {
+        let _ = self.device.detach();
+        let _ = fs::remove_file(&self.file);
+    }
+}
+
+/// Loop devices can only be set up by root
+fn is_root() -> bool {
+    fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0) && Path::new("/dev/loop-control").exists()
+}
+
+/// Probe the superblock at `offset` of `device`
+fn probe(device: &Path, offset: u64) -> Superblock {
+    let mut bytes = vec![0u8; 128 * 1024];
+    let mut file = File::open(device).unwrap();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.read_exact(&mut bytes).unwrap();
+    Superblock::from_bytes(&bytes).expect("No superblock found")
+}
+
+#[test]
+#[ignore = "needs root and loop devices"]
+fn test_apply_loopback() {
+    if !is_root() {
+        eprintln!("Skipping, root privileges and loop devices are required");
+        return;
+    }
+
+    let image = LoopImage::new(1024 * MIB);
+    let device = loopback::Device::from_device_path(image.path()).expect("Loop device not found");
+
+    let mut provisioner = Provisioner::new();
+    provisioner.push_device(BlockDevice::loopback_device(device));
+    for def in Parser::new_for_path("tests/loopback.kdl").unwrap().strategies {
+        provisioner.add_strategy(def);
+    }
+
+    let plans = provisioner.plan();
+    assert_eq!(plans.len(), 1);
+    let report = plans[0].apply();
+    assert!(report.is_success(), "{report:#?}");
+
+    let DeviceStatus::Written(partitions) = &report.devices[0].2 else {
+        panic!("Disk was not written: {report:#?}");
+    };
+    assert_eq!(partitions.len(), 2);
+    for partition in partitions {
+        assert!(partition.device.exists(), "{:?} was not created", partition.device);
+    }
+
+    // The table on disk matches the plan
+    let table = GptConfig::new()
+        .writable(false)
+        .open_from_device(File::open(image.path()).unwrap())
+        .unwrap();
+    let written = table.partitions();
+    assert_eq!(written.len(), 2);
+    assert_eq!(written[&1].part_type_guid, partition_types::LINUX_SWAP);
+    assert_eq!((written[&1].last_lba - written[&1].first_lba + 1) * 512, 64 * MIB);
+    assert_eq!(written[&2].name, "root");
+    // Pending markers are gone once the filesystems exist
+    assert!(written.values().all(|p| p.flags & ATTR_PENDING == 0));
+
+    let root = probe(image.path(), written[&2].first_lba * 512);
+    assert_eq!(root.kind(), Kind::Ext4);
+    assert_eq!(root.label().unwrap(), "root");
+}
@@ -18,7 +18,7 @@ use zerocopy::*;
 /// - Size and usage information
 /// - Root tree locations
 /// - Compatibility flags
-#[derive(FromBytes, Debug)]
+#[derive(FromBytes, Immutable, KnownLayout, Debug)]
 #[repr(C)]
 pub struct Btrfs {
     /// Checksum of the superblock data
@@ -116,13 +116,23 @@ impl Detection for Btrfs {
 }
 
 impl Btrfs {
-    /// Return the encoded UUID for this superblock as a string
-    pub fn uuid(&self) -> Result<String, Error> {
-        Ok(Uuid::from_bytes(self.fsid).hyphenated().to_string())
+    /// Return the filesystem UUID of this superblock
+    pub fn uuid(&self) -> Result<Uuid, Error> {
+        Ok(Uuid::from_bytes(self.fsid))
     }
 
     /// Return the volume label as a string
     pub fn label(&self) -> Result<String, Error> {
         Ok(std::str::from_utf8(&self.label)?.trim_end_matches('\0').to_owned())
     }
+
+    /// Return the size of the filesystem in bytes, across all of its devices
+    pub fn capacity(&self) -> u64 {
+        self.total_bytes.get()
+    }
+
+    /// Return the bytes not used by data or metadata, across all of its devices
+    pub fn free_space(&self) -> u64 {
+        self.total_bytes.get().saturating_sub(self.bytes_used.get())
+    }
 }
@@ -8,13 +8,19 @@
 //! The superblock contains critical metadata about the filesystem including UUID, volume label,
 //! and various configuration parameters.
 
-use crate::{Detection, Error};
+use std::{
+    io::{Read, Seek, Write},
+    mem::offset_of,
+};
+
+use crate::{fixed_label, read_at, write_at, Detection, Error};
+use crc::{Crc, CRC_32_ISCSI};
 use uuid::Uuid;
 use zerocopy::*;
 
 /// EXT4 Superblock definition that mirrors the on-disk format used by the Linux kernel.
 /// Contains metadata and configuration for an EXT4 filesystem.
-#[derive(Debug, FromBytes)]
+#[derive(Debug, FromBytes, Immutable, KnownLayout)]
 #[repr(C)]
 pub struct Ext4 {
     /// Total count of inodes in filesystem
@@ -82,7 +88,7 @@ pub struct Ext4 {
     /// 128-bit filesystem identifier
     pub uuid: [u8; 16],
     /// Volume name
-    pub volume_name: [u8; 16],
+    pub volume_name: [u8; LABEL_LEN],
     /// Directory where last mounted
     pub last_mounted: [u8; 64],
     /// For compression
@@ -119,6 +125,8 @@ pub struct Ext4 {
     pub jnl_blocks: [U32<LittleEndian>; 17],
     /// High 32-bits of block count
     pub blocks_count_hi: U32<LittleEndian>,
+    /// High 32-bits of reserved block count
+    pub r_blocks_count_hi: U32<LittleEndian>,
     /// High 32-bits of free block count
     pub free_blocks_count_hi: U32<LittleEndian>,
     /// Minimum inode extra size
@@ -193,6 +201,30 @@ pub const MAGIC: U16<LittleEndian> = U16::new(0xEF53);
 /// Start position of superblock in filesystem
 pub const START_POSITION: u64 = 1024;
 
+/// Incompatible feature flag for block counts above 2^32
+const INCOMPAT_64BIT: u32 = 0x80;
+
+/// Read-only compatible feature flag for checksummed metadata
+const RO_COMPAT_METADATA_CSUM: u32 = 0x400;
+
+/// Length of the volume label in bytes
+pub const LABEL_LEN: usize = 16;
+
+/// The superblock checksum is a crc32c without the final inversion
+const CHECKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// Incompatible feature flag for group descriptors stored with their meta block group
+const INCOMPAT_META_BG: u32 = 0x10;
+
+/// Compatible feature flag for the inode holding reserved GDT blocks
+const COMPAT_RESIZE_INODE: u32 = 0x10;
+
+/// Size of a group descriptor without the 64bit feature
+const DESC_SIZE: u64 = 32;
+
+/// Most blocks addressable by extents on a 64-bit filesystem
+const MAX_BLOCKS_64BIT: u64 = 1 << 48;
+
 impl Detection for Ext4 {
     type Magic = U16<LittleEndian>;
 
@@ -208,13 +240,122 @@ impl Detection for Ext4 {
 }
 
 impl Ext4 {
-    /// Return the encoded UUID for this superblock
-    pub fn uuid(&self) -> Result<String, Error> {
-        Ok(Uuid::from_bytes(self.uuid).hyphenated().to_string())
+    /// Return the UUID of this superblock
+    pub fn uuid(&self) -> Result<Uuid, Error> {
+        Ok(Uuid::from_bytes(self.uuid))
     }
 
     /// Return the volume label as valid utf8
     pub fn label(&self) -> Result<String, super::Error> {
-        Ok(std::str::from_utf8(&self.volume_name)?.into())
+        Ok(std::str::from_utf8(&self.volume_name)?
+            .trim_end_matches('\0')
+            .to_owned())
+    }
+
+    /// Write `label` to the superblock of this filesystem on `device`
+    ///
+    /// The label is at most 16 bytes. Only the primary superblock is updated, like the
+    /// kernel does, as the label of the backup superblocks is never read.
+    pub fn write_label<D: Read + Write + Seek>(&self, device: &mut D, label: &str) -> Result<(), Error> {
+        let name = fixed_label::<LABEL_LEN>(label)?;
+        let mut block = read_at(device, START_POSITION, Self::SIZE)?;
+        block[offset_of!(Ext4, volume_name)..][..LABEL_LEN].copy_from_slice(&name);
+        if self.feature_ro_compat.get() & RO_COMPAT_METADATA_CSUM != 0 {
+            let offset = offset_of!(Ext4, checksum);
+            let checksum = !CHECKSUM.checksum(&block[..offset]);
+            block[offset..offset + 4].copy_from_slice(&checksum.to_le_bytes());
+        }
+        write_at(device, START_POSITION, &block)?;
+        Ok(())
+    }
+
+    /// Return the block size in bytes
+    pub fn block_size(&self) -> u64 {
+        1024 << self.log_block_size.get()
+    }
+
+    /// Return the number of blocks in the filesystem
+    pub fn block_count(&self) -> u64 {
+        self.blocks(self.block_counts_lo.get(), self.blocks_count_hi.get())
+    }
+
+    /// Return the size of the filesystem in bytes
+    pub fn capacity(&self) -> u64 {
+        self.block_count() * self.block_size()
+    }
+
+    /// Returns true if blocks were reserved for growing the group descriptor table
+    pub fn has_resize_inode(&self) -> bool {
+        self.feature_compat.get() & COMPAT_RESIZE_INODE != 0
+    }
+
+    /// Returns true if group descriptors live in meta block groups, lifting the table size limit
+    pub fn has_meta_bg(&self) -> bool {
+        self.feature_incompat.get() & INCOMPAT_META_BG != 0
+    }
+
+    /// Return the largest size in bytes the filesystem can grow to without being recreated
+    ///
+    /// Every new block group needs a group descriptor. With `meta_bg` they are added freely, with
+    /// `resize_inode` the descriptor table grows into the reserved GDT blocks, and otherwise only
+    /// the unused slots of the last descriptor block are left. Block numbers are limited to
+    /// 32 bits unless the filesystem is 64-bit.
+    ///
+    /// This is the limit for an online resize. An offline `resize2fs` can sometimes go further
+    /// by moving data out of the way of a larger descriptor table.
+    pub fn max_grow_size(&self) -> u64 {
+        let addressable = if self.feature_incompat.get() & INCOMPAT_64BIT != 0 {
+            MAX_BLOCKS_64BIT
+        } else {
+            u64::from(u32::MAX)
+        };
+        let blocks_per_group = u64::from(self.blocks_per_group.get());
+        if blocks_per_group == 0 {
+            return self.capacity();
+        }
+
+        let max_blocks = if self.has_meta_bg() {
+            addressable
+        } else {
+            let first_data_block = u64::from(self.first_data_block.get());
+            let descs_per_block = (self.block_size() / self.desc_size()).max(1);
+            let groups = self
+                .block_count()
+                .saturating_sub(first_data_block)
+                .div_ceil(blocks_per_group);
+            let mut gdt_blocks = groups.div_ceil(descs_per_block);
+            if self.has_resize_inode() {
+                gdt_blocks += u64::from(self.reserved_gdt_blocks.get());
+            }
+            (first_data_block + gdt_blocks * descs_per_block * blocks_per_group).min(addressable)
+        };
+        max_blocks.max(self.block_count()) * self.block_size()
+    }
+
+    /// Returns true if the filesystem can grow beyond its current size without being recreated
+    pub fn can_grow(&self) -> bool {
+        self.max_grow_size() > self.capacity()
+    }
+
+    /// Return the free space in bytes, including the blocks reserved for root
+    pub fn free_space(&self) -> u64 {
+        self.blocks(self.free_blocks_count_lo.get(), self.free_blocks_count_hi.get()) * self.block_size()
+    }
+
+    /// Size of a group descriptor, which may only exceed 32 bytes on 64-bit filesystems
+    fn desc_size(&self) -> u64 {
+        match u64::from(self.desc_size.get()) {
+            size if self.feature_incompat.get() & INCOMPAT_64BIT != 0 && size > DESC_SIZE => size,
+            _ => DESC_SIZE,
+        }
+    }
+
+    /// The high 32 bits of block counts are only valid on 64-bit filesystems
+    fn blocks(&self, lo: u32, hi: u32) -> u64 {
+        if self.feature_incompat.get() & INCOMPAT_64BIT != 0 {
+            (u64::from(hi) << 32) | u64::from(lo)
+        } else {
+            u64::from(lo)
+        }
     }
 }
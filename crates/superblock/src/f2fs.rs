@@ -13,7 +13,13 @@
 //! - Encryption settings
 //! - Device information
 
-use crate::{Detection, Error};
+use std::{
+    io::{Read, Seek, Write},
+    mem::offset_of,
+};
+
+use crate::{read_at, write_at, Detection, Error};
+use crc::{Algorithm, Crc};
 use uuid::Uuid;
 use zerocopy::*;
 
@@ -35,7 +41,7 @@ pub const MAX_STOP_REASON: usize = 32;
 pub const MAX_ERRORS: usize = 16;
 
 /// Represents the F2FS superblock structure that exists on disk
-#[derive(Debug, FromBytes, Unaligned)]
+#[derive(Debug, FromBytes, Immutable, KnownLayout, Unaligned)]
 #[repr(C, packed)]
 pub struct F2FS {
     /// Magic number to identify F2FS filesystem
@@ -133,7 +139,7 @@ pub struct F2FS {
 }
 
 /// Represents a device entry in the F2FS superblock
-#[derive(Debug, Clone, Copy, FromBytes)]
+#[derive(Debug, Clone, Copy, FromBytes, Immutable)]
 #[repr(C, packed)]
 pub struct Device {
     /// Device path
@@ -160,11 +166,28 @@ impl Detection for F2FS {
 pub const MAGIC: U32<LittleEndian> = U32::new(0xF2F52010);
 /// Starting position of superblock in bytes
 pub const START_POSITION: u64 = 1024;
+/// Position of the second copy of the superblock, in the next 4KiB block
+pub const BACKUP_POSITION: u64 = 4096 + START_POSITION;
+
+/// Feature flag for a checksummed superblock
+const FEATURE_SB_CHKSUM: u32 = 0x800;
+
+/// The kernel's `crc32_le()` seeded with the magic number, without inversions
+const CHECKSUM: Crc<u32> = Crc::<u32>::new(&Algorithm {
+    width: 32,
+    poly: 0x04c11db7,
+    init: 0xF2F52010u32.reverse_bits(),
+    refin: true,
+    refout: true,
+    xorout: 0,
+    check: 0,
+    residue: 0,
+});
 
 impl F2FS {
-    /// Returns the filesystem UUID as a hyphenated string
-    pub fn uuid(&self) -> Result<String, Error> {
-        Ok(Uuid::from_bytes(self.uuid).hyphenated().to_string())
+    /// Returns the filesystem UUID
+    pub fn uuid(&self) -> Result<Uuid, Error> {
+        Ok(Uuid::from_bytes(self.uuid))
     }
 
     /// Returns the volume label as a UTF-16 decoded string
@@ -177,4 +200,34 @@ impl F2FS {
         // Need valid grapheme step and skip (u16)\0 nul termination in fixed block size
         Ok(prelim_label.trim_end_matches('\0').to_owned())
     }
+
+    /// Write `label` to both copies of the superblock of this filesystem on `device`
+    ///
+    /// The label is stored as UTF-16 of at most 512 code units. The superblock checksum is
+    /// updated if the filesystem has one.
+    pub fn write_label<D: Read + Write + Seek>(&self, device: &mut D, label: &str) -> Result<(), Error> {
+        let units = label.encode_utf16().collect::<Vec<_>>();
+        if units.len() > MAX_VOLUME_LEN {
+            return Err(Error::LabelTooLong(MAX_VOLUME_LEN));
+        }
+        let checksum_offset = usize::try_from(self.checksum_offset.get()).unwrap_or(usize::MAX);
+
+        for position in [START_POSITION, BACKUP_POSITION] {
+            let mut block = read_at(device, position, Self::SIZE)?;
+            if block[..4] != *MAGIC.as_bytes() {
+                continue;
+            }
+            let name = &mut block[offset_of!(F2FS, volume_name)..][..MAX_VOLUME_LEN * 2];
+            name.fill(0);
+            for (bytes, unit) in name.chunks_exact_mut(2).zip(&units) {
+                bytes.copy_from_slice(&unit.to_le_bytes());
+            }
+            if self.feature.get() & FEATURE_SB_CHKSUM != 0 && checksum_offset + 4 <= block.len() {
+                let checksum = CHECKSUM.checksum(&block[..checksum_offset]);
+                block[checksum_offset..checksum_offset + 4].copy_from_slice(&checksum.to_le_bytes());
+            }
+            write_at(device, position, &block)?;
+        }
+        Ok(())
+    }
 }
@@ -10,9 +10,12 @@
 //! - Volume name and UUID
 //! - Encryption settings
 
-use std::io;
+use std::{
+    io::{self, Read, Seek, Write},
+    mem::offset_of,
+};
 
-use crate::{Detection, Error};
+use crate::{read_at, write_at, Detection, Error};
 use zerocopy::*;
 
 /// Starting position of superblock in bytes
@@ -20,8 +23,38 @@ pub const START_POSITION: u64 = 0;
 
 const MAGIC: [u8; 2] = [0x55, 0xAA];
 
+/// Length of the volume label in bytes
+pub const LABEL_LEN: usize = 11;
+
+/// Boot sector label of volumes without one
+const NO_NAME: &[u8; LABEL_LEN] = b"NO NAME    ";
+
+/// Extended boot signature announcing the volume ID and label fields
+const EXTENDED_SIGNATURE: u8 = 0x29;
+
+/// Size of a directory entry in bytes
+const DIR_ENTRY_SIZE: usize = 32;
+
+/// Offset of the attributes within a directory entry
+const DIR_ENTRY_ATTR: usize = 11;
+
+/// Attribute of the volume label entry
+const ATTR_VOLUME_ID: u8 = 0x08;
+
+/// Attributes of long file name entries, which include [`ATTR_VOLUME_ID`]
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// First name byte of a deleted directory entry
+const DELETED: u8 = 0xE5;
+
+/// Lowest FAT32 entry marking the end of a cluster chain
+const FAT32_END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+
+/// Most root directory clusters followed, guarding against loops in a corrupt FAT
+const MAX_ROOT_CLUSTERS: usize = 4096;
+
 #[repr(C, packed)]
-#[derive(FromBytes, Unaligned, Debug)]
+#[derive(FromBytes, Immutable, KnownLayout, Unaligned, Debug)]
 pub struct Fat {
     /// Boot strap short or near jump
     pub ignored: [u8; 3],
@@ -118,6 +151,7 @@ impl Detection for Fat {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FatType {
     Fat16,
     Fat32,
@@ -133,8 +167,10 @@ impl Fat {
         }
     }
 
-    /// Returns the filesystem id
-    pub fn uuid(&self) -> Result<String, Error> {
+    /// Returns the volume serial number, e.g. `A1B2-C3D4`
+    ///
+    /// FAT has no UUID, this is what udev exposes in `/dev/disk/by-uuid` instead.
+    pub fn volume_id(&self) -> Result<String, Error> {
         match self.fat_type()? {
             FatType::Fat16 => vol_id(self.fat16()?.common.vol_id),
             FatType::Fat32 => vol_id(self.fat32()?.common.vol_id),
@@ -149,6 +185,127 @@ impl Fat {
         }
     }
 
+    /// Returns the label of the volume label entry in the root directory, if there is one
+    ///
+    /// Windows only updates this entry when relabeling a volume, leaving the boot sector
+    /// label stale, so like blkid it should be preferred over [`Fat::label()`].
+    pub fn root_label<R: Read + Seek>(&self, reader: &mut R) -> Result<Option<String>, Error> {
+        match self.root_entries(reader)?.label {
+            Some((_, name)) => Ok(Some(vol_label(&name)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write `label` to the boot sector and the root directory of this volume on `device`
+    ///
+    /// The label is at most 11 bytes and padded with spaces, an empty label removes it. The
+    /// FAT32 backup boot sector is updated as well.
+    pub fn write_label<D: Read + Write + Seek>(&self, device: &mut D, label: &str) -> Result<(), Error> {
+        let mut name = [b' '; LABEL_LEN];
+        name.get_mut(..label.len())
+            .ok_or(Error::LabelTooLong(LABEL_LEN))?
+            .copy_from_slice(label.as_bytes());
+
+        // The root directory first, it is what Windows and blkid show
+        let root = self.root_entries(device)?;
+        match root.label {
+            Some((offset, _)) if label.is_empty() => write_at(device, offset, &[DELETED])?,
+            Some((offset, _)) => write_at(device, offset, &name)?,
+            None if label.is_empty() => {}
+            None => {
+                let offset = root
+                    .free
+                    .ok_or_else(|| io::Error::other("no free entry in the root directory for the label"))?;
+                let mut entry = [0u8; DIR_ENTRY_SIZE];
+                entry[..LABEL_LEN].copy_from_slice(&name);
+                entry[DIR_ENTRY_ATTR] = ATTR_VOLUME_ID;
+                write_at(device, offset, &entry)?;
+            }
+        }
+
+        let (common, fields, backup) = match self.fat_type()? {
+            FatType::Fat16 => (self.fat16()?.common, offset_of!(Fat16Fields, common), None),
+            FatType::Fat32 => {
+                let fat32 = self.fat32()?;
+                let backup = match fat32.backup_boot.get() {
+                    0 | 0xFFFF => None,
+                    sector => Some(u64::from(sector) * u64::from(self.sector_size.get())),
+                };
+                (fat32.common, offset_of!(Fat32Fields, common), backup)
+            }
+        };
+        if common.signature != EXTENDED_SIGNATURE {
+            return Ok(());
+        }
+        let offset = (offset_of!(Fat, shared) + fields + offset_of!(Fat16And32Fields, vol_label)) as u64;
+        let name = if label.is_empty() { NO_NAME } else { &name };
+        for boot in std::iter::once(START_POSITION).chain(backup) {
+            write_at(device, boot + offset, name)?;
+        }
+        Ok(())
+    }
+
+    /// Find the volume label entry and the first free entry of the root directory
+    fn root_entries<R: Read + Seek>(&self, reader: &mut R) -> Result<RootEntries, Error> {
+        let mut found = RootEntries::default();
+        for (start, len) in self.root_extents(reader)? {
+            let entries = read_at(reader, start, len)?;
+            for (index, entry) in entries.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                let offset = start + (index * DIR_ENTRY_SIZE) as u64;
+                let attr = entry[DIR_ENTRY_ATTR];
+                match entry[0] {
+                    // No entries follow
+                    0 => {
+                        found.free.get_or_insert(offset);
+                        return Ok(found);
+                    }
+                    DELETED => {
+                        found.free.get_or_insert(offset);
+                    }
+                    _ if attr & ATTR_LONG_NAME == ATTR_LONG_NAME => {}
+                    _ if attr & ATTR_VOLUME_ID != 0 && found.label.is_none() => {
+                        let mut name = [0u8; LABEL_LEN];
+                        name.copy_from_slice(&entry[..LABEL_LEN]);
+                        found.label = Some((offset, name));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Byte ranges holding the root directory
+    ///
+    /// FAT16 has a fixed size root directory after the FATs, FAT32 keeps it in a chain of
+    /// clusters like any other directory.
+    fn root_extents<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<(u64, usize)>, Error> {
+        let sector_size = u64::from(self.sector_size.get());
+        let reserved = u64::from(self._reserved.get());
+        let fats = u64::from(self.fats);
+
+        match self.fat_type()? {
+            FatType::Fat16 => {
+                let start = (reserved + fats * u64::from(self.fat_length.get())) * sector_size;
+                Ok(vec![(start, usize::from(self.dir_entries.get()) * DIR_ENTRY_SIZE)])
+            }
+            FatType::Fat32 => {
+                let fat32 = self.fat32()?;
+                let data = (reserved + fats * u64::from(fat32.fat32_length.get())) * sector_size;
+                let cluster_size = u64::from(self.sec_per_clus) * sector_size;
+
+                let mut extents = vec![];
+                let mut cluster = fat32.root_cluster.get();
+                while (2..FAT32_END_OF_CHAIN).contains(&cluster) && extents.len() < MAX_ROOT_CLUSTERS {
+                    extents.push((data + u64::from(cluster - 2) * cluster_size, cluster_size as usize));
+                    let entry = read_at(reader, reserved * sector_size + u64::from(cluster) * 4, 4)?;
+                    cluster = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) & 0x0FFF_FFFF;
+                }
+                Ok(extents)
+            }
+        }
+    }
+
     fn fat16(&self) -> Result<Fat16Fields, Error> {
         Ok(Fat16Fields::read_from_bytes(&self.shared[..size_of::<Fat16Fields>()])
             .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Error Reading FAT16 Superblock"))?)
@@ -160,7 +317,20 @@ impl Fat {
     }
 }
 
+/// Locations found while scanning the root directory
+#[derive(Default)]
+struct RootEntries {
+    /// Offset and name of the volume label entry
+    label: Option<(u64, [u8; LABEL_LEN])>,
+    /// Offset of the first unused entry
+    free: Option<u64>,
+}
+
+/// Decode a space padded label, `NO NAME` meaning there is none
 fn vol_label(vol_label: &[u8; 11]) -> Result<String, Error> {
+    if vol_label == NO_NAME {
+        return Ok(String::new());
+    }
     Ok(String::from_utf8_lossy(vol_label).trim_end_matches(' ').to_string())
 }
 
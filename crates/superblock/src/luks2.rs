@@ -12,6 +12,7 @@
 //!
 
 mod config;
+pub mod integrity;
 mod superblock;
 
 pub use config::*;
@@ -4,7 +4,15 @@
 
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom},
+    time::Duration,
+};
+use zerocopy::FromBytes;
+
+use super::integrity::{self, Integrity};
+use crate::{read_at, Error};
 
 /// Top-level LUKS2 configuration structure representing a LUKS2 encrypted device.
 /// This structure contains all the configuration needed to manage a LUKS2 device,
@@ -23,6 +31,55 @@ pub struct Luks2Config {
     // pub digests: HashMap<u64, Value>,
 }
 
+impl Luks2Config {
+    /// The data segment holding the payload, i.e. the `crypt` segment with the lowest ID
+    pub fn data_segment(&self) -> Option<&Luks2Segment> {
+        self.segments
+            .iter()
+            .filter(|(_, segment)| segment.segment_type == "crypt")
+            .min_by_key(|(id, _)| **id)
+            .map(|(_, segment)| segment)
+    }
+
+    /// Returns true if activating the volume needs a dm-integrity device below dm-crypt
+    pub fn requires_integrity(&self) -> bool {
+        self.segments.values().any(Luks2Segment::requires_integrity)
+    }
+
+    /// Read the dm-integrity superblock at the start of the data segment
+    ///
+    /// Returns `Ok(None)` for volumes without integrity protection.
+    pub fn read_integrity<R: Read + Seek>(&self, reader: &mut R) -> Result<Option<Integrity>, Error> {
+        let Some(segment) = self.data_segment().filter(|s| s.requires_integrity()) else {
+            return Ok(None);
+        };
+        let bytes = read_at(reader, segment.offset_bytes()?, size_of::<Integrity>())?;
+        match Integrity::read_from_bytes(&bytes) {
+            Ok(block) if block.magic == integrity::MAGIC => Ok(Some(block)),
+            _ => Err(Error::UnknownSuperblock),
+        }
+    }
+
+    /// Size in bytes of the decrypted payload, as seen through the activated device
+    ///
+    /// Integrity protected volumes lose space to per-sector tags and the journal, so
+    /// their size comes from the dm-integrity superblock. Otherwise a dynamic segment
+    /// extends to the end of `reader`.
+    pub fn payload_size<R: Read + Seek>(&self, reader: &mut R) -> Result<u64, Error> {
+        let segment = self.data_segment().ok_or(Error::UnsupportedFeature)?;
+        if let Some(integrity) = self.read_integrity(reader)? {
+            return Ok(integrity.capacity());
+        }
+        match segment.size_bytes()? {
+            Some(size) => Ok(size),
+            None => {
+                let end = reader.seek(SeekFrom::End(0))?;
+                Ok(end.saturating_sub(segment.offset_bytes()?))
+            }
+        }
+    }
+}
+
 /// Core LUKS2 configuration data containing essential metadata about the encrypted device.
 #[serde_as]
 #[derive(Debug, Deserialize, Serialize)]
@@ -72,7 +129,7 @@ pub struct Luks2Kdf {
     /// Only applicable when kdf_type is "argon2i" or "argon2id"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub time: Option<u64>,
-    /// Memory usage in bytes for Argon2
+    /// Memory usage in KiB for Argon2
     /// Only applicable when kdf_type is "argon2i" or "argon2id"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub memory: Option<u64>,
@@ -82,6 +139,101 @@ pub struct Luks2Kdf {
     pub cpus: Option<u64>,
 }
 
+/// Speed of the machine at deriving keys, e.g. as measured by `cryptsetup benchmark`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfBenchmark {
+    /// PBKDF2 iterations per second
+    pub pbkdf2_iterations_per_second: u64,
+    /// Argon2 memory processed per second by a single thread, in KiB
+    pub argon2_kib_per_second: u64,
+    /// Threads available to Argon2
+    pub cpus: u64,
+}
+
+/// Estimated resources needed to unlock a keyslot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnlockCost {
+    /// Time spent deriving the key
+    pub duration: Duration,
+    /// Memory needed in bytes, zero for PBKDF2
+    pub memory: u64,
+}
+
+impl Luks2Kdf {
+    /// Returns true for the memory hard Argon2 variants
+    pub fn is_argon2(&self) -> bool {
+        self.kdf_type.starts_with("argon2")
+    }
+
+    /// Estimate the cost of unlocking with these parameters on a machine as fast as `benchmark`
+    ///
+    /// Argon2 lanes beyond the available threads run one after another. Returns `None` for
+    /// unknown KDFs, missing parameters or a benchmark without a rate for the KDF.
+    pub fn unlock_cost(&self, benchmark: &KdfBenchmark) -> Option<UnlockCost> {
+        if self.is_argon2() {
+            let memory = self.memory?;
+            let work = self.time? * memory;
+            let lanes = self.cpus.unwrap_or(1).min(benchmark.cpus).max(1);
+            let rate = benchmark.argon2_kib_per_second.checked_mul(lanes).filter(|r| *r > 0)?;
+            Some(UnlockCost {
+                duration: Duration::from_secs_f64(work as f64 / rate as f64),
+                memory: memory * 1024,
+            })
+        } else if self.kdf_type == "pbkdf2" {
+            let rate = Some(benchmark.pbkdf2_iterations_per_second).filter(|r| *r > 0)?;
+            Some(UnlockCost {
+                duration: Duration::from_secs_f64(self.iterations? as f64 / rate as f64),
+                memory: 0,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Order in which cryptsetup tries keyslots when none is given
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum KeyslotPriority {
+    /// Only used when explicitly selected
+    Ignore,
+    /// Tried after keyslots of high priority
+    Normal,
+    /// Tried first
+    High,
+}
+
+impl TryFrom<u8> for KeyslotPriority {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Ignore),
+            1 => Ok(Self::Normal),
+            2 => Ok(Self::High),
+            _ => Err(format!("invalid keyslot priority {value}")),
+        }
+    }
+}
+
+impl From<KeyslotPriority> for u8 {
+    fn from(priority: KeyslotPriority) -> Self {
+        priority as u8
+    }
+}
+
+/// Anti-forensic splitting of the key material, making partial erasure sufficient
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Luks2AntiForensic {
+    /// Type of splitter, `luks1`
+    #[serde(rename = "type")]
+    pub af_type: String,
+    /// Number of stripes the key is split into
+    pub stripes: u64,
+    /// Hash used to diffuse the stripes
+    pub hash: String,
+}
+
 /// Configuration for a single keyslot containing key material and derivation settings.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Luks2Keyslot {
@@ -92,12 +244,32 @@ pub struct Luks2Keyslot {
     /// Size of the keyslot key in bytes
     pub key_size: u64,
 
+    /// Priority when unlocking, normal if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<KeyslotPriority>,
+
+    /// Anti-forensic splitter of the stored key material
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub af: Option<Luks2AntiForensic>,
+
     /// Storage area configuration defining where and how key material is stored
     pub area: Luks2KeyslotArea,
     /// Key derivation parameters used to process passwords into keys
     pub kdf: Luks2Kdf,
 }
 
+impl Luks2Keyslot {
+    /// Priority of the keyslot when unlocking without selecting one
+    pub fn priority(&self) -> KeyslotPriority {
+        self.priority.unwrap_or(KeyslotPriority::Normal)
+    }
+
+    /// Returns true if cryptsetup tries this keyslot when none is selected
+    pub fn unlocks_by_default(&self) -> bool {
+        self.priority() != KeyslotPriority::Ignore
+    }
+}
+
 /// Configuration for keyslot storage area defining where encrypted keys are stored.
 #[serde_as]
 #[derive(Debug, Deserialize, Serialize)]
@@ -121,6 +293,35 @@ pub struct Luks2KeyslotArea {
     pub key_size: u64,
 }
 
+/// Integrity protection of a segment, provided by dm-integrity
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Luks2Integrity {
+    /// Integrity algorithm, `aead` for authenticated ciphers or e.g. `hmac(sha256)`
+    #[serde(rename = "type")]
+    pub integrity_type: String,
+    /// Encryption of the dm-integrity journal, usually `none`
+    pub journal_encryption: String,
+    /// Integrity protection of the dm-integrity journal, usually `none`
+    pub journal_integrity: String,
+    /// Size of the integrity key in bytes, part of the volume key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_size: Option<u64>,
+}
+
+impl Luks2Integrity {
+    /// Returns true if the segment cipher authenticates data itself, e.g. `aes-gcm-random`
+    pub fn is_aead(&self) -> bool {
+        self.integrity_type == "aead"
+    }
+
+    /// Optional dm-crypt table parameter selecting integrity, for tags of `tag_size` bytes
+    ///
+    /// The tag size, including any random IV, is recorded in the dm-integrity superblock.
+    pub fn dm_crypt_option(&self, tag_size: u16) -> String {
+        format!("integrity:{}:{}", tag_size, self.integrity_type)
+    }
+}
+
 /// Configuration for a disk segment defining an encrypted region of the device.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Luks2Segment {
@@ -137,4 +338,32 @@ pub struct Luks2Segment {
     pub encryption: String,
     /// Sector size in bytes - the granularity of encryption
     pub sector_size: u64,
+    /// Integrity protection, requiring dm-integrity below dm-crypt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<Luks2Integrity>,
+}
+
+impl Luks2Segment {
+    /// Returns true if the segment is integrity protected
+    pub fn requires_integrity(&self) -> bool {
+        self.integrity.is_some()
+    }
+
+    /// Offset in bytes where the segment begins on the device
+    pub fn offset_bytes(&self) -> Result<u64, Error> {
+        self.offset
+            .parse()
+            .map_err(|_| Error::InvalidSegment(self.offset.clone()))
+    }
+
+    /// Size of the segment in bytes, `None` if it extends to the end of the device
+    pub fn size_bytes(&self) -> Result<Option<u64>, Error> {
+        match self.size.as_str() {
+            "dynamic" => Ok(None),
+            size => size
+                .parse()
+                .map(Some)
+                .map_err(|_| Error::InvalidSegment(size.to_owned())),
+        }
+    }
 }
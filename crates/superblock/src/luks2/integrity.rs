@@ -0,0 +1,83 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! # dm-integrity superblock support
+//!
+//! LUKS2 volumes formatted with `--integrity` keep a dm-integrity device below the
+//! dm-crypt mapping, starting at the data segment offset. Its superblock records the
+//! size of the per-sector tags and how many sectors remain for data once the tags and
+//! journal have been taken out.
+
+use zerocopy::*;
+
+use crate::Detection;
+
+/// Length of the magic number field in bytes
+pub const MAGIC_LEN: usize = 8;
+
+/// dm-integrity superblock, as found at the start of the integrity device
+#[derive(FromBytes, Immutable, KnownLayout, Unaligned, Debug)]
+#[repr(C, packed)]
+pub struct Integrity {
+    /// Magic number, `integrt\0`
+    pub magic: [u8; MAGIC_LEN],
+    /// Superblock version
+    pub version: u8,
+    /// Log2 of the sectors interleaved between tag areas
+    pub log2_interleave_sectors: i8,
+    /// Size of the tag stored for every sector, in bytes
+    pub integrity_tag_size: U16<LittleEndian>,
+    /// Number of journal sections
+    pub journal_sections: U32<LittleEndian>,
+    /// Number of 512 byte sectors available for data
+    pub provided_data_sectors: U64<LittleEndian>,
+    /// Superblock flags
+    pub flags: U32<LittleEndian>,
+    /// Log2 of the sectors per integrity block
+    pub log2_sectors_per_block: u8,
+    /// Log2 of the blocks per bitmap bit
+    pub log2_blocks_per_bitmap_bit: u8,
+    /// Padding bytes
+    pub pad: [u8; 2],
+    /// Next sector to recalculate tags for
+    pub recalc_sector: U64<LittleEndian>,
+    /// Padding bytes
+    pub pad2: [u8; 8],
+    /// Salt for the journal MAC
+    pub salt: [u8; 16],
+}
+
+/// Magic number identifying a dm-integrity superblock
+pub const MAGIC: [u8; MAGIC_LEN] = *b"integrt\0";
+
+impl Detection for Integrity {
+    type Magic = [u8; MAGIC_LEN];
+
+    const OFFSET: u64 = 0;
+
+    const MAGIC_OFFSET: u64 = 0;
+
+    const SIZE: usize = std::mem::size_of::<Integrity>();
+
+    fn is_valid_magic(magic: &Self::Magic) -> bool {
+        *magic == MAGIC
+    }
+}
+
+impl Integrity {
+    /// Size of the tag stored for every sector, in bytes
+    pub fn tag_size(&self) -> u16 {
+        self.integrity_tag_size.get()
+    }
+
+    /// Size of an integrity block in bytes, which matches the dm-crypt sector size
+    pub fn block_size(&self) -> u64 {
+        512 << self.log2_sectors_per_block
+    }
+
+    /// Size in bytes left for data once tags and journal have been accounted for
+    pub fn capacity(&self) -> u64 {
+        self.provided_data_sectors.get() * 512
+    }
+}
@@ -49,7 +49,7 @@ pub const CHECKSUM_LEN: usize = 64;
 /// Per the `cryptsetup` docs for dm-crypt backed LUKS2, header is at first byte.
 /// The header contains metadata about the encrypted volume including magic number,
 /// version, checksums and JSON configuration.
-#[derive(FromBytes, Unaligned, Debug)]
+#[derive(FromBytes, Immutable, KnownLayout, Unaligned, Debug)]
 #[repr(C, packed)]
 pub struct Luks2 {
     /// Magic number identifying LUKS2 format
@@ -107,8 +107,16 @@ impl Detection for Luks2 {
 impl Luks2 {
     /// Get the UUID of the LUKS2 volume
     ///
-    /// Note: LUKS2 stores string UUID rather than 128-bit sequence
-    pub fn uuid(&self) -> Result<String, crate::Error> {
+    /// Fails if the stored UUID is not a valid UUID, see [`Luks2::uuid_string()`]
+    pub fn uuid(&self) -> Result<uuid::Uuid, crate::Error> {
+        Ok(uuid::Uuid::parse_str(&self.uuid_string()?)?)
+    }
+
+    /// Get the UUID of the LUKS2 volume as stored
+    ///
+    /// Note: LUKS2 stores string UUID rather than 128-bit sequence, and `cryptsetup`
+    /// accepts any string when formatting with `--uuid`
+    pub fn uuid_string(&self) -> Result<String, crate::Error> {
         Ok(std::str::from_utf8(&self.uuid)?.trim_end_matches('\0').to_owned())
     }
 
@@ -12,7 +12,13 @@
 //! - Quota tracking data
 //! - Log and realtime extent details
 
-use crate::Detection;
+use std::{
+    io::{Read, Seek, Write},
+    mem::offset_of,
+};
+
+use crate::{fixed_label, read_at, write_at, Detection, Error};
+use crc::{Crc, CRC_32_ISCSI};
 use uuid::Uuid;
 use zerocopy::*;
 
@@ -36,11 +42,20 @@ pub type Lsn = I64<BigEndian>;
 /// Maximum length of XFS volume label (12 bytes)
 pub const MAX_LABEL_LEN: usize = 12;
 
+/// Mask of the version number in `versionnum`
+const VERSION_MASK: u16 = 0xf;
+
+/// Version 5 superblocks carry a checksum
+const VERSION_5: u16 = 5;
+
+/// Superblocks are checksummed with crc32c, stored little endian
+const CHECKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
 /// XFS superblock structure, containing filesystem metadata and parameters
 ///
 /// This structure maps directly to the on-disk format of an XFS superblock.
 /// All multi-byte integer fields are stored in big-endian byte order.
-#[derive(FromBytes, Debug)]
+#[derive(FromBytes, Immutable, KnownLayout, Debug)]
 #[repr(C, align(8))]
 pub struct XFS {
     /// Magic number, must contain 'XFSB'
@@ -165,15 +180,55 @@ pub struct XFS {
 pub const MAGIC: U32<BigEndian> = U32::new(0x58465342);
 
 impl XFS {
-    /// Returns the filesystem UUID as a properly formatted string
-    pub fn uuid(&self) -> Result<String, super::Error> {
-        Ok(Uuid::from_bytes(self.uuid).hyphenated().to_string())
+    /// Returns the filesystem UUID
+    pub fn uuid(&self) -> Result<Uuid, super::Error> {
+        Ok(Uuid::from_bytes(self.uuid))
     }
 
     /// Returns the volume label as a UTF-8 string, trimming any null termination
     pub fn label(&self) -> Result<String, super::Error> {
         Ok(std::str::from_utf8(&self.fname)?.trim_end_matches('\0').to_owned())
     }
+
+    /// Returns the size of the data section in bytes
+    pub fn capacity(&self) -> u64 {
+        self.dblocks.get() * u64::from(self.blocksize.get())
+    }
+
+    /// Returns the free space of the data section in bytes
+    ///
+    /// The counter is only written back on unmount, it is stale for mounted filesystems.
+    pub fn free_space(&self) -> u64 {
+        self.fdblocks.get() * u64::from(self.blocksize.get())
+    }
+
+    /// Write `label` to the superblocks of this filesystem on `device`
+    ///
+    /// The label is at most 12 bytes. Like `xfs_admin -L`, the secondary superblock of
+    /// every allocation group is updated as well, so `xfs_repair` finds them consistent.
+    pub fn write_label<D: Read + Write + Seek>(&self, device: &mut D, label: &str) -> Result<(), Error> {
+        let name = fixed_label::<MAX_LABEL_LEN>(label)?;
+        let sector = usize::from(self.sectsize.get()).max(Self::SIZE);
+        let ag_size = u64::from(self.agblocks.get()) * u64::from(self.blocksize.get());
+        let checksummed = self.versionnum.get() & VERSION_MASK == VERSION_5;
+
+        for ag in 0..u64::from(self.agcount.get()) {
+            let offset = ag * ag_size;
+            let mut block = read_at(device, offset, sector)?;
+            if block[..4] != *MAGIC.as_bytes() {
+                continue;
+            }
+            block[offset_of!(XFS, fname)..][..MAX_LABEL_LEN].copy_from_slice(&name);
+            if checksummed {
+                let crc = offset_of!(XFS, crc);
+                block[crc..crc + 4].fill(0);
+                let checksum = CHECKSUM.checksum(&block);
+                block[crc..crc + 4].copy_from_slice(&checksum.to_le_bytes());
+            }
+            write_at(device, offset, &block)?;
+        }
+        Ok(())
+    }
 }
 
 impl Detection for XFS {